@@ -0,0 +1,341 @@
+//! `blip archive`: a snapshot of a tree as a tar or zip file, written
+//! straight from the object store, for release tarballs and the like.
+//!
+//! Entries carry the time of the commit archived, so that archiving the
+//! same commit twice gives the same bytes. As git does, the hash of that
+//! commit is recorded too: in a pax global header of tar files and as the
+//! comment of zip files. Files keep their mode, executable or a symbolic
+//! link. Zip entries are stored uncompressed.
+
+use std::{collections::BTreeMap, io::Write};
+
+use crate::{
+    submodule,
+    types::{Error, FileMode, FileService, Result, Signature},
+};
+
+/// Size of a tar block; headers take one and data is padded to them.
+const BLOCK: usize = 512;
+
+/// Size tar files are padded to, twenty blocks as tar itself writes.
+const RECORD: usize = 20 * BLOCK;
+
+const FILE_MODE: u32 = 0o100644;
+const EXECUTABLE_MODE: u32 = 0o100755;
+const SYMLINK_MODE: u32 = 0o120777;
+const DIR_MODE: u32 = 0o040755;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn parse(name: &str) -> Option<ArchiveFormat> {
+        match name {
+            "tar" => Some(ArchiveFormat::Tar),
+            "zip" => Some(ArchiveFormat::Zip),
+            _ => None,
+        }
+    }
+
+    /// The format a file name's extension asks for.
+    pub fn from_file_name(name: &str) -> Option<ArchiveFormat> {
+        let (_, extension) = name.rsplit_once('.')?;
+        ArchiveFormat::parse(extension)
+    }
+}
+
+/// An entry of an archive: a directory, or a file with its contents, the
+/// path a symbolic link points at for one.
+struct Entry {
+    path: String,
+    data: Option<Vec<u8>>,
+    mode: FileMode,
+}
+
+/// Writes the files of `tree` to `out` in `format`, every path starting
+/// with `prefix`. `commit` is the hash of the commit the tree is from,
+/// if any, and `time` when the entries were last modified.
+pub fn write(
+    file_service: &FileService,
+    tree: &str,
+    commit: Option<&str>,
+    time: &Signature,
+    format: ArchiveFormat,
+    prefix: &str,
+    out: &mut dyn Write,
+) -> Result<()> {
+    // directories are listed before what they contain, with a trailing slash
+    let mut paths: BTreeMap<String, Option<&String>> = BTreeMap::new();
+    let (files, modes) = file_service.read_tree_entries(tree)?;
+    let submodules = submodule::paths(file_service, &files)?;
+    for (path, hash) in files.iter() {
+        // submodules are archived as empty directories, like git does
+        let full = match submodules.contains(path) {
+            true => format!("{prefix}{path}/"),
+            false => format!("{prefix}{path}"),
+        };
+        let mut end = 0;
+        while let Some(slash) = full[end..].find('/') {
+            end += slash + 1;
+            paths.insert(full[..end].to_string(), None);
+        }
+        if !submodules.contains(path) {
+            paths.insert(full, Some(hash));
+        }
+    }
+
+    let mut entries = Vec::new();
+    for (path, hash) in paths {
+        let data = hash.map(|hash| file_service.read_blob(hash)).transpose()?;
+        let mode = modes.get(&path[prefix.len()..]).copied();
+        entries.push(Entry {
+            path,
+            data,
+            mode: mode.unwrap_or_default(),
+        });
+    }
+    match format {
+        ArchiveFormat::Tar => write_tar(&entries, commit, time, out),
+        ArchiveFormat::Zip => write_zip(&entries, commit, time, out),
+    }
+}
+
+fn write_tar(
+    entries: &[Entry],
+    commit: Option<&str>,
+    time: &Signature,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut written = 0;
+    let mut put = |out: &mut dyn Write, bytes: &[u8]| -> Result<()> {
+        out.write_all(bytes)?;
+        let padding = (BLOCK - bytes.len() % BLOCK) % BLOCK;
+        out.write_all(&vec![0; padding])?;
+        written += bytes.len() + padding;
+        Ok(())
+    };
+
+    if let Some(commit) = commit {
+        let record = pax_record("comment", commit);
+        put(
+            out,
+            &tar_header("pax_global_header", 0, record.len(), time, b'g', ""),
+        )?;
+        put(out, &record)?;
+    }
+    for entry in entries {
+        let (mode, size, kind, link) = match &entry.data {
+            Some(data) if entry.mode == FileMode::Symlink => {
+                (SYMLINK_MODE, 0, b'2', String::from_utf8_lossy(data))
+            }
+            Some(data) if entry.mode == FileMode::Executable => {
+                (EXECUTABLE_MODE, data.len(), b'0', "".into())
+            }
+            Some(data) => (FILE_MODE, data.len(), b'0', "".into()),
+            None => (DIR_MODE, 0, b'5', "".into()),
+        };
+
+        // paths and link targets too long for the header go in a pax
+        // header of their own
+        let mut records = Vec::new();
+        if split_name(&entry.path).is_none() {
+            records.extend(pax_record("path", &entry.path));
+        }
+        if link.len() > 100 {
+            records.extend(pax_record("linkpath", &link));
+        }
+        if !records.is_empty() {
+            put(
+                out,
+                &tar_header("pax_header", 0, records.len(), time, b'x', ""),
+            )?;
+            put(out, &records)?;
+        }
+
+        let name = match split_name(&entry.path) {
+            Some(_) => entry.path.as_str(),
+            None => truncate(&entry.path, 100),
+        };
+        let header = tar_header(name, mode, size, time, kind, truncate(&link, 100));
+        put(out, &header)?;
+        if let Some(data) = entry.data.as_ref().filter(|_| kind == b'0') {
+            put(out, data)?;
+        }
+    }
+
+    // two empty blocks end the archive, which is padded to a whole record
+    let end = (written + 2 * BLOCK).div_ceil(RECORD) * RECORD;
+    out.write_all(&vec![0; end - written])?;
+    Ok(())
+}
+
+/// The longest start of `text` of at most `max` bytes.
+fn truncate(text: &str, max: usize) -> &str {
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// A ustar header block. `link` is the path a symbolic link points at.
+fn tar_header(
+    path: &str,
+    mode: u32,
+    size: usize,
+    time: &Signature,
+    kind: u8,
+    link: &str,
+) -> Vec<u8> {
+    let mut header = vec![0u8; BLOCK];
+    let mut field = |offset: usize, value: &[u8]| {
+        header[offset..offset + value.len()].copy_from_slice(value);
+    };
+    let (prefix, name) = split_name(path).unwrap_or(("", path));
+    field(0, name.as_bytes());
+    field(100, format!("{:07o}", mode & 0o7777).as_bytes());
+    field(108, b"0000000");
+    field(116, b"0000000");
+    field(124, format!("{size:011o}").as_bytes());
+    field(136, format!("{:011o}", time.time.max(0)).as_bytes());
+    field(148, b"        ");
+    field(156, &[kind]);
+    field(157, link.as_bytes());
+    field(257, b"ustar\x0000");
+    field(265, b"root");
+    field(297, b"root");
+    field(345, prefix.as_bytes());
+
+    let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    header
+}
+
+/// Splits a path into the prefix and name fields of a ustar header, or
+/// returns `None` if it does not fit them.
+fn split_name(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+        return Some(("", path));
+    }
+    // directories keep their trailing slash in the name
+    let search = &path[..path.len() - 1];
+    let (prefix, name) = search
+        .match_indices('/')
+        .map(|(at, _)| (&path[..at], &path[at + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100)?;
+    Some((prefix, name))
+}
+
+/// A `<length> <key>=<value>` line of a pax header, the length counting
+/// the whole line.
+fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let rest = key.len() + value.len() + 3;
+    let mut length = rest + rest.to_string().len();
+    if length.to_string().len() != rest.to_string().len() {
+        length += 1;
+    }
+    format!("{length} {key}={value}\n").into_bytes()
+}
+
+fn write_zip(
+    entries: &[Entry],
+    commit: Option<&str>,
+    time: &Signature,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let too_large = || Error::Usage("the archive is too large for a zip file".into());
+    let (_, year, month, day, secs) = time.civil();
+    let (dos_time, dos_date) = match year {
+        ..1980 => (0, (1 << 5) | 1),
+        _ => (
+            ((secs / 3600) << 11) | ((secs % 3600 / 60) << 5) | (secs % 60 / 2),
+            ((year - 1980) << 9) | ((month as i64) << 5) | day,
+        ),
+    };
+    let (dos_time, dos_date) = (dos_time as u16, dos_date as u16);
+    // names are UTF-8
+    let flags: u16 = 1 << 11;
+
+    let mut offset: u32 = 0;
+    let mut directory = Vec::new();
+    for entry in entries {
+        let data = entry.data.as_deref().unwrap_or_default();
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let crc = crc32(data);
+        let name = entry.path.as_bytes();
+
+        let mut local = Vec::new();
+        local.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        local.extend_from_slice(&10u16.to_le_bytes());
+        local.extend_from_slice(&flags.to_le_bytes());
+        local.extend_from_slice(&0u16.to_le_bytes());
+        local.extend_from_slice(&dos_time.to_le_bytes());
+        local.extend_from_slice(&dos_date.to_le_bytes());
+        local.extend_from_slice(&crc.to_le_bytes());
+        local.extend_from_slice(&size.to_le_bytes());
+        local.extend_from_slice(&size.to_le_bytes());
+        local.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        local.extend_from_slice(&0u16.to_le_bytes());
+        local.extend_from_slice(name);
+        out.write_all(&local)?;
+        out.write_all(data)?;
+
+        // a symbolic link is stored as the path it points at
+        let (mode, attributes) = match entry.data {
+            Some(_) if entry.mode == FileMode::Symlink => (SYMLINK_MODE, 0),
+            Some(_) if entry.mode == FileMode::Executable => (EXECUTABLE_MODE, 0),
+            Some(_) => (FILE_MODE, 0),
+            // the MS-DOS directory attribute
+            None => (DIR_MODE, 0x10),
+        };
+        directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        // made by Unix, so that the modes are read
+        directory.extend_from_slice(&(3u16 << 8 | 20).to_le_bytes());
+        directory.extend_from_slice(&10u16.to_le_bytes());
+        directory.extend_from_slice(&flags.to_le_bytes());
+        directory.extend_from_slice(&0u16.to_le_bytes());
+        directory.extend_from_slice(&dos_time.to_le_bytes());
+        directory.extend_from_slice(&dos_date.to_le_bytes());
+        directory.extend_from_slice(&crc.to_le_bytes());
+        directory.extend_from_slice(&size.to_le_bytes());
+        directory.extend_from_slice(&size.to_le_bytes());
+        directory.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        directory.extend_from_slice(&[0; 8]);
+        directory.extend_from_slice(&(mode << 16 | attributes).to_le_bytes());
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name);
+
+        offset =
+            u32::try_from(offset as usize + local.len() + data.len()).map_err(|_| too_large())?;
+    }
+
+    let count = u16::try_from(entries.len()).map_err(|_| too_large())?;
+    let comment = commit.unwrap_or_default().as_bytes();
+    out.write_all(&directory)?;
+    let mut end = Vec::new();
+    end.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    end.extend_from_slice(&[0; 4]);
+    end.extend_from_slice(&count.to_le_bytes());
+    end.extend_from_slice(&count.to_le_bytes());
+    end.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+    end.extend_from_slice(&offset.to_le_bytes());
+    end.extend_from_slice(&(comment.len() as u16).to_le_bytes());
+    end.extend_from_slice(comment);
+    out.write_all(&end)?;
+    Ok(())
+}
+
+/// The CRC-32 zip files check their entries with.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
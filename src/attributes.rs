@@ -0,0 +1,670 @@
+//! Attributes: settings `.blipattributes` files give to paths, telling
+//! diff, merge and checkout how to treat the files, the way
+//! `.gitattributes` does in git. Each line is a pattern, matched like an
+//! ignore pattern, followed by attributes:
+//!
+//! ```text
+//! *.png    binary
+//! *.sh     text eol=lf
+//! *.bat    eol=crlf
+//! *.psd    filter=lfs -merge
+//! ```
+//!
+//! `name` sets an attribute, `-name` unsets it, `name=value` gives it a
+//! value and `!name` returns it to unspecified. `[attr]name ...` lines
+//! define macros, such as the built-in `binary` standing for `-diff -merge
+//! -text`.
+//!
+//! The user-global file comes first, then `.blipattributes` files from the
+//! root down and `info/attributes` of the repository last, with later and
+//! deeper lines taking precedence.
+//!
+//! The attributes that change how files are handled are:
+//!
+//! - `diff`: unset, the file is shown as binary in diffs, set, as text.
+//!   Left unspecified, files with a NUL byte near the start or that are not
+//!   UTF-8 there are shown as binary.
+//! - `merge`: unset, or `merge=binary`, the file is not merged line by line
+//!   but our version is kept as a conflict. `merge=union` keeps the lines
+//!   of both sides where they conflict. `merge=<name>` merges the file with
+//!   the command in `merge.<name>.driver`, run with `%O`, `%A` and `%B`
+//!   standing for files holding the base, our and their version, `%P` for
+//!   its path and `%L` for the length of conflict markers. The command
+//!   leaves the result in `%A` and exits with a non-zero status if
+//!   conflicts remain; `true` as the command keeps our version as it is.
+//! - `text`: set, line endings are normalized to LF when the file is
+//!   stored; unset, they are left alone; `text=auto` normalizes only files
+//!   that do not look binary. Left unspecified, `core.autocrlf` decides.
+//! - `eol`: the line endings the file is checked out with, `lf` or `crlf`,
+//!   either value making it text. Left unspecified, `core.autocrlf` or else
+//!   `core.eol` decides.
+//! - `filter`: `filter=<name>` runs the file through the command in
+//!   `filter.<name>.clean` when it is stored and `filter.<name>.smudge` when
+//!   it is checked out, with `%f` standing for its path. A failing filter
+//!   leaves the contents as they were, unless `filter.<name>.required` is
+//!   set.
+//!
+//! `core.autocrlf` set to `true` treats every file that does not look
+//! binary as `text=auto` and checks text out with CRLF line endings, while
+//! `input` only normalizes them when files are stored. Without it, text is
+//! checked out with the line endings of `core.eol`: `lf`, `crlf` or
+//! `native`, the default, for those of the platform.
+
+use std::{
+    collections::BTreeMap,
+    fs::{self, DirBuilder, OpenOptions},
+    io::Write,
+    os::unix::fs::DirBuilderExt,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::{
+    config::{self, Config},
+    ignore::{self, Ignore},
+    types::{Context, Error, Result},
+};
+
+/// Name of the per-directory attributes files.
+pub const ATTRIBUTES_FILE: &str = ".blipattributes";
+
+/// How much of a file is looked at to tell whether it is binary, as git
+/// does.
+const BINARY_PROBE: usize = 8000;
+
+/// How many characters make up conflict markers, as `%L` tells merge
+/// drivers.
+const MARKER_SIZE: usize = 7;
+
+/// The value a path has for an attribute.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum State {
+    Set,
+    Unset,
+    Value(String),
+    Unspecified,
+}
+
+/// One line of an attributes file.
+#[derive(Debug)]
+struct Rule {
+    glob: String,
+    /// Directory the rule was read from, relative to the root, whose
+    /// contents it applies to. Empty for the root and files outside the
+    /// working tree.
+    base: String,
+    /// Patterns containing a slash match the whole path below `base`,
+    /// others only the final component at any depth.
+    anchored: bool,
+    /// With macros expanded, in the order they apply.
+    attributes: Vec<(String, State)>,
+}
+
+/// `core.autocrlf`: whether files that are neither text nor binary by
+/// their attributes are treated as text when they look like it, and with
+/// which line endings they are checked out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AutoCrlf {
+    False,
+    True,
+    Input,
+}
+
+/// Line endings of text files in the working tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Eol {
+    Lf,
+    Crlf,
+}
+
+/// How a file changed on both sides of a merge is merged, as its `merge`
+/// attribute says.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeDriver {
+    /// Line by line, with conflict markers where both sides changed the
+    /// same lines.
+    Text,
+    /// Not at all, keeping our version as a conflict.
+    Binary,
+    /// Line by line, keeping the lines of both sides where they conflict.
+    Union,
+    /// By the command of `merge.<name>.driver`.
+    Custom { name: String, command: String },
+}
+
+/// The commands a `filter.<name>` section of the config gives.
+#[derive(Debug, Default)]
+struct Filter {
+    clean: Option<String>,
+    smudge: Option<String>,
+    required: bool,
+}
+
+/// The attributes of the paths of one working tree.
+#[derive(Debug)]
+pub struct Attributes {
+    root_dir: PathBuf,
+    /// Where merge drivers get the directories of their temporary files.
+    common_dir: PathBuf,
+    rules: Vec<Rule>,
+    macros: BTreeMap<String, Vec<(String, State)>>,
+    /// The filters the rules name, by name.
+    filters: BTreeMap<String, Filter>,
+    /// The commands of the merge drivers the rules name that the config
+    /// gives one, by name.
+    drivers: BTreeMap<String, String>,
+    autocrlf: AutoCrlf,
+    /// What `core.eol` asks text to be checked out with.
+    eol: Eol,
+}
+
+impl State {
+    /// Renders the state the way `blip check-attr` prints it.
+    pub fn describe(&self) -> &str {
+        match self {
+            State::Set => "set",
+            State::Unset => "unset",
+            State::Value(value) => value,
+            State::Unspecified => "unspecified",
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, path: &str) -> bool {
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(&self.base)
+                .and_then(|path| path.strip_prefix('/'))
+            {
+                Some(relative) => relative,
+                None => return false,
+            }
+        };
+        if self.anchored {
+            ignore::glob_match(&self.glob, relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            ignore::glob_match(&self.glob, name)
+        }
+    }
+}
+
+impl Attributes {
+    /// Reads the global attributes file, every `.blipattributes` in the
+    /// working tree at `root_dir`, skipping ignored directories and nested
+    /// repositories, and `info/attributes` of the repository at
+    /// `common_dir`. A bare repository has no working tree to read.
+    pub fn load(root_dir: &Path, common_dir: &Path, bare: bool) -> Result<Attributes> {
+        let mut attributes = Attributes {
+            root_dir: root_dir.to_path_buf(),
+            common_dir: common_dir.to_path_buf(),
+            rules: Vec::new(),
+            macros: BTreeMap::from([(
+                "binary".to_string(),
+                vec![
+                    ("diff".to_string(), State::Unset),
+                    ("merge".to_string(), State::Unset),
+                    ("text".to_string(), State::Unset),
+                ],
+            )]),
+            filters: BTreeMap::new(),
+            drivers: BTreeMap::new(),
+            autocrlf: AutoCrlf::False,
+            eol: Eol::NATIVE,
+        };
+        let global = config::user_dir().map(|dir| dir.join("attributes"));
+        if let Some(global) = global.filter(|path| path.is_file()) {
+            attributes.add_file(&global, "")?;
+        }
+        if !bare {
+            let ignore = Ignore::load(root_dir, &root_dir.join(".blip"))?;
+            attributes.load_dir(root_dir, "", &ignore)?;
+        }
+        let info = common_dir.join("info").join("attributes");
+        if info.is_file() {
+            attributes.add_file(&info, "")?;
+        }
+
+        let config = Config::load(Some(common_dir))?;
+        attributes.autocrlf = match config.get("core.autocrlf") {
+            Some(value) if value.eq_ignore_ascii_case("input") => AutoCrlf::Input,
+            _ => match config.get_bool("core.autocrlf")? {
+                Some(true) => AutoCrlf::True,
+                _ => AutoCrlf::False,
+            },
+        };
+        attributes.eol = match config.get("core.eol").as_deref() {
+            None | Some("native") => Eol::NATIVE,
+            Some("lf") => Eol::Lf,
+            Some("crlf") => Eol::Crlf,
+            Some(value) => {
+                return Err(Error::InvalidConfig(format!(
+                    "bad value '{value}' for 'core.eol'"
+                )))
+            }
+        };
+        for rule in attributes.rules.iter() {
+            for (name, state) in rule.attributes.iter() {
+                match (name.as_str(), state) {
+                    ("filter", State::Value(filter))
+                        if !attributes.filters.contains_key(filter) =>
+                    {
+                        let section = format!("filter.{filter}");
+                        let loaded = Filter {
+                            clean: config.get(&format!("{section}.clean")),
+                            smudge: config.get(&format!("{section}.smudge")),
+                            required: config
+                                .get_bool(&format!("{section}.required"))?
+                                .unwrap_or(false),
+                        };
+                        attributes.filters.insert(filter.clone(), loaded);
+                    }
+                    ("merge", State::Value(driver)) => {
+                        if let Some(command) = config.get(&format!("merge.{driver}.driver")) {
+                            attributes.drivers.insert(driver.clone(), command);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(attributes)
+    }
+
+    fn load_dir(&mut self, dir: &Path, relative: &str, ignore: &Ignore) -> Result<()> {
+        let file = dir.join(ATTRIBUTES_FILE);
+        if file.is_file() {
+            self.add_file(&file, relative)?;
+        }
+
+        let mut subdirs: Vec<PathBuf> = fs::read_dir(dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect()
+            })
+            .context("read", dir)?;
+        subdirs.retain(|path| path.is_dir() && !path.join(".blip").exists());
+        subdirs.sort();
+
+        for subdir in subdirs {
+            let Some(name) = subdir.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if name == ".blip" {
+                continue;
+            }
+            let path = if relative.is_empty() {
+                name.to_string()
+            } else {
+                format!("{relative}/{name}")
+            };
+            if !ignore.is_ignored(&path, true) {
+                self.load_dir(&subdir, &path, ignore)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, file: &Path, base: &str) -> Result<()> {
+        let contents = fs::read_to_string(file).context("read", file)?;
+        for line in contents.lines() {
+            self.add_line(line, base);
+        }
+        Ok(())
+    }
+
+    fn add_line(&mut self, line: &str, base: &str) {
+        let mut words = line.split_whitespace();
+        let Some(pattern) = words.next().filter(|word| !word.starts_with('#')) else {
+            return;
+        };
+
+        let mut attributes = Vec::new();
+        for word in words {
+            let (name, state) = match word.split_once('=') {
+                Some((name, value)) => (name, State::Value(value.to_string())),
+                None => match word.strip_prefix('-') {
+                    Some(name) => (name, State::Unset),
+                    None => match word.strip_prefix('!') {
+                        Some(name) => (name, State::Unspecified),
+                        None => (word, State::Set),
+                    },
+                },
+            };
+            if name.is_empty() {
+                continue;
+            }
+            if state == State::Set {
+                if let Some(expansion) = self.macros.get(name) {
+                    attributes.extend(expansion.iter().cloned());
+                }
+            }
+            attributes.push((name.to_string(), state));
+        }
+
+        if let Some(name) = pattern.strip_prefix("[attr]") {
+            self.macros.insert(name.to_string(), attributes);
+            return;
+        }
+        // as in git, a pattern can only match files, never directories
+        if pattern.ends_with('/') {
+            return;
+        }
+        let anchored = pattern.contains('/');
+        let glob = pattern.strip_prefix('/').unwrap_or(pattern);
+        if glob.is_empty() || pattern.starts_with('!') {
+            return;
+        }
+        self.rules.push(Rule {
+            glob: glob.to_string(),
+            base: base.to_string(),
+            anchored,
+            attributes,
+        });
+    }
+
+    /// Every attribute `path`, relative to the root, has a value for.
+    pub fn get(&self, path: &str) -> BTreeMap<String, State> {
+        let mut attributes = BTreeMap::new();
+        for rule in self.rules.iter().filter(|rule| rule.matches(path)) {
+            for (name, state) in rule.attributes.iter() {
+                match state {
+                    State::Unspecified => attributes.remove(name),
+                    state => attributes.insert(name.clone(), state.clone()),
+                };
+            }
+        }
+        attributes
+    }
+
+    /// The value `path` has for the attribute `name`.
+    pub fn state(&self, path: &str, name: &str) -> State {
+        self.get(path).remove(name).unwrap_or(State::Unspecified)
+    }
+
+    /// Whether diffs show `path`, with `contents` on either side, as
+    /// binary rather than line by line.
+    pub fn is_binary_diff(&self, path: &str, contents: [&[u8]; 2]) -> bool {
+        match self.state(path, "diff") {
+            State::Unset => true,
+            State::Set | State::Value(_) => false,
+            State::Unspecified => contents.iter().any(|data| {
+                let probe = &data[..data.len().min(BINARY_PROBE)];
+                // a character cut off at the end of the probe still counts
+                probe.contains(&0)
+                    || std::str::from_utf8(probe).is_err_and(|error| error.error_len().is_some())
+            }),
+        }
+    }
+
+    /// How merges merge `path`. A driver the config gives no command for
+    /// merges line by line, as git does.
+    pub fn merge_driver(&self, path: &str) -> MergeDriver {
+        let name = match self.state(path, "merge") {
+            State::Unset => return MergeDriver::Binary,
+            State::Value(name) => name,
+            _ => return MergeDriver::Text,
+        };
+        match name.as_str() {
+            "binary" => MergeDriver::Binary,
+            "union" => MergeDriver::Union,
+            _ => match self.drivers.get(&name) {
+                Some(command) => MergeDriver::Custom {
+                    command: command.clone(),
+                    name,
+                },
+                None => MergeDriver::Text,
+            },
+        }
+    }
+
+    /// Merges `path` with a merge driver's `command`, run by the shell in
+    /// the root of the working tree on temporary files holding `contents`,
+    /// its base, our and their version. Returns what the driver left in
+    /// our file and whether it merged without conflicts, which a driver
+    /// that cannot be run did not.
+    ///
+    /// The files are made new in a directory of the repository's only its
+    /// owner can enter, so that nothing put in their place beforehand is
+    /// written through.
+    pub fn run_merge_driver(
+        &self,
+        command: &str,
+        path: &str,
+        contents: (&[u8], &[u8], &[u8]),
+    ) -> Result<(Vec<u8>, bool)> {
+        static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = self.common_dir.join(format!(
+            "merge_{}_{}",
+            process::id(),
+            TEMP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .context("create", &dir)?;
+        let (base, ours, theirs) = (dir.join("O"), dir.join("A"), dir.join("B"));
+
+        let run = || -> Result<(Vec<u8>, bool)> {
+            for (file, data) in [
+                (&base, contents.0),
+                (&ours, contents.1),
+                (&theirs, contents.2),
+            ] {
+                OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(file)
+                    .and_then(|mut file| file.write_all(data))
+                    .context("write", file)?;
+            }
+
+            let mut expanded = String::new();
+            let mut chars = command.chars();
+            while let Some(c) = chars.next() {
+                if c != '%' {
+                    expanded.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('O') => expanded.push_str(&shell_quote(&base.to_string_lossy())),
+                    Some('A') => expanded.push_str(&shell_quote(&ours.to_string_lossy())),
+                    Some('B') => expanded.push_str(&shell_quote(&theirs.to_string_lossy())),
+                    Some('P') => expanded.push_str(&shell_quote(path)),
+                    Some('L') => expanded.push_str(&MARKER_SIZE.to_string()),
+                    Some(other) => expanded.extend(['%', other]),
+                    None => expanded.push('%'),
+                }
+            }
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(expanded)
+                .current_dir(&self.root_dir)
+                .status();
+            let merged = fs::read(&ours).context("read", &ours)?;
+            Ok((merged, status.is_ok_and(|status| status.success())))
+        };
+        let result = run();
+        let _ = fs::remove_dir_all(&dir);
+        result
+    }
+
+    /// Whether the contents of `path` may differ between the working tree
+    /// and the object store, so that they must go through
+    /// [`Attributes::to_store`] and [`Attributes::to_worktree`].
+    pub fn converts(&self, path: &str) -> bool {
+        let attributes = self.get(path);
+        let text = match attributes.get("text") {
+            None => self.autocrlf != AutoCrlf::False,
+            Some(state) => *state != State::Unset,
+        };
+        text || attributes.contains_key("eol") || attributes.contains_key("filter")
+    }
+
+    /// The contents of the file at `path` as they are stored: cleaned by its
+    /// filter, then with CRLF line endings turned into LF if it is text.
+    pub fn to_store(&self, path: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        let attributes = self.get(path);
+        let data = match self.filter(&attributes) {
+            Some((name, filter)) => self.run_filter(name, filter, &filter.clean, path, data)?,
+            None => data,
+        };
+        Ok(match self.is_text(&attributes, &data) {
+            true => crlf_to_lf(data),
+            false => data,
+        })
+    }
+
+    /// The contents of the blob for `path` as they are checked out: with LF
+    /// line endings turned into CRLF if it is text checked out with CRLF,
+    /// then smudged by its filter.
+    pub fn to_worktree(&self, path: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        let attributes = self.get(path);
+        let crlf = self.checkout_eol(&attributes) == Eol::Crlf;
+        let data = match crlf && self.is_text(&attributes, &data) {
+            true => lf_to_crlf(data),
+            false => data,
+        };
+        match self.filter(&attributes) {
+            Some((name, filter)) => self.run_filter(name, filter, &filter.smudge, path, data),
+            None => Ok(data),
+        }
+    }
+
+    /// Whether a file with `attributes` and contents `data` is text, whose
+    /// line endings are converted.
+    fn is_text(&self, attributes: &BTreeMap<String, State>, data: &[u8]) -> bool {
+        match attributes.get("text") {
+            Some(State::Set) => true,
+            Some(State::Unset) => false,
+            Some(State::Value(value)) if value == "auto" => !looks_binary(data),
+            _ if attributes.contains_key("eol") => true,
+            _ => self.autocrlf != AutoCrlf::False && !looks_binary(data),
+        }
+    }
+
+    /// The line endings text with `attributes` is checked out with.
+    fn checkout_eol(&self, attributes: &BTreeMap<String, State>) -> Eol {
+        match attributes.get("eol") {
+            Some(State::Value(eol)) if eol == "crlf" => Eol::Crlf,
+            Some(State::Value(eol)) if eol == "lf" => Eol::Lf,
+            _ => match self.autocrlf {
+                AutoCrlf::True => Eol::Crlf,
+                AutoCrlf::Input => Eol::Lf,
+                AutoCrlf::False => self.eol,
+            },
+        }
+    }
+
+    fn filter<'a>(
+        &'a self,
+        attributes: &'a BTreeMap<String, State>,
+    ) -> Option<(&'a str, &'a Filter)> {
+        let Some(State::Value(name)) = attributes.get("filter") else {
+            return None;
+        };
+        self.filters.get(name).map(|filter| (name.as_str(), filter))
+    }
+
+    /// Pipes `data` through `command`, run by the shell in the root of the
+    /// working tree.
+    fn run_filter(
+        &self,
+        name: &str,
+        filter: &Filter,
+        command: &Option<String>,
+        path: &str,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let Some(command) = command else {
+            return match filter.required {
+                true => Err(Error::FilterFailed {
+                    filter: name.to_string(),
+                    path: path.to_string(),
+                }),
+                false => Ok(data),
+            };
+        };
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command.replace("%f", &shell_quote(path)))
+            .current_dir(&self.root_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn();
+
+        let output = child.and_then(|mut child| {
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+            // written from another thread, as the filter may fill its
+            // output pipe before reading all of its input
+            let input = data.clone();
+            let writer = thread::spawn(move || stdin.write_all(&input));
+            let output = child.wait_with_output()?;
+            // a filter that stops reading early is judged by its status
+            let _ = writer.join();
+            Ok(output)
+        });
+        match output {
+            Ok(output) if output.status.success() => Ok(output.stdout),
+            _ if filter.required => Err(Error::FilterFailed {
+                filter: name.to_string(),
+                path: path.to_string(),
+            }),
+            _ => Ok(data),
+        }
+    }
+}
+
+impl Eol {
+    /// The line endings of the platform.
+    const NATIVE: Eol = match cfg!(windows) {
+        true => Eol::Crlf,
+        false => Eol::Lf,
+    };
+}
+
+/// Whether `data` looks like the contents of a binary file rather than
+/// text, as git judges it: near the start, it has a NUL byte or a carriage
+/// return that does not end a line, which converting line endings would
+/// lose.
+pub fn looks_binary(data: &[u8]) -> bool {
+    let probe = &data[..data.len().min(BINARY_PROBE)];
+    probe.iter().enumerate().any(|(i, &byte)| {
+        byte == 0 || (byte == b'\r' && i + 1 < probe.len() && probe[i + 1] != b'\n')
+    })
+}
+
+/// Quotes `text` as a single word for the shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+fn crlf_to_lf(data: Vec<u8>) -> Vec<u8> {
+    if !data.windows(2).any(|pair| pair == b"\r\n") {
+        return data;
+    }
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        if byte != b'\r' || data.get(i + 1) != Some(&b'\n') {
+            out.push(byte);
+        }
+    }
+    out
+}
+
+fn lf_to_crlf(data: Vec<u8>) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        if byte == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(byte);
+    }
+    out
+}
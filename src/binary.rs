@@ -0,0 +1,218 @@
+//! The data of git's binary patches: the contents of a file as a zlib
+//! stream, written out in base85 lines of up to 52 bytes each, every one
+//! led by a letter saying how many bytes it holds, `A` to `Z` for 1 to 26
+//! and `a` to `z` for 27 to 52.
+//!
+//! The zlib streams written keep the data in stored blocks, uncompressed,
+//! and only those are read back: git reads what is written here, but its
+//! own binary patches are not understood.
+
+/// The digits of git's base85, in order of value.
+const ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// The most bytes a line holds.
+const LINE_BYTES: usize = 52;
+
+/// The most bytes a stored block holds.
+const BLOCK_BYTES: usize = 0xffff;
+
+/// Writes `data` as the lines of a binary patch, each with its newline.
+pub fn encode(data: &[u8]) -> String {
+    base85_lines(&zlib_stored(data))
+}
+
+/// Writes `stream` out in base85 lines, each with its newline.
+fn base85_lines(stream: &[u8]) -> String {
+    let mut out = String::new();
+    for line in stream.chunks(LINE_BYTES) {
+        out.push(match line.len() {
+            len @ 1..=26 => (b'A' + len as u8 - 1) as char,
+            len => (b'a' + len as u8 - 27) as char,
+        });
+        for group in line.chunks(4) {
+            let mut word = [0; 4];
+            word[..group.len()].copy_from_slice(group);
+            let mut value = u32::from_be_bytes(word);
+            let mut digits = [0; 5];
+            for digit in digits.iter_mut().rev() {
+                *digit = ALPHABET[(value % 85) as usize];
+                value /= 85;
+            }
+            out.extend(digits.iter().map(|&digit| digit as char));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Reads the data back from the `lines` of a binary patch, without their
+/// newlines, or returns `None` if they are malformed or hold compressed
+/// data.
+pub fn decode(lines: &[&str]) -> Option<Vec<u8>> {
+    let mut stream = Vec::new();
+    for line in lines {
+        let (&length, digits) = line.as_bytes().split_first()?;
+        let length = match length {
+            b'A'..=b'Z' => (length - b'A') as usize + 1,
+            b'a'..=b'z' => (length - b'a') as usize + 27,
+            _ => return None,
+        };
+        if digits.len() != length.div_ceil(4) * 5 {
+            return None;
+        }
+        let mut bytes = Vec::with_capacity(digits.len() / 5 * 4);
+        for group in digits.chunks(5) {
+            let mut value = 0u32;
+            for digit in group {
+                let digit = ALPHABET.iter().position(|c| c == digit)? as u32;
+                value = value.checked_mul(85)?.checked_add(digit)?;
+            }
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        stream.extend_from_slice(&bytes[..length]);
+    }
+    unzlib_stored(&stream)
+}
+
+/// Wraps `data` in a zlib stream of stored blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate, a 32K window, no dictionary, the fastest level
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(BLOCK_BYTES).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[0x01, 0x00, 0x00, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(u8::from(blocks.peek().is_none()));
+        let length = block.len() as u16;
+        out.extend_from_slice(&length.to_le_bytes());
+        out.extend_from_slice(&(!length).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+/// The data in a zlib stream of stored blocks, `None` if it has any other
+/// kind of block or does not check out.
+fn unzlib_stored(stream: &[u8]) -> Option<Vec<u8>> {
+    let [method, flags, ref rest @ ..] = *stream else {
+        return None;
+    };
+    let header = u16::from_be_bytes([method, flags]);
+    if method & 0x0f != 8 || header % 31 != 0 || flags & 0x20 != 0 {
+        return None;
+    }
+    let mut data = Vec::new();
+    let mut rest = rest;
+    loop {
+        let [block, l0, l1, n0, n1, ref tail @ ..] = *rest else {
+            return None;
+        };
+        let length = u16::from_le_bytes([l0, l1]);
+        if block & 0b110 != 0 || length != !u16::from_le_bytes([n0, n1]) {
+            return None;
+        }
+        let (bytes, tail) = tail.split_at_checked(length as usize)?;
+        data.extend_from_slice(bytes);
+        rest = tail;
+        if block & 1 == 1 {
+            break;
+        }
+    }
+    let checksum: [u8; 4] = rest.try_into().ok()?;
+    (u32::from_be_bytes(checksum) == adler32(&data)).then_some(data)
+}
+
+/// The Adler-32 checksum zlib streams end with.
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: &[u8]) -> Option<Vec<u8>> {
+        let text = encode(data);
+        assert!(text.ends_with('\n'));
+        decode(&text.lines().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn data_round_trips() {
+        assert_eq!(round_trip(b"").as_deref(), Some(&b""[..]));
+        assert_eq!(
+            round_trip(b"\0\x01\xff").as_deref(),
+            Some(&b"\0\x01\xff"[..])
+        );
+        // several lines, the last one short, and several stored blocks
+        let data: Vec<u8> = (0..3 * BLOCK_BYTES + 7).map(|i| (i * 7) as u8).collect();
+        assert_eq!(round_trip(&data), Some(data));
+    }
+
+    #[test]
+    fn lines_are_led_by_their_length() {
+        let text = encode(&[0; 100]);
+        let lengths: Vec<_> = text.lines().map(|line| &line[..1]).collect();
+        // 2 bytes of header, 5 of block header, 100 of data, 4 of checksum
+        assert_eq!(lengths, ["z", "z", "G"]);
+        assert!(text
+            .lines()
+            .all(|line| line.len() <= 1 + LINE_BYTES / 4 * 5));
+    }
+
+    #[test]
+    fn the_checksum_is_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn malformed_lines_are_refused() {
+        let text = encode(b"some data");
+        let line = text.trim_end();
+        assert!(decode(&[line]).is_some());
+        // a length that is not a letter, or does not match the digits
+        assert_eq!(decode(&[&format!("0{}", &line[1..])]), None);
+        assert_eq!(decode(&[&format!("A{}", &line[1..])]), None);
+        assert_eq!(decode(&[&line[..line.len() - 1]]), None);
+        assert_eq!(decode(&[""]), None);
+        // a digit outside the alphabet, or a group too big for 32 bits
+        assert_eq!(decode(&[&format!("{}\"", &line[..line.len() - 1])]), None);
+        assert_eq!(decode(&["D~~~~~"]), None);
+    }
+
+    #[test]
+    fn malformed_streams_are_refused() {
+        let decode_stream = |stream: &[u8]| {
+            let text = base85_lines(stream);
+            decode(&text.lines().collect::<Vec<_>>())
+        };
+        let stream = zlib_stored(b"some data");
+        assert_eq!(decode_stream(&stream).as_deref(), Some(&b"some data"[..]));
+
+        // a bad checksum, a truncated stream, trailing bytes
+        let mut corrupt = stream.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert_eq!(decode_stream(&corrupt), None);
+        assert_eq!(decode_stream(&stream[..stream.len() - 5]), None);
+        assert_eq!(decode_stream(&[&stream[..], b"x"].concat()), None);
+        // a block length that its complement does not match
+        let mut corrupt = stream.clone();
+        corrupt[3] ^= 1;
+        assert_eq!(decode_stream(&corrupt), None);
+        // a bad header, and a compressed stream as git itself writes
+        assert_eq!(decode_stream(&[&[0x78, 0x02], &stream[2..]].concat()), None);
+        let compressed = [
+            0x78, 0x9c, 0x4b, 0x4c, 0x4a, 0x06, 0x00, 0x02, 0x4d, 0x01, 0x27,
+        ];
+        assert_eq!(decode_stream(&compressed), None);
+    }
+}
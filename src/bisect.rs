@@ -0,0 +1,377 @@
+//! Bisect: a binary search of the history for the commit that introduced
+//! a change, usually a bug, given a commit that has it and commits that do
+//! not.
+//!
+//! The commits left to test are those the bad commit reaches and no good
+//! one does. Each round checks out the one that splits them most evenly,
+//! to be marked good or bad in turn, or skipped when it cannot be tested,
+//! until only the bad commit is left, or only it and skipped ones.
+//! The search keeps its state in `.blip` while it runs:
+//!
+//! - `BISECT_START`: the branch HEAD was on when it started, or the commit
+//!   of a detached HEAD, to go back to;
+//! - `BISECT_BAD`: the commit last marked bad;
+//! - `BISECT_GOOD`: the commits marked good, one per line;
+//! - `BISECT_SKIP`: the commits skipped, one per line.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+    process,
+};
+
+use crate::{
+    history,
+    refs::Refs,
+    sequencer,
+    types::{Context, Error, FileService, Result},
+    worktree::WorkTree,
+};
+
+/// The file of `.blip` that says where the search started from.
+const START_FILE: &str = "BISECT_START";
+const BAD_FILE: &str = "BISECT_BAD";
+const GOOD_FILE: &str = "BISECT_GOOD";
+const SKIP_FILE: &str = "BISECT_SKIP";
+
+/// The exit code of a `bisect run` command that could not test a commit.
+const SKIP_CODE: i32 = 125;
+
+/// What a tested commit is marked as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    /// The commit does not have the change searched for.
+    Good,
+    /// The commit has the change searched for.
+    Bad,
+    /// The commit cannot be tested, and another is tried instead.
+    Skip,
+}
+
+impl Term {
+    pub fn name(self) -> &'static str {
+        match self {
+            Term::Good => "good",
+            Term::Bad => "bad",
+            Term::Skip => "skip",
+        }
+    }
+}
+
+/// Where the search stands after a commit is marked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bisection {
+    /// The search needs a bad commit and at least one good one to go on;
+    /// whether each is known yet.
+    Waiting { bad: bool, good: bool },
+    /// `hash` is checked out to be tested, leaving about `remaining`
+    /// commits to test after it in about `steps` more rounds.
+    Testing {
+        hash: String,
+        remaining: usize,
+        steps: u32,
+    },
+    /// The first bad commit.
+    Found(String),
+    /// Only skipped commits are left to test, so the first bad commit is
+    /// any of these, the bad commit and the skipped ones it reaches.
+    Skipped(Vec<String>),
+}
+
+/// Whether a search is in progress.
+pub fn in_progress(file_service: &FileService) -> bool {
+    file_service.blip_dir.join(START_FILE).exists()
+}
+
+/// The branch, or the commit of a detached HEAD, the search in progress
+/// started from.
+pub fn started_from(file_service: &FileService) -> Result<Option<String>> {
+    match fs::read_to_string(file_service.blip_dir.join(START_FILE)) {
+        Ok(start) => Ok(Some(start.trim_end().to_string())),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Starts a search from HEAD, which needs a clean working tree, with the
+/// `bad` commit and the `good` ones if they are already known.
+pub fn start(file_service: &FileService, bad: Option<&str>, good: &[String]) -> Result<Bisection> {
+    if in_progress(file_service) {
+        return Err(Error::Usage(
+            "already bisecting; use 'blip bisect reset' first".into(),
+        ));
+    }
+    sequencer::check_idle(file_service)?;
+    let Some(head) = file_service.read_head_commit()? else {
+        return Err(Error::Usage("cannot bisect on an empty branch".into()));
+    };
+    let worktree = WorkTree::new(file_service);
+    let status = worktree.status(&file_service.read_index()?, Some(&head))?;
+    if !status.is_clean() {
+        let paths = status.staged.into_iter().chain(status.unstaged);
+        return Err(Error::DirtyWorkTree(paths.map(|(_, path)| path).collect()));
+    }
+
+    let bad = bad.map(|bad| file_service.resolve(bad)).transpose()?;
+    let good = resolve_all(file_service, good)?;
+    let start = match Refs::new(file_service).current_branch(file_service)? {
+        Some(branch) => branch,
+        None => head.hash().unwrap_or_default().to_string(),
+    };
+    fs::write(file_service.blip_dir.join(START_FILE), format!("{start}\n"))?;
+    if let Some(bad) = bad {
+        fs::write(file_service.blip_dir.join(BAD_FILE), format!("{bad}\n"))?;
+    }
+    write_marked(file_service, GOOD_FILE, &good)?;
+    next(file_service)
+}
+
+/// Marks `revisions`, or HEAD when there are none, as `term` and checks out
+/// the next commit to test. A commit marked bad replaces the one marked
+/// before it.
+pub fn mark(file_service: &FileService, term: Term, revisions: &[String]) -> Result<Bisection> {
+    if !in_progress(file_service) {
+        return Err(Error::Usage(
+            "not bisecting; use 'blip bisect start' first".into(),
+        ));
+    }
+    let head = ["HEAD".to_string()];
+    let revisions = if revisions.is_empty() {
+        &head[..]
+    } else {
+        revisions
+    };
+    let mut hashes = resolve_all(file_service, revisions)?;
+
+    match term {
+        Term::Good => {
+            let mut good = read_marked(file_service, GOOD_FILE)?;
+            hashes.retain(|hash| !good.contains(hash));
+            good.extend(hashes);
+            write_marked(file_service, GOOD_FILE, &good)?;
+        }
+        Term::Bad => {
+            let [bad] = &hashes[..] else {
+                return Err(Error::Usage("only one commit can be marked bad".into()));
+            };
+            fs::write(file_service.blip_dir.join(BAD_FILE), format!("{bad}\n"))?;
+        }
+        Term::Skip => {
+            let mut skipped = read_marked(file_service, SKIP_FILE)?;
+            hashes.retain(|hash| !skipped.contains(hash));
+            skipped.extend(hashes);
+            write_marked(file_service, SKIP_FILE, &skipped)?;
+        }
+    }
+    next(file_service)
+}
+
+/// Tests each commit the search checks out with `command`, run through the
+/// shell at the root of the working tree, marking it good when it exits 0,
+/// skipping it when it exits 125 and marking it bad when it exits 1 to 127
+/// otherwise, until the first bad commit is found, or only skipped commits
+/// are left. Returns where the search ended. `tested` is told how each
+/// round went.
+///
+/// Any other exit, such as from a signal, stops the search where it is.
+pub fn run(
+    file_service: &FileService,
+    command: &[String],
+    mut tested: impl FnMut(Term, &Bisection) -> Result<()>,
+) -> Result<Bisection> {
+    if !in_progress(file_service) {
+        return Err(Error::Usage(
+            "not bisecting; use 'blip bisect start' first".into(),
+        ));
+    }
+    let mut bisection = next(file_service)?;
+    loop {
+        match bisection {
+            Bisection::Waiting { .. } => {
+                return Err(Error::Usage(
+                    "bisect run needs a bad and a good commit first".into(),
+                ))
+            }
+            Bisection::Found(_) | Bisection::Skipped(_) => return Ok(bisection),
+            Bisection::Testing { .. } => {}
+        }
+
+        let program = &command[0];
+        let status = process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("{program} \"$@\""))
+            .arg(program)
+            .args(&command[1..])
+            .current_dir(&file_service.root_dir)
+            .status()
+            .context("run", Path::new(program))?;
+        let term = match status.code() {
+            Some(0) => Term::Good,
+            Some(SKIP_CODE) => Term::Skip,
+            Some(1..=127) => Term::Bad,
+            code => {
+                let how = code.map_or("was killed".to_string(), |code| {
+                    format!("exited with {code}")
+                });
+                return Err(Error::Usage(format!(
+                    "bisect run stopped: '{}' {how}",
+                    command.join(" ")
+                )));
+            }
+        };
+        bisection = mark(file_service, term, &[])?;
+        tested(term, &bisection)?;
+    }
+}
+
+/// Ends the search, going back to the branch or commit it started from.
+/// Returns that branch or commit.
+pub fn reset(file_service: &FileService) -> Result<String> {
+    let Some(start) = started_from(file_service)? else {
+        return Err(Error::Usage("not bisecting".into()));
+    };
+    let refs = Refs::new(file_service);
+    match refs.read(&Refs::branch_ref(&start)) {
+        Some(hash) => switch(file_service, &hash, Some(&start))?,
+        None => switch(file_service, &start, None)?,
+    }
+    for name in [START_FILE, BAD_FILE, GOOD_FILE, SKIP_FILE] {
+        let _ = fs::remove_file(file_service.blip_dir.join(name));
+    }
+    Ok(start)
+}
+
+/// Works out the commits left to test from what is marked and checks out
+/// the one to test next.
+fn next(file_service: &FileService) -> Result<Bisection> {
+    let bad = fs::read_to_string(file_service.blip_dir.join(BAD_FILE))
+        .ok()
+        .map(|bad| bad.trim_end().to_string());
+    let good = read_marked(file_service, GOOD_FILE)?;
+    let Some(bad) = bad.filter(|_| !good.is_empty()) else {
+        return Ok(Bisection::Waiting {
+            bad: file_service.blip_dir.join(BAD_FILE).exists(),
+            good: !good.is_empty(),
+        });
+    };
+
+    let mut excluded = HashSet::new();
+    for hash in &good {
+        excluded.extend(history::ancestors(file_service, hash)?);
+    }
+    if excluded.contains(&bad) {
+        return Err(Error::Usage(format!(
+            "the bad commit {bad} is an ancestor of a good commit"
+        )));
+    }
+
+    // the candidates in the order they are found from the bad commit, which
+    // settles ties, with their parents among the candidates
+    let mut order = Vec::new();
+    let mut parents: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut queue = vec![bad.clone()];
+    while let Some(hash) = queue.pop() {
+        if excluded.contains(&hash) || parents.contains_key(&hash) {
+            continue;
+        }
+        let commit = file_service.read_commit_headers(&hash)?;
+        let kept: Vec<String> = commit
+            .parents()
+            .iter()
+            .filter(|parent| !excluded.contains(*parent))
+            .cloned()
+            .collect();
+        queue.extend(kept.iter().rev().cloned());
+        parents.insert(hash.clone(), kept);
+        order.push(hash);
+    }
+    if order.len() == 1 {
+        return Ok(Bisection::Found(bad));
+    }
+
+    // testing a commit leaves either the candidates it reaches, if it is
+    // bad, or the rest, if it is good; the best one leaves the fewest
+    let skipped = read_marked(file_service, SKIP_FILE)?;
+    let total = order.len();
+    let best = order[1..]
+        .iter()
+        .filter(|hash| !skipped.contains(hash))
+        .map(|hash| {
+            let reached = reach(&parents, hash);
+            (reached.max(total - reached) - 1, hash)
+        })
+        .min_by_key(|(left, _)| *left);
+    let Some((remaining, hash)) = best else {
+        return Ok(Bisection::Skipped(order));
+    };
+    switch(file_service, hash, None)?;
+    Ok(Bisection::Testing {
+        hash: hash.clone(),
+        remaining,
+        steps: usize::BITS - remaining.leading_zeros(),
+    })
+}
+
+/// The number of candidates `hash` reaches, itself included.
+fn reach(parents: &BTreeMap<String, Vec<String>>, hash: &str) -> usize {
+    let mut seen = HashSet::new();
+    let mut queue = vec![hash];
+    while let Some(hash) = queue.pop() {
+        if seen.insert(hash) {
+            queue.extend(parents[hash].iter().map(String::as_str));
+        }
+    }
+    seen.len()
+}
+
+/// Checks out `hash`, on `branch` or with a detached HEAD, as long as no
+/// change in the working tree or the index is in the way.
+fn switch(file_service: &FileService, hash: &str, branch: Option<&str>) -> Result<()> {
+    let commit = file_service.read_commit(hash)?;
+    let head = file_service.read_head_commit()?;
+    let mut index = file_service.lock_index()?;
+    let worktree = WorkTree::new(file_service);
+    let status = worktree.status(&index, head.as_ref())?;
+    let mut blocked: Vec<String> = status
+        .staged
+        .into_iter()
+        .chain(status.unstaged)
+        .map(|(_, path)| path)
+        .collect();
+    blocked.extend(
+        status
+            .untracked
+            .into_iter()
+            .filter(|path| commit.files().contains_key(path)),
+    );
+    if !blocked.is_empty() {
+        return Err(Error::DirtyWorkTree(blocked));
+    }
+
+    let empty = BTreeMap::new();
+    let current = head.as_ref().map_or(&empty, |head| head.files());
+    worktree.checkout(file_service, current, commit.files(), commit.modes())?;
+    file_service.set_head(branch, hash)?;
+    index.clear()
+}
+
+fn resolve_all(file_service: &FileService, revisions: &[String]) -> Result<Vec<String>> {
+    revisions
+        .iter()
+        .map(|revision| file_service.resolve(revision))
+        .collect()
+}
+
+/// The commits listed in `file` of `.blip`, those marked good or skipped.
+fn read_marked(file_service: &FileService, file: &str) -> Result<Vec<String>> {
+    match fs::read_to_string(file_service.blip_dir.join(file)) {
+        Ok(marked) => Ok(marked.lines().map(str::to_string).collect()),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+fn write_marked(file_service: &FileService, file: &str, marked: &[String]) -> Result<()> {
+    let lines: String = marked.iter().map(|hash| format!("{hash}\n")).collect();
+    fs::write(file_service.blip_dir.join(file), lines)?;
+    Ok(())
+}
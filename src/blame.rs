@@ -0,0 +1,168 @@
+//! Blame: which commit last changed each line of a file.
+//!
+//! Lines start out charged to the working tree and are handed down the
+//! history from HEAD: a commit passes each line its parent has unchanged,
+//! as [`diff::diff_lines`] matches them, on to that parent and keeps the
+//! rest. A merge offers its lines to each parent in turn. Whatever a
+//! commit keeps is what it last changed.
+//!
+//! Lines that only differ in whitespace can be taken for unchanged, see
+//! [`Whitespace`]. Ignoring blank lines, those a commit replaces with
+//! others where it changes the file are taken for unchanged too, so that
+//! only the blank lines it adds in all are its own.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, mem,
+};
+
+use crate::{
+    diff::{self, Edit, Whitespace},
+    history::CommitWalk,
+    types::{Error, FileService, Result},
+};
+
+/// A line of the blamed file and where it was last changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    /// The commit that last changed the line, `None` when it is changed in
+    /// the working tree and not committed yet.
+    pub commit: Option<String>,
+    /// The number of the line, from 1, in the file as that commit has it.
+    pub original: usize,
+    pub text: String,
+}
+
+/// Blames every line of `path`, relative to the root of the working tree,
+/// as the working tree has it, or as HEAD has it when it is deleted there,
+/// overlooking the changes in whitespace `whitespace` says.
+pub fn blame(
+    file_service: &FileService,
+    path: &str,
+    whitespace: Whitespace,
+) -> Result<Vec<BlameLine>> {
+    let head = file_service.read_head_commit()?;
+    let Some((head, blob)) = head.and_then(|head| {
+        let blob = head.files().get(path)?.clone();
+        Some((head, blob))
+    }) else {
+        return Err(Error::Usage(format!("no such path '{path}' in HEAD")));
+    };
+
+    let committed = lines(&file_service.read_blob(&blob)?);
+    let current = match fs::read(file_service.root_dir.join(path)) {
+        Ok(contents) => lines(&contents),
+        Err(_) => committed.clone(),
+    };
+    let mut blamed: Vec<BlameLine> = current
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| BlameLine {
+            commit: None,
+            original: i + 1,
+            text,
+        })
+        .collect();
+
+    // the lines each commit still has to account for, as pairs of their
+    // index in `blamed` and in that commit's version of the file
+    let head_hash = head.hash().unwrap_or_default().to_string();
+    let texts: Vec<&str> = blamed.iter().map(|line| line.text.as_str()).collect();
+    let all = (0..blamed.len()).map(|i| (i, i));
+    let unchanged = pass_on(&committed, &texts, all, whitespace);
+    let mut pending = HashMap::new();
+    if !unchanged.is_empty() {
+        pending.insert(head_hash.clone(), unchanged);
+    }
+
+    for commit in CommitWalk::new(file_service, Some(head_hash)) {
+        if pending.is_empty() {
+            break;
+        }
+        let commit = commit?;
+        let hash = commit.hash().unwrap_or_default().to_string();
+        let Some(mut kept) = pending.remove(&hash) else {
+            continue;
+        };
+        let blob = &commit.files()[path];
+        let ours = lines(&file_service.read_blob(blob)?);
+        let ours: Vec<&str> = ours.iter().map(String::as_str).collect();
+
+        for parent in commit.parents() {
+            let parent_commit = file_service.read_commit(parent)?;
+            let passed = match parent_commit.files().get(path) {
+                None => continue,
+                Some(parent_blob) if parent_blob == blob => mem::take(&mut kept),
+                Some(parent_blob) => {
+                    let theirs = lines(&file_service.read_blob(parent_blob)?);
+                    let passed = pass_on(&theirs, &ours, kept.iter().copied(), whitespace);
+                    let moved: HashSet<usize> = passed.iter().map(|&(i, _)| i).collect();
+                    kept.retain(|(i, _)| !moved.contains(i));
+                    passed
+                }
+            };
+            if !passed.is_empty() {
+                pending.entry(parent.clone()).or_default().extend(passed);
+            }
+        }
+        charge(&mut blamed, &hash, kept);
+    }
+
+    // lines of commits the walk went past already, which only happens with
+    // committer dates out of order, stay with the commit they reached
+    for (hash, kept) in pending {
+        charge(&mut blamed, &hash, kept);
+    }
+    Ok(blamed)
+}
+
+/// Of the `lines` of `new`, pairs of their index in the blamed file and in
+/// `new`, returns those `old` has unchanged, paired with their index in
+/// `old` instead.
+fn pass_on(
+    old: &[String],
+    new: &[&str],
+    lines: impl Iterator<Item = (usize, usize)>,
+    whitespace: Whitespace,
+) -> Vec<(usize, usize)> {
+    let old: Vec<&str> = old.iter().map(String::as_str).collect();
+    let mut from = HashMap::new();
+    // the blank lines deleted and inserted since the last unchanged line,
+    // paired up in order when blank lines are ignored
+    let (mut deleted, mut inserted) = (Vec::new(), Vec::new());
+    let edits = diff::diff_lines_ignoring(&old, new, whitespace);
+    for edit in edits.into_iter().map(Some).chain([None]) {
+        match edit {
+            Some(Edit::Delete { old: i }) if old[i].trim().is_empty() => deleted.push(i),
+            Some(Edit::Insert { new: i }) if new[i].trim().is_empty() => inserted.push(i),
+            Some(Edit::Delete { .. } | Edit::Insert { .. }) => {}
+            Some(Edit::Equal { .. }) | None => {
+                if whitespace.ignore_blank_lines {
+                    from.extend(inserted.iter().copied().zip(deleted.iter().copied()));
+                }
+                deleted.clear();
+                inserted.clear();
+            }
+        }
+        if let Some(Edit::Equal { old, new }) = edit {
+            from.insert(new, old);
+        }
+    }
+    lines
+        .filter_map(|(i, line)| from.get(&line).map(|&old| (i, old)))
+        .collect()
+}
+
+fn charge(blamed: &mut [BlameLine], hash: &str, lines: Vec<(usize, usize)>) {
+    for (i, line) in lines {
+        blamed[i].commit = Some(hash.to_string());
+        blamed[i].original = line + 1;
+    }
+}
+
+fn lines(contents: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(contents)
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
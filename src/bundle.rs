@@ -0,0 +1,215 @@
+//! Bundles: refs and the objects they need in a single file, to move
+//! history between repositories that cannot reach each other.
+//!
+//! A bundle starts with a `# blip bundle v1` line, then has a `-<hash>
+//! <subject>` line for each commit the repository reading it must already
+//! have and a `<hash> <name>` line for each ref, an empty line and a pack,
+//! see [`crate::pack`], of every object the refs need beyond those
+//! commits.
+//!
+//! Bundles can be cloned and fetched from like any other repository, see
+//! [`crate::transport`].
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    hash::HashAlgorithm,
+    pack, reachable,
+    refs::Refs,
+    remote,
+    types::{Context, Error, FileService, ObjectKind, Result},
+};
+
+const SIGNATURE: &str = "# blip bundle v1";
+
+#[derive(Debug)]
+pub struct Bundle {
+    /// The commits the objects of the bundle build on, with their
+    /// subjects.
+    pub prerequisites: Vec<(String, String)>,
+    /// The refs of the bundle by name, `HEAD` included.
+    pub refs: Vec<(String, String)>,
+    pub pack: Vec<u8>,
+}
+
+impl Bundle {
+    /// Bundles the refs named in `revisions`, short names allowed, with
+    /// every object they need. Revisions starting with `^` are left out
+    /// along with their history, which the bundle then requires.
+    pub fn create(file_service: &FileService, revisions: &[String]) -> Result<Bundle> {
+        let refs = Refs::new(file_service);
+        let mut bundled = Vec::new();
+        let mut excluded = Vec::new();
+        for revision in revisions {
+            if let Some(revision) = revision.strip_prefix('^') {
+                excluded.push(file_service.peel(&file_service.resolve(revision)?)?);
+                continue;
+            }
+            let name = match revision.as_str() {
+                "HEAD" => Some("HEAD".to_string()),
+                _ => refs.full_name(revision),
+            };
+            let hash = match name.as_deref() {
+                Some("HEAD") => file_service.read_head_hash()?,
+                Some(name) => refs.read(name),
+                None => None,
+            };
+            let (Some(name), Some(hash)) = (name, hash) else {
+                return Err(Error::Usage(format!("'{revision}' is not a ref")));
+            };
+            if !bundled.contains(&(name.clone(), hash.clone())) {
+                bundled.push((name, hash));
+            }
+        }
+        if bundled.is_empty() {
+            return Err(Error::Usage("refusing to create an empty bundle".into()));
+        }
+
+        let none = BTreeSet::new();
+        let have = remote::closure(file_service, excluded.clone(), &HashSet::new(), &none)?;
+        let roots = bundled.iter().map(|(_, hash)| hash.clone()).collect();
+        let send = remote::closure(file_service, roots, &have, &none)?;
+
+        // the parents a shallow clone never had must come from elsewhere too
+        let mut required = excluded;
+        for hash in send.iter().filter(|hash| file_service.is_shallow(hash)) {
+            let (_, data) = file_service.read_raw(hash)?;
+            for (parent, kind) in reachable::links(hash, ObjectKind::Commit, &data)? {
+                if kind == ObjectKind::Commit && !required.contains(&parent) {
+                    required.push(parent);
+                }
+            }
+        }
+        let mut prerequisites = Vec::new();
+        for hash in required {
+            let subject = match file_service.read_commit_headers(&hash) {
+                Ok(commit) => commit
+                    .message()
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+                Err(_) => String::new(),
+            };
+            prerequisites.push((hash, subject));
+        }
+
+        Ok(Bundle {
+            prerequisites,
+            refs: bundled,
+            pack: pack::build(file_service, &send.into_iter().collect())?,
+        })
+    }
+
+    /// Whether `path` is a file starting like a bundle.
+    pub fn is_bundle(path: &Path) -> bool {
+        let Ok(file) = fs::File::open(path) else {
+            return false;
+        };
+        let mut line = String::new();
+        let read = BufReader::new(file.take(SIGNATURE.len() as u64 + 1)).read_line(&mut line);
+        read.is_ok() && line.trim_end() == SIGNATURE
+    }
+
+    /// Reads the bundle at `path`, checking its pack against its checksum.
+    pub fn read(path: &Path) -> Result<Bundle> {
+        let data = fs::read(path).context("read", path)?;
+        Bundle::parse(data)
+            .filter(|bundle| pack::is_intact(bundle.hash_algorithm(), &bundle.pack))
+            .ok_or_else(|| Error::InvalidBundle(path.to_path_buf()))
+    }
+
+    fn parse(mut data: Vec<u8>) -> Option<Bundle> {
+        let end = data.windows(2).position(|window| window == b"\n\n")?;
+        let head = std::str::from_utf8(&data[..end]).ok()?;
+        let mut lines = head.lines();
+        if lines.next()? != SIGNATURE {
+            return None;
+        }
+
+        let mut prerequisites = Vec::new();
+        let mut refs = Vec::new();
+        for line in lines {
+            match line.strip_prefix('-') {
+                Some(line) => {
+                    let (hash, subject) = line.split_once(' ').unwrap_or((line, ""));
+                    prerequisites.push((hash.to_string(), subject.to_string()));
+                }
+                None => {
+                    let (hash, name) = line.split_once(' ')?;
+                    refs.push((name.to_string(), hash.to_string()));
+                }
+            }
+        }
+        data.drain(..end + 2);
+        Some(Bundle {
+            prerequisites,
+            refs,
+            pack: data,
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut data = format!("{SIGNATURE}\n");
+        for (hash, subject) in self.prerequisites.iter() {
+            data.push_str(&format!("-{hash} {subject}\n"));
+        }
+        for (name, hash) in self.refs.iter() {
+            data.push_str(&format!("{hash} {name}\n"));
+        }
+        data.push('\n');
+        let mut data = data.into_bytes();
+        data.extend_from_slice(&self.pack);
+        fs::write(path, data).context("write", path)
+    }
+
+    /// The hash function the bundle was made with, as its hashes show.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        let hash = self.refs.first().map(|(_, hash)| hash.len());
+        match hash {
+            Some(len) if len == HashAlgorithm::Sha256.hex_len() => HashAlgorithm::Sha256,
+            _ => HashAlgorithm::Sha1,
+        }
+    }
+
+    /// The prerequisites `file_service` lacks.
+    pub fn missing(&self, file_service: &FileService) -> Result<Vec<&(String, String)>> {
+        let mut missing = Vec::new();
+        for prerequisite in self.prerequisites.iter() {
+            if !file_service.has_object(&prerequisite.0)? {
+                missing.push(prerequisite);
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Stores the objects of the bundle in `file_service`, which must have
+    /// every prerequisite. Refs are left alone.
+    pub fn unbundle(&self, file_service: &FileService) -> Result<()> {
+        let missing = self.missing(file_service)?;
+        if !missing.is_empty() {
+            return Err(Error::Usage(format!(
+                "the repository lacks these prerequisite commits: {}",
+                missing
+                    .iter()
+                    .map(|(hash, _)| hash.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+        let objects = pack::unpack(file_service.hash_algorithm, &self.pack)
+            .ok_or_else(|| Error::Usage("the pack in the bundle is malformed".into()))?;
+        for (kind, data) in objects {
+            let hash = file_service.hash_raw(kind, &data);
+            if !file_service.has_object(&hash)? {
+                file_service.write_loose(&hash, kind, &data)?;
+            }
+        }
+        Ok(())
+    }
+}
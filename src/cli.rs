@@ -0,0 +1,2261 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use regex::Regex;
+
+use crate::{
+    archive::ArchiveFormat,
+    bisect::Term,
+    config::{Config, ConfigScope},
+    diff::{self, RenameDetection, StatFormat, Whitespace, RENAME_THRESHOLD},
+    gc::Expiry,
+    hash::HashAlgorithm,
+    history::{self, CommitFilter},
+    remote::Force,
+    stash::Stash,
+    trailer,
+    types::{Error, FileService, Result},
+};
+
+/// Where `blip serve` listens unless told otherwise.
+pub const DEFAULT_SERVE_ADDR: &str = "127.0.0.1:7878";
+
+pub const USAGE: &str = "\
+usage: blip <command> [<args>]
+
+commands:
+    init [--bare] [-b <branch>] [--object-format=<sha1|sha256>] [<path>]
+                            Create an empty blip repository
+    clone [--depth <n>] <source> [<directory>]
+                            Copy a repository into a new directory
+    add [-A|-u|-p|-i] [-f] [-j <jobs>] [<paths>...]
+                            Add file contents to the index
+    rm [--cached] [-r] [-f] <paths>...
+                            Remove files from the working tree and the index
+    mv <source> <destination>
+                            Move or rename a tracked file or directory
+    clean [-n|-f] [-d]      Remove untracked files from the working tree
+    config [--system|--global|--local] get|set|unset|list [<name> [<value>]]
+                            Get and set repository options
+    commit [-a] [-n] [-S] [-s] [--amend] [--trailer <k>=<v>] [-m <msg>...|-F <file>]
+                            Record staged changes to the repository
+    status [<pathspec>...]  Show the working tree status
+    log [-n <n>] [--author|--grep <re>] [--since|--until <date>] [--first-parent]
+        [--full-history] [--follow] [--graph] [--no-abbrev-commit] [-p]
+        [--oneline|--format=<fmt>] [--stat|--numstat|--shortstat] [<revision>...]
+        [[--] <pathspec>...]
+                            Show the commit history
+    show [--no-abbrev-commit] [<object>...]
+                            Show a commit with its patch, a tag, a tree or a blob
+    blame [-L <start>,<end>] [-w|-b] [--ignore-blank-lines] <file>
+                            Show the commit that last changed each line of a file
+    grep [-n] <pattern> [<commit>]
+                            Print lines of tracked files matching a regex
+    bisect start [<bad> [<good>...]] | good|bad|skip [<commit>...] | run <cmd>... | reset
+                            Binary-search the history for the commit that introduced a bug
+    checkout <commit>       Switch branches or restore a commit's files
+    switch [-c <new-branch>|--detach] [<branch>|<commit>]
+                            Switch branches, keeping local changes out of the way
+    branch [-v[v]|-d|-u <upstream>|--unset-upstream] [<name>]
+                            List, create or delete branches, or set their upstream
+    tag [-a|-s -m <msg>|-d|-l|-v]
+                            Create, list, delete or verify tags
+    diff [--cached] [--name-status] [--binary] [-M[<n>]|-C[<n>]|--no-renames]
+        [-w|-b] [--ignore-blank-lines] [<a> [<b>]] [-- <pathspec>...]
+                            Show changes between commits and the working tree
+    reset [--soft|--mixed|--hard] [<commit>]
+                            Move the current branch to another commit
+    restore [--staged] <paths>...
+                            Restore working tree files from the index, or unstage them
+    stash [push [-m <msg>]|list|pop|apply|drop [<stash>]]
+                            Set aside local changes and reapply them later
+    worktree add [-b <branch>|--detach] <path> [<commit>] | list | remove [-f] <path>
+                            Check out more branches in their own directories
+    submodule [add <url> <path> | update | status]
+                            Check out other repositories inside this one
+    remote [-v | add <name> <url> | remove <name>]
+                            List, add or remove remote repositories
+    fetch [--deepen <n>] [<remote>]
+                            Download objects and refs from a remote
+    push [-u] [-f|--force-with-lease[=<branch>[:<expect>]]] [<remote> [<branch>]]
+                            Update a remote branch with local commits
+    pull [--rebase|--no-rebase]
+                            Fetch the upstream and merge or rebase onto it
+    serve [--addr <addr>] [--enable-push] [<path>...]
+                            Serve repositories over HTTP for clone, fetch and push
+    upload-pack <path>      Answer fetches over SSH; run by the other side
+    receive-pack <path>     Answer pushes over SSH; run by the other side
+    bundle create <file> <ref>... | verify <file> | unbundle <file>
+                            Move refs and their history through a single file
+    fast-export [<ref>...]  Write history as a stream for git fast-import
+    fast-import             Read history from a git fast-export stream on stdin
+    format-patch [-o <dir>|--stdout] <range>
+                            Write commits as emails with their patches
+    am [<mbox>...]          Commit the patches of emails from format-patch
+    apply [--cached] [--check] [--reject] [--fuzz <n>] [<patch>...]
+                            Apply a patch to the working tree or the index
+    archive [--format=tar|zip] [--prefix=<prefix>] [-o <file>] <tree-ish>
+                            Write the files of a commit to a tar or zip archive
+    merge [--no-ff|--ff-only] <branch> | --continue | --abort
+                            Join another branch into the current one
+    merge-base [--all|--is-ancestor] <a> <b>
+                            Find the best common ancestor of two commits
+    cherry-pick <commit>... | --continue | --skip | --abort
+                            Apply the changes commits made on top of HEAD
+    revert <commit>... | --continue | --skip | --abort
+                            Undo the changes commits made with new commits
+    rebase [-i] <upstream> | --continue | --skip | --abort
+                            Replay the branch's own commits on top of upstream
+    ls-files [-s] [<pathspec>...]
+                            List tracked files, with their hashes for -s
+    ls-tree [-r [-t]] <tree-ish>
+                            List the entries of a tree
+    check-attr (-a | <attr>...) [--] <path>...
+                            Show the attributes .blipattributes gives paths
+    write-tree              Store the tracked files as a tree and print its hash
+    commit-tree <tree> [-p <parent>]... [-m <msg>]...
+                            Create a commit of a tree without moving any ref
+    symbolic-ref [-q] [--short] HEAD [<ref>]
+                            Read or set the ref HEAD points at
+    show-ref [--head] [--heads] [--tags] [-s] [<pattern>...]
+                            List refs with the objects they point at
+    rev-parse <revision>... Print the object hashes revisions resolve to
+    rev-list [--count] <commit>... [^<commit>...] [--not <commit>...]
+                            List the commits reachable from some commits but not others
+    verify-commit <commit>...
+                            Check the signatures of commits
+    verify-tag <tag>...     Check the signatures of tags
+    repack                  Pack all objects into a single packfile
+    pack-refs [--no-prune]  Move loose refs into the packed-refs file
+    gc [--prune=<days>|now|never]
+                            Pack reachable objects and prune unreachable ones
+    fsck                    Verify the objects, refs and index
+    prune [-n] [--expire=<days>|now|never]
+                            Delete unreachable loose objects";
+
+#[derive(Debug)]
+pub enum Command {
+    Init {
+        path: String,
+        /// Overrides `init.defaultBranch` for the initial branch name.
+        branch: Option<String>,
+        /// Overrides `init.defaultObjectFormat` for the hash function.
+        object_format: Option<HashAlgorithm>,
+        /// Makes the directory itself the repository, without a working
+        /// tree.
+        bare: bool,
+    },
+    Clone {
+        source: String,
+        /// Defaults to the last component of `source`.
+        destination: Option<String>,
+        /// Fetch only this many commits of history.
+        depth: Option<usize>,
+    },
+    Add {
+        paths: Vec<String>,
+        mode: AddMode,
+        force: bool,
+        /// Overrides `add.jobs` for the number of hashing threads.
+        jobs: Option<usize>,
+        /// Asks which hunks of the changes to tracked files to stage.
+        patch: bool,
+        /// Opens a menu for staging and unstaging files.
+        interactive: bool,
+    },
+    Commit {
+        message: CommitMessage,
+        /// Replaces the commit at HEAD rather than adding one on top.
+        amend: bool,
+        /// Stages changes to tracked files first, as `add -u` does.
+        all: bool,
+        /// Adds a `Signed-off-by:` trailer for the committer.
+        signoff: bool,
+        /// Trailers to add to the message, as `(key, value)`.
+        trailers: Vec<(String, String)>,
+        /// Skips the pre-commit and commit-msg hooks.
+        no_verify: bool,
+        /// Signs with `user.signingKey`, as `commit.gpgSign` does for every
+        /// commit.
+        sign: bool,
+    },
+    Status {
+        pathspecs: Vec<String>,
+    },
+    Log {
+        /// The commits to show the history of, HEAD when there are none;
+        /// `^<revision>` and `<a>..<b>` leave out what is reachable from
+        /// one. Without a `--`, the arguments from the first that names no
+        /// revision on are pathspecs.
+        revisions: Vec<String>,
+        pathspecs: Vec<String>,
+        /// Whether a `--` told the revisions from the pathspecs.
+        separated: bool,
+        /// The author, message and time the commits shown have to match.
+        filter: CommitFilter,
+        /// How many commits to show at most.
+        max_count: Option<usize>,
+        format: LogFormat,
+    },
+    Checkout {
+        target: String,
+    },
+    Switch {
+        /// The branch to switch to, the start of the new branch, or the
+        /// commit to detach HEAD at; HEAD when left out for the latter two.
+        target: Option<String>,
+        /// The name of a branch to create and switch to.
+        create: Option<String>,
+        detach: bool,
+    },
+    Branch {
+        action: BranchAction,
+    },
+    Tag {
+        action: TagAction,
+    },
+    Diff {
+        commits: Vec<String>,
+        cached: bool,
+        pathspecs: Vec<String>,
+        /// How to look for renames, `Some(None)` for not at all; `None`
+        /// goes by `diff.renames`.
+        renames: Option<Option<RenameDetection>>,
+        /// Lists the changed files with how they changed instead.
+        name_status: bool,
+        /// Writes out binary files in full, for `apply`.
+        binary: bool,
+        whitespace: Whitespace,
+    },
+    Merge {
+        action: MergeAction,
+    },
+    CherryPick {
+        action: SequenceAction,
+    },
+    Revert {
+        action: SequenceAction,
+    },
+    Rebase {
+        action: RebaseAction,
+    },
+    RevParse {
+        revisions: Vec<String>,
+    },
+    RevList {
+        /// The commits to list the ancestors of, themselves included.
+        include: Vec<String>,
+        /// The commits whose ancestors are left out, themselves included.
+        exclude: Vec<String>,
+        /// Prints how many commits there are instead.
+        count: bool,
+    },
+    VerifyCommit {
+        commits: Vec<String>,
+    },
+    VerifyTag {
+        tags: Vec<String>,
+    },
+    SymbolicRef {
+        name: String,
+        /// Points `name` at this ref instead of printing it.
+        target: Option<String>,
+        /// Prints the ref without `refs/heads/` or `refs/tags/`.
+        short: bool,
+        /// Exits with 1 instead of an error when HEAD is detached.
+        quiet: bool,
+    },
+    ShowRef {
+        /// Match refs whose name ends in one of these, component-wise.
+        patterns: Vec<String>,
+        /// Lists HEAD as well.
+        head: bool,
+        heads: bool,
+        tags: bool,
+        /// Prints only the hashes.
+        hash_only: bool,
+    },
+    WriteTree,
+    CommitTree {
+        tree: String,
+        parents: Vec<String>,
+        /// Read from stdin when not given.
+        message: Option<String>,
+    },
+    LsFiles {
+        pathspecs: Vec<String>,
+        /// Prints the mode, hash and stage of each file as well.
+        stage: bool,
+    },
+    LsTree {
+        tree: String,
+        recursive: bool,
+        /// With `recursive`, lists subtrees as well as their contents.
+        show_trees: bool,
+    },
+    CheckAttr {
+        /// Ignored with `all`.
+        attributes: Vec<String>,
+        /// Shows every attribute the paths have a value for.
+        all: bool,
+        paths: Vec<String>,
+    },
+    /// Objects are revisions, or `<revision>:<path>` for a file or
+    /// directory in a commit.
+    Show {
+        objects: Vec<String>,
+        /// Shortens commit hashes to unique prefixes, as `log` does.
+        abbrev_commit: bool,
+    },
+    Blame {
+        file: String,
+        /// The first line to blame and the last, from 1, or `None` to go
+        /// on to the end of the file.
+        lines: Option<(usize, Option<usize>)>,
+        /// The changes in whitespace that do not count as changing a line.
+        whitespace: Whitespace,
+    },
+    Grep {
+        pattern: String,
+        /// Searches the commit's files rather than the working tree's.
+        commit: Option<String>,
+        /// Prefixes each line with its number.
+        line_number: bool,
+    },
+    Bisect {
+        action: BisectAction,
+    },
+    MergeBase {
+        a: String,
+        b: String,
+        all: bool,
+        is_ancestor: bool,
+    },
+    Rm {
+        paths: Vec<String>,
+        cached: bool,
+        recursive: bool,
+        force: bool,
+    },
+    Mv {
+        source: String,
+        destination: String,
+    },
+    Reset {
+        target: String,
+        mode: ResetMode,
+    },
+    Restore {
+        paths: Vec<String>,
+        /// Restores the index from HEAD instead of the working tree from
+        /// the index.
+        staged: bool,
+    },
+    Stash {
+        action: StashAction,
+    },
+    Worktree {
+        action: WorktreeAction,
+    },
+    Submodule {
+        action: SubmoduleAction,
+    },
+    Remote {
+        action: RemoteAction,
+    },
+    Fetch {
+        /// Defaults to the current branch's remote, or `origin`.
+        remote: Option<String>,
+        /// Extend a shallow history by this many commits.
+        deepen: Option<usize>,
+    },
+    Push {
+        /// Defaults to the current branch's remote, or `origin`.
+        remote: Option<String>,
+        /// Defaults to the current branch.
+        branch: Option<String>,
+        force: Force,
+        /// Makes the pushed branch the upstream of the local one.
+        set_upstream: bool,
+    },
+    Pull {
+        /// Overrides `pull.rebase`, which defaults to merging.
+        rebase: Option<bool>,
+    },
+    Serve {
+        /// Address and port to listen on.
+        addr: String,
+        /// Repositories to serve, by default the current one.
+        paths: Vec<String>,
+        /// Whether anyone who can reach the server may push to them.
+        enable_push: bool,
+    },
+    UploadPack {
+        path: String,
+    },
+    ReceivePack {
+        path: String,
+    },
+    Bundle {
+        action: BundleAction,
+    },
+    FastExport {
+        /// Defaults to every branch and tag.
+        refs: Vec<String>,
+    },
+    FastImport,
+    FormatPatch {
+        /// `<since>` or `<since>..<until>`.
+        range: String,
+        /// Where to write the patches, by default the current directory.
+        output_directory: Option<String>,
+        /// Writes the patches to the standard output instead of files.
+        stdout: bool,
+    },
+    Am {
+        /// Defaults to the standard input.
+        mailboxes: Vec<String>,
+    },
+    Apply {
+        /// Defaults to the standard input.
+        patches: Vec<String>,
+        /// Applies to the index instead of the working tree.
+        cached: bool,
+        /// Only reports whether the patches apply.
+        check: bool,
+        /// Applies the hunks that match and writes the others to `.rej`
+        /// files, instead of applying nothing.
+        reject: bool,
+        /// Context lines a hunk may leave out at either end to match.
+        fuzz: usize,
+    },
+    Archive {
+        tree_ish: String,
+        /// Defaults to the one `output` is named for, then to tar.
+        format: Option<ArchiveFormat>,
+        /// Put in front of every path, usually a directory such as `blip-1.0/`.
+        prefix: String,
+        /// Defaults to the standard output.
+        output: Option<String>,
+    },
+    Clean {
+        /// Without it clean only reports what it would remove.
+        force: bool,
+        directories: bool,
+    },
+    Repack,
+    PackRefs {
+        /// Keeps the loose ref files after packing them.
+        no_prune: bool,
+    },
+    Gc {
+        /// Overrides `gc.pruneExpire`.
+        prune: Option<Expiry>,
+    },
+    Fsck,
+    Prune {
+        dry_run: bool,
+        /// Overrides `gc.pruneExpire`.
+        expire: Option<Expiry>,
+    },
+    /// A `!` alias, run by the shell with the remaining arguments.
+    Shell {
+        command: String,
+        args: Vec<String>,
+    },
+    Config {
+        action: ConfigAction,
+        /// Restricts reads to one file and redirects writes to it, which
+        /// otherwise go to the repository config.
+        scope: Option<ConfigScope>,
+    },
+    Help,
+}
+
+/// Which files `add` considers beyond those explicitly named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddMode {
+    /// Only the given paths, new or tracked.
+    Paths,
+    /// Tracked files only, staging their modifications and deletions.
+    Update,
+    /// Tracked and untracked files alike.
+    All,
+}
+
+/// How `log` shows each commit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogFormat {
+    pub pretty: Pretty,
+    /// Shortens commit hashes to unique prefixes.
+    pub abbrev_commit: bool,
+    /// Draws the history's branches and merges left of the commits.
+    pub graph: bool,
+    /// Adds the patch against the first parent.
+    pub patch: bool,
+    /// Adds a summary of the files changed against the first parent.
+    pub stat: Option<StatFormat>,
+}
+
+/// What `log` says about each commit: one of the presets `--format` names,
+/// or a format string of `%` placeholders.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Pretty {
+    /// The hash and subject on one line.
+    Oneline,
+    /// The hash, parents, author and subject.
+    Short,
+    /// The hash, parents, author, date and message.
+    #[default]
+    Medium,
+    /// The hash, parents, author, committer and message.
+    Full,
+    /// A line made of the format string, after `format:` if it starts
+    /// with that.
+    Format(String),
+}
+
+impl Pretty {
+    pub fn parse(value: &str) -> Result<Pretty> {
+        match value {
+            "oneline" => Ok(Pretty::Oneline),
+            "short" => Ok(Pretty::Short),
+            "medium" => Ok(Pretty::Medium),
+            "full" => Ok(Pretty::Full),
+            _ => match value.strip_prefix("format:") {
+                Some(format) => Ok(Pretty::Format(format.to_string())),
+                None if value.contains('%') => Ok(Pretty::Format(value.to_string())),
+                None => Err(Error::Usage(format!("invalid format '{value}'"))),
+            },
+        }
+    }
+}
+
+/// Where `commit` takes its message from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommitMessage {
+    /// The `-m` messages, each a paragraph of its own.
+    Given(String),
+    /// A file, or the standard input for `-`.
+    File(String),
+    /// The editor, see [`Repository::commit_edited`](crate::Repository::commit_edited).
+    Edit,
+}
+
+/// How much of the repository `reset` rewinds besides the branch itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetMode {
+    /// Only the branch; the index keeps the changes staged.
+    Soft,
+    /// The branch and the index.
+    Mixed,
+    /// The branch, the index and the working tree.
+    Hard,
+}
+
+#[derive(Debug)]
+pub enum BranchAction {
+    /// Lists the branches, with `-v` their tips and how they compare to
+    /// their upstreams, with `-vv` the upstreams too.
+    List {
+        verbose: usize,
+    },
+    Create {
+        name: String,
+        start: Option<String>,
+    },
+    Delete {
+        names: Vec<String>,
+    },
+    /// Sets the upstream of a branch, by default the current one, to a
+    /// remote-tracking branch, or forgets it when `upstream` is `None`.
+    SetUpstream {
+        name: Option<String>,
+        upstream: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+pub enum TagAction {
+    List {
+        pattern: Option<String>,
+    },
+    Create {
+        name: String,
+        target: Option<String>,
+        /// Set for annotated tags, which are stored as tag objects.
+        message: Option<String>,
+        force: bool,
+        /// Signs the tag object with `user.signingKey`.
+        sign: bool,
+    },
+    Delete {
+        names: Vec<String>,
+    },
+    Verify {
+        names: Vec<String>,
+    },
+}
+
+/// Stash entries are addressed by their position, `stash@{0}` being the
+/// most recent.
+#[derive(Debug)]
+pub enum StashAction {
+    Push { message: Option<String> },
+    List,
+    Apply { index: usize },
+    Pop { index: usize },
+    Drop { index: usize },
+}
+
+/// How to go on with an operation that stopped halfway, see
+/// [`sequencer`](crate::sequencer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Commits the resolved conflicts or the amendment the operation
+    /// stopped for and carries out the rest.
+    Continue,
+    /// Drops the commit the operation stopped at and carries out the rest.
+    Skip,
+    /// Puts everything back as it was before the operation.
+    Abort,
+}
+
+#[derive(Debug)]
+pub enum MergeAction {
+    Start {
+        target: String,
+        fast_forward: FastForward,
+    },
+    Resume(Resume),
+}
+
+/// What cherry-pick and revert are asked to do.
+#[derive(Debug)]
+pub enum SequenceAction {
+    Start { commits: Vec<String> },
+    Resume(Resume),
+}
+
+#[derive(Debug)]
+pub enum RebaseAction {
+    Start {
+        upstream: String,
+        /// Has the todo list of the commits edited first.
+        interactive: bool,
+    },
+    Resume(Resume),
+}
+
+#[derive(Debug)]
+pub enum BisectAction {
+    Start {
+        bad: Option<String>,
+        good: Vec<String>,
+    },
+    /// Marks the commits, HEAD if there are none, and checks out the next
+    /// one to test.
+    Mark { term: Term, commits: Vec<String> },
+    /// Marks each commit checked out by whether `command` succeeds in it
+    /// until the first bad commit is found.
+    Run { command: Vec<String> },
+    /// Goes back to where the search started.
+    Reset,
+}
+
+#[derive(Debug)]
+pub enum BundleAction {
+    Create {
+        file: String,
+        /// Refs to bundle, and `^<rev>` for history to leave out.
+        revisions: Vec<String>,
+    },
+    /// Lists the bundle's refs and checks that this repository has what it
+    /// builds on.
+    Verify { file: String },
+    /// Stores the bundle's objects and lists its refs.
+    Unbundle { file: String },
+}
+
+#[derive(Debug)]
+pub enum SubmoduleAction {
+    /// Clones `url` into `path`, unless a repository is already there, and
+    /// stages it as a submodule.
+    Add {
+        url: String,
+        path: String,
+    },
+    /// Clones missing submodules and checks out the commits they are pinned
+    /// at, recursively.
+    Update,
+    Status,
+}
+
+#[derive(Debug)]
+pub enum WorktreeAction {
+    Add {
+        path: String,
+        /// A branch to create for the working tree.
+        branch: Option<String>,
+        /// Detaches HEAD even if `commit` is a branch.
+        detach: bool,
+        /// Defaults to a branch named after `path`, made from HEAD unless
+        /// it exists.
+        commit: Option<String>,
+    },
+    List,
+    Remove {
+        path: String,
+        /// Removes a working tree with changes or untracked files too.
+        force: bool,
+    },
+}
+
+#[derive(Debug)]
+pub enum RemoteAction {
+    List {
+        /// Prints each remote's URL as well.
+        verbose: bool,
+    },
+    Add {
+        name: String,
+        url: String,
+    },
+    Remove {
+        name: String,
+    },
+}
+
+/// Config entries are named `section.key` or `section.subsection.key`.
+#[derive(Debug)]
+pub enum ConfigAction {
+    Get { name: String },
+    Set { name: String, value: String },
+    Unset { name: String },
+    List,
+}
+
+/// Whether a merge may simply move the branch when it is behind the
+/// merged commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FastForward {
+    Allow,
+    Never,
+    Only,
+}
+
+/// Parsed arguments of a single subcommand.
+///
+/// Options are declared up front as comma separated aliases (`"-m,--message"`)
+/// and are looked up afterwards by any one of those aliases.
+#[derive(Debug, Default)]
+pub struct Args {
+    flags: Vec<&'static str>,
+    options: Vec<(&'static str, String)>,
+    positional: Vec<String>,
+    /// How many positional arguments came before a `--`, if there was one.
+    separator: Option<usize>,
+}
+
+impl Args {
+    pub fn parse<I>(args: I, flags: &[&'static str], options: &[&'static str]) -> Result<Args>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                parsed.separator = Some(parsed.positional.len());
+                parsed.positional.extend(args.by_ref());
+                break;
+            }
+            if !arg.starts_with('-') || arg == "-" {
+                parsed.positional.push(arg);
+                continue;
+            }
+
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+
+            if let Some(spec) = Args::lookup(options, name) {
+                let value = match inline {
+                    Some(value) => value,
+                    None => args
+                        .next()
+                        .ok_or_else(|| Error::Usage(format!("option '{name}' requires a value")))?,
+                };
+                parsed.options.push((spec, value));
+            } else if let Some(spec) = Args::lookup(flags, name) {
+                parsed.flags.push(spec);
+            } else if let Some(spec) = name
+                .get(..2)
+                .filter(|_| !name.starts_with("--") && name.len() > 2)
+                .and_then(|short| Args::lookup(options, short))
+            {
+                // a short option with its value attached, as in `-n2`
+                parsed.options.push((spec, name[2..].to_string()));
+            } else if !name.starts_with("--") && name.len() > 2 {
+                // combined short flags such as `-fd`
+                for c in name[1..].chars() {
+                    let short = format!("-{c}");
+                    let Some(spec) = Args::lookup(flags, &short) else {
+                        return Err(Error::Usage(format!("unknown option '{short}'")));
+                    };
+                    parsed.flags.push(spec);
+                }
+            } else {
+                return Err(Error::Usage(format!("unknown option '{name}'")));
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn lookup(specs: &[&'static str], name: &str) -> Option<&'static str> {
+        specs.iter().find(|spec| Args::matches(spec, name)).copied()
+    }
+
+    fn matches(spec: &str, name: &str) -> bool {
+        spec.split(',').any(|alias| alias == name)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|spec| Args::matches(spec, name))
+    }
+
+    /// Returns how many times a flag was given, as in `-vv`.
+    pub fn count(&self, name: &str) -> usize {
+        self.flags
+            .iter()
+            .filter(|spec| Args::matches(spec, name))
+            .count()
+    }
+
+    /// Returns the last value given for an option.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).pop()
+    }
+
+    /// Returns every value given for a repeatable option, in order.
+    pub fn values(&self, name: &str) -> Vec<&str> {
+        self.options
+            .iter()
+            .filter(|(spec, _)| Args::matches(spec, name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn into_positional(self) -> Vec<String> {
+        self.positional
+    }
+
+    /// Splits the positional arguments at `--`, all of them going first
+    /// when there is none.
+    pub fn split_positional(mut self) -> (Vec<String>, Vec<String>) {
+        let after = match self.separator {
+            Some(at) => self.positional.split_off(at),
+            None => Vec::new(),
+        };
+        (self.positional, after)
+    }
+}
+
+pub fn parse<I>(args: I) -> Result<Command>
+where
+    I: IntoIterator<Item = String>,
+{
+    parse_expanding(args, &mut Vec::new())
+}
+
+/// Parses a command line, `expanded` holding the aliases already expanded
+/// on the way here so that an alias referring back to itself is caught.
+fn parse_expanding<I>(args: I, expanded: &mut Vec<String>) -> Result<Command>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let Some(name) = args.next() else {
+        return Ok(Command::Help);
+    };
+
+    match name.as_str() {
+        "init" => {
+            let args = Args::parse(
+                args,
+                &["--bare"],
+                &["-b,--initial-branch", "--object-format"],
+            )?;
+            let path = match args.positional() {
+                [] => ".".to_string(),
+                [path] => path.clone(),
+                _ => return Err(Error::Usage("init takes at most one path".into())),
+            };
+            let branch = args
+                .value("--initial-branch")
+                .map(|branch| branch.to_string());
+            let object_format = match args.value("--object-format") {
+                Some(name) => Some(
+                    HashAlgorithm::parse(name)
+                        .ok_or_else(|| Error::Usage(format!("unknown object format '{name}'")))?,
+                ),
+                None => None,
+            };
+            Ok(Command::Init {
+                path,
+                branch,
+                object_format,
+                bare: args.flag("--bare"),
+            })
+        }
+        "clone" => {
+            let args = Args::parse(args, &[], &["--depth"])?;
+            let depth = match args.value("--depth") {
+                Some(depth) => Some(parse_depth(depth)?),
+                None => None,
+            };
+            match args.into_positional().as_slice() {
+                [source] => Ok(Command::Clone {
+                    source: source.clone(),
+                    destination: None,
+                    depth,
+                }),
+                [source, destination] => Ok(Command::Clone {
+                    source: source.clone(),
+                    destination: Some(destination.clone()),
+                    depth,
+                }),
+                [] => Err(Error::Usage("clone requires a repository to clone".into())),
+                _ => Err(Error::Usage("too many arguments to clone".into())),
+            }
+        }
+        "add" => {
+            let args = Args::parse(
+                args,
+                &[
+                    "-A,--all",
+                    "-u,--update",
+                    "-f,--force",
+                    "-p,--patch",
+                    "-i,--interactive",
+                ],
+                &["-j,--jobs"],
+            )?;
+            let mode = match (args.flag("--all"), args.flag("--update")) {
+                (true, true) => return Err(Error::Usage("-A and -u are incompatible".into())),
+                (true, false) => AddMode::All,
+                (false, true) => AddMode::Update,
+                (false, false) => AddMode::Paths,
+            };
+            let force = args.flag("--force");
+            let jobs = match args.value("--jobs") {
+                Some(jobs) => Some(
+                    jobs.parse()
+                        .map_err(|_| Error::Usage(format!("invalid number of jobs '{jobs}'")))?,
+                ),
+                None => None,
+            };
+            let patch = args.flag("--patch");
+            let interactive = args.flag("--interactive");
+            if (patch || interactive) && mode != AddMode::Paths {
+                return Err(Error::Usage(
+                    "-p and -i are incompatible with -A and -u".into(),
+                ));
+            }
+            if patch && interactive {
+                return Err(Error::Usage("-p and -i are incompatible".into()));
+            }
+            let paths = args.into_positional();
+            if paths.is_empty() && mode == AddMode::Paths && !patch && !interactive {
+                return Err(Error::Usage("nothing specified, nothing added".into()));
+            }
+            Ok(Command::Add {
+                paths,
+                mode,
+                force,
+                jobs,
+                patch,
+                interactive,
+            })
+        }
+        "commit" => {
+            let args = Args::parse(
+                args,
+                &[
+                    "-a,--all",
+                    "-n,--no-verify",
+                    "-S,--gpg-sign",
+                    "-s,--signoff",
+                    "--amend",
+                ],
+                &["-m,--message", "-F,--file", "--trailer"],
+            )?;
+            let amend = args.flag("--amend");
+            // each -m is a paragraph of its own
+            let messages = args.values("--message");
+            let message = match (messages.is_empty(), args.value("--file")) {
+                (false, Some(_)) => return Err(Error::Usage("-m and -F are incompatible".into())),
+                (false, None) => CommitMessage::Given(messages.join("\n\n")),
+                (true, Some(file)) => CommitMessage::File(file.to_string()),
+                (true, None) => CommitMessage::Edit,
+            };
+            let trailers = args
+                .values("--trailer")
+                .into_iter()
+                .map(|spec| {
+                    spec.split_once(['=', ':'])
+                        .and_then(|(key, value)| {
+                            trailer::parse_line(&format!("{}: {}", key.trim(), value.trim()))
+                        })
+                        .ok_or_else(|| Error::Usage(format!("invalid trailer '{spec}'")))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(Command::Commit {
+                message,
+                amend,
+                all: args.flag("--all"),
+                signoff: args.flag("--signoff"),
+                trailers,
+                no_verify: args.flag("--no-verify"),
+                sign: args.flag("--gpg-sign"),
+            })
+        }
+        "status" => {
+            let args = Args::parse(args, &[], &[])?;
+            Ok(Command::Status {
+                pathspecs: args.into_positional(),
+            })
+        }
+        "log" => {
+            // `-<n>` is short for `-n <n>`
+            let mut separated = false;
+            let args = args.map(|arg| {
+                separated |= arg == "--";
+                match arg.strip_prefix('-') {
+                    Some(count)
+                        if !separated
+                            && !count.is_empty()
+                            && count.bytes().all(|b| b.is_ascii_digit()) =>
+                    {
+                        format!("--max-count={count}")
+                    }
+                    _ => arg,
+                }
+            });
+            let args = Args::parse(
+                args,
+                &[
+                    "--abbrev-commit",
+                    "--no-abbrev-commit",
+                    "--oneline",
+                    "--graph",
+                    "--first-parent",
+                    "--full-history",
+                    "--follow",
+                    "-p,--patch",
+                    "--stat",
+                    "--numstat",
+                    "--shortstat",
+                ],
+                &[
+                    "-n,--max-count",
+                    "--author",
+                    "--grep",
+                    "--since",
+                    "--until",
+                    "--format,--pretty",
+                ],
+            )?;
+            let max_count =
+                match args.value("--max-count") {
+                    Some(count) => Some(count.parse().map_err(|_| {
+                        Error::Usage(format!("max count '{count}' is not a number"))
+                    })?),
+                    None => None,
+                };
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs() as i64);
+            let time = |name| match args.value(name) {
+                Some(value) => history::parse_time(value, now)
+                    .map(Some)
+                    .ok_or_else(|| Error::Usage(format!("invalid date '{value}'"))),
+                None => Ok(None),
+            };
+            let filter = CommitFilter {
+                authors: parse_patterns(&args.values("--author"))?,
+                greps: parse_patterns(&args.values("--grep"))?,
+                since: time("--since")?,
+                until: time("--until")?,
+                first_parent: args.flag("--first-parent"),
+                full_history: args.flag("--full-history"),
+                follow: args.flag("--follow"),
+            };
+            let stats = [
+                (args.flag("--stat"), StatFormat::Stat),
+                (args.flag("--numstat"), StatFormat::NumStat),
+                (args.flag("--shortstat"), StatFormat::ShortStat),
+            ];
+            let mut chosen = stats.iter().filter(|(set, _)| *set).map(|(_, stat)| *stat);
+            let stat = chosen.next();
+            if chosen.next().is_some() {
+                return Err(Error::Usage(
+                    "--stat, --numstat and --shortstat are incompatible".into(),
+                ));
+            }
+            let oneline = args.flag("--oneline");
+            let pretty = match args.value("--format") {
+                Some(format) => Pretty::parse(format)?,
+                None if oneline => Pretty::Oneline,
+                None => Pretty::Medium,
+            };
+            let format = LogFormat {
+                pretty,
+                abbrev_commit: !args.flag("--no-abbrev-commit"),
+                graph: args.flag("--graph"),
+                patch: args.flag("--patch"),
+                stat,
+            };
+            let separated = args.separator.is_some();
+            let (revisions, pathspecs) = args.split_positional();
+            Ok(Command::Log {
+                revisions,
+                pathspecs,
+                separated,
+                filter,
+                max_count,
+                format,
+            })
+        }
+        "checkout" => {
+            let args = Args::parse(args, &[], &[])?;
+            let [target] = args.positional() else {
+                return Err(Error::Usage(
+                    "checkout takes exactly one commit or branch".into(),
+                ));
+            };
+            Ok(Command::Checkout {
+                target: target.clone(),
+            })
+        }
+        "switch" => {
+            let args = Args::parse(args, &["--detach"], &["-c,--create"])?;
+            let create = args.value("--create").map(str::to_string);
+            let detach = args.flag("--detach");
+            if create.is_some() && detach {
+                return Err(Error::Usage("-c and --detach are incompatible".into()));
+            }
+            let target = match args.positional() {
+                [] if create.is_none() && !detach => {
+                    return Err(Error::Usage("switch needs a branch to switch to".into()))
+                }
+                [] => None,
+                [target] => Some(target.clone()),
+                _ => return Err(Error::Usage("switch takes at most one branch".into())),
+            };
+            Ok(Command::Switch {
+                target,
+                create,
+                detach,
+            })
+        }
+        "branch" => {
+            let args = Args::parse(
+                args,
+                &[
+                    "-d,--delete",
+                    "-l,--list",
+                    "-v,--verbose",
+                    "--unset-upstream",
+                ],
+                &["-u,--set-upstream-to"],
+            )?;
+            let delete = args.flag("--delete");
+            let verbose = args.count("--verbose");
+            let list = args.flag("--list");
+            let upstream = args.value("--set-upstream-to").map(str::to_string);
+            let unset_upstream = args.flag("--unset-upstream");
+            let action = match args.into_positional().as_slice() {
+                _ if upstream.is_some() && unset_upstream => {
+                    return Err(Error::Usage(
+                        "--set-upstream-to and --unset-upstream are incompatible".into(),
+                    ))
+                }
+                names @ ([] | [_]) if upstream.is_some() || unset_upstream => {
+                    BranchAction::SetUpstream {
+                        name: names.first().cloned(),
+                        upstream,
+                    }
+                }
+                _ if upstream.is_some() || unset_upstream => {
+                    return Err(Error::Usage("too many arguments to branch".into()))
+                }
+                names if delete && !names.is_empty() => BranchAction::Delete {
+                    names: names.to_vec(),
+                },
+                _ if delete => return Err(Error::Usage("branch name required".into())),
+                [] => BranchAction::List { verbose },
+                _ if list => return Err(Error::Usage("branch --list takes no names".into())),
+                [name] => BranchAction::Create {
+                    name: name.clone(),
+                    start: None,
+                },
+                [name, start] => BranchAction::Create {
+                    name: name.clone(),
+                    start: Some(start.clone()),
+                },
+                _ => return Err(Error::Usage("too many arguments to branch".into())),
+            };
+            Ok(Command::Branch { action })
+        }
+        "tag" => {
+            let args = Args::parse(
+                args,
+                &[
+                    "-a,--annotate",
+                    "-s,--sign",
+                    "-d,--delete",
+                    "-l,--list",
+                    "-v,--verify",
+                    "-f,--force",
+                ],
+                &["-m,--message"],
+            )?;
+            let sign = args.flag("--sign");
+            let message = match args.value("--message") {
+                Some(message) => Some(message.to_string()),
+                None if args.flag("--annotate") || sign => {
+                    return Err(Error::Usage(
+                        "annotated tags require a message (-m <msg>)".into(),
+                    ))
+                }
+                None => None,
+            };
+            let force = args.flag("--force");
+            let delete = args.flag("--delete");
+            let list = args.flag("--list");
+            let verify = args.flag("--verify");
+
+            let action = match args.into_positional().as_slice() {
+                names if delete && !names.is_empty() => TagAction::Delete {
+                    names: names.to_vec(),
+                },
+                names if verify && !names.is_empty() => TagAction::Verify {
+                    names: names.to_vec(),
+                },
+                _ if delete || verify => return Err(Error::Usage("tag name required".into())),
+                [] => TagAction::List { pattern: None },
+                [pattern] if list => TagAction::List {
+                    pattern: Some(pattern.clone()),
+                },
+                [name] => TagAction::Create {
+                    name: name.clone(),
+                    target: None,
+                    message,
+                    force,
+                    sign,
+                },
+                [name, target] if !list => TagAction::Create {
+                    name: name.clone(),
+                    target: Some(target.clone()),
+                    message,
+                    force,
+                    sign,
+                },
+                _ => return Err(Error::Usage("too many arguments to tag".into())),
+            };
+            Ok(Command::Tag { action })
+        }
+        "diff" => {
+            let mut renames = None;
+            let mut rest = Vec::new();
+            for arg in args {
+                match parse_find_renames(&arg)? {
+                    Some(detection) => renames = Some(Some(detection)),
+                    None if arg == "--no-renames" => renames = Some(None),
+                    None => rest.push(arg),
+                }
+            }
+            let args = Args::parse(
+                rest,
+                &[
+                    "--cached,--staged",
+                    "--name-status",
+                    "--binary",
+                    "-w,--ignore-all-space",
+                    "-b,--ignore-space-change",
+                    "--ignore-blank-lines",
+                ],
+                &[],
+            )?;
+            let cached = args.flag("--cached");
+            let name_status = args.flag("--name-status");
+            let binary = args.flag("--binary");
+            let whitespace = parse_whitespace(&args);
+            let (commits, pathspecs) = args.split_positional();
+            if commits.len() > 2 || (cached && commits.len() > 1) {
+                return Err(Error::Usage("diff takes at most two commits".into()));
+            }
+            Ok(Command::Diff {
+                commits,
+                cached,
+                pathspecs,
+                renames,
+                name_status,
+                binary,
+                whitespace,
+            })
+        }
+        "merge" => {
+            let args = Args::parse(
+                args,
+                &["--ff", "--no-ff", "--ff-only", "--continue", "--abort"],
+                &[],
+            )?;
+            if let Some(resume) = parse_resume(&args)? {
+                if !args.positional().is_empty() {
+                    return Err(Error::Usage(
+                        "merge --continue and --abort take no branch".into(),
+                    ));
+                }
+                let action = MergeAction::Resume(resume);
+                return Ok(Command::Merge { action });
+            }
+            let fast_forward = match (args.flag("--no-ff"), args.flag("--ff-only")) {
+                (true, true) => {
+                    return Err(Error::Usage(
+                        "--no-ff and --ff-only are incompatible".into(),
+                    ))
+                }
+                (true, false) => FastForward::Never,
+                (false, true) => FastForward::Only,
+                (false, false) => FastForward::Allow,
+            };
+            let [target] = args.positional() else {
+                return Err(Error::Usage(
+                    "merge takes exactly one branch or commit".into(),
+                ));
+            };
+            let action = MergeAction::Start {
+                target: target.clone(),
+                fast_forward,
+            };
+            Ok(Command::Merge { action })
+        }
+        "cherry-pick" => Ok(Command::CherryPick {
+            action: parse_sequence("cherry-pick", args)?,
+        }),
+        "revert" => Ok(Command::Revert {
+            action: parse_sequence("revert", args)?,
+        }),
+        "rebase" => {
+            let args = Args::parse(
+                args,
+                &["-i,--interactive", "--continue", "--skip", "--abort"],
+                &[],
+            )?;
+            let interactive = args.flag("--interactive");
+            let action = match (parse_resume(&args)?, args.positional()) {
+                (None, [upstream]) => RebaseAction::Start {
+                    upstream: upstream.clone(),
+                    interactive,
+                },
+                (Some(resume), []) if !interactive => RebaseAction::Resume(resume),
+                _ => {
+                    return Err(Error::Usage(
+                        "rebase takes one upstream, --continue, --skip or --abort".into(),
+                    ))
+                }
+            };
+            Ok(Command::Rebase { action })
+        }
+        "blame" => {
+            let args = Args::parse(
+                args,
+                &[
+                    "-w,--ignore-all-space",
+                    "-b,--ignore-space-change",
+                    "--ignore-blank-lines",
+                ],
+                &["-L"],
+            )?;
+            let lines = args.value("-L").map(parse_line_range).transpose()?;
+            let [file] = args.positional() else {
+                return Err(Error::Usage("blame takes exactly one file".into()));
+            };
+            Ok(Command::Blame {
+                file: file.clone(),
+                lines,
+                whitespace: parse_whitespace(&args),
+            })
+        }
+        "grep" => {
+            let args = Args::parse(args, &["-n,--line-number"], &[])?;
+            let (pattern, commit) = match args.positional() {
+                [pattern] => (pattern.clone(), None),
+                [pattern, commit] => (pattern.clone(), Some(commit.clone())),
+                _ => {
+                    return Err(Error::Usage(
+                        "grep takes a pattern and an optional commit".into(),
+                    ))
+                }
+            };
+            Ok(Command::Grep {
+                pattern,
+                commit,
+                line_number: args.flag("--line-number"),
+            })
+        }
+        "bisect" => {
+            let mut args = args.collect::<Vec<_>>().into_iter();
+            let action = match args.next().as_deref() {
+                Some("start") => {
+                    let mut commits = Args::parse(args, &[], &[])?.into_positional();
+                    let bad = (!commits.is_empty()).then(|| commits.remove(0));
+                    BisectAction::Start { bad, good: commits }
+                }
+                Some(term @ ("good" | "bad" | "skip")) => {
+                    let commits = Args::parse(args, &[], &[])?.into_positional();
+                    if term == "bad" && commits.len() > 1 {
+                        return Err(Error::Usage("bisect bad takes at most one commit".into()));
+                    }
+                    let term = match term {
+                        "good" => Term::Good,
+                        "bad" => Term::Bad,
+                        _ => Term::Skip,
+                    };
+                    BisectAction::Mark { term, commits }
+                }
+                // the command keeps its own options
+                Some("run") => {
+                    let command: Vec<String> = args.collect();
+                    if command.is_empty() {
+                        return Err(Error::Usage("bisect run takes a command".into()));
+                    }
+                    BisectAction::Run { command }
+                }
+                Some("reset") => {
+                    if !Args::parse(args, &[], &[])?.positional().is_empty() {
+                        return Err(Error::Usage("bisect reset takes no arguments".into()));
+                    }
+                    BisectAction::Reset
+                }
+                Some(subcommand) => {
+                    return Err(Error::Usage(format!(
+                        "unknown bisect command '{subcommand}'"
+                    )))
+                }
+                None => {
+                    return Err(Error::Usage(
+                        "bisect takes start, good, bad, skip, run or reset".into(),
+                    ))
+                }
+            };
+            Ok(Command::Bisect { action })
+        }
+        "show" => {
+            let args = Args::parse(args, &["--abbrev-commit", "--no-abbrev-commit"], &[])?;
+            Ok(Command::Show {
+                abbrev_commit: !args.flag("--no-abbrev-commit"),
+                objects: args.into_positional(),
+            })
+        }
+        "symbolic-ref" => {
+            let args = Args::parse(args, &["-q,--quiet", "--short"], &[])?;
+            let (name, target) = match args.positional() {
+                [name] => (name.clone(), None),
+                [name, target] => (name.clone(), Some(target.clone())),
+                _ => {
+                    return Err(Error::Usage(
+                        "symbolic-ref takes a name and an optional ref".into(),
+                    ))
+                }
+            };
+            Ok(Command::SymbolicRef {
+                name,
+                target,
+                short: args.flag("--short"),
+                quiet: args.flag("--quiet"),
+            })
+        }
+        "show-ref" => {
+            let args = Args::parse(args, &["--head", "--heads", "--tags", "-s,--hash"], &[])?;
+            Ok(Command::ShowRef {
+                head: args.flag("--head"),
+                heads: args.flag("--heads"),
+                tags: args.flag("--tags"),
+                hash_only: args.flag("--hash"),
+                patterns: args.into_positional(),
+            })
+        }
+        "write-tree" => {
+            Args::parse(args, &[], &[])?;
+            Ok(Command::WriteTree)
+        }
+        "commit-tree" => {
+            let args = Args::parse(args, &[], &["-p", "-m,--message"])?;
+            let [tree] = args.positional() else {
+                return Err(Error::Usage("commit-tree takes exactly one tree".into()));
+            };
+            let messages = args.values("--message");
+            Ok(Command::CommitTree {
+                tree: tree.clone(),
+                parents: args.values("-p").into_iter().map(String::from).collect(),
+                message: (!messages.is_empty()).then(|| messages.join("\n\n")),
+            })
+        }
+        "ls-files" => {
+            let args = Args::parse(args, &["-s,--stage"], &[])?;
+            Ok(Command::LsFiles {
+                stage: args.flag("--stage"),
+                pathspecs: args.into_positional(),
+            })
+        }
+        "ls-tree" => {
+            let args = Args::parse(args, &["-r", "-t"], &[])?;
+            let [tree] = args.positional() else {
+                return Err(Error::Usage("ls-tree takes exactly one tree-ish".into()));
+            };
+            Ok(Command::LsTree {
+                tree: tree.clone(),
+                recursive: args.flag("-r"),
+                show_trees: args.flag("-t"),
+            })
+        }
+        "check-attr" => {
+            let args = Args::parse(args, &["-a,--all"], &[])?;
+            let all = args.flag("--all");
+            let (mut before, after) = args.split_positional();
+            let (attributes, paths) = match (all, after.is_empty()) {
+                (true, _) => (Vec::new(), [before, after].concat()),
+                (false, false) => (before, after),
+                // without `--` only the first argument is an attribute
+                (false, true) => {
+                    let paths = before.split_off(before.len().min(1));
+                    (before, paths)
+                }
+            };
+            if paths.is_empty() || (!all && attributes.is_empty()) {
+                return Err(Error::Usage(
+                    "check-attr needs attributes, or -a, and paths".into(),
+                ));
+            }
+            Ok(Command::CheckAttr {
+                attributes,
+                all,
+                paths,
+            })
+        }
+        "rev-parse" => {
+            let args = Args::parse(args, &[], &[])?;
+            let revisions = args.into_positional();
+            if revisions.is_empty() {
+                return Err(Error::Usage("rev-parse requires a revision".into()));
+            }
+            Ok(Command::RevParse { revisions })
+        }
+        "rev-list" => {
+            // `--not` flips whether the commits after it are left out, as `^`
+            // does for one, and `A..B` stands for `^A B`
+            let (mut include, mut exclude, mut options) = (Vec::new(), Vec::new(), Vec::new());
+            let mut not = false;
+            for arg in args {
+                if arg == "--not" {
+                    not = !not;
+                } else if arg.starts_with('-') {
+                    options.push(arg);
+                } else if let Some((since, until)) = arg.split_once("..") {
+                    let or_head = |name: &str| match name {
+                        "" => "HEAD".to_string(),
+                        name => name.to_string(),
+                    };
+                    let (since, until) = (or_head(since), or_head(until));
+                    if not {
+                        include.push(since);
+                        exclude.push(until);
+                    } else {
+                        exclude.push(since);
+                        include.push(until);
+                    }
+                } else {
+                    let (name, negated) = match arg.strip_prefix('^') {
+                        Some(name) => (name.to_string(), !not),
+                        None => (arg, not),
+                    };
+                    if negated {
+                        exclude.push(name);
+                    } else {
+                        include.push(name);
+                    }
+                }
+            }
+            let args = Args::parse(options, &["--count"], &[])?;
+            if include.is_empty() {
+                return Err(Error::Usage("rev-list requires a commit".into()));
+            }
+            Ok(Command::RevList {
+                include,
+                exclude,
+                count: args.flag("--count"),
+            })
+        }
+        "verify-commit" => {
+            let args = Args::parse(args, &[], &[])?;
+            let commits = args.into_positional();
+            if commits.is_empty() {
+                return Err(Error::Usage("verify-commit requires a commit".into()));
+            }
+            Ok(Command::VerifyCommit { commits })
+        }
+        "verify-tag" => {
+            let args = Args::parse(args, &[], &[])?;
+            let tags = args.into_positional();
+            if tags.is_empty() {
+                return Err(Error::Usage("verify-tag requires a tag".into()));
+            }
+            Ok(Command::VerifyTag { tags })
+        }
+        "merge-base" => {
+            let args = Args::parse(args, &["-a,--all", "--is-ancestor"], &[])?;
+            let [a, b] = args.positional() else {
+                return Err(Error::Usage("merge-base takes exactly two commits".into()));
+            };
+            Ok(Command::MergeBase {
+                a: a.clone(),
+                b: b.clone(),
+                all: args.flag("--all"),
+                is_ancestor: args.flag("--is-ancestor"),
+            })
+        }
+        "rm" => {
+            let args = Args::parse(args, &["--cached", "-r", "-f,--force"], &[])?;
+            let (cached, recursive, force) =
+                (args.flag("--cached"), args.flag("-r"), args.flag("--force"));
+            let paths = args.into_positional();
+            if paths.is_empty() {
+                return Err(Error::Usage("no pathspec given, nothing removed".into()));
+            }
+            Ok(Command::Rm {
+                paths,
+                cached,
+                recursive,
+                force,
+            })
+        }
+        "mv" => {
+            let args = Args::parse(args, &[], &[])?;
+            let [source, destination] = args.positional() else {
+                return Err(Error::Usage("mv takes a source and a destination".into()));
+            };
+            Ok(Command::Mv {
+                source: source.clone(),
+                destination: destination.clone(),
+            })
+        }
+        "reset" => {
+            let args = Args::parse(args, &["--soft", "--mixed", "--hard"], &[])?;
+            let modes = [
+                (args.flag("--soft"), ResetMode::Soft),
+                (args.flag("--mixed"), ResetMode::Mixed),
+                (args.flag("--hard"), ResetMode::Hard),
+            ];
+            let mut chosen = modes.iter().filter(|(set, _)| *set).map(|(_, mode)| *mode);
+            let mode = chosen.next().unwrap_or(ResetMode::Mixed);
+            if chosen.next().is_some() {
+                return Err(Error::Usage("only one reset mode may be given".into()));
+            }
+            let target = match args.positional() {
+                [] => "HEAD".to_string(),
+                [target] => target.clone(),
+                _ => return Err(Error::Usage("reset takes at most one commit".into())),
+            };
+            Ok(Command::Reset { target, mode })
+        }
+        "restore" => {
+            let args = Args::parse(args, &["-S,--staged"], &[])?;
+            let staged = args.flag("--staged");
+            let paths = args.into_positional();
+            if paths.is_empty() {
+                return Err(Error::Usage("restore needs at least one path".into()));
+            }
+            Ok(Command::Restore { paths, staged })
+        }
+        "stash" => {
+            // a bare `stash`, or one starting with options, means push
+            let mut args: Vec<String> = args.collect();
+            let subcommand = match args.first() {
+                Some(first) if !first.starts_with('-') => args.remove(0),
+                _ => "push".to_string(),
+            };
+            let args = Args::parse(args, &[], &["-m,--message"])?;
+            let index = match args.positional() {
+                [] => 0,
+                [name] => Stash::parse_name(name)?,
+                _ => return Err(Error::Usage("too many arguments to stash".into())),
+            };
+
+            let action = match subcommand.as_str() {
+                "push" | "save" if args.positional().is_empty() => StashAction::Push {
+                    message: args.value("--message").map(|message| message.to_string()),
+                },
+                "list" if args.positional().is_empty() => StashAction::List,
+                "apply" => StashAction::Apply { index },
+                "pop" => StashAction::Pop { index },
+                "drop" => StashAction::Drop { index },
+                "push" | "save" | "list" => {
+                    return Err(Error::Usage(format!(
+                        "stash {subcommand} takes no arguments"
+                    )))
+                }
+                _ => {
+                    return Err(Error::Usage(format!(
+                        "unknown stash command '{subcommand}'"
+                    )))
+                }
+            };
+            Ok(Command::Stash { action })
+        }
+        "worktree" => {
+            let args = Args::parse(args, &["--detach", "-f,--force"], &["-b"])?;
+            let action = match args.positional() {
+                [subcommand, path, rest @ ..] if subcommand == "add" && rest.len() <= 1 => {
+                    if args.flag("--detach") && args.value("-b").is_some() {
+                        return Err(Error::Usage(
+                            "worktree add takes either -b or --detach".into(),
+                        ));
+                    }
+                    WorktreeAction::Add {
+                        path: path.clone(),
+                        branch: args.value("-b").map(str::to_string),
+                        detach: args.flag("--detach"),
+                        commit: rest.first().cloned(),
+                    }
+                }
+                [subcommand] if subcommand == "list" => WorktreeAction::List,
+                [subcommand, path] if subcommand == "remove" => WorktreeAction::Remove {
+                    path: path.clone(),
+                    force: args.flag("--force"),
+                },
+                [subcommand, ..] if ["add", "list", "remove"].contains(&subcommand.as_str()) => {
+                    return Err(Error::Usage(format!(
+                        "wrong number of arguments to worktree {subcommand}"
+                    )))
+                }
+                [subcommand, ..] => {
+                    return Err(Error::Usage(format!(
+                        "unknown worktree command '{subcommand}'"
+                    )))
+                }
+                [] => return Err(Error::Usage("worktree needs a command".into())),
+            };
+            Ok(Command::Worktree { action })
+        }
+        "submodule" => {
+            let args = Args::parse(args, &[], &[])?;
+            let action = match args.positional() {
+                [subcommand, url, path] if subcommand == "add" => SubmoduleAction::Add {
+                    url: url.clone(),
+                    path: path.clone(),
+                },
+                [subcommand] if subcommand == "update" => SubmoduleAction::Update,
+                [subcommand] if subcommand == "status" => SubmoduleAction::Status,
+                [] => SubmoduleAction::Status,
+                [subcommand, ..] if ["add", "update", "status"].contains(&subcommand.as_str()) => {
+                    return Err(Error::Usage(format!(
+                        "wrong number of arguments to submodule {subcommand}"
+                    )))
+                }
+                [subcommand, ..] => {
+                    return Err(Error::Usage(format!(
+                        "unknown submodule command '{subcommand}'"
+                    )))
+                }
+            };
+            Ok(Command::Submodule { action })
+        }
+        "remote" => {
+            let mut args: Vec<String> = args.collect();
+            let subcommand = match args.first() {
+                Some(first) if !first.starts_with('-') => args.remove(0),
+                _ => "list".to_string(),
+            };
+            let args = Args::parse(args, &["-v,--verbose"], &[])?;
+            let action = match (subcommand.as_str(), args.positional()) {
+                ("list", []) => RemoteAction::List {
+                    verbose: args.flag("--verbose"),
+                },
+                ("add", [name, url]) => RemoteAction::Add {
+                    name: name.clone(),
+                    url: url.clone(),
+                },
+                ("remove" | "rm", [name]) => RemoteAction::Remove { name: name.clone() },
+                ("list" | "add" | "remove" | "rm", _) => {
+                    return Err(Error::Usage(format!(
+                        "wrong number of arguments to remote {subcommand}"
+                    )))
+                }
+                _ => {
+                    return Err(Error::Usage(format!(
+                        "unknown remote command '{subcommand}'"
+                    )))
+                }
+            };
+            Ok(Command::Remote { action })
+        }
+        "fetch" => {
+            let args = Args::parse(args, &[], &["--deepen"])?;
+            let deepen = match args.value("--deepen") {
+                Some(deepen) => Some(parse_depth(deepen)?),
+                None => None,
+            };
+            match args.into_positional().as_slice() {
+                [] => Ok(Command::Fetch {
+                    remote: None,
+                    deepen,
+                }),
+                [remote] => Ok(Command::Fetch {
+                    remote: Some(remote.clone()),
+                    deepen,
+                }),
+                _ => Err(Error::Usage("fetch takes a single remote".into())),
+            }
+        }
+        "push" => {
+            // the lease takes an optional value, which Args cannot express
+            let (leases, args): (Vec<String>, Vec<String>) =
+                args.partition(|arg| arg.starts_with("--force-with-lease"));
+            let args = Args::parse(args, &["-f,--force", "-u,--set-upstream"], &[])?;
+            let mut positional = args.positional().iter().cloned();
+            let (remote, branch) = (positional.next(), positional.next());
+            if positional.next().is_some() {
+                return Err(Error::Usage("push takes a remote and a branch".into()));
+            }
+            let force = match leases.last().map(|lease| lease.split_once('=')) {
+                _ if args.flag("--force") => Force::Always,
+                None => Force::Never,
+                Some(None) => Force::WithLease(None),
+                Some(Some((_, value))) => {
+                    let (name, expected) = match value.split_once(':') {
+                        Some((name, expected)) => (name, Some(expected.to_string())),
+                        None => (value, None),
+                    };
+                    if branch.as_deref().is_some_and(|branch| branch != name) {
+                        return Err(Error::Usage(format!(
+                            "--force-with-lease names '{name}', which is not being pushed"
+                        )));
+                    }
+                    Force::WithLease(expected)
+                }
+            };
+            Ok(Command::Push {
+                remote,
+                branch,
+                force,
+                set_upstream: args.flag("--set-upstream"),
+            })
+        }
+        "pull" => {
+            let args = Args::parse(args, &["-r,--rebase", "--no-rebase"], &[])?;
+            if !args.positional().is_empty() {
+                return Err(Error::Usage("pull takes no arguments".into()));
+            }
+            let rebase = match (args.flag("--rebase"), args.flag("--no-rebase")) {
+                (true, true) => {
+                    return Err(Error::Usage(
+                        "--rebase and --no-rebase are incompatible".into(),
+                    ))
+                }
+                (true, false) => Some(true),
+                (false, true) => Some(false),
+                (false, false) => None,
+            };
+            Ok(Command::Pull { rebase })
+        }
+        "upload-pack" | "receive-pack" => {
+            let args = Args::parse(args, &[], &[])?;
+            let [path] = args.positional() else {
+                return Err(Error::Usage(format!("{name} takes exactly one path")));
+            };
+            let path = path.clone();
+            Ok(match name.as_str() {
+                "upload-pack" => Command::UploadPack { path },
+                _ => Command::ReceivePack { path },
+            })
+        }
+        "bundle" => {
+            let args = Args::parse(args, &[], &[])?;
+            let action = match args.positional() {
+                [subcommand, file, revisions @ ..] if subcommand == "create" => {
+                    if revisions.is_empty() {
+                        return Err(Error::Usage("bundle create needs refs to bundle".into()));
+                    }
+                    BundleAction::Create {
+                        file: file.clone(),
+                        revisions: revisions.to_vec(),
+                    }
+                }
+                [subcommand, file] if subcommand == "verify" => {
+                    BundleAction::Verify { file: file.clone() }
+                }
+                [subcommand, file] if subcommand == "unbundle" => {
+                    BundleAction::Unbundle { file: file.clone() }
+                }
+                [subcommand, ..]
+                    if ["create", "verify", "unbundle"].contains(&subcommand.as_str()) =>
+                {
+                    return Err(Error::Usage(format!(
+                        "wrong number of arguments to bundle {subcommand}"
+                    )))
+                }
+                [subcommand, ..] => {
+                    return Err(Error::Usage(format!(
+                        "unknown bundle command '{subcommand}'"
+                    )))
+                }
+                [] => return Err(Error::Usage("bundle needs a command".into())),
+            };
+            Ok(Command::Bundle { action })
+        }
+        "fast-export" => {
+            let args = Args::parse(args, &[], &[])?;
+            Ok(Command::FastExport {
+                refs: args.into_positional(),
+            })
+        }
+        "fast-import" => {
+            Args::parse(args, &[], &[])?;
+            Ok(Command::FastImport)
+        }
+        "format-patch" => {
+            let args = Args::parse(args, &["--stdout"], &["-o,--output-directory"])?;
+            let [range] = args.positional() else {
+                return Err(Error::Usage("format-patch takes exactly one range".into()));
+            };
+            Ok(Command::FormatPatch {
+                range: range.clone(),
+                output_directory: args.value("--output-directory").map(str::to_string),
+                stdout: args.flag("--stdout"),
+            })
+        }
+        "am" => {
+            let args = Args::parse(args, &[], &[])?;
+            Ok(Command::Am {
+                mailboxes: args.into_positional(),
+            })
+        }
+        "apply" => {
+            let args = Args::parse(args, &["--cached", "--check", "--reject"], &["--fuzz"])?;
+            let fuzz = match args.value("--fuzz") {
+                Some(fuzz) => fuzz
+                    .parse()
+                    .map_err(|_| Error::Usage(format!("fuzz '{fuzz}' is not a number")))?,
+                None => 0,
+            };
+            Ok(Command::Apply {
+                cached: args.flag("--cached"),
+                check: args.flag("--check"),
+                reject: args.flag("--reject"),
+                fuzz,
+                patches: args.into_positional(),
+            })
+        }
+        "archive" => {
+            let args = Args::parse(args, &[], &["--format", "--prefix", "-o,--output"])?;
+            let format = match args.value("--format") {
+                Some(name) => Some(
+                    ArchiveFormat::parse(name)
+                        .ok_or_else(|| Error::Usage(format!("unknown archive format '{name}'")))?,
+                ),
+                None => None,
+            };
+            let [tree_ish] = args.positional() else {
+                return Err(Error::Usage("archive takes exactly one tree-ish".into()));
+            };
+            Ok(Command::Archive {
+                tree_ish: tree_ish.clone(),
+                format,
+                prefix: args.value("--prefix").unwrap_or_default().to_string(),
+                output: args.value("--output").map(str::to_string),
+            })
+        }
+        "serve" => {
+            let args = Args::parse(args, &["--enable-push"], &["--addr"])?;
+            let addr = args
+                .value("--addr")
+                .unwrap_or(DEFAULT_SERVE_ADDR)
+                .to_string();
+            Ok(Command::Serve {
+                addr,
+                enable_push: args.flag("--enable-push"),
+                paths: args.into_positional(),
+            })
+        }
+        "clean" => {
+            let args = Args::parse(args, &["-n,--dry-run", "-f,--force", "-d"], &[])?;
+            if !args.positional().is_empty() {
+                return Err(Error::Usage("clean takes no paths".into()));
+            }
+            Ok(Command::Clean {
+                force: args.flag("--force") && !args.flag("--dry-run"),
+                directories: args.flag("-d"),
+            })
+        }
+        "repack" => {
+            Args::parse(args, &[], &[])?;
+            Ok(Command::Repack)
+        }
+        "pack-refs" => {
+            let args = Args::parse(args, &["--no-prune"], &[])?;
+            Ok(Command::PackRefs {
+                no_prune: args.flag("--no-prune"),
+            })
+        }
+        "gc" => {
+            let args = Args::parse(args, &[], &["--prune"])?;
+            let prune = match args.value("--prune") {
+                Some(value) => Some(
+                    Expiry::parse(value)
+                        .ok_or_else(|| Error::Usage(format!("invalid expiry '{value}'")))?,
+                ),
+                None => None,
+            };
+            Ok(Command::Gc { prune })
+        }
+        "prune" => {
+            let args = Args::parse(args, &["-n,--dry-run"], &["--expire"])?;
+            let expire = match args.value("--expire") {
+                Some(value) => Some(
+                    Expiry::parse(value)
+                        .ok_or_else(|| Error::Usage(format!("invalid expiry '{value}'")))?,
+                ),
+                None => None,
+            };
+            Ok(Command::Prune {
+                dry_run: args.flag("--dry-run"),
+                expire,
+            })
+        }
+        "fsck" => {
+            Args::parse(args, &[], &[])?;
+            Ok(Command::Fsck)
+        }
+        "config" => {
+            let args = Args::parse(args, &["--system", "--global", "--local"], &[])?;
+            let scopes = [
+                (args.flag("--system"), ConfigScope::System),
+                (args.flag("--global"), ConfigScope::Global),
+                (args.flag("--local"), ConfigScope::Local),
+            ];
+            let mut chosen = scopes
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, scope)| *scope);
+            let scope = chosen.next();
+            if chosen.next().is_some() {
+                return Err(Error::Usage("only one config file at a time".into()));
+            }
+            let action = match args.positional() {
+                [action, name] if action == "get" => ConfigAction::Get { name: name.clone() },
+                [action, name, value] if action == "set" => ConfigAction::Set {
+                    name: name.clone(),
+                    value: value.clone(),
+                },
+                [action, name] if action == "unset" => ConfigAction::Unset { name: name.clone() },
+                [action] if action == "list" => ConfigAction::List,
+                _ => {
+                    return Err(Error::Usage(
+                        "usage: config get <name> | set <name> <value> | unset <name> | list"
+                            .into(),
+                    ))
+                }
+            };
+            Ok(Command::Config { action, scope })
+        }
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        other => expand_alias(other, args.collect(), expanded),
+    }
+}
+
+/// Replaces an `alias.<name>` from the config with its definition. A
+/// definition starting with `!` is run by the shell instead.
+fn expand_alias(name: &str, args: Vec<String>, expanded: &mut Vec<String>) -> Result<Command> {
+    let config = match FileService::new() {
+        Ok(file_service) => file_service.config()?.clone(),
+        Err(_) => Config::load(None)?,
+    };
+    let Some(definition) = config.get(&format!("alias.{name}")) else {
+        return Err(Error::Usage(format!("'{name}' is not a blip command")));
+    };
+    if expanded.iter().any(|alias| alias == name) {
+        return Err(Error::Usage(format!(
+            "alias loop detected: expansion of '{name}' does not terminate"
+        )));
+    }
+    expanded.push(name.to_string());
+
+    if let Some(command) = definition.strip_prefix('!') {
+        return Ok(Command::Shell {
+            command: command.to_string(),
+            args,
+        });
+    }
+
+    let mut words = split_words(&definition)
+        .ok_or_else(|| Error::InvalidConfig(format!("unbalanced quotes in alias '{name}'")))?;
+    if words.is_empty() {
+        return Err(Error::InvalidConfig(format!("alias '{name}' is empty")));
+    }
+    words.extend(args);
+    parse_expanding(words, expanded)
+}
+
+/// The one of `--continue`, `--skip` and `--abort` given, if any.
+fn parse_resume(args: &Args) -> Result<Option<Resume>> {
+    let mut given = [
+        ("--continue", Resume::Continue),
+        ("--skip", Resume::Skip),
+        ("--abort", Resume::Abort),
+    ]
+    .into_iter()
+    .filter(|(flag, _)| args.flag(flag))
+    .map(|(_, resume)| resume);
+    match (given.next(), given.next()) {
+        (resume, None) => Ok(resume),
+        _ => Err(Error::Usage(
+            "--continue, --skip and --abort are incompatible".into(),
+        )),
+    }
+}
+
+/// Parses the arguments of `cherry-pick` or `revert`, named `name`.
+fn parse_sequence<I>(name: &str, args: I) -> Result<SequenceAction>
+where
+    I: IntoIterator<Item = String>,
+{
+    let args = Args::parse(args, &["--continue", "--skip", "--abort"], &[])?;
+    match (parse_resume(&args)?, args.positional()) {
+        (None, [_, ..]) => Ok(SequenceAction::Start {
+            commits: args.into_positional(),
+        }),
+        (Some(resume), []) => Ok(SequenceAction::Resume(resume)),
+        _ => Err(Error::Usage(format!(
+            "{name} takes commits, --continue, --skip or --abort"
+        ))),
+    }
+}
+
+/// Parses the `<start>,<end>` given to `blame -L`, where the end may also
+/// be `+<count>` lines or left out to go on to the end of the file.
+fn parse_line_range(range: &str) -> Result<(usize, Option<usize>)> {
+    let invalid = || Error::Usage(format!("invalid line range '{range}'"));
+    let (start, end) = range.split_once(',').unwrap_or((range, ""));
+    let start: usize = start
+        .parse()
+        .ok()
+        .filter(|&start| start > 0)
+        .ok_or_else(invalid)?;
+    let end = match end.strip_prefix('+') {
+        _ if end.is_empty() => None,
+        Some(count) => match count.parse::<usize>() {
+            Ok(count) if count > 0 => Some((start - 1).checked_add(count).ok_or_else(invalid)?),
+            _ => return Err(invalid()),
+        },
+        None => Some(
+            end.parse()
+                .ok()
+                .filter(|&end| end >= start)
+                .ok_or_else(invalid)?,
+        ),
+    };
+    Ok((start, end))
+}
+
+/// Compiles the patterns given to `--author` or `--grep`.
+fn parse_patterns(patterns: &[&str]) -> Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|_| Error::Usage(format!("invalid pattern '{pattern}'")))
+        })
+        .collect()
+}
+
+/// Reads `-w`, `-b` and `--ignore-blank-lines`.
+fn parse_whitespace(args: &Args) -> Whitespace {
+    Whitespace {
+        ignore_all: args.flag("--ignore-all-space"),
+        ignore_change: args.flag("--ignore-space-change"),
+        ignore_blank_lines: args.flag("--ignore-blank-lines"),
+    }
+}
+
+/// Parses `-M`, `-C`, `--find-renames` or `--find-copies`, which may carry
+/// a similarity threshold as in `-M60%` or `--find-renames=60`. `None` if
+/// `arg` is none of them.
+fn parse_find_renames(arg: &str) -> Result<Option<RenameDetection>> {
+    let (threshold, copies) = if let Some(threshold) = arg.strip_prefix("-M") {
+        (threshold, false)
+    } else if let Some(threshold) = arg.strip_prefix("-C") {
+        (threshold, true)
+    } else {
+        let (name, threshold) = match arg.split_once('=') {
+            Some((name, threshold)) if !threshold.is_empty() => (name, threshold),
+            _ => (arg, ""),
+        };
+        match name {
+            "--find-renames" => (threshold, false),
+            "--find-copies" => (threshold, true),
+            _ => return Ok(None),
+        }
+    };
+    let threshold = match threshold {
+        "" => RENAME_THRESHOLD,
+        threshold => diff::parse_threshold(threshold)
+            .ok_or_else(|| Error::Usage(format!("invalid similarity threshold in '{arg}'")))?,
+    };
+    Ok(Some(RenameDetection { threshold, copies }))
+}
+
+/// Parses the number of commits given to `--depth` or `--deepen`.
+fn parse_depth(depth: &str) -> Result<usize> {
+    match depth.parse() {
+        Ok(depth) if depth > 0 => Ok(depth),
+        _ => Err(Error::Usage(format!(
+            "depth '{depth}' is not a positive number"
+        ))),
+    }
+}
+
+/// Splits an alias definition into words, honouring single and double
+/// quotes and backslash escapes the way a shell would.
+fn split_words(input: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => word.get_or_insert_default().push(chars.next()?),
+            (Some(_), c) => word.get_or_insert_default().push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    words.extend(word);
+    Some(words)
+}
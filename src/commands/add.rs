@@ -0,0 +1,521 @@
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    cli::AddMode,
+    diff::{self, DiffOptions},
+    editor,
+    patch::{self, Hunk},
+    pathspec::Pathspec,
+    repository::{Repository, UnstagedChange},
+    types::Result,
+    worktree::WorkTree,
+};
+
+/// The file of `.blip` a hunk is edited in.
+const EDIT_FILE: &str = "ADD_EDIT.patch";
+
+const PATCH_HELP: &str = "\
+y - stage this hunk
+n - do not stage this hunk
+q - quit; do not stage this hunk or any of the remaining ones
+a - stage this hunk and all later hunks in the file
+d - do not stage this hunk or any of the later hunks in the file
+s - split the current hunk into smaller hunks
+e - manually edit the current hunk
+? - print help";
+
+const EDIT_HELP: &str = "\
+# ---
+# To remove '-' lines, make them ' ' lines (context).
+# To remove '+' lines, delete them.
+# Lines starting with # will be removed.
+# If the hunk no longer applies, it is left as it was.
+# If all lines of the hunk are removed, the edit is aborted.
+";
+
+/// The commands of the `add -i` menu, numbered from 1 in this order.
+const COMMANDS: [&str; 9] = [
+    "status",
+    "update",
+    "revert",
+    "add untracked",
+    "patch",
+    "diff",
+    "discard",
+    "quit",
+    "help",
+];
+
+const MENU_HELP: &str = "\
+status        - show the paths with changes
+update        - stage the working tree changes of the chosen paths
+revert        - unstage the staged changes of the chosen paths
+add untracked - start tracking the chosen untracked files
+patch         - pick hunks of the chosen paths to stage
+diff          - show the staged changes of the chosen paths
+discard       - throw away the working tree changes of the chosen paths
+quit          - leave the menu
+help          - print this";
+
+pub fn run(
+    files: &[String],
+    mode: AddMode,
+    force: bool,
+    jobs: Option<usize>,
+    patch: bool,
+    interactive: bool,
+) -> Result<()> {
+    let repository = Repository::open(".")?;
+    let pathspecs = files
+        .iter()
+        .map(|file| Pathspec::from_cwd(repository.file_service(), file))
+        .collect::<Result<Vec<_>>>()?;
+    if patch {
+        return add_patch(&repository, &mut io::stdin().lock(), &pathspecs);
+    }
+    if interactive {
+        return add_interactive(&repository, &pathspecs);
+    }
+    repository.add(&pathspecs, mode, force, jobs)
+}
+
+/// An answer to a yes, no or quit question.
+enum Answer {
+    Stage,
+    Skip,
+    Quit,
+}
+
+/// Goes through the hunks of the unstaged changes to the tracked files
+/// matching `pathspecs`, asking for each whether to stage it, and stages
+/// the chosen ones file by file.
+fn add_patch(
+    repository: &Repository,
+    input: &mut impl BufRead,
+    pathspecs: &[String],
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let changes = repository.unstaged_changes(pathspecs)?;
+    if changes.is_empty() {
+        writeln!(out, "No changes.")?;
+        return Ok(());
+    }
+    for change in changes {
+        writeln!(out, "diff --git a/{0} b/{0}", change.path)?;
+        let quit = match change.current {
+            None => match ask(input, "Stage deletion [y,n,q,?]? ")? {
+                Answer::Stage => {
+                    repository.stage_contents(&change.path, None)?;
+                    false
+                }
+                Answer::Skip => false,
+                Answer::Quit => true,
+            },
+            Some(ref current) => stage_hunks(repository, input, &change, current)?,
+        };
+        if quit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Asks about each hunk of `change` and stages the chosen ones. Returns
+/// whether the user quit.
+fn stage_hunks(
+    repository: &Repository,
+    input: &mut impl BufRead,
+    change: &UnstagedChange,
+    current: &str,
+) -> Result<bool> {
+    let mut out = io::stdout().lock();
+    writeln!(out, "--- a/{0}\n+++ b/{0}", change.path)?;
+    let mut hunks: Vec<(Hunk, Option<bool>)> = patch::diff(&change.staged, current, 3)
+        .into_iter()
+        .map(|hunk| (hunk, None))
+        .collect();
+    let mut quit = false;
+    let mut i = 0;
+    while i < hunks.len() {
+        write!(out, "{}", hunks[i].0)?;
+        let prompt = format!(
+            "({}/{}) Stage this hunk [y,n,q,a,d,s,e,?]? ",
+            i + 1,
+            hunks.len()
+        );
+        let Some(answer) = read_answer(input, &prompt)? else {
+            quit = true;
+            break;
+        };
+        match answer.as_str() {
+            "y" => hunks[i].1 = Some(true),
+            "n" => hunks[i].1 = Some(false),
+            "a" | "d" | "q" => {
+                for (_, staged) in &mut hunks[i..] {
+                    *staged = Some(answer == "a");
+                }
+                quit = answer == "q";
+                break;
+            }
+            "s" => {
+                let split = hunks[i].0.split();
+                if split.len() == 1 {
+                    writeln!(out, "Sorry, cannot split this hunk")?;
+                } else {
+                    writeln!(out, "Split into {} hunks.", split.len())?;
+                    hunks.splice(i..=i, split.into_iter().map(|hunk| (hunk, None)));
+                }
+                continue;
+            }
+            "e" => {
+                if let Some(edited) = edit_hunk(repository, &change.staged, &hunks[i].0)? {
+                    hunks[i] = (edited, Some(true));
+                } else {
+                    continue;
+                }
+            }
+            _ => {
+                writeln!(out, "{PATCH_HELP}")?;
+                continue;
+            }
+        }
+        i += 1;
+    }
+
+    let chosen: Vec<&Hunk> = hunks
+        .iter()
+        .filter(|(_, staged)| *staged == Some(true))
+        .map(|(hunk, _)| hunk)
+        .collect();
+    if !chosen.is_empty() {
+        match patch::apply_hunks(&change.staged, &chosen) {
+            Some(contents) => repository.stage_contents(&change.path, Some(&contents))?,
+            None => writeln!(out, "The chosen hunks of {} do not apply.", change.path)?,
+        }
+    }
+    Ok(quit)
+}
+
+/// Has `hunk` edited, returning the edited hunk, or `None` when the edit
+/// was given up or does not apply to `staged`.
+fn edit_hunk(repository: &Repository, staged: &str, hunk: &Hunk) -> Result<Option<Hunk>> {
+    let mut out = io::stdout().lock();
+    let file_service = repository.file_service();
+    let path = file_service.blip_dir.join(EDIT_FILE);
+    fs::write(&path, format!("# Manual hunk edit mode\n{hunk}{EDIT_HELP}"))?;
+    let text = editor::edit(file_service, &path).and_then(|()| Ok(fs::read_to_string(&path)?));
+    let _ = fs::remove_file(&path);
+    let edited = match hunk.edited(&text?) {
+        Ok(edited) if edited.lines.is_empty() => return Ok(None),
+        Ok(edited) => edited,
+        Err(error) => {
+            writeln!(out, "{error}")?;
+            return Ok(None);
+        }
+    };
+    if patch::apply_hunks(staged, &[&edited]).is_none() {
+        writeln!(out, "Your edited hunk does not apply.")?;
+        return Ok(None);
+    }
+    Ok(Some(edited))
+}
+
+/// Asks a yes, no or quit question, where anything else prints the help.
+fn ask(input: &mut impl BufRead, prompt: &str) -> Result<Answer> {
+    let mut out = io::stdout().lock();
+    loop {
+        match read_answer(input, prompt)?.as_deref() {
+            Some("y") => return Ok(Answer::Stage),
+            Some("n") => return Ok(Answer::Skip),
+            Some("q") | None => return Ok(Answer::Quit),
+            Some(_) => writeln!(
+                out,
+                "y - stage it\nn - do not stage it\nq - quit\n? - print help"
+            )?,
+        }
+    }
+}
+
+/// A tracked path with changes, with a summary of the staged and the
+/// unstaged ones.
+struct FileStatus {
+    path: String,
+    staged: Option<String>,
+    unstaged: Option<String>,
+}
+
+/// Shows a menu for staging, unstaging and reviewing the changes to the
+/// files matching `pathspecs`, until it is quit.
+fn add_interactive(repository: &Repository, pathspecs: &[String]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let mut input = io::stdin().lock();
+    print_status(&file_statuses(repository, pathspecs)?)?;
+    loop {
+        writeln!(out, "*** Commands ***")?;
+        for (row, commands) in COMMANDS.chunks(4).enumerate() {
+            let line: String = commands
+                .iter()
+                .enumerate()
+                .map(|(i, command)| format!("  {}: {command:<14}", row * 4 + i + 1))
+                .collect();
+            writeln!(out, "{}", line.trim_end())?;
+        }
+        let Some(answer) = read_line(&mut input, "What now> ")? else {
+            break;
+        };
+        let command = match answer.parse::<usize>() {
+            Ok(number) => COMMANDS.get(number.wrapping_sub(1)).copied(),
+            Err(_) if answer.is_empty() => continue,
+            Err(_) => COMMANDS
+                .iter()
+                .find(|command| command.starts_with(&answer))
+                .copied(),
+        };
+        let Some(command) = command else {
+            writeln!(out, "Huh ({answer})?")?;
+            continue;
+        };
+
+        let statuses = file_statuses(repository, pathspecs)?;
+        let staged = || statuses.iter().filter(|status| status.staged.is_some());
+        let unstaged = || statuses.iter().filter(|status| status.unstaged.is_some());
+        match command {
+            "status" => print_status(&statuses)?,
+            "update" => {
+                let paths = choose(&mut input, "Update", unstaged())?;
+                if !paths.is_empty() {
+                    repository.add(&paths, AddMode::Paths, false, None)?;
+                    writeln!(out, "updated {} path(s)", paths.len())?;
+                }
+            }
+            "revert" => {
+                let paths = choose(&mut input, "Revert", staged())?;
+                if !paths.is_empty() {
+                    repository.unstage(&paths)?;
+                    writeln!(out, "reverted {} path(s)", paths.len())?;
+                }
+            }
+            "add untracked" => {
+                let paths = choose_untracked(repository, &mut input, pathspecs)?;
+                if !paths.is_empty() {
+                    repository.add(&paths, AddMode::Paths, false, None)?;
+                    writeln!(out, "added {} path(s)", paths.len())?;
+                }
+            }
+            "patch" => {
+                let paths = choose(&mut input, "Patch update", unstaged())?;
+                if !paths.is_empty() {
+                    add_patch(repository, &mut input, &paths)?;
+                }
+            }
+            "diff" => {
+                let paths = choose(&mut input, "Review diff", staged())?;
+                if !paths.is_empty() {
+                    write!(
+                        out,
+                        "{}",
+                        repository.diff(&[], true, &paths, DiffOptions::default())?
+                    )?;
+                }
+            }
+            "discard" => {
+                let paths = choose(&mut input, "Discard", unstaged())?;
+                let prompt = format!("Discard the changes to {} path(s) [y,n]? ", paths.len());
+                if !paths.is_empty() && read_answer(&mut input, &prompt)?.as_deref() == Some("y") {
+                    repository.discard_changes(&paths)?;
+                    writeln!(out, "discarded {} path(s)", paths.len())?;
+                }
+            }
+            "quit" => {
+                writeln!(out, "Bye.")?;
+                break;
+            }
+            _ => writeln!(out, "{MENU_HELP}")?,
+        }
+    }
+    Ok(())
+}
+
+/// The tracked paths matching `pathspecs` with staged or unstaged changes,
+/// leaving out unmerged ones.
+fn file_statuses(repository: &Repository, pathspecs: &[String]) -> Result<Vec<FileStatus>> {
+    let file_service = repository.file_service();
+    let worktree = WorkTree::new(file_service);
+    let index = file_service.read_index()?;
+    let head = file_service.read_head_commit()?;
+    let status = worktree.status_matching(&index, head.as_ref(), &Pathspec::new(pathspecs)?)?;
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+    let committed = head.as_ref().map(|head| head.files());
+    let submodules = worktree.submodules()?;
+
+    let staged: BTreeSet<&String> = status.staged.iter().map(|(_, path)| path).collect();
+    let unstaged: BTreeSet<&String> = status.unstaged.iter().map(|(_, path)| path).collect();
+    let mut statuses = Vec::new();
+    for &path in staged.union(&unstaged) {
+        // submodules count as empty, so only their adding or deleting shows
+        let read = |hash: Option<&String>| -> Result<Option<Vec<u8>>> {
+            match hash {
+                Some(_) if submodules.contains(path) => Ok(Some(Vec::new())),
+                Some(hash) => Ok(Some(file_service.read_blob(hash)?)),
+                None => Ok(None),
+            }
+        };
+        let index_side = read(tracked.get(path))?;
+        let staged = if staged.contains(path) {
+            let committed = read(committed.and_then(|files| files.get(path)))?;
+            Some(summary(committed, index_side.clone()))
+        } else {
+            None
+        };
+        let unstaged = if unstaged.contains(path) {
+            let present = file_service.root_dir.join(path).is_file();
+            let current = if present && !submodules.contains(path) {
+                Some(worktree.read_converted(path)?)
+            } else {
+                None
+            };
+            Some(summary(index_side, current))
+        } else {
+            None
+        };
+        statuses.push(FileStatus {
+            path: path.clone(),
+            staged,
+            unstaged,
+        });
+    }
+    Ok(statuses)
+}
+
+/// Sums up a change as the numbers of lines added and deleted.
+fn summary(old: Option<Vec<u8>>, new: Option<Vec<u8>>) -> String {
+    let (old, new) = (old.unwrap_or_default(), new.unwrap_or_default());
+    if old.contains(&0) || new.contains(&0) {
+        return "binary".to_string();
+    }
+    let (added, deleted) = diff::line_counts(
+        &String::from_utf8_lossy(&old),
+        &String::from_utf8_lossy(&new),
+    );
+    format!("+{added}/-{deleted}")
+}
+
+fn print_status(statuses: &[FileStatus]) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    if statuses.is_empty() {
+        return writeln!(out, "No changes.");
+    }
+    writeln!(out, "{:>5}{:>12} {:>12} path", "", "staged", "unstaged")?;
+    for (i, status) in statuses.iter().enumerate() {
+        writeln!(out, "{:>3}: {}", i + 1, status_line(status))?;
+    }
+    writeln!(out)
+}
+
+fn status_line(status: &FileStatus) -> String {
+    format!(
+        "{:>12} {:>12} {}",
+        status.staged.as_deref().unwrap_or("nothing"),
+        status.unstaged.as_deref().unwrap_or("nothing"),
+        status.path
+    )
+}
+
+/// Lists `statuses` and reads which of their paths to act on.
+fn choose<'a>(
+    input: &mut impl BufRead,
+    prompt: &str,
+    statuses: impl Iterator<Item = &'a FileStatus>,
+) -> Result<Vec<String>> {
+    let mut out = io::stdout().lock();
+    let statuses: Vec<&FileStatus> = statuses.collect();
+    if statuses.is_empty() {
+        writeln!(out, "No changes.")?;
+        return Ok(Vec::new());
+    }
+    writeln!(out, "{:>5}{:>12} {:>12} path", "", "staged", "unstaged")?;
+    let lines: Vec<String> = statuses.iter().map(|status| status_line(status)).collect();
+    let chosen = select(input, prompt, &lines)?;
+    Ok(chosen
+        .into_iter()
+        .map(|i| statuses[i].path.clone())
+        .collect())
+}
+
+fn choose_untracked(
+    repository: &Repository,
+    input: &mut impl BufRead,
+    pathspecs: &[String],
+) -> Result<Vec<String>> {
+    let mut out = io::stdout().lock();
+    let file_service = repository.file_service();
+    let index = file_service.read_index()?;
+    let head = file_service.read_head_commit()?;
+    let pathspec = Pathspec::new(pathspecs)?;
+    let untracked = WorkTree::new(file_service)
+        .status_matching(&index, head.as_ref(), &pathspec)?
+        .untracked;
+    if untracked.is_empty() {
+        writeln!(out, "No untracked files.")?;
+        return Ok(Vec::new());
+    }
+    let chosen = select(input, "Add untracked", &untracked)?;
+    Ok(chosen.into_iter().map(|i| untracked[i].clone()).collect())
+}
+
+/// Prints `lines` numbered from 1 and reads which to choose: numbers,
+/// ranges such as `2-4`, or `*` for all of them, separated by commas or
+/// spaces. Returns their indexes in order, none for an empty answer.
+fn select(input: &mut impl BufRead, prompt: &str, lines: &[String]) -> Result<Vec<usize>> {
+    let mut out = io::stdout().lock();
+    for (i, line) in lines.iter().enumerate() {
+        writeln!(out, "{:>3}: {line}", i + 1)?;
+    }
+    'ask: loop {
+        let Some(answer) = read_line(input, &format!("{prompt}>> "))? else {
+            return Ok(Vec::new());
+        };
+        let mut chosen = BTreeSet::new();
+        for choice in answer.split([',', ' ']).filter(|choice| !choice.is_empty()) {
+            let range = match choice.split_once('-') {
+                _ if choice == "*" => Some((1, lines.len())),
+                Some((start, end)) => start.parse().ok().zip(end.parse().ok()),
+                None => choice.parse().ok().map(|number| (number, number)),
+            };
+            match range {
+                Some((start, end)) if 1 <= start && start <= end && end <= lines.len() => {
+                    chosen.extend(start - 1..end);
+                }
+                _ => {
+                    writeln!(out, "Huh ({choice})?")?;
+                    continue 'ask;
+                }
+            }
+        }
+        return Ok(chosen.into_iter().collect());
+    }
+}
+
+/// Prints `prompt` and reads the first letter of the answer, or `None` at
+/// the end of the input.
+fn read_answer(input: &mut impl BufRead, prompt: &str) -> Result<Option<String>> {
+    Ok(read_line(input, prompt)?.map(|line| line.chars().take(1).collect()))
+}
+
+/// Prints `prompt` and reads a line of answer, or `None` at the end of the
+/// input.
+fn read_line(input: &mut impl BufRead, prompt: &str) -> Result<Option<String>> {
+    let mut out = io::stdout().lock();
+    write!(out, "{prompt}")?;
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        writeln!(out)?;
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
@@ -0,0 +1,32 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    mail::{self, Email},
+    types::{Context, FileService, Result},
+};
+
+pub fn run(mailboxes: &[String]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let mut emails = Vec::new();
+    if mailboxes.is_empty() {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        emails.extend(Email::parse_mbox(&text)?);
+    }
+    for mailbox in mailboxes {
+        let path = Path::new(mailbox);
+        let text = fs::read_to_string(path).context("read", path)?;
+        emails.extend(Email::parse_mbox(&text)?);
+    }
+
+    for commit in mail::am(&file_service, &emails)? {
+        let subject = commit.message().lines().next().unwrap_or_default();
+        writeln!(out, "Applying: {subject}")?;
+    }
+    Ok(())
+}
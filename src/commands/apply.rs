@@ -0,0 +1,181 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::{
+    patch::{self, FilePatch},
+    types::{Blob, Context, Error, FileService, Result},
+    worktree::WorkTree,
+};
+
+pub fn run(patches: &[String], cached: bool, check: bool, reject: bool, fuzz: usize) -> Result<()> {
+    // the index lock is let go of before exiting, which skips destructors
+    if !apply(patches, cached, check, reject, fuzz)? {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Applies `patches`, returning whether all of their hunks applied; with
+/// `reject` those that did not are left in `.rej` files.
+fn apply(patches: &[String], cached: bool, check: bool, reject: bool, fuzz: usize) -> Result<bool> {
+    let file_service = FileService::new()?;
+    let worktree = WorkTree::new(&file_service);
+    // only the index is changed in `cached` mode, the working tree otherwise
+    let mut index = if cached {
+        file_service.lock_index()?
+    } else {
+        file_service.read_index()?
+    };
+    let head = file_service.read_head_commit()?;
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+
+    let mut files = Vec::new();
+    if patches.is_empty() {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        files.extend(patch::parse(&text)?);
+    }
+    for path in patches {
+        let path = Path::new(path);
+        let text = fs::read_to_string(path).context("read", path)?;
+        files.extend(patch::parse(&text)?);
+    }
+    if files.is_empty() {
+        return Err(Error::Usage("no patch found in the input".into()));
+    }
+
+    // what the patches so far made of each file, `None` once deleted
+    let mut contents: BTreeMap<String, Option<Vec<u8>>> = BTreeMap::new();
+    let current = |contents: &BTreeMap<String, Option<Vec<u8>>>, path: &str| {
+        if let Some(content) = contents.get(path) {
+            return Ok(content.clone());
+        }
+        match cached {
+            true => tracked
+                .get(path)
+                .map(|hash| file_service.read_blob(hash))
+                .transpose(),
+            false => match worktree.hash(path)? {
+                Some(_) => Ok(Some(worktree.read_file(path)?)),
+                None => Ok(None),
+            },
+        }
+    };
+
+    let place = if cached {
+        "in the index"
+    } else {
+        "in the working tree"
+    };
+    let mut failed = Vec::new();
+    let mut rejects = Vec::new();
+    for file in files.iter() {
+        let paths = file.old_path.iter().chain(&file.new_path);
+        if let Some(path) = paths
+            .filter(|_| !cached)
+            .find(|path| worktree.is_beyond_symlink(path))
+        {
+            failed.push(format!("{path}: is beyond a symbolic link"));
+            continue;
+        }
+        let old = match &file.old_path {
+            Some(path) => match current(&contents, path)? {
+                Some(old) => old,
+                None => {
+                    failed.push(format!("{path}: does not exist {place}"));
+                    continue;
+                }
+            },
+            None => {
+                if current(&contents, file.path())?.is_some() {
+                    failed.push(format!("{}: already exists {place}", file.path()));
+                    continue;
+                }
+                Vec::new()
+            }
+        };
+
+        if file.binary.is_some() {
+            match file.apply_data(&old) {
+                Some(new) => record(&mut contents, file, new),
+                None => failed.push(format!(
+                    "{}: binary patch does not apply {place}",
+                    file.path()
+                )),
+            }
+            continue;
+        }
+        let Ok(old) = String::from_utf8(old) else {
+            return Err(Error::Usage(format!("{} is not a text file", file.path())));
+        };
+        let (new, rejected) = file.apply_fuzzy(&old, fuzz);
+        if !rejected.is_empty() {
+            let lines = rejected.iter().map(|hunk| hunk.old_start.to_string());
+            let lines = lines.collect::<Vec<_>>().join(", ");
+            if !reject {
+                failed.push(format!(
+                    "{}: patch does not apply at line {lines}",
+                    file.path()
+                ));
+                continue;
+            }
+            eprintln!(
+                "Rejected {} hunk(s) of {} at line {lines}",
+                rejected.len(),
+                file.path()
+            );
+            rejects.push((file.path().to_string(), file.reject(&rejected)));
+        }
+        record(&mut contents, file, new.into_bytes());
+    }
+
+    if !failed.is_empty() {
+        for failure in failed {
+            eprintln!("error: {failure}");
+        }
+        return Err(Error::Usage(
+            "the patch does not apply; nothing was changed".into(),
+        ));
+    }
+    if check {
+        return Ok(rejects.is_empty());
+    }
+
+    for (path, content) in contents {
+        match (content, cached) {
+            (Some(data), true) => {
+                let blob = Blob::from_data(data, file_service.hash_algorithm);
+                file_service.write_blob(&blob)?;
+                index.update(&path, blob.hash());
+            }
+            (None, true) => index.remove(&path),
+            (Some(data), false) => worktree.write_file(&path, &data)?,
+            (None, false) => worktree.remove_file(&path)?,
+        }
+    }
+    if cached {
+        file_service.write_index(&index)?;
+    }
+    if rejects.is_empty() {
+        return Ok(true);
+    }
+    for (path, text) in rejects {
+        worktree.write_file(&format!("{path}.rej"), text.as_bytes())?;
+    }
+    Ok(false)
+}
+
+/// Notes what `file` leaves of the file it patched, `new` under its new
+/// path and nothing under the old one.
+fn record(contents: &mut BTreeMap<String, Option<Vec<u8>>>, file: &FilePatch, new: Vec<u8>) {
+    if let Some(path) = &file.old_path {
+        contents.insert(path.clone(), None);
+    }
+    if let Some(path) = &file.new_path {
+        contents.insert(path.clone(), Some(new));
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    archive::{self, ArchiveFormat},
+    revision,
+    types::{Context, FileService, ObjectKind, Result},
+};
+
+pub fn run(
+    tree_ish: &str,
+    format: Option<ArchiveFormat>,
+    prefix: &str,
+    output: Option<&str>,
+) -> Result<()> {
+    let file_service = FileService::new()?;
+    let tree = revision::resolve_tree(&file_service, tree_ish)?;
+
+    // archives of commits record the commit and take its time
+    let target = file_service.peel(&revision::resolve_object(&file_service, tree_ish)?)?;
+    let commit = match file_service.read_raw(&target)?.0 {
+        Some(ObjectKind::Commit) => Some(file_service.read_commit_headers(&target)?),
+        _ => None,
+    };
+    let time = match commit.as_ref().and_then(|c| c.committer().or(c.author())) {
+        Some(signature) => signature.clone(),
+        None => file_service.signature("COMMITTER")?,
+    };
+    let commit = commit.is_some().then_some(target.as_str());
+
+    let format = format
+        .or_else(|| output.and_then(ArchiveFormat::from_file_name))
+        .unwrap_or(ArchiveFormat::Tar);
+    match output {
+        Some(path) => {
+            let file = fs::File::create(path).context("create", Path::new(path))?;
+            let mut out = BufWriter::new(file);
+            archive::write(
+                &file_service,
+                &tree,
+                commit,
+                &time,
+                format,
+                prefix,
+                &mut out,
+            )?;
+            out.flush().context("write", Path::new(path))?;
+        }
+        None => {
+            let mut out = BufWriter::new(io::stdout().lock());
+            archive::write(
+                &file_service,
+                &tree,
+                commit,
+                &time,
+                format,
+                prefix,
+                &mut out,
+            )?;
+            out.flush()?;
+        }
+    }
+    Ok(())
+}
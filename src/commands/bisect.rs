@@ -0,0 +1,94 @@
+use std::io::{self, Write};
+
+use crate::{
+    bisect::{self, Bisection},
+    cli::BisectAction,
+    refs::Refs,
+    revision::Abbrev,
+    types::{FileService, Result},
+};
+
+use super::log;
+
+pub fn run(action: BisectAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let bisection = match action {
+        BisectAction::Start { bad, good } => bisect::start(&file_service, bad.as_deref(), &good)?,
+        BisectAction::Mark { term, commits } => bisect::mark(&file_service, term, &commits)?,
+        BisectAction::Run { command } => {
+            let bisection = bisect::run(&file_service, &command, |term, bisection| {
+                writeln!(out, "running '{}': {}", command.join(" "), term.name())?;
+                match bisection {
+                    Bisection::Found(_) | Bisection::Skipped(_) => Ok(()),
+                    bisection => report(&file_service, bisection),
+                }
+            })?;
+            report(&file_service, &bisection)?;
+            if let Bisection::Found(_) = bisection {
+                writeln!(out, "bisect found the first bad commit")?;
+            }
+            return Ok(());
+        }
+        BisectAction::Reset => {
+            let start = bisect::reset(&file_service)?;
+            if Refs::new(&file_service).is_branch(&start) {
+                writeln!(out, "Switched to branch '{start}'")?;
+            } else {
+                let commit = file_service.read_commit(&start)?;
+                let summary = commit.message().lines().next().unwrap_or_default();
+                let short = Abbrev::new(&file_service)?.shorten(&start);
+                writeln!(out, "HEAD is now at {short} {summary}")?;
+            }
+            return Ok(());
+        }
+    };
+    report(&file_service, &bisection)
+}
+
+/// Prints where the search stands: what it waits for, the commit checked
+/// out to test or the first bad commit, or the commits it could be.
+fn report(file_service: &FileService, bisection: &Bisection) -> Result<()> {
+    let mut out = io::stdout().lock();
+    match bisection {
+        Bisection::Waiting { bad, good } => {
+            let waiting = match (bad, good) {
+                (false, false) => "both good and bad commits",
+                (true, _) => "a good commit, bad commit known",
+                (false, true) => "a bad commit, good commits known",
+            };
+            writeln!(out, "status: waiting for {waiting}")?;
+        }
+        Bisection::Testing {
+            hash,
+            remaining,
+            steps,
+        } => {
+            let commit = file_service.read_commit(hash)?;
+            let summary = commit.message().lines().next().unwrap_or_default();
+            writeln!(
+                out,
+                "Bisecting: {remaining} revisions left to test after this (roughly {steps} steps)"
+            )?;
+            writeln!(out, "[{hash}] {summary}")?;
+        }
+        Bisection::Found(hash) => {
+            let commit = file_service.read_commit(hash)?;
+            writeln!(out, "{hash} is the first bad commit")?;
+            log::print_commit(
+                &mut io::stdout(),
+                &commit,
+                &Abbrev::new(file_service)?,
+                false,
+            )?;
+        }
+        Bisection::Skipped(hashes) => {
+            writeln!(out, "There are only 'skip'ped commits left to test.")?;
+            writeln!(out, "The first bad commit could be any of:")?;
+            for hash in hashes {
+                writeln!(out, "{hash}")?;
+            }
+        }
+    }
+    Ok(())
+}
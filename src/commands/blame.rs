@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::{
+    blame,
+    diff::Whitespace,
+    revision::Abbrev,
+    types::{Error, FileService, Result, Signature},
+};
+
+/// Prints each line of `file`, or of the 1-based, inclusive range `lines`
+/// of it running to the end when that has none, with the commit, author
+/// and date that last changed it, not counting the changes in whitespace
+/// `whitespace` overlooks.
+pub fn run(
+    file: &str,
+    lines: Option<(usize, Option<usize>)>,
+    whitespace: Whitespace,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let path = file_service.repo_path(file)?;
+    let blamed = blame::blame(&file_service, &path, whitespace)?;
+
+    let (start, end) = lines.unwrap_or((1, None));
+    let end = end.unwrap_or(blamed.len()).min(blamed.len());
+    if start > blamed.len().max(1) {
+        return Err(Error::Usage(format!(
+            "file {file} has only {} lines",
+            blamed.len()
+        )));
+    }
+    let blamed = blamed.get(start - 1..end).unwrap_or_default();
+
+    let abbrev = Abbrev::new(&file_service)?;
+    let mut uncommitted = file_service.signature("AUTHOR")?;
+    uncommitted.name = "Not Committed Yet".to_string();
+    let mut authors: HashMap<&str, Signature> = HashMap::new();
+    for line in blamed {
+        if let Some(hash) = &line.commit {
+            if !authors.contains_key(hash.as_str()) {
+                let commit = file_service.read_commit(hash)?;
+                let author = commit.author().cloned().unwrap_or(Signature {
+                    name: String::new(),
+                    email: String::new(),
+                    time: 0,
+                    offset: 0,
+                });
+                authors.insert(hash, author);
+            }
+        }
+    }
+
+    let hash_width = blamed
+        .iter()
+        .filter_map(|line| line.commit.as_deref())
+        .map(|hash| abbrev.shorten(hash).len())
+        .max()
+        .unwrap_or(7);
+    let author = |commit: &Option<String>| match commit {
+        Some(hash) => &authors[hash.as_str()],
+        None => &uncommitted,
+    };
+    let name_width = blamed
+        .iter()
+        .map(|line| author(&line.commit).name.chars().count())
+        .max()
+        .unwrap_or_default();
+    let number_width = end.to_string().len();
+
+    for (i, line) in blamed.iter().enumerate() {
+        let short = match &line.commit {
+            Some(hash) => abbrev.shorten(hash).to_string(),
+            None => "0".repeat(hash_width),
+        };
+        let signature = author(&line.commit);
+        writeln!(
+            out,
+            "{short:<hash_width$} ({:<name_width$} {} {:>number_width$}) {}",
+            signature.name,
+            signature.iso_date(),
+            start + i,
+            line.text,
+        )?;
+    }
+    Ok(())
+}
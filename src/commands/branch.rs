@@ -0,0 +1,126 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::BranchAction,
+    refs::Refs,
+    remote::Upstream,
+    revision::Abbrev,
+    types::{Error, FileService, Result},
+    worktrees,
+};
+
+pub fn run(action: BranchAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let refs = Refs::new(&file_service);
+
+    match action {
+        BranchAction::List { verbose } => {
+            let current = refs.current_branch(&file_service)?;
+            let branches = refs.branches()?;
+            let width = branches
+                .iter()
+                .map(|(name, _)| name.chars().count())
+                .max()
+                .unwrap_or_default();
+            let abbrev = Abbrev::new(&file_service)?;
+            for (name, hash) in branches {
+                let marker = if current.as_deref() == Some(name.as_str()) {
+                    '*'
+                } else {
+                    ' '
+                };
+                if verbose == 0 {
+                    writeln!(out, "{marker} {name}")?;
+                    continue;
+                }
+                let commit = file_service.read_commit(&hash)?;
+                let summary = commit.message().lines().next().unwrap_or_default();
+                let tracking = match Upstream::load(&file_service, &name)? {
+                    Some(upstream) => tracking(&file_service, &upstream, &hash, verbose > 1)?,
+                    None => None,
+                };
+                let tracking = tracking.map(|tracking| format!("[{tracking}] "));
+                writeln!(
+                    out,
+                    "{marker} {name:<width$} {} {}{summary}",
+                    abbrev.shorten(&hash),
+                    tracking.unwrap_or_default()
+                )?;
+            }
+        }
+        BranchAction::Create { name, start } => {
+            let hash = file_service.resolve(start.as_deref().unwrap_or("HEAD"))?;
+            refs.create_branch(&name, &hash)?;
+        }
+        BranchAction::SetUpstream { name, upstream } => {
+            let name = match name {
+                Some(name) if refs.is_branch(&name) => name,
+                Some(name) => return Err(Error::Usage(format!("branch '{name}' does not exist"))),
+                None => refs
+                    .current_branch(&file_service)?
+                    .ok_or_else(|| Error::Usage("HEAD is not on a branch".into()))?,
+            };
+            match upstream {
+                Some(upstream) => {
+                    let upstream = Upstream::parse(&file_service, &upstream)?;
+                    upstream.set(&file_service, &name)?;
+                    writeln!(
+                        out,
+                        "branch '{name}' set up to track '{}/{}'.",
+                        upstream.remote,
+                        upstream
+                            .merge
+                            .strip_prefix("refs/heads/")
+                            .unwrap_or(&upstream.merge)
+                    )?;
+                }
+                None => Upstream::unset(&file_service, &name)?,
+            }
+        }
+        BranchAction::Delete { names } => {
+            let current = refs.current_branch(&file_service)?;
+            for name in names {
+                if current.as_deref() == Some(name.as_str()) {
+                    return Err(Error::Usage(format!(
+                        "cannot delete branch '{name}' as it is checked out"
+                    )));
+                }
+                worktrees::check_not_checked_out(&file_service, &Refs::branch_ref(&name))?;
+                let hash = refs.read(&Refs::branch_ref(&name)).unwrap_or_default();
+                refs.delete_branch(&name)?;
+                writeln!(
+                    out,
+                    "Deleted branch {name} (was {}).",
+                    &hash[..hash.len().min(7)]
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How the branch at `hash` compares to its upstream, naming the upstream
+/// when `named`; nothing for a branch up to date with an unnamed one.
+fn tracking(
+    file_service: &FileService,
+    upstream: &Upstream,
+    hash: &str,
+    named: bool,
+) -> Result<Option<String>> {
+    let counts = match upstream.ahead_behind(file_service, hash)? {
+        None => Some("gone".to_string()),
+        Some((0, 0)) => None,
+        Some((ahead, 0)) => Some(format!("ahead {ahead}")),
+        Some((0, behind)) => Some(format!("behind {behind}")),
+        Some((ahead, behind)) => Some(format!("ahead {ahead}, behind {behind}")),
+    };
+    if !named {
+        return Ok(counts);
+    }
+    let name = upstream.short_name(file_service)?;
+    Ok(Some(match counts {
+        Some(counts) => format!("{name}: {counts}"),
+        None => name,
+    }))
+}
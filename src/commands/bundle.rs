@@ -0,0 +1,56 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    bundle::Bundle,
+    cli::BundleAction,
+    types::{FileService, Result},
+};
+
+pub fn run(action: BundleAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    match action {
+        BundleAction::Create { file, revisions } => {
+            Bundle::create(&file_service, &revisions)?.write(Path::new(&file))?;
+        }
+        BundleAction::Verify { file } => {
+            let bundle = Bundle::read(Path::new(&file))?;
+            writeln!(out, "The bundle contains {} ref(s):", bundle.refs.len())?;
+            for (name, hash) in bundle.refs.iter() {
+                writeln!(out, "{hash} {name}")?;
+            }
+            if bundle.prerequisites.is_empty() {
+                writeln!(out, "The bundle records a complete history.")?;
+            } else {
+                writeln!(
+                    out,
+                    "The bundle requires {} commit(s):",
+                    bundle.prerequisites.len()
+                )?;
+                for (hash, subject) in bundle.prerequisites.iter() {
+                    writeln!(out, "{hash} {subject}")?;
+                }
+            }
+            let missing = bundle.missing(&file_service)?;
+            if !missing.is_empty() {
+                eprintln!("error: the repository lacks these prerequisite commits:");
+                for (hash, subject) in missing {
+                    eprintln!("error: {hash} {subject}");
+                }
+                std::process::exit(1);
+            }
+            writeln!(out, "{file} is okay")?;
+        }
+        BundleAction::Unbundle { file } => {
+            let bundle = Bundle::read(Path::new(&file))?;
+            bundle.unbundle(&file_service)?;
+            for (name, hash) in bundle.refs.iter() {
+                writeln!(out, "{hash} {name}")?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use std::io::{self, Write};
+
+use crate::{
+    types::{FileService, Result},
+    worktree::WorkTree,
+};
+
+pub fn run(attributes: &[String], all: bool, paths: &[String]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let worktree = WorkTree::new(&file_service);
+    let loaded = worktree.attributes()?;
+
+    for path in paths {
+        let repo_path = file_service.repo_path(path)?;
+        if all {
+            for (name, state) in loaded.get(&repo_path) {
+                writeln!(out, "{path}: {name}: {}", state.describe())?;
+            }
+            continue;
+        }
+        for name in attributes {
+            writeln!(
+                out,
+                "{path}: {name}: {}",
+                loaded.state(&repo_path, name).describe()
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+use crate::{
+    refs::Refs,
+    revision::Abbrev,
+    types::{Error, FileService, Result},
+    worktree::WorkTree,
+    worktrees,
+};
+
+pub fn run(target: &str) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let hash = file_service.resolve(target)?;
+    let is_branch = Refs::new(&file_service).is_branch(target);
+    if is_branch {
+        worktrees::check_not_checked_out(&file_service, &Refs::branch_ref(target))?;
+    }
+
+    let commit = file_service.read_commit(&hash)?;
+    let head = file_service.read_head_commit()?;
+    let mut index = file_service.lock_index()?;
+    let worktree = WorkTree::new(&file_service);
+    let status = worktree.status(&index, head.as_ref())?;
+
+    // uncommitted changes and untracked files the target would overwrite
+    let mut blocked: BTreeSet<String> = status
+        .staged
+        .iter()
+        .chain(status.unstaged.iter())
+        .map(|(_, path)| path.clone())
+        .collect();
+    blocked.extend(
+        status
+            .untracked
+            .into_iter()
+            .filter(|path| commit.files().contains_key(path)),
+    );
+    if !blocked.is_empty() {
+        return Err(Error::DirtyWorkTree(blocked.into_iter().collect()));
+    }
+
+    let empty = BTreeMap::new();
+    let current = head.as_ref().map_or(&empty, |head| head.files());
+    worktree.checkout(&file_service, current, commit.files(), commit.modes())?;
+
+    file_service.set_head(is_branch.then_some(target), &hash)?;
+    index.clear()?;
+
+    if is_branch {
+        writeln!(out, "Switched to branch '{target}'")?;
+    } else {
+        let summary = commit.message().lines().next().unwrap_or_default();
+        let short = Abbrev::new(&file_service)?.shorten(&hash);
+        writeln!(out, "HEAD is now at {short} {summary}")?;
+    }
+    Ok(())
+}
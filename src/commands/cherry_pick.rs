@@ -0,0 +1,57 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::{Resume, SequenceAction},
+    revision::Abbrev,
+    sequencer::{self, Action, Operation, Outcome, Step},
+    types::{Error, FileService, Result},
+};
+
+use super::rebase::report_stop;
+
+pub fn run(action: SequenceAction) -> Result<()> {
+    sequence(Operation::CherryPick, action)
+}
+
+/// Carries out `operation`, a cherry-pick or a revert, which differ only
+/// in what their steps do with each commit.
+pub(super) fn sequence(operation: Operation, action: SequenceAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let outcome = match action {
+        SequenceAction::Start { commits } => {
+            let head = sequencer::prepare(&file_service, operation)?;
+            let action = match operation {
+                Operation::Revert => Action::Revert,
+                _ => Action::Pick,
+            };
+            let mut todo = Vec::new();
+            for commit in commits {
+                let hash = file_service.resolve(&commit)?;
+                if file_service.read_commit(&hash)?.parents().len() > 1 {
+                    return Err(Error::Usage(format!(
+                        "commit {hash} is a merge, which cannot be {}ed",
+                        action.name()
+                    )));
+                }
+                todo.push(Step { action, hash });
+            }
+            let onto = file_service.read_commit(head.hash().unwrap_or_default())?;
+            sequencer::start(&file_service, operation, &head, todo, onto)?
+        }
+        SequenceAction::Resume(Resume::Continue) => sequencer::resume(&file_service, operation)?,
+        SequenceAction::Resume(Resume::Skip) => sequencer::skip(&file_service, operation)?,
+        SequenceAction::Resume(Resume::Abort) => return sequencer::abort(&file_service, operation),
+    };
+
+    let Outcome::Done(commits) = outcome else {
+        return report_stop(&file_service, operation, &outcome);
+    };
+    let abbrev = Abbrev::new(&file_service)?;
+    for hash in commits {
+        let commit = file_service.read_commit(&hash)?;
+        let summary = commit.message().lines().next().unwrap_or_default();
+        writeln!(out, "[{}] {summary}", abbrev.shorten(&hash))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+use crate::{
+    types::{FileService, Result},
+    worktree::WorkTree,
+};
+
+pub fn run(force: bool, directories: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let worktree = WorkTree::new(&file_service);
+    let index = file_service.read_index()?;
+    let head = file_service.read_head_commit()?;
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+    let untracked = worktree.status(&index, head.as_ref())?.untracked;
+
+    // directories holding at least one tracked file are never removed whole
+    let mut occupied = BTreeSet::new();
+    for path in tracked.keys() {
+        occupied.extend(parents(path));
+    }
+
+    // each untracked file is removed on its own, or as part of the
+    // outermost directory above it that holds nothing tracked
+    let mut targets: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for path in untracked {
+        match parents(&path).find(|dir| !occupied.contains(*dir)) {
+            Some(dir) if directories => targets
+                .entry(format!("{dir}/"))
+                .or_default()
+                .push(path.clone()),
+            Some(_) => {}
+            None => targets.entry(path.clone()).or_default().push(path.clone()),
+        }
+    }
+
+    for (target, files) in targets {
+        if !force {
+            writeln!(out, "Would remove {target}")?;
+            continue;
+        }
+        for file in files {
+            worktree.remove_file(&file)?;
+        }
+        writeln!(out, "Removing {target}")?;
+    }
+    Ok(())
+}
+
+/// The directories leading down to `path`, outermost first.
+fn parents(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/').map(move |(end, _)| &path[..end])
+}
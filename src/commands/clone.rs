@@ -0,0 +1,29 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    remote,
+    types::{Error, Result},
+};
+
+pub fn run(source: &str, destination: Option<&str>, depth: Option<usize>) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let destination = match destination {
+        Some(destination) => destination,
+        None => Path::new(source.trim_end_matches('/'))
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| Error::Usage(format!("cannot guess a directory for '{source}'")))?,
+    };
+    writeln!(out, "Cloning into '{destination}'...")?;
+    let cloned = remote::clone(source, Path::new(destination), depth)?;
+    if cloned.empty {
+        writeln!(
+            out,
+            "warning: You appear to have cloned an empty repository."
+        )?;
+    }
+    Ok(())
+}
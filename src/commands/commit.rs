@@ -0,0 +1,66 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    cli::{AddMode, CommitMessage},
+    repository::Repository,
+    revision::Abbrev,
+    trailer,
+    types::{Context, Result},
+};
+
+pub fn run(
+    message: CommitMessage,
+    amend: bool,
+    all: bool,
+    signoff: bool,
+    mut trailers: Vec<(String, String)>,
+    no_verify: bool,
+    sign: bool,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let repository = Repository::open(".")?;
+    let msg = match message {
+        CommitMessage::Given(msg) => Some(msg),
+        CommitMessage::File(path) if path == "-" => {
+            let mut msg = String::new();
+            io::stdin().read_to_string(&mut msg)?;
+            Some(msg)
+        }
+        CommitMessage::File(path) => {
+            Some(fs::read_to_string(&path).context("read", Path::new(&path))?)
+        }
+        CommitMessage::Edit => None,
+    };
+    if all {
+        repository.add(&[], AddMode::Update, false, None)?;
+    }
+    if signoff {
+        let committer = repository.file_service().signature("COMMITTER")?;
+        let value = format!("{} <{}>", committer.name, committer.email);
+        trailers.push(("Signed-off-by".to_string(), value));
+    }
+    let commit = match msg {
+        None => repository.commit_edited(amend, &trailers, no_verify, sign)?,
+        Some(msg) => {
+            let msg = trailer::append(&msg, &trailers);
+            if amend {
+                repository.amend(Some(&msg), no_verify, sign)?
+            } else {
+                repository.commit(&msg, no_verify, sign)?
+            }
+        }
+    };
+
+    if let Some(hash) = commit.hash() {
+        let summary = commit.message().lines().next().unwrap_or_default();
+        let short = Abbrev::new(repository.file_service())?
+            .shorten(hash)
+            .to_string();
+        writeln!(out, "[{short}] {summary}")?;
+    }
+    Ok(())
+}
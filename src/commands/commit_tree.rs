@@ -0,0 +1,33 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    revision,
+    types::{Commit, FileService, Result},
+};
+
+pub fn run(tree: &str, parents: &[String], message: Option<&str>) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let tree = revision::resolve_tree(&file_service, tree)?;
+
+    let message = match message {
+        Some(message) => message.to_string(),
+        None => {
+            let mut message = String::new();
+            io::stdin().read_to_string(&mut message)?;
+            message
+        }
+    };
+    let mut commit = Commit::new(None, &message);
+    for parent in parents {
+        commit.add_parent(&file_service.resolve(parent)?);
+    }
+    commit.set_author(file_service.signature("AUTHOR")?);
+    commit.set_committer(file_service.signature("COMMITTER")?);
+    writeln!(
+        out,
+        "{}",
+        file_service.write_commit_tree(&mut commit, &tree)?
+    )?;
+    Ok(())
+}
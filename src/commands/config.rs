@@ -0,0 +1,52 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::ConfigAction,
+    config::{Config, ConfigFile, ConfigScope},
+    types::{Error, FileService, Result},
+};
+
+pub fn run(action: ConfigAction, scope: Option<ConfigScope>) -> Result<()> {
+    let mut out = io::stdout().lock();
+    // global and system config can be used outside of any repository
+    let file_service = FileService::new().ok();
+    let blip_dir = file_service.as_ref().map(|fs| fs.common_dir.as_path());
+
+    let config = match scope {
+        Some(scope) => Config::scoped(scope, blip_dir)?,
+        None => Config::load(blip_dir)?,
+    };
+    // writes go to the repository config unless told otherwise
+    let writable = || -> Result<ConfigFile> {
+        let path = scope
+            .unwrap_or(ConfigScope::Local)
+            .path(blip_dir)
+            .ok_or(Error::NoDirectory)?;
+        ConfigFile::load(path)
+    };
+
+    match action {
+        ConfigAction::Get { name } => match config.get(&name) {
+            Some(value) => writeln!(out, "{value}")?,
+            None => return Err(Error::Usage(format!("'{name}' is not set"))),
+        },
+        ConfigAction::Set { name, value } => {
+            let mut file = writable()?;
+            file.set(&name, &value)?;
+            file.save()?;
+        }
+        ConfigAction::Unset { name } => {
+            let mut file = writable()?;
+            if !file.unset(&name)? {
+                return Err(Error::Usage(format!("'{name}' is not set")));
+            }
+            file.save()?;
+        }
+        ConfigAction::List => {
+            for (_, name, value) in config.list() {
+                writeln!(out, "{name}={value}")?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+
+use crate::{
+    diff::{DiffOptions, RenameDetection, Whitespace},
+    pathspec::Pathspec,
+    repository::Repository,
+    types::Result,
+};
+
+pub fn run(
+    commits: &[String],
+    cached: bool,
+    pathspecs: &[String],
+    renames: Option<Option<RenameDetection>>,
+    name_status: bool,
+    binary: bool,
+    whitespace: Whitespace,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let repository = Repository::open(".")?;
+    let pathspecs = pathspecs
+        .iter()
+        .map(|spec| Pathspec::from_cwd(repository.file_service(), spec))
+        .collect::<Result<Vec<_>>>()?;
+    let renames = match renames {
+        Some(renames) => renames,
+        None => RenameDetection::from_config(repository.file_service().config()?)?,
+    };
+
+    if name_status {
+        for (status, path) in repository.diff_names(commits, cached, &pathspecs, renames)? {
+            writeln!(out, "{status}\t{path}")?;
+        }
+        return Ok(());
+    }
+    let options = DiffOptions {
+        renames,
+        binary,
+        whitespace,
+    };
+    write!(
+        out,
+        "{}",
+        repository.diff(commits, cached, &pathspecs, options)?
+    )?;
+    Ok(())
+}
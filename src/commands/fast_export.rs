@@ -0,0 +1,14 @@
+use std::io::{self, BufWriter, Write};
+
+use crate::{
+    fast_export,
+    types::{FileService, Result},
+};
+
+pub fn run(refs: &[String]) -> Result<()> {
+    let file_service = FileService::new()?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    fast_export::export(&file_service, refs, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
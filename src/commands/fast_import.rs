@@ -0,0 +1,19 @@
+use std::io::{self, BufReader, Write};
+
+use crate::{
+    fast_import,
+    types::{FileService, Result},
+};
+
+pub fn run() -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let mut input = BufReader::new(io::stdin().lock());
+    let stats = fast_import::import(&file_service, &mut input, &mut io::stdout())?;
+    writeln!(
+        out,
+        "Imported {} blob(s), {} commit(s) and {} tag(s); updated {} ref(s)",
+        stats.blobs, stats.commits, stats.tags, stats.refs
+    )?;
+    Ok(())
+}
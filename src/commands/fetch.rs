@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+
+use crate::{
+    refs::Refs,
+    remote::{self, Depth, RefUpdate, Remote, UpdateStatus, Upstream, DEFAULT_REMOTE},
+    revision::Abbrev,
+    types::{Error, FileService, Result},
+};
+
+pub fn run(name: Option<&str>, deepen: Option<usize>) -> Result<()> {
+    let file_service = FileService::new()?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => upstream_remote(&file_service)?,
+    };
+    let Some(remote) = Remote::load(&file_service, &name)? else {
+        return Err(Error::Usage(format!(
+            "'{name}' does not appear to be a remote"
+        )));
+    };
+
+    let depth = deepen.map_or(Depth::Full, Depth::Deepen);
+    let updates = remote::fetch(&file_service, &remote, depth)?;
+    if !report(&file_service, &remote, &updates)? {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Prints the refs a fetch changed, returning whether none were rejected.
+pub(super) fn report(
+    file_service: &FileService,
+    remote: &Remote,
+    updates: &[RefUpdate],
+) -> Result<bool> {
+    let mut out = io::stdout().lock();
+    let changed: Vec<_> = updates
+        .iter()
+        .filter(|update| update.status != UpdateStatus::UpToDate)
+        .collect();
+    if changed.is_empty() {
+        return Ok(true);
+    }
+
+    writeln!(out, "From {}", remote.url)?;
+    let abbrev = Abbrev::new(file_service)?;
+    for update in changed.iter() {
+        let from = short_name(&update.remote);
+        let to = short_name(&update.local);
+        let new = abbrev.shorten(&update.new);
+        let old = update.old.as_deref().map(|old| abbrev.shorten(old));
+        match (update.status, old) {
+            (UpdateStatus::FastForward, Some(old)) => {
+                writeln!(out, "   {old}..{new}  {from} -> {to}")?
+            }
+            (UpdateStatus::Forced, Some(old)) => {
+                writeln!(out, " + {old}...{new} {from} -> {to}  (forced update)")?
+            }
+            (UpdateStatus::Rejected, _) => writeln!(
+                out,
+                " ! [rejected]        {from} -> {to}  (non-fast-forward)"
+            )?,
+            _ if update.remote.starts_with("refs/tags/") => {
+                writeln!(out, " * [new tag]         {from} -> {to}")?
+            }
+            _ => writeln!(out, " * [new branch]      {from} -> {to}")?,
+        }
+    }
+    Ok(changed
+        .iter()
+        .all(|update| update.status != UpdateStatus::Rejected))
+}
+
+/// The remote the current branch tracks, or `origin`.
+pub(super) fn upstream_remote(file_service: &FileService) -> Result<String> {
+    let upstream = match Refs::new(file_service).current_branch(file_service)? {
+        Some(branch) => Upstream::load(file_service, &branch)?,
+        None => None,
+    };
+    Ok(upstream.map_or_else(|| DEFAULT_REMOTE.to_string(), |upstream| upstream.remote))
+}
+
+/// A ref name without its `refs/heads/`, `refs/tags/` or `refs/remotes/`
+/// prefix.
+pub(super) fn short_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .into_iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
@@ -0,0 +1,62 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    mail::{self, Email},
+    repository::Repository,
+    types::{Context, Result},
+};
+
+/// Longest file name given to a patch, before its extension.
+const MAX_NAME: usize = 64;
+
+pub fn run(range: &str, output_directory: Option<&str>, stdout: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let repository = Repository::open(".")?;
+    let commits = mail::commits(repository.file_service(), range)?;
+    if let (Some(directory), false) = (output_directory, stdout) {
+        fs::create_dir_all(directory).context("create", directory.as_ref())?;
+    }
+
+    for (i, commit) in commits.iter().enumerate() {
+        let email = Email::from_commit(&repository, commit)?;
+        let text = email.format(commit.hash().unwrap_or_default(), i + 1, commits.len());
+        if stdout {
+            write!(out, "{text}")?;
+            continue;
+        }
+        let name = file_name(i + 1, &email.subject);
+        let path = match output_directory {
+            Some(directory) => PathBuf::from(directory).join(name),
+            None => PathBuf::from(name),
+        };
+        fs::write(&path, text).context("write", &path)?;
+        writeln!(out, "{}", path.display())?;
+    }
+    Ok(())
+}
+
+/// Names a patch after its number and subject, e.g. `0001-Fix-the-build.patch`.
+fn file_name(number: usize, subject: &str) -> String {
+    let mut name = format!("{number:04}-");
+    let mut dash = false;
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            if dash && !name.ends_with('-') {
+                name.push('-');
+            }
+            name.push(c);
+            dash = false;
+        } else {
+            dash = true;
+        }
+        if name.len() >= 5 + MAX_NAME {
+            break;
+        }
+    }
+    let name = name.trim_end_matches(['-', '.']);
+    format!("{name}.patch")
+}
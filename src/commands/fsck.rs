@@ -0,0 +1,25 @@
+use std::io::{self, Write};
+
+use crate::{
+    fsck,
+    types::{FileService, Result},
+};
+
+pub fn run() -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let report = fsck::fsck(&file_service)?;
+
+    for problem in report.problems.iter() {
+        eprintln!("error: {problem}");
+    }
+    for (hash, kind) in report.dangling.iter() {
+        let kind = kind.map_or("object", |kind| kind.as_str());
+        writeln!(out, "dangling {kind} {hash}")?;
+    }
+
+    if !report.problems.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
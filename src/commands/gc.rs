@@ -0,0 +1,29 @@
+use std::io::{self, Write};
+
+use crate::{
+    gc::{self, Expiry},
+    types::{FileService, Result},
+};
+
+pub fn run(prune: Option<Expiry>) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let stats = gc::gc(&file_service, prune)?;
+    if stats.expired > 0 {
+        writeln!(out, "Expired {} reflog entries", stats.expired)?;
+    }
+    if stats.refs > 0 {
+        writeln!(out, "Packed {} refs", stats.refs)?;
+    }
+    if let Some(pack) = stats.pack {
+        writeln!(
+            out,
+            "Packed {} objects ({} as deltas) into {}",
+            pack.objects, pack.deltas, pack.name
+        )?;
+    }
+    if stats.pruned > 0 {
+        writeln!(out, "Pruned {} unreachable objects", stats.pruned)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use std::io::{self, Write};
+
+use regex::Regex;
+
+use crate::{
+    repository::Repository,
+    types::{Error, Result},
+};
+
+pub fn run(pattern: &str, commit: Option<&str>, line_number: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let repository = Repository::open(".")?;
+    let pattern =
+        Regex::new(pattern).map_err(|_| Error::Usage(format!("invalid pattern '{pattern}'")))?;
+
+    // lines of a commit's files are prefixed with the commit as it was named
+    let prefix = commit
+        .map(|commit| format!("{commit}:"))
+        .unwrap_or_default();
+    for found in repository.grep(&pattern, commit)? {
+        if line_number {
+            writeln!(
+                out,
+                "{prefix}{}:{}:{}",
+                found.path, found.number, found.line
+            )?;
+        } else {
+            writeln!(out, "{prefix}{}:{}", found.path, found.line)?;
+        }
+    }
+    Ok(())
+}
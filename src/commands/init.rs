@@ -0,0 +1,22 @@
+use std::io::{self, Write};
+
+use crate::{hash::HashAlgorithm, repository::Repository, types::Result};
+
+pub fn run(
+    path: &str,
+    branch: Option<&str>,
+    object_format: Option<HashAlgorithm>,
+    bare: bool,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let repository = match bare {
+        true => Repository::init_bare(path, branch, object_format)?,
+        false => Repository::init(path, branch, object_format)?,
+    };
+    writeln!(
+        out,
+        "Initialized empty Blip repository in {}",
+        repository.file_service().blip_dir.display()
+    )?;
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    cli::{LogFormat, Pretty},
+    diff::{self, RenameDetection},
+    graph::Graph,
+    history::{CommitFilter, CommitWalk},
+    pathspec::Pathspec,
+    repository::Repository,
+    revision::Abbrev,
+    trailer,
+    types::{Commit, Error, FileService, Result},
+};
+
+pub fn run(
+    revisions: &[String],
+    pathspecs: &[String],
+    separated: bool,
+    filter: &CommitFilter,
+    max_count: Option<usize>,
+    format: &LogFormat,
+) -> Result<()> {
+    let repository = Repository::open(".")?;
+    let file_service = repository.file_service();
+    let (include, exclude, pathspecs) =
+        split_arguments(file_service, revisions, pathspecs, separated)?;
+    let mut walk = match include.is_empty() {
+        true => repository.log()?,
+        false => CommitWalk::new(file_service, None),
+    };
+    for hash in include {
+        walk.push(hash);
+    }
+    for hash in exclude {
+        walk.hide(&hash)?;
+    }
+    let pathspecs = pathspecs
+        .iter()
+        .map(|spec| Pathspec::from_cwd(repository.file_service(), spec))
+        .collect::<Result<Vec<_>>>()?;
+    let abbrev = Abbrev::new(repository.file_service())?;
+    let renames = RenameDetection::from_config(repository.file_service().config()?)?;
+
+    // one-line formats are neither separated nor set apart from the changes
+    let one_line = matches!(format.pretty, Pretty::Oneline | Pretty::Format(_));
+    let mut out = io::stdout().lock();
+    let mut drawn = format.graph.then(Graph::new);
+    let commits = repository.log_matching(walk, &pathspecs, filter)?;
+    for (i, commit) in commits.take(max_count.unwrap_or(usize::MAX)).enumerate() {
+        let commit = commit?;
+        let mut text = format_commit(&commit, &abbrev, format.abbrev_commit, &format.pretty);
+        // what the commit changed, against its first parent
+        let mut changes = Vec::new();
+        if let Some(stat) = format.stat {
+            changes.push(diff::stat(&repository.commit_stat(&commit, renames)?, stat));
+        }
+        if format.patch {
+            changes.push(repository.commit_patch(&commit, renames)?);
+        }
+        for (j, change) in changes
+            .iter()
+            .filter(|change| !change.is_empty())
+            .enumerate()
+        {
+            if j > 0 || !one_line {
+                text.push('\n');
+            }
+            text.push_str(change);
+        }
+        match drawn {
+            Some(ref mut graph) => {
+                if i > 0 && !one_line {
+                    writeln!(out, "{}", graph.padding())?;
+                }
+                let hash = commit.hash().unwrap_or_default();
+                let parents = match commit.parents() {
+                    [first, ..] if filter.first_parent => std::slice::from_ref(first),
+                    parents => parents,
+                };
+                write!(out, "{}", graph.draw(hash, parents, &text))?;
+            }
+            None => {
+                if i > 0 && !one_line {
+                    writeln!(out)?;
+                }
+                write!(out, "{text}")?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Sorts the arguments of `log` into the commits to start from, those to
+/// leave out with their ancestors and the pathspecs. Without a `--` the
+/// arguments from the first that names no revision on are pathspecs, and
+/// one that names both a revision and a file is refused.
+fn split_arguments(
+    file_service: &FileService,
+    revisions: &[String],
+    pathspecs: &[String],
+    separated: bool,
+) -> Result<(Vec<String>, Vec<String>, Vec<String>)> {
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    for (i, arg) in revisions.iter().enumerate() {
+        // each name with whether what it reaches is left out, HEAD for the
+        // missing side of a range
+        let names = match (arg.split_once(".."), arg.strip_prefix('^')) {
+            (Some((since, until)), _) => vec![(since, true), (until, false)],
+            (None, Some(name)) => vec![(name, true)],
+            (None, None) => vec![(arg.as_str(), false)],
+        };
+        let mut resolved = Vec::new();
+        for (name, hidden) in names {
+            let name = if name.is_empty() { "HEAD" } else { name };
+            match file_service.resolve(name) {
+                Ok(hash) => resolved.push((hash, hidden)),
+                Err(Error::UnknownRevision(_)) if !separated => {
+                    return Ok((include, exclude, revisions[i..].to_vec()));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        if !separated && Path::new(arg).exists() {
+            return Err(Error::Usage(format!(
+                "ambiguous argument '{arg}': both a revision and a file, use '--' to separate them"
+            )));
+        }
+        for (hash, hidden) in resolved {
+            match hidden {
+                true => exclude.push(hash),
+                false => include.push(hash),
+            }
+        }
+    }
+    Ok((include, exclude, pathspecs.to_vec()))
+}
+
+/// Writes a commit's hash, parents, author and indented message to `out`,
+/// the way `log` and `show` do.
+pub(super) fn print_commit(
+    out: &mut impl Write,
+    commit: &Commit,
+    abbrev: &Abbrev,
+    abbrev_commit: bool,
+) -> io::Result<()> {
+    let text = format_commit(commit, abbrev, abbrev_commit, &Pretty::Medium);
+    write!(out, "{text}")
+}
+
+fn format_commit(commit: &Commit, abbrev: &Abbrev, abbrev_commit: bool, pretty: &Pretty) -> String {
+    let show = |hash: &str| {
+        if abbrev_commit {
+            abbrev.shorten(hash).to_string()
+        } else {
+            hash.to_string()
+        }
+    };
+    let hash = show(commit.hash().unwrap_or_default());
+    let subject = commit.message().lines().next().unwrap_or_default();
+    match pretty {
+        Pretty::Oneline => return format!("{hash} {subject}\n"),
+        Pretty::Format(format) => return format!("{}\n", expand(format, commit, abbrev)),
+        Pretty::Short | Pretty::Medium | Pretty::Full => {}
+    }
+
+    let mut out = format!("commit {hash}\n");
+    match commit.parents() {
+        [] => {}
+        [parent] => out.push_str(&format!("Parent: {}\n", show(parent))),
+        parents => {
+            let short: Vec<&str> = parents
+                .iter()
+                .map(|parent| abbrev.shorten(parent))
+                .collect();
+            out.push_str(&format!("Merge:  {}\n", short.join(" ")));
+        }
+    }
+    if let Some(author) = commit.author() {
+        out.push_str(&format!("Author: {} <{}>\n", author.name, author.email));
+        if *pretty == Pretty::Medium {
+            out.push_str(&format!("Date:   {}\n", author.date()));
+        }
+    }
+    if let (Pretty::Full, Some(committer)) = (pretty, commit.committer()) {
+        out.push_str(&format!(
+            "Commit: {} <{}>\n",
+            committer.name, committer.email
+        ));
+    }
+    out.push('\n');
+    if *pretty == Pretty::Short {
+        out.push_str(&format!("    {subject}\n"));
+        return out;
+    }
+    for line in commit.message().lines() {
+        out.push_str(&format!("    {line}\n"));
+    }
+    out
+}
+
+/// Fills in the `%` placeholders of a `--format` string for `commit`,
+/// leaving those it does not know as they are:
+///
+/// - `%H` and `%h`: the hash, whole and abbreviated; `%T` and `%t`, `%P`
+///   and `%p` likewise for the tree and the parents
+/// - `%an`, `%ae`, `%ad`, `%ai` and `%at`: the author's name, email and
+///   date, as `log` shows it, ISO-like or in seconds since the epoch; `%cn`
+///   and so on for the committer
+/// - `%s`, `%b` and `%B`: the subject, the body after it and the whole
+///   message
+/// - `%(trailers)`: the trailers ending the message, see [`trailer`]
+/// - `%n` and `%%`: a newline and a `%`
+fn expand(format: &str, commit: &Commit, abbrev: &Abbrev) -> String {
+    let mut out = String::new();
+    let mut rest = format;
+    while let Some(at) = rest.find('%') {
+        out.push_str(&rest[..at]);
+        rest = &rest[at + 1..];
+        match placeholder(rest, commit, abbrev) {
+            Some((value, used)) => {
+                out.push_str(&value);
+                rest = &rest[used..];
+            }
+            None => out.push('%'),
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The value of the placeholder `spec` starts with, and its length.
+fn placeholder(spec: &str, commit: &Commit, abbrev: &Abbrev) -> Option<(String, usize)> {
+    const TRAILERS: &str = "(trailers)";
+    if spec.starts_with(TRAILERS) {
+        let trailers = trailer::parse(commit.message())
+            .into_iter()
+            .map(|(key, value)| format!("{key}: {value}\n"))
+            .collect();
+        return Some((trailers, TRAILERS.len()));
+    }
+
+    let hash = commit.hash().unwrap_or_default();
+    let tree = commit.tree().unwrap_or_default();
+    let message = commit.message();
+    let mut chars = spec.chars();
+    let value = match chars.next()? {
+        '%' => "%".to_string(),
+        'n' => "\n".to_string(),
+        'H' => hash.to_string(),
+        'h' => abbrev.shorten(hash).to_string(),
+        'T' => tree.to_string(),
+        't' => abbrev.shorten(tree).to_string(),
+        'P' => commit.parents().join(" "),
+        'p' => {
+            let short: Vec<&str> = commit
+                .parents()
+                .iter()
+                .map(|parent| abbrev.shorten(parent))
+                .collect();
+            short.join(" ")
+        }
+        's' => message.lines().next().unwrap_or_default().to_string(),
+        'b' => message
+            .split_once('\n')
+            .map_or("", |(_, body)| body.trim_start_matches('\n'))
+            .to_string(),
+        'B' => message.to_string(),
+        role @ ('a' | 'c') => {
+            let signature = if role == 'a' {
+                commit.author()
+            } else {
+                commit.committer()
+            };
+            let field = chars.next().filter(|field| "nedit".contains(*field))?;
+            let value = signature.map_or_else(String::new, |signature| match field {
+                'n' => signature.name.clone(),
+                'e' => signature.email.clone(),
+                'd' => signature.date(),
+                'i' => signature.iso_date(),
+                _ => signature.time.to_string(),
+            });
+            return Some((value, 2));
+        }
+        _ => return None,
+    };
+    Some((value, 1))
+}
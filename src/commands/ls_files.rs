@@ -0,0 +1,39 @@
+use std::io::{self, Write};
+
+use crate::{
+    pathspec::Pathspec,
+    types::{FileService, Result},
+    worktree::WorkTree,
+};
+
+pub fn run(pathspecs: &[String], stage: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let pathspecs = pathspecs
+        .iter()
+        .map(|spec| Pathspec::from_cwd(&file_service, spec))
+        .collect::<Result<Vec<_>>>()?;
+    let pathspec = Pathspec::new(&pathspecs)?;
+    let index = file_service.read_index()?;
+    let head = file_service.read_head_commit()?;
+
+    let modes = WorkTree::tracked_modes(&index, head.as_ref());
+    for (path, hash) in WorkTree::tracked_files(&index, head.as_ref()) {
+        if !pathspec.matches(&path) {
+            continue;
+        }
+        if stage {
+            // blip keeps one conflict-marked version of an unmerged file
+            let stage = if index.conflicts().contains(&path) {
+                1
+            } else {
+                0
+            };
+            let mode = modes.get(&path).copied().unwrap_or_default().git_mode();
+            writeln!(out, "{mode} {hash} {stage}\t{path}")?;
+        } else {
+            writeln!(out, "{path}")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+
+use crate::{
+    revision,
+    types::{FileService, ObjectKind, Result},
+};
+
+pub fn run(tree: &str, recursive: bool, show_trees: bool) -> Result<()> {
+    let file_service = FileService::new()?;
+    let hash = revision::resolve_tree(&file_service, tree)?;
+    list(&file_service, &hash, "", recursive, show_trees)
+}
+
+/// Prints the entries of a tree as `<mode> <kind> <hash>\t<path>`,
+/// descending into subtrees when `recursive`, in which case subtrees are
+/// only listed themselves with `show_trees`.
+fn list(
+    file_service: &FileService,
+    hash: &str,
+    prefix: &str,
+    recursive: bool,
+    show_trees: bool,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    for (name, entry) in file_service.read_tree(hash)?.entries() {
+        let path = format!("{prefix}{name}");
+        let is_tree = entry.kind == ObjectKind::Tree;
+        if !is_tree || !recursive || show_trees {
+            let mode = match entry.kind {
+                ObjectKind::Tree => "040000",
+                ObjectKind::Commit => "160000",
+                _ => entry.mode.git_mode(),
+            };
+            writeln!(out, "{mode} {} {}\t{path}", entry.kind.as_str(), entry.hash)?;
+        }
+        if is_tree && recursive {
+            list(
+                file_service,
+                &entry.hash,
+                &format!("{path}/"),
+                recursive,
+                show_trees,
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+use crate::{
+    cli::{FastForward, MergeAction, Resume},
+    history,
+    merge::{self, Files},
+    refs::Refs,
+    repository::Repository,
+    revision::Abbrev,
+    sequencer::{self, Operation},
+    types::{Commit, Error, FileService, Result},
+    worktree::WorkTree,
+};
+
+pub fn run(action: MergeAction) -> Result<()> {
+    let file_service = FileService::new()?;
+    let (target, fast_forward) = match action {
+        MergeAction::Start {
+            ref target,
+            fast_forward,
+        } => (target, fast_forward),
+        MergeAction::Resume(Resume::Continue) => {
+            if sequencer::in_progress(&file_service)? != Some(Operation::Merge) {
+                return Err(Error::Usage("no merge in progress".into()));
+            }
+            let message = fs::read_to_string(file_service.blip_dir.join("MERGE_MSG"))?;
+            Repository::open(".")?.commit(&message, false, false)?;
+            return Ok(());
+        }
+        MergeAction::Resume(Resume::Abort) => {
+            return sequencer::abort(&file_service, Operation::Merge)
+        }
+        MergeAction::Resume(Resume::Skip) => {
+            return Err(Error::Usage("a merge has no commit to skip".into()))
+        }
+    };
+    let theirs = file_service.resolve(target)?;
+    let message = format!(
+        "Merge {} '{target}'",
+        if Refs::new(&file_service).is_branch(target) {
+            "branch"
+        } else {
+            "commit"
+        }
+    );
+    merge(&file_service, &theirs, target, &message, fast_forward)
+}
+
+/// Merges the commit `theirs` into HEAD, `label` naming it in conflict
+/// markers and `message` becoming the message of the merge commit. Exits
+/// with a failure when the merge stops on conflicts.
+pub(super) fn merge(
+    file_service: &FileService,
+    theirs: &str,
+    label: &str,
+    message: &str,
+    fast_forward: FastForward,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let worktree = WorkTree::new(file_service);
+    let mut index = file_service.lock_index()?;
+    let head = sequencer::prepare(file_service, Operation::Merge)?;
+    let ours = head.hash().unwrap_or_default().to_string();
+    let theirs = theirs.to_string();
+
+    let base = history::merge_base(file_service, &ours, &theirs)?;
+    if base.as_deref() == Some(theirs.as_str()) {
+        writeln!(out, "Already up to date.")?;
+        return Ok(());
+    }
+
+    let their_commit = file_service.read_commit(&theirs)?;
+
+    if base.as_deref() == Some(ours.as_str()) && fast_forward != FastForward::Never {
+        worktree.checkout(
+            file_service,
+            head.files(),
+            their_commit.files(),
+            their_commit.modes(),
+        )?;
+        file_service.update_head(&theirs)?;
+        index.clear()?;
+
+        let abbrev = Abbrev::new(file_service)?;
+        writeln!(
+            out,
+            "Updating {}..{}",
+            abbrev.shorten(&ours),
+            abbrev.shorten(&theirs)
+        )?;
+        writeln!(out, "Fast-forward")?;
+        return Ok(());
+    }
+    if fast_forward == FastForward::Only {
+        return Err(Error::Usage(
+            "not possible to fast-forward, aborting".into(),
+        ));
+    }
+
+    let base_commit = match base {
+        Some(ref base) => Some(file_service.read_commit(base)?),
+        None => None,
+    };
+
+    let merged = merge::merge_trees(
+        file_service,
+        worktree.attributes()?,
+        base_commit.as_ref().map_or(Files::EMPTY, Files::from),
+        (&head).into(),
+        (&their_commit).into(),
+        ("HEAD", label),
+    )?;
+    worktree.checkout(file_service, head.files(), &merged.files, &merged.modes)?;
+
+    if !merged.conflicts.is_empty() {
+        for path in merged.conflicts.iter() {
+            writeln!(out, "CONFLICT (content): Merge conflict in {path}")?;
+        }
+        merged.stage(&mut index, &head);
+        file_service.write_index(&index)?;
+        fs::write(file_service.blip_dir.join("MERGE_MSG"), message)?;
+        sequencer::stop_merge(file_service, &theirs)?;
+
+        writeln!(
+            out,
+            "Automatic merge failed; fix conflicts and then commit the result."
+        )?;
+        std::process::exit(1);
+    }
+
+    let mut commit = Commit::new(Some(&head), message);
+    commit.add_parent(&theirs);
+    commit.set_files(merged.files);
+    commit.set_modes(merged.modes);
+    commit.set_author(file_service.signature("AUTHOR")?);
+    commit.set_committer(file_service.signature("COMMITTER")?);
+    file_service.write_commit(&mut commit, head.hash())?;
+    index.clear()?;
+
+    writeln!(out, "Merge made by the 'three-way' strategy.")?;
+    Ok(())
+}
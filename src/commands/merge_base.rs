@@ -0,0 +1,29 @@
+use std::io::{self, Write};
+
+use crate::{
+    history,
+    types::{FileService, Result},
+};
+
+pub fn run(a: &str, b: &str, all: bool, is_ancestor: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let a = file_service.resolve(a)?;
+    let b = file_service.resolve(b)?;
+
+    if is_ancestor {
+        if !history::is_ancestor(&file_service, &a, &b)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let bases = history::merge_bases(&file_service, &a, &b)?;
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+    for base in bases.iter().take(if all { bases.len() } else { 1 }) {
+        writeln!(out, "{base}")?;
+    }
+    Ok(())
+}
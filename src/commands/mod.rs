@@ -0,0 +1,265 @@
+use std::io::{self, Write};
+
+mod add;
+mod am;
+mod apply;
+mod archive;
+mod bisect;
+mod blame;
+mod branch;
+mod bundle;
+mod check_attr;
+mod checkout;
+mod cherry_pick;
+mod clean;
+mod clone;
+mod commit;
+mod commit_tree;
+mod config;
+mod diff;
+mod fast_export;
+mod fast_import;
+mod fetch;
+mod format_patch;
+mod fsck;
+mod gc;
+mod grep;
+mod init;
+mod log;
+mod ls_files;
+mod ls_tree;
+mod merge;
+mod merge_base;
+mod mv;
+mod pack_refs;
+mod prune;
+mod pull;
+mod push;
+mod rebase;
+mod receive_pack;
+mod remote;
+mod repack;
+mod reset;
+mod restore;
+mod rev_list;
+mod rev_parse;
+mod revert;
+mod rm;
+mod serve;
+mod shell;
+mod show;
+mod show_ref;
+mod stash;
+mod status;
+mod submodule;
+mod switch;
+mod symbolic_ref;
+mod tag;
+mod upload_pack;
+mod verify_commit;
+mod verify_tag;
+mod worktree;
+mod write_tree;
+
+use crate::{
+    cli::{Command, USAGE},
+    types::Result,
+};
+
+pub fn run(command: Command) -> Result<()> {
+    let mut out = io::stdout().lock();
+    match command {
+        Command::Init {
+            path,
+            branch,
+            object_format,
+            bare,
+        } => init::run(&path, branch.as_deref(), object_format, bare),
+        Command::Clone {
+            source,
+            destination,
+            depth,
+        } => clone::run(&source, destination.as_deref(), depth),
+        Command::Add {
+            paths,
+            mode,
+            force,
+            jobs,
+            patch,
+            interactive,
+        } => add::run(&paths, mode, force, jobs, patch, interactive),
+        Command::Commit {
+            message,
+            amend,
+            all,
+            signoff,
+            trailers,
+            no_verify,
+            sign,
+        } => commit::run(message, amend, all, signoff, trailers, no_verify, sign),
+        Command::Status { pathspecs } => status::run(&pathspecs),
+        Command::Log {
+            revisions,
+            pathspecs,
+            separated,
+            filter,
+            max_count,
+            format,
+        } => log::run(
+            &revisions, &pathspecs, separated, &filter, max_count, &format,
+        ),
+        Command::Checkout { target } => checkout::run(&target),
+        Command::Switch {
+            target,
+            create,
+            detach,
+        } => switch::run(target.as_deref(), create.as_deref(), detach),
+        Command::Branch { action } => branch::run(action),
+        Command::Tag { action } => tag::run(action),
+        Command::Diff {
+            commits,
+            cached,
+            pathspecs,
+            renames,
+            name_status,
+            binary,
+            whitespace,
+        } => diff::run(
+            &commits,
+            cached,
+            &pathspecs,
+            renames,
+            name_status,
+            binary,
+            whitespace,
+        ),
+        Command::Merge { action } => merge::run(action),
+        Command::CherryPick { action } => cherry_pick::run(action),
+        Command::Revert { action } => revert::run(action),
+        Command::Rebase { action } => rebase::run(action),
+        Command::RevParse { revisions } => rev_parse::run(&revisions),
+        Command::RevList {
+            include,
+            exclude,
+            count,
+        } => rev_list::run(&include, &exclude, count),
+        Command::VerifyCommit { commits } => verify_commit::run(&commits),
+        Command::VerifyTag { tags } => verify_tag::run(&tags),
+        Command::SymbolicRef {
+            name,
+            target,
+            short,
+            quiet,
+        } => symbolic_ref::run(&name, target.as_deref(), short, quiet),
+        Command::ShowRef {
+            patterns,
+            head,
+            heads,
+            tags,
+            hash_only,
+        } => show_ref::run(&patterns, head, heads, tags, hash_only),
+        Command::WriteTree => write_tree::run(),
+        Command::CommitTree {
+            tree,
+            parents,
+            message,
+        } => commit_tree::run(&tree, &parents, message.as_deref()),
+        Command::LsFiles { pathspecs, stage } => ls_files::run(&pathspecs, stage),
+        Command::CheckAttr {
+            attributes,
+            all,
+            paths,
+        } => check_attr::run(&attributes, all, &paths),
+        Command::LsTree {
+            tree,
+            recursive,
+            show_trees,
+        } => ls_tree::run(&tree, recursive, show_trees),
+        Command::Show {
+            objects,
+            abbrev_commit,
+        } => show::run(&objects, abbrev_commit),
+        Command::Blame {
+            file,
+            lines,
+            whitespace,
+        } => blame::run(&file, lines, whitespace),
+        Command::Grep {
+            pattern,
+            commit,
+            line_number,
+        } => grep::run(&pattern, commit.as_deref(), line_number),
+        Command::Bisect { action } => bisect::run(action),
+        Command::MergeBase {
+            a,
+            b,
+            all,
+            is_ancestor,
+        } => merge_base::run(&a, &b, all, is_ancestor),
+        Command::Rm {
+            paths,
+            cached,
+            recursive,
+            force,
+        } => rm::run(&paths, cached, recursive, force),
+        Command::Mv {
+            source,
+            destination,
+        } => mv::run(&source, &destination),
+        Command::Reset { target, mode } => reset::run(&target, mode),
+        Command::Restore { paths, staged } => restore::run(&paths, staged),
+        Command::Stash { action } => stash::run(action),
+        Command::Worktree { action } => worktree::run(action),
+        Command::Submodule { action } => submodule::run(action),
+        Command::Remote { action } => remote::run(action),
+        Command::Fetch { remote, deepen } => fetch::run(remote.as_deref(), deepen),
+        Command::Push {
+            remote,
+            branch,
+            force,
+            set_upstream,
+        } => push::run(remote.as_deref(), branch.as_deref(), &force, set_upstream),
+        Command::Pull { rebase } => pull::run(rebase),
+        Command::Serve {
+            addr,
+            paths,
+            enable_push,
+        } => serve::run(&addr, &paths, enable_push),
+        Command::UploadPack { path } => upload_pack::run(&path),
+        Command::ReceivePack { path } => receive_pack::run(&path),
+        Command::Bundle { action } => bundle::run(action),
+        Command::FastExport { refs } => fast_export::run(&refs),
+        Command::FastImport => fast_import::run(),
+        Command::FormatPatch {
+            range,
+            output_directory,
+            stdout,
+        } => format_patch::run(&range, output_directory.as_deref(), stdout),
+        Command::Am { mailboxes } => am::run(&mailboxes),
+        Command::Apply {
+            patches,
+            cached,
+            check,
+            reject,
+            fuzz,
+        } => apply::run(&patches, cached, check, reject, fuzz),
+        Command::Archive {
+            tree_ish,
+            format,
+            prefix,
+            output,
+        } => archive::run(&tree_ish, format, &prefix, output.as_deref()),
+        Command::Clean { force, directories } => clean::run(force, directories),
+        Command::Repack => repack::run(),
+        Command::PackRefs { no_prune } => pack_refs::run(no_prune),
+        Command::Gc { prune } => gc::run(prune),
+        Command::Fsck => fsck::run(),
+        Command::Prune { dry_run, expire } => prune::run(dry_run, expire),
+        Command::Config { action, scope } => config::run(action, scope),
+        Command::Shell { command, args } => shell::run(&command, &args),
+        Command::Help => {
+            writeln!(out, "{USAGE}")?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fs, path::Path};
+
+use crate::{
+    types::{Error, FileService, Result},
+    worktree::WorkTree,
+};
+
+pub fn run(source: &str, destination: &str) -> Result<()> {
+    let file_service = FileService::new()?;
+    let mut index = file_service.lock_index()?;
+    let head = file_service.read_head_commit()?;
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+    let modes = WorkTree::tracked_modes(&index, head.as_ref());
+
+    let source = file_service.repo_path(source)?;
+    let mut destination = file_service.repo_path(destination)?;
+
+    // moving onto an existing directory moves into it
+    let root = &file_service.root_dir;
+    if root.join(&destination).is_dir() {
+        let name = Path::new(&source)
+            .file_name()
+            .and_then(|name| name.to_str());
+        let Some(name) = name else {
+            return Err(Error::Usage(format!("bad source '{source}'")));
+        };
+        destination = if destination.is_empty() {
+            name.to_string()
+        } else {
+            format!("{destination}/{name}")
+        };
+    }
+
+    // a tracked file, or every tracked file under a directory
+    let prefix = format!("{source}/");
+    let moved: Vec<(String, String)> = tracked
+        .iter()
+        .filter(|(path, _)| **path == source || path.starts_with(&prefix))
+        .map(|(path, hash)| (path.clone(), hash.clone()))
+        .collect();
+
+    if moved.is_empty() || fs::symlink_metadata(root.join(&source)).is_err() {
+        return Err(Error::Usage(format!(
+            "not under version control, source={source}"
+        )));
+    }
+    if root.join(&destination).exists() {
+        return Err(Error::Usage(format!(
+            "destination exists, destination={destination}"
+        )));
+    }
+
+    if let Some(parent) = root.join(&destination).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(root.join(&source), root.join(&destination))?;
+
+    for (path, hash) in moved {
+        let renamed = format!("{destination}{}", &path[source.len()..]);
+        index.remove(&path);
+        let mode = modes.get(&path).copied().unwrap_or_default();
+        index.update_with_mode(&renamed, &hash, mode);
+    }
+
+    if let Err(e) = file_service.write_index(&index) {
+        // put the file back so the working tree matches the untouched index
+        fs::rename(root.join(&destination), root.join(&source))?;
+        return Err(e);
+    }
+    Ok(())
+}
@@ -0,0 +1,10 @@
+use crate::{
+    refs::Refs,
+    types::{FileService, Result},
+};
+
+pub fn run(no_prune: bool) -> Result<()> {
+    let file_service = FileService::new()?;
+    Refs::new(&file_service).pack(!no_prune)?;
+    Ok(())
+}
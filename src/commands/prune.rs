@@ -0,0 +1,20 @@
+use std::io::{self, Write};
+
+use crate::{
+    gc::{self, Expiry},
+    types::{FileService, Result},
+};
+
+pub fn run(dry_run: bool, expire: Option<Expiry>) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let pruned = gc::prune(&file_service, expire, dry_run)?;
+    if dry_run {
+        for hash in pruned.iter() {
+            writeln!(out, "Would prune {hash}")?;
+        }
+    } else if !pruned.is_empty() {
+        writeln!(out, "Pruned {} unreachable objects", pruned.len())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::FastForward,
+    rebase::{self, Rebased},
+    refs::Refs,
+    remote::{self, Depth, Remote, Upstream},
+    sequencer::Operation,
+    types::{Error, FileService, Result},
+};
+
+use super::{
+    fetch::{self, short_name},
+    merge,
+    rebase::report_stop,
+};
+
+pub fn run(rebase: Option<bool>) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let refs = Refs::new(&file_service);
+    let Some(branch) = refs.current_branch(&file_service)? else {
+        return Err(Error::Usage("you are not currently on a branch".into()));
+    };
+    let Some(upstream) = Upstream::load(&file_service, &branch)? else {
+        return Err(Error::Usage(format!(
+            "there is no tracking information for the current branch; \
+             set it with 'blip branch -u <remote>/<branch> {branch}'"
+        )));
+    };
+    let Some(remote) = Remote::load(&file_service, &upstream.remote)? else {
+        return Err(Error::Usage(format!(
+            "'{}' does not appear to be a remote",
+            upstream.remote
+        )));
+    };
+    let rebase = match rebase {
+        Some(rebase) => rebase,
+        None => file_service
+            .config()?
+            .get_bool("pull.rebase")?
+            .unwrap_or(false),
+    };
+
+    let updates = remote::fetch(&file_service, &remote, Depth::Full)?;
+    fetch::report(&file_service, &remote, &updates)?;
+
+    let tracking = upstream.tracking_ref(&file_service)?.ok_or_else(|| {
+        Error::Usage(format!(
+            "remote '{}' is not configured to fetch {}",
+            remote.name, upstream.merge
+        ))
+    })?;
+    let Some(theirs) = refs.read(&tracking) else {
+        return Err(Error::Usage(format!(
+            "no such ref was fetched: {}",
+            upstream.merge
+        )));
+    };
+
+    if !rebase {
+        let message = format!(
+            "Merge branch '{}' of {}",
+            short_name(&upstream.merge),
+            remote.url
+        );
+        return merge::merge(
+            &file_service,
+            &theirs,
+            short_name(&tracking),
+            &message,
+            FastForward::Allow,
+        );
+    }
+    match rebase::rebase(&file_service, &theirs, false)? {
+        Rebased::UpToDate => writeln!(out, "Current branch {branch} is up to date.")?,
+        Rebased::FastForward => {
+            writeln!(out, "Fast-forwarded {branch} to {}.", short_name(&tracking))?
+        }
+        Rebased::Applied(_) => {
+            writeln!(out, "Successfully rebased and updated refs/heads/{branch}.")?
+        }
+        Rebased::Stopped(outcome) => report_stop(&file_service, Operation::Rebase, &outcome)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::io::{self, Write};
+
+use crate::{
+    refs::Refs,
+    remote::{self, Force, Remote, UpdateStatus, Upstream},
+    revision::Abbrev,
+    types::{Error, FileService, Result},
+};
+
+use super::fetch::{short_name, upstream_remote};
+
+pub fn run(
+    name: Option<&str>,
+    branch: Option<&str>,
+    force: &Force,
+    set_upstream: bool,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => upstream_remote(&file_service)?,
+    };
+    let Some(remote) = Remote::load(&file_service, &name)? else {
+        return Err(Error::Usage(format!(
+            "'{name}' does not appear to be a remote"
+        )));
+    };
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => Refs::new(&file_service)
+            .current_branch(&file_service)?
+            .ok_or_else(|| Error::Usage("you are not currently on a branch".into()))?,
+    };
+
+    let update = remote::push(&file_service, &remote, &branch, force)?;
+    let rejected = matches!(update.status, UpdateStatus::Rejected | UpdateStatus::Stale);
+    if set_upstream && !rejected {
+        let upstream = Upstream {
+            remote: remote.name.clone(),
+            merge: update.remote.clone(),
+        };
+        upstream.set(&file_service, &branch)?;
+        writeln!(
+            out,
+            "branch '{branch}' set up to track '{}/{branch}'.",
+            remote.name
+        )?;
+    }
+    if update.status == UpdateStatus::UpToDate {
+        writeln!(out, "Everything up-to-date")?;
+        return Ok(());
+    }
+
+    writeln!(out, "To {}", remote.url)?;
+    let abbrev = Abbrev::new(&file_service)?;
+    let name = short_name(&update.remote);
+    let new = abbrev.shorten(&update.new);
+    let old = update.old.as_deref().map(|old| abbrev.shorten(old));
+    match (update.status, old) {
+        (UpdateStatus::FastForward, Some(old)) => {
+            writeln!(out, "   {old}..{new}  {name} -> {name}")?
+        }
+        (UpdateStatus::Forced, Some(old)) => {
+            writeln!(out, " + {old}...{new} {name} -> {name} (forced update)")?
+        }
+        (UpdateStatus::Rejected | UpdateStatus::Stale, _) => {
+            let reason = match (update.status, update.old.as_deref()) {
+                (UpdateStatus::Stale, _) => "stale info",
+                (_, Some(old)) if !file_service.has_object(old)? => "fetch first",
+                _ => "non-fast-forward",
+            };
+            writeln!(out, " ! [rejected]        {name} -> {name} ({reason})")?;
+            std::process::exit(1);
+        }
+        _ => writeln!(out, " * [new branch]      {name} -> {name}")?,
+    }
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::{RebaseAction, Resume},
+    rebase::{self, Rebased},
+    refs::Refs,
+    revision::Abbrev,
+    sequencer::{self, Action, Operation, Outcome},
+    types::{FileService, Result},
+};
+
+pub fn run(action: RebaseAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let rebased = match action {
+        RebaseAction::Start {
+            ref upstream,
+            interactive,
+        } => {
+            let hash = file_service.resolve(upstream)?;
+            rebase::rebase(&file_service, &hash, interactive)?
+        }
+        RebaseAction::Resume(Resume::Continue) => {
+            sequencer::resume(&file_service, Operation::Rebase)?.into()
+        }
+        RebaseAction::Resume(Resume::Skip) => {
+            sequencer::skip(&file_service, Operation::Rebase)?.into()
+        }
+        RebaseAction::Resume(Resume::Abort) => {
+            return sequencer::abort(&file_service, Operation::Rebase)
+        }
+    };
+
+    let head = match Refs::new(&file_service).current_branch(&file_service)? {
+        Some(branch) => Refs::branch_ref(&branch),
+        None => "detached HEAD".to_string(),
+    };
+    match rebased {
+        Rebased::UpToDate => writeln!(out, "Current branch is up to date.")?,
+        Rebased::FastForward | Rebased::Applied(_) => {
+            writeln!(out, "Successfully rebased and updated {head}.")?
+        }
+        Rebased::Stopped(outcome) => report_stop(&file_service, Operation::Rebase, &outcome)?,
+    }
+    Ok(())
+}
+
+/// Tells where `operation` stopped and how to go on from there, exiting
+/// with a failure if it stopped on conflicts.
+pub fn report_stop(
+    file_service: &FileService,
+    operation: Operation,
+    outcome: &Outcome,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let name = operation.name();
+    let abbrev = Abbrev::new(file_service)?;
+    match outcome {
+        Outcome::Done(_) => {}
+        Outcome::Stopped { hash, conflicts } => {
+            for path in conflicts {
+                writeln!(out, "CONFLICT (content): Merge conflict in {path}")?;
+            }
+            let commit = file_service.read_commit(hash)?;
+            let subject = commit.message().lines().next().unwrap_or_default();
+            let verb = match operation {
+                Operation::Revert => Action::Revert.name(),
+                _ => "apply",
+            };
+            writeln!(
+                out,
+                "could not {verb} {}... {subject}",
+                abbrev.shorten(hash)
+            )?;
+            writeln!(
+                out,
+                "Resolve all conflicts, stage them with 'blip add', then run \
+                 'blip {name} --continue'.\nRun 'blip {name} --skip' to drop the commit, \
+                 or 'blip {name} --abort' to give up."
+            )?;
+            std::process::exit(1);
+        }
+        Outcome::Editing { hash } => {
+            writeln!(out, "Stopped at {}", abbrev.shorten(hash))?;
+            writeln!(
+                out,
+                "Make your changes and stage them with 'blip add' to amend the commit, \
+                 then run 'blip {name} --continue'."
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::RemoteAction,
+    remote::Remote,
+    types::{FileService, Result},
+};
+
+pub fn run(action: RemoteAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    match action {
+        RemoteAction::List { verbose } => {
+            for remote in Remote::list(&file_service)? {
+                if verbose {
+                    writeln!(out, "{}\t{} (fetch)", remote.name, remote.url)?;
+                } else {
+                    writeln!(out, "{}", remote.name)?;
+                }
+            }
+        }
+        RemoteAction::Add { name, url } => {
+            Remote::add(&file_service, &name, &url)?;
+        }
+        RemoteAction::Remove { name } => Remote::remove(&file_service, &name)?,
+    }
+    Ok(())
+}
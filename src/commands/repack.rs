@@ -0,0 +1,20 @@
+use std::io::{self, Write};
+
+use crate::{
+    pack,
+    types::{FileService, Result},
+};
+
+pub fn run() -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    match pack::repack(&file_service)? {
+        Some(stats) => writeln!(
+            out,
+            "Packed {} objects ({} as deltas) into {}",
+            stats.objects, stats.deltas, stats.name
+        )?,
+        None => writeln!(out, "Nothing to pack")?,
+    }
+    Ok(())
+}
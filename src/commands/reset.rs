@@ -0,0 +1,62 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::ResetMode,
+    refs::Refs,
+    revision::Abbrev,
+    types::{FileService, Result},
+    worktree::{Change, WorkTree},
+};
+
+pub fn run(target: &str, mode: ResetMode) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let worktree = WorkTree::new(&file_service);
+    let mut index = file_service.lock_index()?;
+    let head = file_service.read_head_commit()?;
+
+    let hash = file_service.resolve(target)?;
+    let commit = file_service.read_commit(&hash)?;
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+    let (files, modes) = (commit.files(), commit.modes());
+
+    match mode {
+        // keep everything staged, now relative to the new HEAD
+        ResetMode::Soft => {
+            let tracked_modes = WorkTree::tracked_modes(&index, head.as_ref());
+            index.stage_files(files, modes, &tracked, &tracked_modes)
+        }
+        ResetMode::Mixed => index.stage_files(files, modes, files, modes),
+        ResetMode::Hard => {
+            let current = worktree.snapshot(&tracked)?;
+            worktree.checkout(&file_service, &current, files, modes)?;
+            index.stage_files(files, modes, files, modes);
+        }
+    }
+
+    if let Some(old) = head.as_ref().and_then(|head| head.hash()) {
+        Refs::new(&file_service).write("ORIG_HEAD", old)?;
+    }
+    file_service.update_head(&hash)?;
+    file_service.write_index(&index)?;
+
+    match mode {
+        ResetMode::Hard => {
+            let summary = commit.message().lines().next().unwrap_or_default();
+            let short = Abbrev::new(&file_service)?.shorten(&hash);
+            writeln!(out, "HEAD is now at {short} {summary}")?;
+        }
+        ResetMode::Mixed => {
+            let unstaged = worktree.status(&index, Some(&commit))?.unstaged;
+            if !unstaged.is_empty() {
+                writeln!(out, "Unstaged changes after reset:")?;
+            }
+            for (change, path) in unstaged {
+                let code = if change == Change::Deleted { 'D' } else { 'M' };
+                writeln!(out, "{code}\t{path}")?;
+            }
+        }
+        ResetMode::Soft => {}
+    }
+    Ok(())
+}
@@ -0,0 +1,10 @@
+use crate::{pathspec::Pathspec, repository::Repository, types::Result};
+
+pub fn run(files: &[String], staged: bool) -> Result<()> {
+    let repository = Repository::open(".")?;
+    let pathspecs = files
+        .iter()
+        .map(|file| Pathspec::from_cwd(repository.file_service(), file))
+        .collect::<Result<Vec<_>>>()?;
+    repository.restore(&pathspecs, staged)
+}
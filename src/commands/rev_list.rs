@@ -0,0 +1,32 @@
+use std::io::{self, Write};
+
+use crate::{
+    history::CommitWalk,
+    types::{FileService, Result},
+};
+
+pub fn run(include: &[String], exclude: &[String], count: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let mut walk = CommitWalk::new(&file_service, None);
+    for name in exclude {
+        walk.hide(&file_service.resolve(name)?)?;
+    }
+    for name in include {
+        walk.push(file_service.resolve(name)?);
+    }
+
+    if count {
+        let mut total = 0;
+        for commit in walk {
+            commit?;
+            total += 1;
+        }
+        writeln!(out, "{total}")?;
+        return Ok(());
+    }
+    for commit in walk {
+        writeln!(out, "{}", commit?.hash().unwrap_or_default())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use std::io::{self, Write};
+
+use crate::{
+    revision,
+    types::{FileService, Result},
+};
+
+pub fn run(revisions: &[String]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    for revision in revisions {
+        writeln!(
+            out,
+            "{}",
+            revision::resolve_object(&file_service, revision)?
+        )?;
+    }
+    Ok(())
+}
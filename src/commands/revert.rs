@@ -0,0 +1,7 @@
+use crate::{cli::SequenceAction, sequencer::Operation, types::Result};
+
+use super::cherry_pick;
+
+pub fn run(action: SequenceAction) -> Result<()> {
+    cherry_pick::sequence(Operation::Revert, action)
+}
@@ -0,0 +1,70 @@
+use std::io::{self, Write};
+
+use crate::{
+    types::{Error, FileService, Result},
+    worktree::WorkTree,
+};
+
+pub fn run(paths: &[String], cached: bool, recursive: bool, force: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let worktree = WorkTree::new(&file_service);
+    let mut index = file_service.lock_index()?;
+    let head = file_service.read_head_commit()?;
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+
+    let mut targets = Vec::new();
+    for path in paths {
+        let path = file_service.repo_path(path)?;
+        if tracked.contains_key(&path) {
+            targets.push(path);
+            continue;
+        }
+
+        let prefix = if path.is_empty() {
+            String::new()
+        } else {
+            format!("{path}/")
+        };
+        let matched: Vec<String> = tracked
+            .keys()
+            .filter(|tracked| tracked.starts_with(&prefix))
+            .cloned()
+            .collect();
+        if matched.is_empty() {
+            return Err(Error::Usage(format!(
+                "pathspec '{path}' did not match any files"
+            )));
+        }
+        if !recursive {
+            return Err(Error::Usage(format!(
+                "not removing '{path}' recursively without -r"
+            )));
+        }
+        targets.extend(matched);
+    }
+
+    // refuse to throw away changes that exist nowhere else
+    if !cached && !force {
+        let mut modified = Vec::new();
+        for path in targets.iter() {
+            let current = worktree.hash(path)?;
+            if current.is_some() && current.as_ref() != tracked.get(path) {
+                modified.push(path.clone());
+            }
+        }
+        if !modified.is_empty() {
+            return Err(Error::DirtyWorkTree(modified));
+        }
+    }
+
+    for path in targets {
+        index.remove(&path);
+        if !cached {
+            worktree.remove_file(&path)?;
+        }
+        writeln!(out, "rm '{path}'")?;
+    }
+
+    file_service.write_index(&index)
+}
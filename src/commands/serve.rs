@@ -0,0 +1,24 @@
+use std::{
+    io::{self, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    server::Server,
+    types::{Context, Result},
+};
+
+pub fn run(addr: &str, paths: &[String], enable_push: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let paths: Vec<PathBuf> = match paths {
+        [] => vec![PathBuf::from(".")],
+        paths => paths.iter().map(PathBuf::from).collect(),
+    };
+    let server = Server::new(&paths, enable_push)?;
+    let listener = TcpListener::bind(addr).context("listen on", Path::new(addr))?;
+    for name in server.names() {
+        writeln!(out, "Serving {name} at http://{addr}/{name}")?;
+    }
+    server.run(listener)
+}
@@ -0,0 +1,28 @@
+use std::process::{self, Command};
+
+use crate::types::{FileService, Result};
+
+/// Runs a shell alias from the top of the repository, passing the
+/// arguments on as positional parameters like git does.
+pub fn run(command: &str, args: &[String]) -> Result<()> {
+    let mut shell = Command::new("sh");
+    shell
+        .arg("-c")
+        .arg(format!("{command} \"$@\""))
+        .arg(command)
+        .args(args);
+
+    if let Ok(file_service) = FileService::new() {
+        // tell the alias where it was started from, relative to the root
+        let prefix = file_service.repo_path(".")?;
+        shell
+            .current_dir(&file_service.root_dir)
+            .env("BLIP_PREFIX", prefix);
+    }
+
+    let status = shell.status()?;
+    if !status.success() {
+        process::exit(status.code().unwrap_or(1));
+    }
+    Ok(())
+}
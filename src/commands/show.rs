@@ -0,0 +1,84 @@
+use std::io::{self, Write};
+
+use crate::{
+    diff::RenameDetection,
+    repository::Repository,
+    revision::{self, Abbrev},
+    types::{ObjectKind, ObjectStore, Result},
+};
+
+use super::log;
+
+pub fn run(objects: &[String], abbrev_commit: bool) -> Result<()> {
+    let repository = Repository::open(".")?;
+    let abbrev = Abbrev::new(repository.file_service())?;
+    let head = ["HEAD".to_string()];
+    let objects = if objects.is_empty() {
+        &head[..]
+    } else {
+        objects
+    };
+
+    let mut out = io::stdout().lock();
+    for (i, name) in objects.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        let hash = revision::resolve_object(repository.file_service(), name)?;
+        show(&mut out, &repository, &abbrev, abbrev_commit, name, &hash)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes to `out` a commit with its patch, its hash shortened if
+/// `abbrev_commit`, a tag followed by what it points at, the entries of a
+/// tree or the raw content of a blob.
+fn show(
+    out: &mut impl Write,
+    repository: &Repository,
+    abbrev: &Abbrev,
+    abbrev_commit: bool,
+    name: &str,
+    hash: &str,
+) -> Result<()> {
+    match repository.file_service().read_object(hash)? {
+        ObjectStore::Commit(commit) => {
+            log::print_commit(out, &commit, abbrev, abbrev_commit)?;
+            let renames = RenameDetection::from_config(repository.file_service().config()?)?;
+            let patch = repository.commit_patch(&commit, renames)?;
+            if !patch.is_empty() {
+                write!(out, "\n{patch}")?;
+            }
+        }
+        ObjectStore::Tag(tag) => {
+            writeln!(out, "tag {}", tag.name())?;
+            if let Some(tagger) = tag.tagger() {
+                writeln!(out, "Tagger: {} <{}>", tagger.name, tagger.email)?;
+                writeln!(out, "Date:   {}", tagger.date())?;
+            }
+            writeln!(out, "\n{}\n", tag.message().trim_end())?;
+            show(
+                out,
+                repository,
+                abbrev,
+                abbrev_commit,
+                tag.object(),
+                tag.object(),
+            )?;
+        }
+        ObjectStore::Tree(tree) => {
+            writeln!(out, "tree {name}\n")?;
+            for (entry_name, entry) in tree.entries() {
+                let slash = if entry.kind == ObjectKind::Tree {
+                    "/"
+                } else {
+                    ""
+                };
+                writeln!(out, "{entry_name}{slash}")?;
+            }
+        }
+        ObjectStore::Blob(blob) => out.write_all(blob.data())?,
+    }
+    Ok(())
+}
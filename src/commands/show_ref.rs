@@ -0,0 +1,52 @@
+use std::io::{self, Write};
+
+use crate::{
+    refs::Refs,
+    types::{FileService, Result},
+};
+
+pub fn run(
+    patterns: &[String],
+    head: bool,
+    heads: bool,
+    tags: bool,
+    hash_only: bool,
+) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let mut refs = Vec::new();
+    if head {
+        if let Some(hash) = file_service.read_head_hash()? {
+            refs.push((hash, "HEAD".to_string()));
+        }
+    }
+    for (name, hash) in Refs::new(&file_service).all()? {
+        let kind_matches = match (heads, tags) {
+            (false, false) => true,
+            (heads, tags) => {
+                (heads && name.starts_with("refs/heads/"))
+                    || (tags && name.starts_with("refs/tags/"))
+            }
+        };
+        // a pattern matches whole trailing components of the name
+        let pattern_matches = patterns.is_empty()
+            || patterns
+                .iter()
+                .any(|pattern| name == *pattern || name.ends_with(&format!("/{pattern}")));
+        if kind_matches && pattern_matches {
+            refs.push((hash, name));
+        }
+    }
+
+    if refs.is_empty() {
+        std::process::exit(1);
+    }
+    for (hash, name) in refs {
+        if hash_only {
+            writeln!(out, "{hash}")?;
+        } else {
+            writeln!(out, "{hash} {name}")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::StashAction,
+    merge::{self, Files},
+    refs::Refs,
+    revision::Abbrev,
+    stash::Stash,
+    types::{Commit, Error, FileService, ObjectKind, Result},
+    worktree::WorkTree,
+};
+
+pub fn run(action: StashAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let stash = Stash::new(&file_service);
+
+    match action {
+        StashAction::Push { message } => push(&file_service, &stash, message.as_deref()),
+        StashAction::List => {
+            for (index, entry) in stash.entries()?.iter().enumerate() {
+                writeln!(out, "{}: {}", Stash::name(index), entry.message)?;
+            }
+            Ok(())
+        }
+        StashAction::Apply { index } => apply(&file_service, &stash, index).map(|_| ()),
+        StashAction::Pop { index } => {
+            // a conflicted apply keeps the entry around for another attempt
+            if apply(&file_service, &stash, index)? {
+                drop(&stash, index)?;
+            }
+            Ok(())
+        }
+        StashAction::Drop { index } => drop(&stash, index),
+    }
+}
+
+fn push(file_service: &FileService, stash: &Stash, message: Option<&str>) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let worktree = WorkTree::new(file_service);
+    let mut index = file_service.lock_index()?;
+    let Some(head) = file_service.read_head_commit()? else {
+        return Err(Error::Usage(
+            "you do not have the initial commit yet".into(),
+        ));
+    };
+
+    let status = worktree.status(&index, Some(&head))?;
+    if !status.unmerged.is_empty() {
+        return Err(Error::UnmergedPaths(status.unmerged));
+    }
+    if status.staged.is_empty() && status.unstaged.is_empty() {
+        writeln!(out, "No local changes to save")?;
+        return Ok(());
+    }
+
+    let hash = head.hash().unwrap_or_default();
+    let branch = Refs::new(file_service)
+        .current_branch(file_service)?
+        .unwrap_or_else(|| "(no branch)".to_string());
+    let summary = head.message().lines().next().unwrap_or_default();
+    let short = Abbrev::new(file_service)?.shorten(hash);
+    let message = match message {
+        Some(message) => format!("On {branch}: {message}"),
+        None => format!("WIP on {branch}: {short} {summary}"),
+    };
+
+    let staged = WorkTree::tracked_files(&index, Some(&head));
+    let mut index_commit = Commit::new(
+        Some(&head),
+        &format!("index on {branch}: {short} {summary}"),
+    );
+    index_commit.set_files(staged.clone());
+    index_commit.set_modes(WorkTree::tracked_modes(&index, Some(&head)));
+    index_commit.set_author(file_service.signature("AUTHOR")?);
+    index_commit.set_committer(file_service.signature("COMMITTER")?);
+    let index_hash = file_service.write_commit_object(&mut index_commit)?;
+
+    let current = worktree.snapshot(&staged)?;
+    for (path, hash) in current.iter() {
+        if staged.get(path) != Some(hash) {
+            worktree.write_blob(file_service, path)?;
+        }
+    }
+    let mut stash_commit = Commit::new(Some(&head), &message);
+    stash_commit.add_parent(&index_hash);
+    stash_commit.set_files(current.clone());
+    stash_commit.set_modes(worktree.modes(&current));
+    stash_commit.set_author(file_service.signature("AUTHOR")?);
+    stash_commit.set_committer(file_service.signature("COMMITTER")?);
+    let stash_hash = file_service.write_commit_object(&mut stash_commit)?;
+    stash.push(&stash_hash, &message, &file_service.signature("COMMITTER")?)?;
+
+    // back to a clean checkout of HEAD
+    worktree.checkout(file_service, &current, head.files(), head.modes())?;
+    index.clear()?;
+
+    writeln!(out, "Saved working directory and index state {message}")?;
+    Ok(())
+}
+
+/// Reapplies a stash entry on top of the current HEAD, returning whether it
+/// applied without conflicts.
+fn apply(file_service: &FileService, stash: &Stash, index: usize) -> Result<bool> {
+    let mut out = io::stdout().lock();
+    let worktree = WorkTree::new(file_service);
+    let mut staging = file_service.lock_index()?;
+    let head = file_service.read_head_commit()?;
+
+    let status = worktree.status(&staging, head.as_ref())?;
+    if !status.is_clean() {
+        let paths = status.staged.into_iter().chain(status.unstaged);
+        return Err(Error::DirtyWorkTree(paths.map(|(_, path)| path).collect()));
+    }
+
+    let entry = stash.get(index)?;
+    let stashed = file_service.read_commit(&entry.hash)?;
+    let (Some(base), Some(index_hash)) = (stashed.parent(), stashed.parents().get(1)) else {
+        return Err(Error::InvalidObject {
+            hash: entry.hash,
+            kind: ObjectKind::Commit,
+        });
+    };
+    let base = file_service.read_commit(base)?;
+    let staged = file_service.read_commit(index_hash)?;
+
+    let head_files = WorkTree::tracked_files(&staging, head.as_ref());
+    let head_modes = WorkTree::tracked_modes(&staging, head.as_ref());
+    let ours = Files {
+        files: &head_files,
+        modes: &head_modes,
+    };
+    let merged = merge::merge_trees(
+        file_service,
+        worktree.attributes()?,
+        (&base).into(),
+        ours,
+        (&stashed).into(),
+        ("Updated upstream", "Stashed changes"),
+    )?;
+    worktree.checkout(file_service, &head_files, &merged.files, &merged.modes)?;
+
+    if head.as_ref().and_then(|head| head.hash()) == base.hash() && merged.conflicts.is_empty() {
+        // still on the commit the stash was taken on, restore the index as it was
+        staging.stage_files(&head_files, &head_modes, staged.files(), staged.modes());
+    } else {
+        // otherwise only keep new files tracked, leaving the rest unstaged
+        for (path, hash) in merged.files.iter() {
+            if merged.conflicts.contains(path) {
+                writeln!(out, "CONFLICT (content): Merge conflict in {path}")?;
+                staging.mark_conflict(path, hash);
+            } else if !head_files.contains_key(path) {
+                let mode = merged.modes.get(path).copied().unwrap_or_default();
+                staging.update_with_mode(path, hash, mode);
+            }
+        }
+    }
+    file_service.write_index(&staging)?;
+
+    if !merged.conflicts.is_empty() {
+        writeln!(out, "The stash entry is kept in case you need it again.")?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+fn drop(stash: &Stash, index: usize) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let entry = stash.drop(index)?;
+    writeln!(out, "Dropped {} ({})", Stash::name(index), entry.hash)?;
+    Ok(())
+}
@@ -0,0 +1,178 @@
+use std::io::{self, Write};
+
+use crate::{
+    bisect,
+    diff::Rename,
+    pathspec::Pathspec,
+    refs::Refs,
+    remote::Upstream,
+    repository::Repository,
+    revision::Abbrev,
+    sequencer,
+    types::{Error, FileService, Result},
+    worktree::{Change, Status, WorkTree},
+};
+
+pub fn run(pathspecs: &[String]) -> Result<()> {
+    let file_service = FileService::new()?;
+    let pathspecs = pathspecs
+        .iter()
+        .map(|spec| Pathspec::from_cwd(&file_service, spec))
+        .collect::<Result<Vec<_>>>()?;
+    let pathspec = Pathspec::new(&pathspecs)?;
+    let (mut index, locked) = match file_service.lock_index() {
+        Ok(index) => (index, true),
+        Err(Error::Locked(_)) => (file_service.read_index()?, false),
+        Err(error) => return Err(error),
+    };
+    let head = file_service.read_head_commit()?;
+    let worktree = WorkTree::new(&file_service);
+
+    // save what was learned about unchanged files for the next run, unless
+    // another process is busy with the index; reading it back makes the
+    // files just hashed older than the index, so they are not hashed again
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+    let matched = tracked.keys().filter(|path| pathspec.matches(path));
+    if worktree.refresh(&mut index, matched)? && locked {
+        file_service.write_index(&index)?;
+        index = file_service.read_index()?;
+    }
+    let status = worktree.status_matching(&index, head.as_ref(), &pathspec)?;
+
+    let mut out = io::stdout().lock();
+    if let Some(branch) = Refs::new(&file_service).current_branch(&file_service)? {
+        writeln!(out, "On branch {branch}")?;
+        let hash = head.as_ref().and_then(|head| head.hash());
+        if let Some((upstream, hash)) = Upstream::load(&file_service, &branch)?.zip(hash) {
+            print_tracking(&mut out, &file_service, &upstream, hash)?;
+        }
+    } else if let Some(hash) = head.as_ref().and_then(|head| head.hash()) {
+        writeln!(
+            out,
+            "HEAD detached at {}",
+            Abbrev::new(&file_service)?.shorten(hash)
+        )?;
+    }
+    if head.is_none() {
+        writeln!(out, "\nNo commits yet")?;
+    }
+    if let Some(operation) = sequencer::in_progress(&file_service)? {
+        let name = operation.name();
+        writeln!(out, "\nYou are in the middle of a {name}.")?;
+        writeln!(
+            out,
+            "  (use \"blip {name} --continue\" once it is resolved)"
+        )?;
+        writeln!(out, "  (use \"blip {name} --abort\" to give it up)")?;
+    }
+    if let Some(start) = bisect::started_from(&file_service)? {
+        writeln!(
+            out,
+            "\nYou are currently bisecting, started from '{start}'."
+        )?;
+        writeln!(out, "  (use \"blip bisect reset\" to get back to it)")?;
+    }
+
+    let renames = Repository::open(".")?.staged_renames(&index, head.as_ref(), &status.staged)?;
+    print_changes(
+        &mut out,
+        "Changes to be committed:",
+        &status.staged,
+        &renames,
+    )?;
+    print_changes(
+        &mut out,
+        "Changes not staged for commit:",
+        &status.unstaged,
+        &[],
+    )?;
+
+    if !status.unmerged.is_empty() {
+        writeln!(out, "\nUnmerged paths:")?;
+        for path in status.unmerged.iter() {
+            writeln!(out, "\tboth modified:   {path}")?;
+        }
+    }
+
+    if !status.untracked.is_empty() {
+        writeln!(out, "\nUntracked files:")?;
+        for path in status.untracked.iter() {
+            writeln!(out, "\t{path}")?;
+        }
+    }
+
+    if status.is_clean() && status.untracked.is_empty() {
+        writeln!(out, "\nnothing to commit, working tree clean")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Says how the current branch, at `hash`, compares to its upstream.
+fn print_tracking(
+    out: &mut impl Write,
+    file_service: &FileService,
+    upstream: &Upstream,
+    hash: &str,
+) -> Result<()> {
+    let name = upstream.short_name(file_service)?;
+    let commits = |count: usize| match count {
+        1 => "1 commit".to_string(),
+        count => format!("{count} commits"),
+    };
+    match upstream.ahead_behind(file_service, hash)? {
+        None => {
+            writeln!(
+                out,
+                "Your branch is based on '{name}', but the upstream is gone."
+            )?;
+            writeln!(out, "  (use \"blip branch --unset-upstream\" to fix this)")?;
+        }
+        Some((0, 0)) => writeln!(out, "Your branch is up to date with '{name}'.")?,
+        Some((ahead, 0)) => {
+            writeln!(
+                out,
+                "Your branch is ahead of '{name}' by {}.",
+                commits(ahead)
+            )?;
+            writeln!(out, "  (use \"blip push\" to publish your local commits)")?;
+        }
+        Some((0, behind)) => {
+            writeln!(
+                out,
+                "Your branch is behind '{name}' by {}, and can be fast-forwarded.",
+                commits(behind)
+            )?;
+            writeln!(out, "  (use \"blip pull\" to update your local branch)")?;
+        }
+        Some((ahead, behind)) => {
+            writeln!(out, "Your branch and '{name}' have diverged,")?;
+            writeln!(
+                out,
+                "and have {ahead} and {behind} different commits each, respectively."
+            )?;
+            writeln!(
+                out,
+                "  (use \"blip pull\" to merge the remote branch into yours)"
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn print_changes(
+    out: &mut impl Write,
+    header: &str,
+    changes: &[(Change, String)],
+    renames: &[Rename],
+) -> io::Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "\n{header}")?;
+    for line in Status::describe(changes, renames) {
+        writeln!(out, "\t{line}")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::io::{self, Write};
+
+use crate::{
+    cli::SubmoduleAction,
+    submodule,
+    types::{FileService, Result},
+};
+
+pub fn run(action: SubmoduleAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    match action {
+        SubmoduleAction::Add { url, path } => {
+            let hash = submodule::add(&file_service, &url, &path)?;
+            writeln!(out, "Added submodule '{path}' at '{hash}'")?;
+        }
+        SubmoduleAction::Update => {
+            for (path, hash) in submodule::update(&file_service)? {
+                writeln!(out, "Submodule path '{path}': checked out '{hash}'")?;
+            }
+        }
+        SubmoduleAction::Status => {
+            // like git: `-` for submodules not cloned yet, `+` for ones at
+            // another commit than the one recorded
+            for state in submodule::states(&file_service)? {
+                let path = &state.submodule.path;
+                match state.checked_out {
+                    None => writeln!(out, "-{} {path}", state.recorded)?,
+                    Some(hash) if hash == state.recorded => writeln!(out, " {hash} {path}")?,
+                    Some(hash) => writeln!(out, "+{hash} {path}")?,
+                }
+            }
+        }
+    }
+    Ok(())
+}
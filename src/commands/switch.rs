@@ -0,0 +1,128 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Write},
+};
+
+use crate::{
+    refs::Refs,
+    revision::Abbrev,
+    types::{Error, FileMode, FileService, Result},
+    worktree::{Change, WorkTree},
+    worktrees,
+};
+
+/// Switches to the branch `target`, to a new branch `create` starting at
+/// `target`, or with `detach` to the commit `target`. Local changes come
+/// along, unless the switch would change the files they are in.
+pub fn run(target: Option<&str>, create: Option<&str>, detach: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let refs = Refs::new(&file_service);
+    let branch = match (create, target) {
+        (Some(name), _) => Some(name),
+        _ if detach => None,
+        (None, Some(target)) if refs.is_branch(target) => Some(target),
+        (None, Some(target)) => {
+            return Err(Error::Usage(format!(
+                "'{target}' is not a branch; use --detach to switch to a commit"
+            )))
+        }
+        (None, None) => return Err(Error::Usage("switch needs a branch to switch to".into())),
+    };
+    if let Some(name) = create {
+        if !Refs::is_valid_name(name) {
+            return Err(Error::InvalidRefName(name.to_string()));
+        }
+        if refs.is_branch(name) {
+            return Err(Error::RefExists(name.to_string()));
+        }
+    } else if let Some(name) = branch {
+        if refs.current_branch(&file_service)?.as_deref() == Some(name) {
+            writeln!(out, "Already on '{name}'")?;
+            return Ok(());
+        }
+        worktrees::check_not_checked_out(&file_service, &Refs::branch_ref(name))?;
+    }
+
+    let hash = file_service.resolve(target.unwrap_or("HEAD"))?;
+    let commit = file_service.read_commit(&hash)?;
+    let head = file_service.read_head_commit()?;
+    let index = file_service.read_index()?;
+    let worktree = WorkTree::new(&file_service);
+    let status = worktree.status(&index, head.as_ref())?;
+
+    // the files the switch changes, by contents or by mode; only changes
+    // to these, and untracked files it would overwrite, are in the way
+    let empty = BTreeMap::new();
+    let current = head.as_ref().map_or(&empty, |head| head.files());
+    let head_mode = |path: &str| {
+        head.as_ref()
+            .map_or(FileMode::Regular, |head| head.mode(path))
+    };
+    let changed: BTreeSet<&String> = current
+        .keys()
+        .chain(commit.files().keys())
+        .filter(|path| {
+            current.get(*path) != commit.files().get(*path) || head_mode(path) != commit.mode(path)
+        })
+        .collect();
+    let local: BTreeMap<&String, Change> = status
+        .staged
+        .iter()
+        .chain(status.unstaged.iter())
+        .map(|(change, path)| (path, *change))
+        .collect();
+    let mut blocked: BTreeSet<String> = local
+        .keys()
+        .filter(|path| changed.contains(*path))
+        .map(|path| path.to_string())
+        .collect();
+    blocked.extend(status.unmerged.iter().cloned());
+    blocked.extend(
+        status
+            .untracked
+            .iter()
+            .filter(|path| commit.files().contains_key(*path))
+            .cloned(),
+    );
+    if !blocked.is_empty() {
+        return Err(Error::DirtyWorkTree(blocked.into_iter().collect()));
+    }
+
+    let only_changed = |files: &BTreeMap<String, String>| -> BTreeMap<String, String> {
+        files
+            .iter()
+            .filter(|(path, _)| changed.contains(path))
+            .map(|(path, hash)| (path.clone(), hash.clone()))
+            .collect()
+    };
+    worktree.checkout(
+        &file_service,
+        &only_changed(current),
+        &only_changed(commit.files()),
+        commit.modes(),
+    )?;
+    if let Some(name) = create {
+        refs.create_branch(name, &hash)?;
+    }
+    file_service.set_head(branch, &hash)?;
+
+    for (path, change) in local {
+        let code = match change {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+        };
+        writeln!(out, "{code}\t{path}")?;
+    }
+    match (create, branch) {
+        (Some(name), _) => writeln!(out, "Switched to a new branch '{name}'")?,
+        (None, Some(name)) => writeln!(out, "Switched to branch '{name}'")?,
+        (None, None) => {
+            let summary = commit.message().lines().next().unwrap_or_default();
+            let short = Abbrev::new(&file_service)?.shorten(&hash);
+            writeln!(out, "HEAD is now at {short} {summary}")?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use std::io::{self, Write};
+
+use crate::{
+    refs::{Head, Refs},
+    types::{Error, FileService, Result},
+};
+
+pub fn run(name: &str, target: Option<&str>, short: bool, quiet: bool) -> Result<()> {
+    let mut out = io::stdout().lock();
+    if name != "HEAD" {
+        return Err(Error::Usage(format!(
+            "'{name}' is not a symbolic ref, only HEAD is"
+        )));
+    }
+    let file_service = FileService::new()?;
+
+    if let Some(target) = target {
+        let valid = target
+            .strip_prefix("refs/")
+            .is_some_and(Refs::is_valid_name);
+        if !valid {
+            return Err(Error::InvalidRefName(target.to_string()));
+        }
+        return file_service.write_head(&Head::Symbolic(target.to_string()));
+    }
+
+    match file_service.read_head()? {
+        Head::Symbolic(target) if short => {
+            let short = ["refs/heads/", "refs/tags/", "refs/"]
+                .iter()
+                .find_map(|prefix| target.strip_prefix(prefix))
+                .unwrap_or(&target);
+            writeln!(out, "{short}")?;
+        }
+        Head::Symbolic(target) => writeln!(out, "{target}")?,
+        Head::Detached(_) if quiet => std::process::exit(1),
+        Head::Detached(_) => {
+            return Err(Error::Usage("ref HEAD is not a symbolic ref".into()));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::io::{self, Write};
+
+use super::verify_tag;
+use crate::{
+    cli::TagAction,
+    refs::Refs,
+    sign::SigningKey,
+    types::{FileService, ObjectKind, Result, Tag},
+};
+
+pub fn run(action: TagAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let refs = Refs::new(&file_service);
+
+    match action {
+        TagAction::List { pattern } => {
+            for (name, _) in refs.tags()? {
+                if pattern.as_deref().is_none_or(|p| name.starts_with(p)) {
+                    writeln!(out, "{name}")?;
+                }
+            }
+        }
+        TagAction::Create {
+            name,
+            target,
+            message,
+            force,
+            sign,
+        } => {
+            let hash = file_service.resolve(target.as_deref().unwrap_or("HEAD"))?;
+            let hash = match message {
+                Some(message) => {
+                    let tagger = file_service.signature("COMMITTER")?;
+                    let mut tag = Tag::new(&hash, ObjectKind::Commit, &name, tagger, &message);
+                    if sign {
+                        tag.set_signing_key(SigningKey::from_config(&file_service)?);
+                    }
+                    file_service.write_tag(&mut tag)?
+                }
+                None => hash,
+            };
+            refs.create_tag(&name, &hash, force)?;
+        }
+        TagAction::Delete { names } => {
+            for name in names {
+                let hash = refs.read(&Refs::tag_ref(&name)).unwrap_or_default();
+                refs.delete_tag(&name)?;
+                writeln!(
+                    out,
+                    "Deleted tag '{name}' (was {})",
+                    &hash[..hash.len().min(7)]
+                )?;
+            }
+        }
+        TagAction::Verify { names } => verify_tag::verify(&file_service, &names)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,7 @@
+use std::path::Path;
+
+use crate::{server, types::Result};
+
+pub fn run(path: &str) -> Result<()> {
+    server::serve_pipe(Path::new(path), false)
+}
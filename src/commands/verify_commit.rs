@@ -0,0 +1,21 @@
+use std::io::{self, Write};
+
+use crate::{
+    sign,
+    types::{FileService, Result},
+};
+
+pub fn run(commits: &[String]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    for revision in commits {
+        let hash = file_service.resolve(revision)?;
+        let verified = sign::verify_object(&file_service, &hash)?;
+        writeln!(
+            out,
+            "Good signature on {hash} by {} with ED25519 key {}",
+            verified.principal, verified.fingerprint
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::io::{self, Write};
+
+use crate::{
+    refs::Refs,
+    sign,
+    types::{Error, FileService, Result},
+};
+
+pub fn run(tags: &[String]) -> Result<()> {
+    let file_service = FileService::new()?;
+    verify(&file_service, tags)
+}
+
+/// Checks the signatures of the tags called `names`, shared with `tag -v`.
+pub(super) fn verify(file_service: &FileService, names: &[String]) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let refs = Refs::new(file_service);
+    for name in names {
+        let Some(hash) = refs.read(&Refs::tag_ref(name)) else {
+            return Err(Error::UnknownRevision(name.clone()));
+        };
+        let verified = sign::verify_object(file_service, &hash)?;
+        writeln!(
+            out,
+            "Good signature on tag '{name}' by {} with ED25519 key {}",
+            verified.principal, verified.fingerprint
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    cli::WorktreeAction,
+    refs::{Head, Refs},
+    revision::Abbrev,
+    types::{Error, FileService, Result},
+    worktrees::{self, Checkout},
+};
+
+pub fn run(action: WorktreeAction) -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    match action {
+        WorktreeAction::Add {
+            path,
+            branch,
+            detach,
+            commit,
+        } => {
+            let refs = Refs::new(&file_service);
+            let start = || file_service.resolve(commit.as_deref().unwrap_or("HEAD"));
+            let checkout = match (branch, commit.as_deref()) {
+                (Some(branch), _) => Checkout::NewBranch(branch, start()?),
+                (None, Some(commit)) if !detach && refs.is_branch(commit) => {
+                    Checkout::Branch(commit.to_string())
+                }
+                _ if detach || commit.is_some() => Checkout::Detached(start()?),
+                // like git, default to a branch named after the directory
+                _ => {
+                    let name = Path::new(&path)
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .ok_or_else(|| {
+                            Error::Usage(format!("cannot name a branch after '{path}'"))
+                        })?
+                        .to_string();
+                    match refs.is_branch(&name) {
+                        true => Checkout::Branch(name),
+                        false => Checkout::NewBranch(name, start()?),
+                    }
+                }
+            };
+            worktrees::add(&file_service, Path::new(&path), &checkout)?;
+            match &checkout {
+                Checkout::Branch(name) => {
+                    writeln!(out, "Preparing worktree (checking out '{name}')")?
+                }
+                Checkout::NewBranch(name, _) => {
+                    writeln!(out, "Preparing worktree (new branch '{name}')")?
+                }
+                Checkout::Detached(hash) => {
+                    let short = Abbrev::new(&file_service)?.shorten(hash).to_string();
+                    writeln!(out, "Preparing worktree (detached HEAD {short})")?;
+                }
+            }
+        }
+        WorktreeAction::List => {
+            let worktrees = worktrees::list(&file_service)?;
+            let abbrev = Abbrev::new(&file_service)?;
+            let width = worktrees
+                .iter()
+                .map(|worktree| worktree.path.display().to_string().len())
+                .max()
+                .unwrap_or_default();
+            for worktree in worktrees {
+                if worktree.bare {
+                    writeln!(out, "{:width$} (bare)", worktree.path.display().to_string())?;
+                    continue;
+                }
+                let (hash, place) = match &worktree.head {
+                    Some(Head::Symbolic(name)) => (
+                        Refs::new(&file_service).read(name),
+                        format!("[{}]", name.strip_prefix("refs/heads/").unwrap_or(name)),
+                    ),
+                    Some(Head::Detached(hash)) => {
+                        (Some(hash.clone()), "(detached HEAD)".to_string())
+                    }
+                    None => (None, "(unreadable HEAD)".to_string()),
+                };
+                let hash = hash
+                    .as_deref()
+                    .map_or("0000000", |hash| abbrev.shorten(hash));
+                let missing = if worktree.is_missing() {
+                    " missing"
+                } else {
+                    ""
+                };
+                writeln!(
+                    out,
+                    "{:width$} {hash} {place}{missing}",
+                    worktree.path.display().to_string()
+                )?;
+            }
+        }
+        WorktreeAction::Remove { path, force } => {
+            worktrees::remove(&file_service, Path::new(&path), force)?;
+        }
+    }
+    Ok(())
+}
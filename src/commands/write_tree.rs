@@ -0,0 +1,22 @@
+use std::io::{self, Write};
+
+use crate::{
+    types::{Error, FileService, Result},
+    worktree::WorkTree,
+};
+
+pub fn run() -> Result<()> {
+    let mut out = io::stdout().lock();
+    let file_service = FileService::new()?;
+    let index = file_service.read_index()?;
+    if !index.conflicts().is_empty() {
+        return Err(Error::UnmergedPaths(
+            index.conflicts().iter().cloned().collect(),
+        ));
+    }
+    let head = file_service.read_head_commit()?;
+    let files = WorkTree::tracked_files(&index, head.as_ref());
+    let modes = WorkTree::tracked_modes(&index, head.as_ref());
+    writeln!(out, "{}", file_service.write_tree(&files, &modes)?)?;
+    Ok(())
+}
@@ -0,0 +1,436 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::types::{Context, Error, Result};
+
+/// Where a config file lives, from the least to the most specific.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ConfigScope {
+    /// `$BLIP_CONFIG_SYSTEM`, or `/etc/blipconfig`.
+    System,
+    /// `$XDG_CONFIG_HOME/blip/config`, or `~/.config/blip/config`.
+    Global,
+    /// `.blip/config` of the repository.
+    Local,
+}
+
+/// One line of a config file, kept verbatim so that rewriting the file after
+/// a `set` leaves comments and layout alone.
+#[derive(Debug, Clone)]
+enum Line {
+    /// A `[section]` or `[section "subsection"]` header, with its name
+    /// normalized to `section` or `section.subsection`.
+    Section(String, String),
+    Entry {
+        section: String,
+        key: String,
+        value: Option<String>,
+        raw: String,
+    },
+    Other(String),
+}
+
+/// An INI style config file such as `.blip/config`:
+///
+/// ```text
+/// [user]
+///     name = Jane Doe
+/// [remote "origin"]
+///     url = ../upstream
+/// ```
+///
+/// Entries are addressed as `user.name` or `remote.origin.url`. Section and
+/// key names are case insensitive, subsection names are not.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    path: PathBuf,
+    lines: Vec<Line>,
+}
+
+impl ConfigFile {
+    /// Reads the config file at `path`, treating a missing file as empty.
+    pub fn load(path: PathBuf) -> Result<ConfigFile> {
+        if !path.is_file() {
+            return Ok(ConfigFile {
+                path,
+                lines: Vec::new(),
+            });
+        }
+        let contents = fs::read_to_string(&path).context("read", &path)?;
+        ConfigFile::parse(path, &contents)
+    }
+
+    /// Parses `contents` as the config file at `path`, which is only
+    /// written to by [`ConfigFile::save`].
+    pub fn parse(path: PathBuf, contents: &str) -> Result<ConfigFile> {
+        let mut config = ConfigFile {
+            path,
+            lines: Vec::new(),
+        };
+        let mut section = String::new();
+        for (number, raw) in contents.lines().enumerate() {
+            let line = raw.trim();
+            let parsed = if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                Line::Other(raw.to_string())
+            } else if line.starts_with('[') {
+                section = ConfigFile::parse_section(line)
+                    .ok_or_else(|| config.error(number, "bad section header"))?;
+                Line::Section(section.clone(), raw.to_string())
+            } else {
+                if section.is_empty() {
+                    return Err(config.error(number, "entry outside of a section"));
+                }
+                let (key, value) = match line.split_once('=') {
+                    Some((key, value)) => (key.trim(), Some(value)),
+                    None => (line, None),
+                };
+                if !ConfigFile::is_valid_key(key) {
+                    return Err(config.error(number, "bad key name"));
+                }
+                let value = match value {
+                    Some(value) => Some(
+                        ConfigFile::parse_value(value)
+                            .ok_or_else(|| config.error(number, "bad value"))?,
+                    ),
+                    None => None,
+                };
+                Line::Entry {
+                    section: section.clone(),
+                    key: key.to_lowercase(),
+                    value,
+                    raw: raw.to_string(),
+                }
+            };
+            config.lines.push(parsed);
+        }
+        Ok(config)
+    }
+
+    fn error(&self, number: usize, reason: &str) -> Error {
+        Error::InvalidConfig(format!(
+            "{reason} at line {} of {}",
+            number + 1,
+            self.path.display()
+        ))
+    }
+
+    fn parse_section(line: &str) -> Option<String> {
+        let inner = line.strip_prefix('[')?.split_once(']')?.0.trim();
+        match inner.split_once(char::is_whitespace) {
+            Some((name, subsection)) => {
+                let subsection = subsection.trim().strip_prefix('"')?.strip_suffix('"')?;
+                ConfigFile::is_valid_key(name)
+                    .then(|| format!("{}.{subsection}", name.to_lowercase()))
+            }
+            None => ConfigFile::is_valid_key(inner).then(|| inner.to_lowercase()),
+        }
+    }
+
+    fn is_valid_key(key: &str) -> bool {
+        key.starts_with(|c: char| c.is_ascii_alphabetic())
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    }
+
+    /// Unquotes a value, dropping any trailing comment.
+    fn parse_value(input: &str) -> Option<String> {
+        let mut value = String::new();
+        let mut quoted = false;
+        // whitespace is only kept when something follows it
+        let mut pending = String::new();
+        let mut chars = input.trim_start().chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' => {
+                    value.push_str(&pending);
+                    pending.clear();
+                    value.push(match chars.next()? {
+                        'n' => '\n',
+                        't' => '\t',
+                        c @ ('"' | '\\') => c,
+                        _ => return None,
+                    });
+                    continue;
+                }
+                '#' | ';' if !quoted => break,
+                c if c.is_whitespace() && !quoted => {
+                    pending.push(c);
+                    continue;
+                }
+                c => {
+                    value.push_str(&pending);
+                    pending.clear();
+                    value.push(c);
+                    continue;
+                }
+            }
+            value.push_str(&pending);
+            pending.clear();
+        }
+        (!quoted).then_some(value)
+    }
+
+    fn format_value(value: &str) -> String {
+        let needs_quotes = value.starts_with(char::is_whitespace)
+            || value.ends_with(char::is_whitespace)
+            || value.contains(['#', ';']);
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\t', "\\t");
+        if needs_quotes {
+            format!("\"{escaped}\"")
+        } else {
+            escaped
+        }
+    }
+
+    /// Splits `section.key` or `section.subsection.key` into the normalized
+    /// section and key.
+    fn split_name(name: &str) -> Result<(String, String)> {
+        let invalid = || Error::Usage(format!("invalid config key '{name}'"));
+        let (section, key) = name.rsplit_once('.').ok_or_else(invalid)?;
+        let section = match section.split_once('.') {
+            Some((section, subsection)) => format!("{}.{subsection}", section.to_lowercase()),
+            None => section.to_lowercase(),
+        };
+        if !ConfigFile::is_valid_key(key) || section.is_empty() {
+            return Err(invalid());
+        }
+        Ok((section, key.to_lowercase()))
+    }
+
+    /// Every value set for `name`, in file order. An entry given without a
+    /// `=` has the value `true`.
+    pub fn get_all(&self, name: &str) -> Vec<String> {
+        let Ok((section, key)) = ConfigFile::split_name(name) else {
+            return Vec::new();
+        };
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Entry {
+                    section: s,
+                    key: k,
+                    value,
+                    ..
+                } if *s == section && *k == key => {
+                    Some(value.clone().unwrap_or_else(|| "true".to_string()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Every entry as `section.key` and value pairs, in file order.
+    pub fn list(&self) -> Vec<(String, String)> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Entry {
+                    section,
+                    key,
+                    value,
+                    ..
+                } => Some((
+                    format!("{section}.{key}"),
+                    value.clone().unwrap_or_else(|| "true".to_string()),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Sets `name`, replacing its last occurrence or appending it to the end
+    /// of its section, which is created when missing.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let (section, key) = ConfigFile::split_name(name)?;
+        let entry = Line::Entry {
+            section: section.clone(),
+            key: key.clone(),
+            value: Some(value.to_string()),
+            raw: format!("\t{key} = {}", ConfigFile::format_value(value)),
+        };
+
+        let existing = self.lines.iter().rposition(|line| {
+            matches!(line, Line::Entry { section: s, key: k, .. } if *s == section && *k == key)
+        });
+        if let Some(pos) = existing {
+            self.lines[pos] = entry;
+            return Ok(());
+        }
+
+        let last_in_section = self.lines.iter().rposition(|line| match line {
+            Line::Section(s, _) | Line::Entry { section: s, .. } => *s == section,
+            Line::Other(_) => false,
+        });
+        match last_in_section {
+            Some(pos) => self.lines.insert(pos + 1, entry),
+            None => {
+                let header = match section.split_once('.') {
+                    Some((name, subsection)) => format!("[{name} \"{subsection}\"]"),
+                    None => format!("[{section}]"),
+                };
+                self.lines.push(Line::Section(section, header));
+                self.lines.push(entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds another value for `name` after any it already has.
+    pub fn add(&mut self, name: &str, value: &str) -> Result<()> {
+        let (section, key) = ConfigFile::split_name(name)?;
+        let last_in_section = self.lines.iter().rposition(|line| match line {
+            Line::Section(s, _) | Line::Entry { section: s, .. } => *s == section,
+            Line::Other(_) => false,
+        });
+        match last_in_section {
+            Some(pos) => self.lines.insert(
+                pos + 1,
+                Line::Entry {
+                    section,
+                    key: key.clone(),
+                    value: Some(value.to_string()),
+                    raw: format!("\t{key} = {}", ConfigFile::format_value(value)),
+                },
+            ),
+            None => return self.set(name, value),
+        }
+        Ok(())
+    }
+
+    /// Removes a section such as `remote.origin` along with every entry in
+    /// it, returning whether it existed.
+    pub fn remove_section(&mut self, section: &str) -> Result<bool> {
+        let (section, _) = ConfigFile::split_name(&format!("{section}.key"))?;
+        let before = self.lines.len();
+        self.lines.retain(|line| match line {
+            Line::Section(s, _) | Line::Entry { section: s, .. } => *s != section,
+            Line::Other(_) => true,
+        });
+        Ok(self.lines.len() != before)
+    }
+
+    /// Removes every occurrence of `name`, returning whether any existed.
+    pub fn unset(&mut self, name: &str) -> Result<bool> {
+        let (section, key) = ConfigFile::split_name(name)?;
+        let before = self.lines.len();
+        self.lines.retain(
+            |line| !matches!(line, Line::Entry { section: s, key: k, .. } if *s == section && *k == key),
+        );
+        Ok(self.lines.len() != before)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut contents = String::new();
+        for line in self.lines.iter() {
+            let (Line::Section(_, raw) | Line::Entry { raw, .. } | Line::Other(raw)) = line;
+            contents.push_str(raw);
+            contents.push('\n');
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).context("create", parent)?;
+        }
+        fs::write(&self.path, contents).context("write", &self.path)
+    }
+}
+
+/// The system, global and repository config files layered on top of each
+/// other, with later layers overriding earlier ones.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    layers: Vec<(ConfigScope, ConfigFile)>,
+}
+
+/// The per-user blip directory, `$XDG_CONFIG_HOME/blip` defaulting to
+/// `~/.config/blip`, which holds the global config and ignore files.
+pub fn user_dir() -> Option<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("blip"))
+}
+
+impl ConfigScope {
+    /// Path of the config file for this scope. Without a repository there
+    /// is no local config.
+    pub fn path(self, blip_dir: Option<&Path>) -> Option<PathBuf> {
+        match self {
+            ConfigScope::System => Some(
+                env::var_os("BLIP_CONFIG_SYSTEM")
+                    .map_or_else(|| PathBuf::from("/etc/blipconfig"), PathBuf::from),
+            ),
+            ConfigScope::Global => user_dir().map(|dir| dir.join("config")),
+            ConfigScope::Local => blip_dir.map(|dir| dir.join("config")),
+        }
+    }
+}
+
+impl Config {
+    /// Reads every config file that applies, `blip_dir` being `None` outside
+    /// of a repository.
+    pub fn load(blip_dir: Option<&Path>) -> Result<Config> {
+        let mut config = Config::default();
+        for scope in [ConfigScope::System, ConfigScope::Global, ConfigScope::Local] {
+            if let Some(path) = scope.path(blip_dir) {
+                config.layers.push((scope, ConfigFile::load(path)?));
+            }
+        }
+        Ok(config)
+    }
+
+    /// A view of a single config file.
+    pub fn scoped(scope: ConfigScope, blip_dir: Option<&Path>) -> Result<Config> {
+        let path = scope
+            .path(blip_dir)
+            .ok_or_else(|| Error::Usage("not in a blip repository".into()))?;
+        Ok(Config {
+            layers: vec![(scope, ConfigFile::load(path)?)],
+        })
+    }
+
+    /// Every value set for `name` across all layers, least specific first.
+    pub fn get_all(&self, name: &str) -> Vec<String> {
+        self.layers
+            .iter()
+            .flat_map(|(_, file)| file.get_all(name))
+            .collect()
+    }
+
+    /// The value of `name`, the last one winning if it is set repeatedly.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.get_all(name).pop()
+    }
+
+    /// Interprets `name` as a boolean the way git does.
+    pub fn get_bool(&self, name: &str) -> Result<Option<bool>> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(value) => match value.to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Ok(Some(true)),
+                "false" | "no" | "off" | "0" | "" => Ok(Some(false)),
+                _ => Err(Error::InvalidConfig(format!(
+                    "bad boolean value '{value}' for '{name}'"
+                ))),
+            },
+        }
+    }
+
+    /// Every entry with the scope it comes from, least specific first.
+    pub fn list(&self) -> Vec<(ConfigScope, String, String)> {
+        self.layers
+            .iter()
+            .flat_map(|(scope, file)| {
+                file.list()
+                    .into_iter()
+                    .map(move |(name, value)| (*scope, name, value))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,66 @@
+//! Conventional Commits: messages whose first line reads
+//! `<type>[(<scope>)][!]: <subject>`, which `commit` holds messages to
+//! when `commit.conventional` is set.
+
+/// The types a header may start with unless `commit.conventionalTypes`
+/// lists others.
+pub const TYPES: [&str; 11] = [
+    "feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert",
+];
+
+/// The longest a header may be, in characters.
+pub const MAX_HEADER: usize = 72;
+
+/// What keeps `message` from being a conventional commit with one of
+/// `types`, nothing if it is one.
+pub fn check(message: &str, types: &[&str]) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut lines = message.lines();
+    let header = lines.next().unwrap_or_default();
+    let Some((prefix, subject)) = header.split_once(':') else {
+        problems.push(format!(
+            "the header '{header}' is not of the form '<type>[(<scope>)][!]: <subject>'"
+        ));
+        return problems;
+    };
+    let prefix = prefix.strip_suffix('!').unwrap_or(prefix);
+    let (kind, scope) = match prefix.split_once('(') {
+        Some((kind, rest)) => match rest.strip_suffix(')') {
+            Some(scope) => (kind, Some(scope)),
+            None => {
+                problems.push(format!("the scope in '{prefix}' is not closed by ')'"));
+                (kind, None)
+            }
+        },
+        None => (prefix, None),
+    };
+    if kind.is_empty() {
+        problems.push("the type is missing".to_string());
+    } else if !types.contains(&kind) {
+        problems.push(format!(
+            "the type '{kind}' is not one of {}",
+            types.join(", ")
+        ));
+    }
+    if let Some(scope) = scope {
+        if scope.trim().is_empty() || scope.contains(['(', ')']) {
+            problems.push(format!("the scope '({scope})' is not valid"));
+        }
+    }
+    if !subject.starts_with(' ') {
+        problems.push("the ':' is not followed by a space".to_string());
+    }
+    if subject.trim().is_empty() {
+        problems.push("the subject is empty".to_string());
+    }
+    let length = header.chars().count();
+    if length > MAX_HEADER {
+        problems.push(format!(
+            "the header is {length} characters long, more than {MAX_HEADER}"
+        ));
+    }
+    if lines.next().is_some_and(|line| !line.trim().is_empty()) {
+        problems.push("the header is not followed by a blank line".to_string());
+    }
+    problems
+}
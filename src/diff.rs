@@ -0,0 +1,790 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::{
+    binary,
+    config::Config,
+    types::{Error, FileMode, Result},
+    worktree::Change,
+};
+
+/// Follows a patch line that does not end in a newline.
+pub const NO_NEWLINE: &str = "\\ No newline at end of file";
+
+/// The mode git gives a submodule, the commit it is at standing in for a
+/// file.
+pub const GITLINK_MODE: &str = "160000";
+
+/// One step of an edit script turning the old lines into the new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Equal { old: usize, new: usize },
+    Delete { old: usize },
+    Insert { new: usize },
+}
+
+/// How many lines a change to one file adds and deletes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStat {
+    pub path: String,
+    pub added: usize,
+    pub deleted: usize,
+    /// The sizes in bytes before and after of a file shown as binary,
+    /// whose lines are not counted.
+    pub binary: Option<(usize, usize)>,
+}
+
+/// The summaries of a change `--stat`, `--numstat` and `--shortstat`
+/// print.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatFormat {
+    /// A line per file with a bar of `+` and `-`, then the totals.
+    Stat,
+    /// Tab separated counts per file, `-` for binary files.
+    NumStat,
+    /// The totals alone.
+    ShortStat,
+}
+
+/// How similar, in percent, two files have to be for one to count as a
+/// rename of the other unless told otherwise.
+pub const RENAME_THRESHOLD: u8 = 50;
+
+/// A file that turns up under another path on the other side of a
+/// comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+    /// How much of the content the two share, in percent.
+    pub similarity: u8,
+    /// Whether `from` is still there, making this a copy.
+    pub copy: bool,
+}
+
+impl Rename {
+    /// `R` or `C` with the similarity, as `--name-status` shows it.
+    pub fn status(&self) -> String {
+        let letter = if self.copy { 'C' } else { 'R' };
+        format!("{letter}{}", self.similarity)
+    }
+}
+
+/// How `diff` renders the changes it finds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffOptions {
+    /// How renames are found, none without.
+    pub renames: Option<RenameDetection>,
+    /// Whether binary files are written out in full, in a patch `apply`
+    /// can apply, rather than only said to differ.
+    pub binary: bool,
+    pub whitespace: Whitespace,
+}
+
+/// The changes in whitespace a comparison of lines overlooks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Whitespace {
+    /// Lines that only differ in whitespace are the same.
+    pub ignore_all: bool,
+    /// Lines that only differ in how much whitespace there is where they
+    /// have some, or in whitespace at their ends, are the same.
+    pub ignore_change: bool,
+    /// Changes that only add or delete blank lines are not shown.
+    pub ignore_blank_lines: bool,
+}
+
+impl Whitespace {
+    /// What is left of `line` to compare, its newline kept.
+    fn normalize(&self, line: &str) -> String {
+        let (text, newline) = match line.strip_suffix('\n') {
+            Some(text) => (text, "\n"),
+            None => (line, ""),
+        };
+        let text = if self.ignore_all {
+            text.split_whitespace().collect()
+        } else if self.ignore_change {
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            text.to_string()
+        };
+        text + newline
+    }
+}
+
+/// How renames are looked for, see [`find_renames`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenameDetection {
+    /// The least similarity, in percent, of a rename.
+    pub threshold: u8,
+    /// Whether added files are also matched with modified ones.
+    pub copies: bool,
+}
+
+impl RenameDetection {
+    /// Renames found with the default threshold.
+    pub const DEFAULT: RenameDetection = RenameDetection {
+        threshold: RENAME_THRESHOLD,
+        copies: false,
+    };
+
+    /// Reads `diff.renames`, `true` unless set, `false` or `copies`, and
+    /// `diff.renameThreshold`, a percentage.
+    pub fn from_config(config: &Config) -> Result<Option<RenameDetection>> {
+        let copies = match config.get("diff.renames").as_deref() {
+            Some("copy" | "copies") => true,
+            _ => match config.get_bool("diff.renames")? {
+                Some(false) => return Ok(None),
+                _ => false,
+            },
+        };
+        let threshold = match config.get("diff.renameThreshold") {
+            Some(value) => parse_threshold(&value).ok_or_else(|| {
+                Error::InvalidConfig(format!(
+                    "bad threshold '{value}' for 'diff.renameThreshold'"
+                ))
+            })?,
+            None => RENAME_THRESHOLD,
+        };
+        Ok(Some(RenameDetection { threshold, copies }))
+    }
+}
+
+/// Reads a similarity threshold such as `60` or `60%`.
+pub fn parse_threshold(value: &str) -> Option<u8> {
+    let threshold: u8 = value.strip_suffix('%').unwrap_or(value).parse().ok()?;
+    (threshold <= 100).then_some(threshold)
+}
+
+/// How much of two texts is the same, in percent of the longer one's
+/// lines.
+pub fn similarity(old: &str, new: &str) -> u8 {
+    if old == new {
+        return 100;
+    }
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let same = diff_lines(&old_lines, &new_lines)
+        .iter()
+        .filter(|edit| matches!(edit, Edit::Equal { .. }))
+        .count();
+    // only the same contents make a whole match
+    let longer = old_lines.len().max(new_lines.len());
+    (same * 100 / longer).min(99) as u8
+}
+
+/// Pairs the deleted files among `changes` with the added ones at least
+/// `detection.threshold` percent similar to them, the most similar pairs
+/// first, reading their contents with `old` and `new`. With copies, the
+/// added files left are matched with the old contents of modified files
+/// too. Empty files are only paired with the same contents.
+pub fn find_renames(
+    changes: &[(Change, String)],
+    detection: RenameDetection,
+    old: impl Fn(&str) -> Result<Option<String>>,
+    new: impl Fn(&str) -> Result<Option<String>>,
+) -> Result<Vec<Rename>> {
+    let paths = |kind: Change| changes.iter().filter(move |(change, _)| *change == kind);
+    if paths(Change::Added).next().is_none() {
+        return Ok(Vec::new());
+    }
+    let read = |read: &dyn Fn(&str) -> Result<Option<String>>, kind: Change| {
+        paths(kind)
+            .map(|(_, path)| Ok((path.as_str(), read(path)?.unwrap_or_default())))
+            .collect::<Result<Vec<_>>>()
+    };
+    let added = read(&new, Change::Added)?;
+    let deleted = read(&old, Change::Deleted)?;
+    let modified = if detection.copies {
+        read(&old, Change::Modified)?
+    } else {
+        Vec::new()
+    };
+
+    let mut renames = Vec::new();
+    let mut taken = BTreeSet::new();
+    for (sources, copy) in [(&deleted, false), (&modified, true)] {
+        let mut candidates = Vec::new();
+        for (to, new) in added.iter().filter(|(to, _)| !taken.contains(to)) {
+            for (from, old) in sources.iter() {
+                if (old.is_empty() || new.is_empty()) && old != new {
+                    continue;
+                }
+                let similarity = similarity(old, new);
+                if similarity >= detection.threshold {
+                    candidates.push((similarity, *from, *to));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)).then(a.2.cmp(b.2)));
+        let mut renamed = BTreeSet::new();
+        for (similarity, from, to) in candidates {
+            // a deleted file goes to one place, a modified one stays
+            if taken.contains(to) || (!copy && renamed.contains(from)) {
+                continue;
+            }
+            taken.insert(to);
+            renamed.insert(from);
+            renames.push(Rename {
+                from: from.to_string(),
+                to: to.to_string(),
+                similarity,
+                copy,
+            });
+        }
+    }
+    renames.sort_by(|a, b| a.to.cmp(&b.to));
+    Ok(renames)
+}
+
+/// The longest a `--stat` bar gets; longer ones are scaled down.
+const STAT_BAR: usize = 40;
+
+/// A run of edits with surrounding context, in unified diff terms.
+#[derive(Debug)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub edits: Vec<Edit>,
+}
+
+/// Computes a shortest edit script between two sequences of lines using
+/// the linear space variant of Myers' O(ND) algorithm.
+pub fn diff_lines<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Edit> {
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    let mut frontiers = Frontiers::new(old.len(), new.len());
+    diff_between(
+        old,
+        new,
+        (0, old.len()),
+        (0, new.len()),
+        &mut frontiers,
+        &mut edits,
+    );
+
+    // within each change deletions come first, as a unified diff shows them
+    let mut start = 0;
+    while start < edits.len() {
+        let end = edits[start..]
+            .iter()
+            .position(|edit| matches!(edit, Edit::Equal { .. }))
+            .map_or(edits.len(), |i| start + i);
+        edits[start..end].sort_by_key(|edit| matches!(edit, Edit::Insert { .. }));
+        start = end + 1;
+    }
+    edits
+}
+
+/// The furthest points reached along each diagonal by the searches from
+/// the start and from the end in [`middle_snake`], reused between calls.
+struct Frontiers {
+    forward: Vec<usize>,
+    backward: Vec<usize>,
+    offset: isize,
+}
+
+impl Frontiers {
+    fn new(n: usize, m: usize) -> Frontiers {
+        let max = (n + m).div_ceil(2) + 1;
+        Frontiers {
+            forward: vec![0; 2 * max + 1],
+            backward: vec![0; 2 * max + 1],
+            offset: max as isize,
+        }
+    }
+}
+
+/// Appends to `edits` a shortest edit script from `old[x0..x1]` to
+/// `new[y0..y1]`, splitting the problem at its middle snake.
+fn diff_between<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    (mut x0, mut x1): (usize, usize),
+    (mut y0, mut y1): (usize, usize),
+    frontiers: &mut Frontiers,
+    edits: &mut Vec<Edit>,
+) {
+    while x0 < x1 && y0 < y1 && old[x0] == new[y0] {
+        edits.push(Edit::Equal { old: x0, new: y0 });
+        x0 += 1;
+        y0 += 1;
+    }
+    let mut suffix = 0;
+    while x0 < x1 && y0 < y1 && old[x1 - 1] == new[y1 - 1] {
+        x1 -= 1;
+        y1 -= 1;
+        suffix += 1;
+    }
+
+    if x0 == x1 {
+        edits.extend((y0..y1).map(|new| Edit::Insert { new }));
+    } else if y0 == y1 {
+        edits.extend((x0..x1).map(|old| Edit::Delete { old }));
+    } else {
+        let (x, y) = middle_snake(&old[x0..x1], &new[y0..y1], frontiers);
+        diff_between(old, new, (x0, x0 + x), (y0, y0 + y), frontiers, edits);
+        diff_between(old, new, (x0 + x, x1), (y0 + y, y1), frontiers, edits);
+    }
+    edits.extend((0..suffix).map(|i| Edit::Equal {
+        old: x1 + i,
+        new: y1 + i,
+    }));
+}
+
+/// Finds where a shortest edit script from `old` to `new` crosses its
+/// middle, searching from both ends at once until the two searches meet.
+/// Neither sequence is empty, and they differ in their first and last
+/// elements, so the point found is never a corner and both halves are
+/// smaller.
+fn middle_snake<T: PartialEq>(old: &[T], new: &[T], frontiers: &mut Frontiers) -> (usize, usize) {
+    let (n, m) = (old.len(), new.len());
+    let delta = n as isize - m as isize;
+    let odd = delta & 1 == 1;
+    let offset = frontiers.offset;
+    let at = |k: isize| (k + offset) as usize;
+    let Frontiers {
+        forward, backward, ..
+    } = frontiers;
+    forward[at(1)] = 0;
+    backward[at(1)] = 0;
+
+    for d in 0..=((n + m).div_ceil(2)) as isize {
+        // forward, x being how far along `old` each diagonal got
+        let mut k = -d;
+        while k <= d {
+            let (below, above) = (at(k - 1), at(k + 1));
+            let mut x = if k == -d || (k != d && forward[below] < forward[above]) {
+                forward[above]
+            } else {
+                forward[below] + 1
+            };
+            let start = (x, (x as isize - k) as usize);
+            let mut y = start.1;
+            while x < n && y < m && old[x] == new[y] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+            let opposite = delta - k;
+            if odd && opposite.abs() < d && x + backward[at(opposite)] >= n {
+                return start;
+            }
+            k += 2;
+        }
+
+        // backward, x being how far back from the end of `old`
+        let mut k = -d;
+        while k <= d {
+            let (below, above) = (at(k - 1), at(k + 1));
+            let mut x = if k == -d || (k != d && backward[below] < backward[above]) {
+                backward[above]
+            } else {
+                backward[below] + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            while x < n && y < m && old[n - x - 1] == new[m - y - 1] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(k)] = x;
+            let opposite = delta - k;
+            if !odd && opposite.abs() <= d && x + forward[at(opposite)] >= n {
+                return (n - x, m - y);
+            }
+            k += 2;
+        }
+    }
+    unreachable!("the searches from both ends always meet")
+}
+
+/// Groups an edit script into hunks with `context` lines around each change.
+pub fn hunks(edits: &[Edit], context: usize) -> Vec<Hunk> {
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal { .. }))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut hunks = Vec::new();
+    let mut i = 0;
+    while i < changes.len() {
+        let start = changes[i].saturating_sub(context);
+        let mut end = (changes[i] + context + 1).min(edits.len());
+        while i + 1 < changes.len() && changes[i + 1] <= end + context {
+            i += 1;
+            end = (changes[i] + context + 1).min(edits.len());
+        }
+        i += 1;
+
+        let slice = &edits[start..end];
+        let (mut old_start, mut new_start) = position(edits, start);
+        let old_len = slice
+            .iter()
+            .filter(|e| !matches!(e, Edit::Insert { .. }))
+            .count();
+        let new_len = slice
+            .iter()
+            .filter(|e| !matches!(e, Edit::Delete { .. }))
+            .count();
+
+        // unified diffs number an empty side by the line before it
+        if old_len > 0 {
+            old_start += 1;
+        }
+        if new_len > 0 {
+            new_start += 1;
+        }
+
+        hunks.push(Hunk {
+            old_start,
+            old_len,
+            new_start,
+            new_len,
+            edits: slice.to_vec(),
+        });
+    }
+    hunks
+}
+
+/// Zero based line numbers in the old and new file at which edit `at` begins.
+fn position(edits: &[Edit], at: usize) -> (usize, usize) {
+    edits[..at]
+        .iter()
+        .fold((0, 0), |(old, new), edit| match edit {
+            Edit::Equal { .. } => (old + 1, new + 1),
+            Edit::Delete { .. } => (old + 1, new),
+            Edit::Insert { .. } => (old, new + 1),
+        })
+}
+
+/// Like [`diff_lines`], but taking lines that only differ in the
+/// whitespace `whitespace` overlooks for the same.
+pub fn diff_lines_ignoring(old: &[&str], new: &[&str], whitespace: Whitespace) -> Vec<Edit> {
+    if !whitespace.ignore_all && !whitespace.ignore_change {
+        return diff_lines(old, new);
+    }
+    let normalize = |lines: &[&str]| -> Vec<String> {
+        lines
+            .iter()
+            .map(|line| whitespace.normalize(line))
+            .collect()
+    };
+    let (old, new) = (normalize(old), normalize(new));
+    let old: Vec<&str> = old.iter().map(String::as_str).collect();
+    let new: Vec<&str> = new.iter().map(String::as_str).collect();
+    diff_lines(&old, &new)
+}
+
+/// Renders the unified diff between two texts, without any file header.
+/// A last line without a newline is marked as such, as git does.
+pub fn unified(old: &str, new: &str, context: usize) -> String {
+    unified_ignoring(old, new, context, Whitespace::default())
+}
+
+/// Like [`unified`], overlooking the changes in whitespace `whitespace`
+/// says. Lines taken for the same are shown as the new text has them.
+pub fn unified_ignoring(old: &str, new: &str, context: usize, whitespace: Whitespace) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines_ignoring(&old_lines, &new_lines, whitespace);
+    let overlooked = |edit: &Edit| match *edit {
+        Edit::Equal { .. } => true,
+        Edit::Delete { old } => old_lines[old].trim().is_empty(),
+        Edit::Insert { new } => new_lines[new].trim().is_empty(),
+    };
+
+    let mut out = String::new();
+    for hunk in hunks(&edits, context) {
+        // a hunk of blank lines alone is left out, those next to other
+        // changes are shown with them
+        if whitespace.ignore_blank_lines && hunk.edits.iter().all(overlooked) {
+            continue;
+        }
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(hunk.old_start, hunk.old_len),
+            range(hunk.new_start, hunk.new_len)
+        )
+        .unwrap();
+        for edit in hunk.edits {
+            let (marker, line) = match edit {
+                Edit::Equal { new, .. } => (' ', new_lines[new]),
+                Edit::Delete { old } => ('-', old_lines[old]),
+                Edit::Insert { new } => ('+', new_lines[new]),
+            };
+            match line.strip_suffix('\n') {
+                Some(line) => writeln!(out, "{marker}{line}"),
+                None => writeln!(out, "{marker}{line}\n{NO_NEWLINE}"),
+            }
+            .unwrap();
+        }
+    }
+    out
+}
+
+/// The numbers of lines added and deleted between two texts.
+pub fn line_counts(old: &str, new: &str) -> (usize, usize) {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+    let added = edits
+        .iter()
+        .filter(|edit| matches!(edit, Edit::Insert { .. }))
+        .count();
+    let deleted = edits
+        .iter()
+        .filter(|edit| matches!(edit, Edit::Delete { .. }))
+        .count();
+    (added, deleted)
+}
+
+/// Renders `stats` the way `format` says, nothing if there are none.
+pub fn stat(stats: &[FileStat], format: StatFormat) -> String {
+    let mut out = String::new();
+    if stats.is_empty() {
+        return out;
+    }
+    if format == StatFormat::NumStat {
+        for stat in stats {
+            if stat.binary.is_some() {
+                writeln!(out, "-\t-\t{}", stat.path).unwrap();
+            } else {
+                writeln!(out, "{}\t{}\t{}", stat.added, stat.deleted, stat.path).unwrap();
+            }
+        }
+        return out;
+    }
+
+    if format == StatFormat::Stat {
+        let name_width = stats
+            .iter()
+            .map(|stat| stat.path.chars().count())
+            .max()
+            .unwrap_or_default();
+        let most = stats
+            .iter()
+            .map(|stat| stat.added + stat.deleted)
+            .max()
+            .unwrap_or_default();
+        let count_width = most.to_string().len();
+        for stat in stats {
+            let path = &stat.path;
+            if let Some((before, after)) = stat.binary {
+                writeln!(out, " {path:name_width$} | Bin {before} -> {after} bytes").unwrap();
+                continue;
+            }
+            let total = stat.added + stat.deleted;
+            // scaled down bars keep at least one mark for each kind of change
+            let (added, deleted) = if most > STAT_BAR {
+                let scale = |count: usize| match count {
+                    0 => 0,
+                    _ => 1 + count * (STAT_BAR - 1) / most,
+                };
+                let added = scale(stat.added);
+                let deleted = scale(total) - added;
+                (added, deleted.max(usize::from(stat.deleted > 0)))
+            } else {
+                (stat.added, stat.deleted)
+            };
+            let bar = format!("{}{}", "+".repeat(added), "-".repeat(deleted));
+            let line = format!(" {path:name_width$} | {total:>count_width$} {bar}");
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
+    }
+
+    let added: usize = stats.iter().map(|stat| stat.added).sum();
+    let deleted: usize = stats.iter().map(|stat| stat.deleted).sum();
+    let plural = |count: usize, one: &str, many: &str| {
+        format!("{count} {}", if count == 1 { one } else { many })
+    };
+    out.push(' ');
+    out.push_str(&plural(stats.len(), "file changed", "files changed"));
+    if added > 0 || deleted == 0 {
+        out.push_str(&format!(
+            ", {}",
+            plural(added, "insertion(+)", "insertions(+)")
+        ));
+    }
+    if deleted > 0 || added == 0 {
+        out.push_str(&format!(
+            ", {}",
+            plural(deleted, "deletion(-)", "deletions(-)")
+        ));
+    }
+    out.push('\n');
+    out
+}
+
+pub(crate) fn range(start: usize, len: usize) -> String {
+    match len {
+        1 => start.to_string(),
+        _ => format!("{start},{len}"),
+    }
+}
+
+/// Compares two flat path to hash maps, reporting every path that was
+/// added, deleted or changed between them.
+pub fn diff_files(
+    old: &BTreeMap<String, String>,
+    new: &BTreeMap<String, String>,
+) -> Vec<(Change, String)> {
+    let mut changes = Vec::new();
+    for (path, hash) in old.iter() {
+        match new.get(path) {
+            None => changes.push((Change::Deleted, path.clone())),
+            Some(new_hash) if new_hash != hash => changes.push((Change::Modified, path.clone())),
+            Some(_) => {}
+        }
+    }
+    for path in new.keys() {
+        if !old.contains_key(path) {
+            changes.push((Change::Added, path.clone()));
+        }
+    }
+    changes.sort_by(|a, b| a.1.cmp(&b.1));
+    changes
+}
+
+/// Renders a git style patch for one file. Either side may be `None` when
+/// the file was added or deleted. `modes` are those of the old and the new
+/// file as git writes them, [`GITLINK_MODE`] for a submodule. A file whose
+/// changes `whitespace` all overlooks gets no patch.
+pub fn file_patch(
+    path: &str,
+    old: Option<&str>,
+    new: Option<&str>,
+    modes: (&str, &str),
+    whitespace: Whitespace,
+) -> String {
+    let mut out = patch_header(path, old.map(|_| modes.0), new.map(|_| modes.1));
+    let body = unified_ignoring(
+        old.unwrap_or_default(),
+        new.unwrap_or_default(),
+        3,
+        whitespace,
+    );
+    if body.is_empty() {
+        let overlooked = old.is_some() && new.is_some() && modes.0 == modes.1;
+        return if overlooked { String::new() } else { out };
+    }
+
+    let (old_name, new_name) = patch_names(path, old.is_some(), new.is_some());
+    writeln!(out, "--- {old_name}\n+++ {new_name}").unwrap();
+    out.push_str(&body);
+    out
+}
+
+/// Renders the patch for a file shown as binary, which only says that it
+/// changed, or with `data` holds both versions in full, `None` for a side
+/// without the file. `modes` are those of the old and the new file.
+pub fn binary_file_patch(
+    path: &str,
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+    modes: (FileMode, FileMode),
+    data: bool,
+) -> String {
+    let modes = (modes.0.git_mode(), modes.1.git_mode());
+    let mut out = patch_header(path, old.map(|_| modes.0), new.map(|_| modes.1));
+    if old == new {
+        return out;
+    }
+    let (old_name, new_name) = patch_names(path, old.is_some(), new.is_some());
+    let (old, new) = (old.unwrap_or_default(), new.unwrap_or_default());
+    out.push_str(&binary_body(&old_name, &new_name, old, new, data));
+    out
+}
+
+/// Says that a binary file changed, or with `data` writes out what it was
+/// and what it became as a `GIT binary patch`: the new contents, then the
+/// old, each after a `literal` line with its size.
+fn binary_body(old_name: &str, new_name: &str, old: &[u8], new: &[u8], data: bool) -> String {
+    if !data {
+        return format!("Binary files {old_name} and {new_name} differ\n");
+    }
+    format!(
+        "GIT binary patch\nliteral {}\n{}\nliteral {}\n{}\n",
+        new.len(),
+        binary::encode(new),
+        old.len(),
+        binary::encode(old)
+    )
+}
+
+/// Renders the patch for a file renamed or copied from `rename.from` to
+/// `rename.to`, whose contents went from `old` to `new`: a header naming
+/// both paths, then what changed in the file, if anything. A file shown as
+/// binary, with `binary`, only says that it changed unless `options` has it
+/// written out.
+pub fn rename_patch(
+    rename: &Rename,
+    old: &[u8],
+    new: &[u8],
+    modes: (FileMode, FileMode),
+    binary: bool,
+    options: DiffOptions,
+) -> String {
+    let (from, to) = (&rename.from, &rename.to);
+    let mut out = format!("diff --git a/{from} b/{to}\n");
+    if modes.0 != modes.1 {
+        writeln!(
+            out,
+            "old mode {}\nnew mode {}",
+            modes.0.git_mode(),
+            modes.1.git_mode()
+        )
+        .unwrap();
+    }
+    let kind = if rename.copy { "copy" } else { "rename" };
+    writeln!(out, "similarity index {}%", rename.similarity).unwrap();
+    writeln!(out, "{kind} from {from}\n{kind} to {to}").unwrap();
+    if old == new {
+        return out;
+    }
+    if binary {
+        out.push_str(&binary_body(
+            &format!("a/{from}"),
+            &format!("b/{to}"),
+            old,
+            new,
+            options.binary,
+        ));
+        return out;
+    }
+    writeln!(out, "--- a/{from}\n+++ b/{to}").unwrap();
+    out.push_str(&unified_ignoring(
+        &String::from_utf8_lossy(old),
+        &String::from_utf8_lossy(new),
+        3,
+        options.whitespace,
+    ));
+    out
+}
+
+/// The `diff --git` line of a patch and what it says about the modes, each
+/// as git writes it.
+fn patch_header(path: &str, old: Option<&str>, new: Option<&str>) -> String {
+    let mut out = format!("diff --git a/{path} b/{path}\n");
+    match (old, new) {
+        (None, Some(new)) => writeln!(out, "new file mode {new}").unwrap(),
+        (Some(old), None) => writeln!(out, "deleted file mode {old}").unwrap(),
+        (Some(old), Some(new)) if old != new => {
+            writeln!(out, "old mode {old}\nnew mode {new}").unwrap()
+        }
+        _ => {}
+    }
+    out
+}
+
+fn patch_names(path: &str, old: bool, new: bool) -> (String, String) {
+    let name = |present: bool, side: &str| match present {
+        true => format!("{side}/{path}"),
+        false => "/dev/null".to_string(),
+    };
+    (name(old, "a"), name(new, "b"))
+}
@@ -0,0 +1,48 @@
+//! The user's editor, for text blip asks for interactively such as the todo
+//! list of an interactive rebase.
+//!
+//! The editor is the `core.editor` config, or else the `VISUAL` or `EDITOR`
+//! environment variable, or else `vi`. It is run through the shell, so it
+//! can come with arguments of its own.
+
+use std::{env, path::Path, process::Command};
+
+use crate::types::{Context, Error, FileService, Result};
+
+/// The editor command configured for `file_service`.
+pub fn command(file_service: &FileService) -> Result<String> {
+    if let Some(editor) = file_service.config()?.get("core.editor") {
+        return Ok(editor);
+    }
+    Ok(["VISUAL", "EDITOR"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.is_empty())
+        .unwrap_or_else(|| "vi".to_string()))
+}
+
+/// Opens `path` in the editor and waits for it to exit, failing when it
+/// exits non-zero.
+pub fn edit(file_service: &FileService, path: &Path) -> Result<()> {
+    let editor = command(file_service)?;
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(&editor)
+        .arg(path)
+        .current_dir(&file_service.root_dir)
+        .status()
+        .context("run", Path::new(&editor))?;
+    if !status.success() {
+        return Err(Error::EditorFailed(editor));
+    }
+    Ok(())
+}
+
+/// `text` without its `#` comment lines, trimmed of surrounding blank
+/// lines and with runs of blank lines collapsed into one.
+pub fn strip_comments(text: &str) -> String {
+    let mut lines: Vec<&str> = text.lines().filter(|line| !line.starts_with('#')).collect();
+    lines.dedup_by(|line, previous| line.trim().is_empty() && previous.trim().is_empty());
+    lines.join("\n").trim().to_string()
+}
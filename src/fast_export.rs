@@ -0,0 +1,197 @@
+//! `blip fast-export`: history as a stream for `git fast-import`, so that
+//! `blip fast-export | git fast-import` turns a repository into a git one.
+//!
+//! Each blob is written once, the first time a commit needs it, and marked
+//! so that later commands can refer to it. Commits refer to their blobs
+//! and parents by mark and list their changes against their first parent.
+//! Annotated tags become `tag` commands and every ref is finally pointed at
+//! its commit with `reset`. Signatures on commits and tags are dropped:
+//! they would not verify against the objects git makes.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Write,
+};
+
+use crate::{
+    refs::Refs,
+    remote, submodule,
+    types::{Error, FileService, ObjectKind, Result},
+};
+
+/// Mode of submodules, which are given by the hash of their commit.
+const SUBMODULE_MODE: &str = "160000";
+
+/// Writes the refs `names`, short names allowed, and their history to
+/// `out`. Without names every branch and tag is exported.
+pub fn export(file_service: &FileService, names: &[String], out: &mut dyn Write) -> Result<()> {
+    let refs = Refs::new(file_service);
+    let mut exported = Vec::new();
+    if names.is_empty() {
+        for (name, hash) in refs.branches()? {
+            exported.push((Refs::branch_ref(&name), hash));
+        }
+        for (name, hash) in refs.tags()? {
+            exported.push((Refs::tag_ref(&name), hash));
+        }
+    }
+    for name in names {
+        let found = refs
+            .full_name(name)
+            .and_then(|full| Some((full.clone(), refs.read(&full)?)));
+        let Some((full, hash)) = found else {
+            return Err(Error::Usage(format!("'{name}' is not a ref")));
+        };
+        exported.push((full, hash));
+    }
+
+    // commits come after their parents, each marked for the ref it was
+    // first reached from
+    let mut tips = Vec::new();
+    for (name, hash) in exported.iter() {
+        let target = file_service.peel(hash)?;
+        if kind(file_service, &target)? == ObjectKind::Commit {
+            tips.push((name.as_str(), target));
+        }
+    }
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack: Vec<_> = tips
+        .iter()
+        .rev()
+        .map(|(name, hash)| (hash.clone(), *name, false))
+        .collect();
+    while let Some((hash, name, expanded)) = stack.pop() {
+        if expanded {
+            order.push((hash, name));
+            continue;
+        }
+        if !visited.insert(hash.clone()) {
+            continue;
+        }
+        let commit = file_service.read_commit_headers(&hash)?;
+        stack.push((hash, name, true));
+        for parent in commit.parents().iter().rev() {
+            if !visited.contains(parent) {
+                stack.push((parent.clone(), name, false));
+            }
+        }
+    }
+
+    let mut marks: HashMap<String, usize> = HashMap::new();
+    for (hash, name) in order {
+        let commit = file_service.read_commit(&hash)?;
+        let parent = match commit.parent() {
+            Some(parent) => Some(file_service.read_commit(parent)?),
+            None => None,
+        };
+        let empty = BTreeMap::new();
+        let base = parent.as_ref().map_or(&empty, |parent| parent.files());
+
+        let submodules = submodule::paths(file_service, commit.files())?;
+        let mut changes = Vec::new();
+        for (path, blob) in commit.files() {
+            let mode = commit.mode(path);
+            let same_mode = parent
+                .as_ref()
+                .is_none_or(|parent| parent.mode(path) == mode);
+            if base.get(path) == Some(blob) && same_mode {
+                continue;
+            }
+            if submodules.contains(path) {
+                changes.push(format!("M {SUBMODULE_MODE} {blob} {}", quote(path)));
+                continue;
+            }
+            if !marks.contains_key(blob) {
+                let mark = marks.len() + 1;
+                marks.insert(blob.clone(), mark);
+                writeln!(out, "blob\nmark :{mark}")?;
+                write_data(out, &file_service.read_blob(blob)?)?;
+            }
+            let mode = mode.git_mode();
+            changes.push(format!("M {mode} :{} {}", marks[blob], quote(path)));
+        }
+        for path in base.keys() {
+            if !commit.files().contains_key(path) {
+                changes.push(format!("D {}", quote(path)));
+            }
+        }
+
+        let mark = marks.len() + 1;
+        marks.insert(hash.clone(), mark);
+        writeln!(out, "commit {name}\nmark :{mark}")?;
+        if let Some(author) = commit.author() {
+            writeln!(out, "author {author}")?;
+        }
+        // git insists on a committer, which the oldest commits lack
+        match commit.committer().or(commit.author()) {
+            Some(committer) => writeln!(out, "committer {committer}")?,
+            None => writeln!(out, "committer unknown <unknown> 0 +0000")?,
+        }
+        write_data(out, format!("{}\n", commit.message()).as_bytes())?;
+        for (i, parent) in commit.parents().iter().enumerate() {
+            let command = if i == 0 { "from" } else { "merge" };
+            writeln!(out, "{command} :{}", marks[parent])?;
+        }
+        for change in changes {
+            writeln!(out, "{change}")?;
+        }
+        writeln!(out)?;
+    }
+
+    for (name, hash) in exported.iter() {
+        // refs to anything but commits are left out
+        let Some(target) = marks.get(&file_service.peel(hash)?) else {
+            continue;
+        };
+        let tag = match name.strip_prefix("refs/tags/") {
+            Some(short) if kind(file_service, hash)? == ObjectKind::Tag => {
+                Some((short, file_service.read_tag(hash)?))
+            }
+            _ => None,
+        };
+        match tag {
+            // tags of tags are left as plain refs
+            Some((short, tag)) if tag.kind() == ObjectKind::Commit => {
+                writeln!(out, "tag {short}\nfrom :{target}")?;
+                if let Some(tagger) = tag.tagger() {
+                    writeln!(out, "tagger {tagger}")?;
+                }
+                write_data(out, format!("{}\n", tag.message()).as_bytes())?;
+            }
+            _ => writeln!(out, "reset {name}\nfrom :{target}\n")?,
+        }
+    }
+    Ok(())
+}
+
+fn kind(file_service: &FileService, hash: &str) -> Result<ObjectKind> {
+    let (stored, _) = file_service.read_raw(hash)?;
+    Ok(remote::object_kind(file_service, hash, stored, None))
+}
+
+/// Writes a `data` command holding `data`.
+fn write_data(out: &mut dyn Write, data: &[u8]) -> Result<()> {
+    writeln!(out, "data {}", data.len())?;
+    out.write_all(data)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// Quotes a path the way git does when it could not be read otherwise.
+fn quote(path: &str) -> String {
+    if !path.contains(['"', '\\', '\n']) {
+        return path.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in path.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,540 @@
+//! `blip fast-import`: history read from a `git fast-export` stream, so
+//! that `git fast-export --all | blip fast-import` moves a git repository
+//! into blip. It reads what [`crate::fast_export`] writes as well.
+//!
+//! Blobs, commits and annotated tags are stored as they are read, while
+//! refs are only updated at a `checkpoint` and once the stream ends. The
+//! work tree and the index are left alone. Files keep their mode, whether
+//! executable or a symbolic link. Submodules are
+//! kept where `.blipmodules` lists them and left out elsewhere, which takes
+//! in those of streams from git, listed in `.gitmodules` instead.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{BufRead, Write},
+};
+
+use crate::{
+    refs::Refs,
+    remote, submodule,
+    types::{Blob, Commit, Error, FileMode, FileService, Result, Signature, Tag},
+};
+
+/// What an import stored.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub blobs: usize,
+    pub commits: usize,
+    pub tags: usize,
+    pub refs: usize,
+}
+
+/// Stores the objects described by the stream on `input` in
+/// `file_service` and points its refs at them. `progress` commands are
+/// echoed to `out`.
+pub fn import(
+    file_service: &FileService,
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+) -> Result<Stats> {
+    let mut importer = Importer {
+        file_service,
+        refs: Refs::new(file_service),
+        input,
+        line: 0,
+        pending: None,
+        marks: HashMap::new(),
+        gitlinks: HashSet::new(),
+        tips: BTreeMap::new(),
+        stats: Stats::default(),
+    };
+    importer.run(out)?;
+    importer.write_refs()?;
+    Ok(importer.stats)
+}
+
+struct Importer<'a> {
+    file_service: &'a FileService,
+    refs: Refs,
+    input: &'a mut dyn BufRead,
+    /// Number of the last line read, for errors.
+    line: usize,
+    /// A line read past the end of the command before it.
+    pending: Option<String>,
+    /// Objects by mark.
+    marks: HashMap<usize, String>,
+    /// Commits of submodules the stream pointed at.
+    gitlinks: HashSet<String>,
+    /// Where the refs the stream touched will point, `None` for those reset
+    /// to nothing.
+    tips: BTreeMap<String, Option<String>>,
+    stats: Stats,
+}
+
+impl Importer<'_> {
+    fn run(&mut self, out: &mut dyn Write) -> Result<()> {
+        let mut done_required = false;
+        while let Some(line) = self.next_line()? {
+            let (command, rest) = line.split_once(' ').unwrap_or((&line, ""));
+            match command {
+                "" => {}
+                "blob" => self.blob()?,
+                "commit" => self.commit(rest)?,
+                "tag" => self.tag(rest)?,
+                "reset" => self.reset(rest)?,
+                "checkpoint" => self.write_refs()?,
+                "progress" => writeln!(out, "progress {rest}")?,
+                "done" => return Ok(()),
+                "feature" => match rest {
+                    "done" => done_required = true,
+                    "date-format=raw" | "force" => {}
+                    _ => return Err(self.invalid(format!("unsupported feature '{rest}'"))),
+                },
+                // options only tune how git runs, which does not apply
+                "option" => {}
+                _ => return Err(self.invalid(format!("unsupported command '{command}'"))),
+            }
+        }
+        if done_required {
+            return Err(self.invalid("the stream ended before 'done'".into()));
+        }
+        Ok(())
+    }
+
+    fn blob(&mut self) -> Result<()> {
+        let mark = self.mark()?;
+        self.skip("original-oid")?;
+        let data = self.data()?;
+        let hash = self.write_blob(data)?;
+        if let Some(mark) = mark {
+            self.marks.insert(mark, hash);
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self, name: &str) -> Result<()> {
+        let name = self.ref_name(name)?;
+        let mark = self.mark()?;
+        self.skip("original-oid")?;
+        let author = self.signature("author")?;
+        let Some(committer) = self.signature("committer")? else {
+            return Err(self.invalid("a commit needs a committer".into()));
+        };
+        self.skip("encoding")?;
+        let message = String::from_utf8_lossy(&self.data()?).into_owned();
+
+        // without a `from` the commit continues the branch it is made on
+        let mut parents = Vec::new();
+        match self.argument("from")? {
+            Some(from) => parents.extend(self.commitish(&from)?),
+            None => match self.tips.get(&name) {
+                Some(tip) => parents.extend(tip.clone()),
+                None => parents.extend(self.refs.read(&name)),
+            },
+        }
+        while let Some(merge) = self.argument("merge")? {
+            parents.extend(self.commitish(&merge)?);
+        }
+
+        let (mut files, mut modes) = match parents.first() {
+            Some(parent) => {
+                let parent = self.file_service.read_commit(parent)?;
+                (parent.files().clone(), parent.modes().clone())
+            }
+            None => (BTreeMap::new(), BTreeMap::new()),
+        };
+        while let Some(line) = self.next_line()? {
+            match line.split_once(' ') {
+                _ if line == "deleteall" => files.clear(),
+                Some(("M", change)) => self.modify(&mut files, &mut modes, change)?,
+                Some(("D", path)) => {
+                    let path = self.path(path)?;
+                    remove(&mut files, &path);
+                }
+                Some((command @ ("C" | "R"), paths)) => {
+                    let (source, target) = self.paths(paths)?;
+                    let prefix = format!("{source}/");
+                    let moved: Vec<_> = files
+                        .iter()
+                        .filter_map(|(path, blob)| {
+                            let under = match path.strip_prefix(&prefix) {
+                                Some(under) => format!("{target}/{under}"),
+                                None if *path == source => target.clone(),
+                                None => return None,
+                            };
+                            Some((under, blob.clone(), modes.get(path).copied()))
+                        })
+                        .collect();
+                    if moved.is_empty() {
+                        return Err(self.invalid(format!("path not in commit: '{source}'")));
+                    }
+                    if command == "R" {
+                        remove(&mut files, &source);
+                    }
+                    for (path, blob, mode) in moved {
+                        match mode {
+                            Some(mode) => modes.insert(path.clone(), mode),
+                            None => modes.remove(&path),
+                        };
+                        files.insert(path, blob);
+                    }
+                }
+                Some(("N", _)) => return Err(self.invalid("notes are not supported".into())),
+                _ => {
+                    self.pending = Some(line);
+                    break;
+                }
+            }
+        }
+
+        let submodules = submodule::paths(self.file_service, &files)?;
+        files.retain(|path, hash| !self.gitlinks.contains(hash) || submodules.contains(path));
+        let mut commit = Commit::new(None, &message);
+        for parent in parents.iter() {
+            commit.add_parent(parent);
+        }
+        commit.set_modes(modes);
+        commit.set_files(files);
+        commit.set_author(author.unwrap_or_else(|| committer.clone()));
+        commit.set_committer(committer);
+        let hash = self.file_service.write_commit_object(&mut commit)?;
+        self.stats.commits += 1;
+        if let Some(mark) = mark {
+            self.marks.insert(mark, hash.clone());
+        }
+        self.tips.insert(name, Some(hash));
+        Ok(())
+    }
+
+    /// Applies a `M <mode> <blob> <path>` change.
+    fn modify(
+        &mut self,
+        files: &mut BTreeMap<String, String>,
+        modes: &mut BTreeMap<String, FileMode>,
+        change: &str,
+    ) -> Result<()> {
+        let mut parts = change.splitn(3, ' ');
+        let (Some(mode), Some(blob), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(self.invalid(format!("malformed change 'M {change}'")));
+        };
+        let path = self.path(path)?;
+        let blob = match blob {
+            "inline" => {
+                let data = self.data()?;
+                self.write_blob(data)?
+            }
+            blob => self.object(blob)?,
+        };
+        match mode {
+            "100644" | "644" => {
+                remove(files, &path);
+                modes.remove(&path);
+                files.insert(path, blob);
+            }
+            "100755" | "755" | "120000" => {
+                let mode = match mode {
+                    "120000" => FileMode::Symlink,
+                    _ => FileMode::Executable,
+                };
+                remove(files, &path);
+                modes.insert(path.clone(), mode);
+                files.insert(path, blob);
+            }
+            "160000" => {
+                remove(files, &path);
+                self.gitlinks.insert(blob.clone());
+                files.insert(path, blob);
+            }
+            _ => return Err(self.invalid(format!("unsupported mode '{mode}' of '{path}'"))),
+        }
+        Ok(())
+    }
+
+    fn tag(&mut self, name: &str) -> Result<()> {
+        let full = Refs::tag_ref(name);
+        if !Refs::is_valid_name(name) {
+            return Err(Error::InvalidRefName(full));
+        }
+        let mark = self.mark()?;
+        let Some(from) = self.argument("from")? else {
+            return Err(self.invalid(format!("tag '{name}' does not say what it tags")));
+        };
+        let Some(object) = self.commitish(&from)? else {
+            return Err(self.invalid(format!("tag '{name}' tags nothing")));
+        };
+        self.skip("original-oid")?;
+        let tagger = match self.signature("tagger")? {
+            Some(tagger) => tagger,
+            None => Signature::parse("unknown <unknown> 0 +0000").expect("a valid signature"),
+        };
+        let message = String::from_utf8_lossy(&self.data()?).into_owned();
+
+        let (stored, _) = self.file_service.read_raw(&object)?;
+        let kind = remote::object_kind(self.file_service, &object, stored, None);
+        let mut tag = Tag::new(&object, kind, name, tagger, &message);
+        let hash = self.file_service.write_tag(&mut tag)?;
+        self.stats.tags += 1;
+        if let Some(mark) = mark {
+            self.marks.insert(mark, hash.clone());
+        }
+        self.tips.insert(full, Some(hash));
+        Ok(())
+    }
+
+    fn reset(&mut self, name: &str) -> Result<()> {
+        let name = self.ref_name(name)?;
+        let tip = match self.argument("from")? {
+            Some(from) => self.commitish(&from)?,
+            None => None,
+        };
+        self.tips.insert(name, tip);
+        Ok(())
+    }
+
+    /// Points every ref the stream has touched so far where it left it.
+    fn write_refs(&mut self) -> Result<()> {
+        for (name, hash) in std::mem::take(&mut self.tips) {
+            if let Some(hash) = hash {
+                if self.refs.read(&name).as_ref() != Some(&hash) {
+                    self.refs.write(&name, &hash)?;
+                    self.stats.refs += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_blob(&mut self, data: Vec<u8>) -> Result<String> {
+        let blob = Blob::from_data(data, self.file_service.hash_algorithm);
+        self.file_service.write_blob(&blob)?;
+        self.stats.blobs += 1;
+        Ok(blob.hash().clone())
+    }
+
+    /// Checks that `name` is a full ref name a commit can be made on.
+    fn ref_name(&self, name: &str) -> Result<String> {
+        match name.strip_prefix("refs/") {
+            Some(short) if Refs::is_valid_name(short) => Ok(name.to_string()),
+            _ => Err(Error::InvalidRefName(name.to_string())),
+        }
+    }
+
+    /// The object a mark or hash names.
+    fn object(&self, name: &str) -> Result<String> {
+        if let Some(mark) = name.strip_prefix(':') {
+            let hash = mark.parse().ok().and_then(|mark| self.marks.get(&mark));
+            return match hash {
+                Some(hash) => Ok(hash.clone()),
+                None => Err(self.invalid(format!("unknown mark '{name}'"))),
+            };
+        }
+        if !self.file_service.hash_algorithm.is_hash(name) {
+            return Err(self.invalid(format!("'{name}' is neither a mark nor a hash")));
+        }
+        Ok(name.to_string())
+    }
+
+    /// The commit a `from` or `merge` names: a mark, a ref the stream has
+    /// made, an existing ref or a revision. `None` for a hash of zeroes,
+    /// which stands for no commit.
+    fn commitish(&self, name: &str) -> Result<Option<String>> {
+        if name.starts_with(':') {
+            return self.object(name).map(Some);
+        }
+        if self.file_service.hash_algorithm.is_hash(name) && name.chars().all(|c| c == '0') {
+            return Ok(None);
+        }
+        if let Some(tip) = self.tips.get(name) {
+            return Ok(tip.clone());
+        }
+        match self.file_service.resolve(name) {
+            Ok(hash) => Ok(Some(hash)),
+            Err(_) => Err(self.invalid(format!("unknown commit '{name}'"))),
+        }
+    }
+
+    /// Reads an optional `mark :<n>` line.
+    fn mark(&mut self) -> Result<Option<usize>> {
+        let Some(mark) = self.argument("mark")? else {
+            return Ok(None);
+        };
+        match mark.strip_prefix(':').and_then(|mark| mark.parse().ok()) {
+            Some(mark) => Ok(Some(mark)),
+            None => Err(self.invalid(format!("malformed mark '{mark}'"))),
+        }
+    }
+
+    /// Reads an optional `<command> <signature>` line.
+    fn signature(&mut self, command: &str) -> Result<Option<Signature>> {
+        let Some(signature) = self.argument(command)? else {
+            return Ok(None);
+        };
+        // git leaves out the space before the email of a nameless author
+        let parsed =
+            Signature::parse(&signature).or_else(|| Signature::parse(&format!(" {signature}")));
+        match parsed {
+            Some(signature) => Ok(Some(signature)),
+            None => Err(self.invalid(format!("malformed {command} '{signature}'"))),
+        }
+    }
+
+    /// Reads past an optional `<command> ...` line.
+    fn skip(&mut self, command: &str) -> Result<()> {
+        self.argument(command).map(|_| ())
+    }
+
+    /// Reads the argument of a `<command> <argument>` line, or leaves the
+    /// next line for later when it is another command.
+    fn argument(&mut self, command: &str) -> Result<Option<String>> {
+        let Some(line) = self.next_line()? else {
+            return Ok(None);
+        };
+        match line.split_once(' ') {
+            Some((found, argument)) if found == command => Ok(Some(argument.to_string())),
+            _ => {
+                self.pending = Some(line);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads a `data <count>` or `data <<<delimiter>` command with what it
+    /// holds.
+    fn data(&mut self) -> Result<Vec<u8>> {
+        let Some(line) = self.next_line()? else {
+            return Err(self.invalid("the stream ended before the data".into()));
+        };
+        let Some(size) = line.strip_prefix("data ") else {
+            return Err(self.invalid(format!("expected data, found '{line}'")));
+        };
+
+        if let Some(delimiter) = size.strip_prefix("<<") {
+            let mut data = Vec::new();
+            loop {
+                let mut line = Vec::new();
+                if self.input.read_until(b'\n', &mut line)? == 0 {
+                    return Err(self.invalid(format!("the stream ended before '{delimiter}'")));
+                }
+                self.line += 1;
+                if line.strip_suffix(b"\n").unwrap_or(&line) == delimiter.as_bytes() {
+                    return Ok(data);
+                }
+                data.extend_from_slice(&line);
+            }
+        }
+
+        let Ok(size) = size.parse::<usize>() else {
+            return Err(self.invalid(format!("malformed data size '{size}'")));
+        };
+        let mut data = vec![0; size];
+        self.input.read_exact(&mut data)?;
+        self.line += data.iter().filter(|&&byte| byte == b'\n').count();
+        // the data may be followed by a newline of its own
+        if self.input.fill_buf()?.first() == Some(&b'\n') {
+            self.input.consume(1);
+            self.line += 1;
+        }
+        Ok(data)
+    }
+
+    /// Reads the next line that is not a comment, without its newline.
+    fn next_line(&mut self) -> Result<Option<String>> {
+        if let Some(line) = self.pending.take() {
+            return Ok(Some(line));
+        }
+        loop {
+            let mut line = Vec::new();
+            if self.input.read_until(b'\n', &mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if line.ends_with(b"\n") {
+                line.pop();
+            }
+            if line.starts_with(b"#") {
+                continue;
+            }
+            return match String::from_utf8(line) {
+                Ok(line) => Ok(Some(line)),
+                Err(_) => Err(self.invalid("a command is not valid UTF-8".into())),
+            };
+        }
+    }
+
+    /// A path, quoted or not, that runs to the end of the line.
+    fn path(&self, path: &str) -> Result<String> {
+        match path.strip_prefix('"') {
+            Some(quoted) => match unquote(quoted) {
+                Some((path, "")) => Ok(path),
+                _ => Err(self.invalid(format!("malformed path {path}"))),
+            },
+            None if !path.is_empty() => Ok(path.to_string()),
+            None => Err(self.invalid("a change is missing its path".into())),
+        }
+    }
+
+    /// The source and target of a copy or rename. An unquoted source ends
+    /// at the first space.
+    fn paths(&self, paths: &str) -> Result<(String, String)> {
+        let split = match paths.strip_prefix('"') {
+            Some(quoted) => unquote(quoted)
+                .and_then(|(source, rest)| Some((source, rest.strip_prefix(' ')?.to_string()))),
+            None => paths
+                .split_once(' ')
+                .map(|(source, rest)| (source.to_string(), rest.to_string())),
+        };
+        match split {
+            Some((source, target)) => Ok((source, self.path(&target)?)),
+            None => Err(self.invalid(format!("malformed paths '{paths}'"))),
+        }
+    }
+
+    fn invalid(&self, message: String) -> Error {
+        Error::InvalidStream {
+            line: self.line,
+            message,
+        }
+    }
+}
+
+/// Removes the file `path` or everything under the directory `path`.
+fn remove(files: &mut BTreeMap<String, String>, path: &str) {
+    let prefix = format!("{path}/");
+    files.retain(|file, _| file != path && !file.starts_with(&prefix));
+}
+
+/// Reads a C-style quoted string up to its closing quote, the opening one
+/// already stripped, returning it with what follows.
+pub(crate) fn unquote(quoted: &str) -> Option<(String, &str)> {
+    let mut bytes = Vec::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((String::from_utf8(bytes).ok()?, &quoted[i + 1..])),
+            '\\' => {
+                let (_, escaped) = chars.next()?;
+                match escaped {
+                    'n' => bytes.push(b'\n'),
+                    't' => bytes.push(b'\t'),
+                    'a' => bytes.push(0x07),
+                    'b' => bytes.push(0x08),
+                    'f' => bytes.push(0x0c),
+                    'r' => bytes.push(b'\r'),
+                    'v' => bytes.push(0x0b),
+                    '0'..='7' => {
+                        // three octal digits make up a byte
+                        let mut byte = escaped.to_digit(8)?;
+                        for _ in 0..2 {
+                            byte = byte * 8 + chars.next()?.1.to_digit(8)?;
+                        }
+                        bytes.push(u8::try_from(byte).ok()?);
+                    }
+                    c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    None
+}
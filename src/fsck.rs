@@ -0,0 +1,246 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+};
+
+use crate::{
+    reachable::{self, Walk},
+    refs::{Head, Refs},
+    stash::Stash,
+    types::{Error, FileService, ObjectKind, Result},
+};
+
+/// Something wrong with the repository.
+#[derive(Debug)]
+pub enum Problem {
+    /// An object that cannot be read or parsed.
+    Corrupt { hash: String, error: Error },
+    /// An object whose contents do not hash to its name.
+    HashMismatch { hash: String, actual: String },
+    /// An object referring to one that does not exist.
+    BrokenLink {
+        from: String,
+        to: String,
+        kind: ObjectKind,
+    },
+    /// A ref file that does not hold a hash.
+    InvalidRef(String),
+    /// A ref, stash entry or index entry naming an object that does not
+    /// exist.
+    MissingTarget { name: String, hash: String },
+    /// A file other than an object that cannot be read, such as the index.
+    Unreadable(Error),
+}
+
+/// The objects an object refers to, with the kind each should be.
+type Links = Vec<(String, ObjectKind)>;
+
+/// What [`fsck`] found.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+    /// Unreachable objects nothing else refers to, with their kind if it is
+    /// known.
+    pub dangling: Vec<(String, Option<ObjectKind>)>,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Corrupt { hash, error } => write!(fmt, "corrupt object {hash}: {error}"),
+            Self::HashMismatch { hash, actual } => {
+                write!(fmt, "hash mismatch for {hash}, contents hash to {actual}")
+            }
+            Self::BrokenLink { from, to, kind } => {
+                write!(fmt, "broken link from {from} to {} {to}", kind.as_str())
+            }
+            Self::InvalidRef(name) => write!(fmt, "invalid ref {name}"),
+            Self::MissingTarget { name, hash } => {
+                write!(fmt, "{name} points to missing object {hash}")
+            }
+            Self::Unreadable(error) => write!(fmt, "{error}"),
+        }
+    }
+}
+
+/// Checks every object, ref and the index:
+///
+/// - objects are re-hashed and must match their name and parse as their
+///   kind, and the objects they refer to must exist;
+/// - refs, HEAD and stash entries must name existing objects;
+/// - the index must parse and its blobs must exist.
+///
+/// Unreachable objects that no other object refers to are reported as
+/// dangling.
+pub fn fsck(file_service: &FileService) -> Result<Report> {
+    let mut report = Report::default();
+
+    let mut hashes: BTreeSet<String> = file_service.loose_objects()?.into_iter().collect();
+    match file_service.packs() {
+        Ok(packs) => {
+            for pack in packs {
+                hashes.extend(pack.hashes().cloned());
+            }
+        }
+        Err(error) => report.problems.push(Problem::Unreadable(error)),
+    }
+
+    let mut kinds = BTreeMap::new();
+    let mut referenced = BTreeSet::new();
+    for hash in hashes.iter() {
+        match check_object(file_service, hash) {
+            Ok((kind, links)) => {
+                for (to, to_kind) in links {
+                    if !hashes.contains(&to) {
+                        report.problems.push(Problem::BrokenLink {
+                            from: hash.clone(),
+                            to: to.clone(),
+                            kind: to_kind,
+                        });
+                    }
+                    referenced.insert(to);
+                }
+                kinds.insert(hash.clone(), kind);
+            }
+            Err(problem) => report.problems.push(problem),
+        }
+    }
+
+    check_refs(file_service, &hashes, &mut report)?;
+
+    // without every root, unreachable objects cannot be told apart
+    let roots = match reachable::roots(file_service) {
+        Ok(roots) => roots,
+        Err(error) => {
+            report.problems.push(Problem::Unreadable(error));
+            return Ok(report);
+        }
+    };
+    // problems along the way have been reported object by object above
+    let reachable: BTreeSet<String> = Walk::new(file_service, roots)
+        .map(|(hash, _)| hash)
+        .collect();
+    for (hash, kind) in kinds {
+        if !reachable.contains(&hash) && !referenced.contains(&hash) {
+            report.dangling.push((hash, kind));
+        }
+    }
+    Ok(report)
+}
+
+/// Re-hashes and parses one object, returning its kind and the objects it
+/// refers to. Objects from before headers have no known kind and are only
+/// re-hashed.
+fn check_object(
+    file_service: &FileService,
+    hash: &str,
+) -> std::result::Result<(Option<ObjectKind>, Links), Problem> {
+    let corrupt = |error| Problem::Corrupt {
+        hash: hash.to_string(),
+        error,
+    };
+
+    let (kind, data) = file_service.read_raw(hash).map_err(corrupt)?;
+    let actual = file_service.hash_raw(kind, &data);
+    if actual != hash {
+        return Err(Problem::HashMismatch {
+            hash: hash.to_string(),
+            actual,
+        });
+    }
+
+    let Some(kind) = kind else {
+        return Ok((None, Vec::new()));
+    };
+    let mut links = reachable::links(hash, kind, &data).map_err(corrupt)?;
+    // the parents of the commits a shallow clone stops at were never fetched
+    if kind == ObjectKind::Commit && file_service.is_shallow(hash) {
+        links.retain(|(_, kind)| *kind != ObjectKind::Commit);
+    }
+    Ok((Some(kind), links))
+}
+
+/// Checks that HEAD, every ref, every stash entry and every index entry
+/// names an object in `hashes`.
+fn check_refs(
+    file_service: &FileService,
+    hashes: &BTreeSet<String>,
+    report: &mut Report,
+) -> Result<()> {
+    let mut targets = Vec::new();
+
+    let refs = Refs::new(file_service);
+    // a packed ref hidden by a loose one of the same name is never read
+    let loose = refs.names()?;
+    for (name, hash) in refs.packed()? {
+        if loose.contains(&name) {
+            continue;
+        }
+        if file_service.hash_algorithm.is_hash(&hash) {
+            targets.push((name, hash));
+        } else {
+            report.problems.push(Problem::InvalidRef(name));
+        }
+    }
+
+    let mut names = vec![
+        "HEAD".to_string(),
+        "ORIG_HEAD".into(),
+        "MERGE_HEAD".into(),
+        "CHERRY_PICK_HEAD".into(),
+        "REVERT_HEAD".into(),
+        "REBASE_HEAD".into(),
+    ];
+    names.extend(loose);
+    for name in names {
+        let path = refs.path(&name);
+        if !path.is_file() {
+            continue;
+        }
+        let Ok(contents) = fs::read_to_string(&path) else {
+            report.problems.push(Problem::InvalidRef(name));
+            continue;
+        };
+        let contents = contents.trim();
+        // a symbolic HEAD is checked through the branch it names
+        if name == "HEAD"
+            && matches!(
+                Head::parse(contents, file_service.hash_algorithm),
+                Some(Head::Symbolic(_))
+            )
+        {
+            continue;
+        }
+        if !file_service.hash_algorithm.is_hash(contents) {
+            report.problems.push(Problem::InvalidRef(name));
+            continue;
+        }
+        targets.push((name, contents.to_string()));
+    }
+
+    match Stash::new(file_service).entries() {
+        Ok(entries) => {
+            for (i, entry) in entries.into_iter().enumerate() {
+                targets.push((Stash::name(i), entry.hash));
+            }
+        }
+        Err(error) => report.problems.push(Problem::Unreadable(error)),
+    }
+
+    match file_service.read_index() {
+        Err(Error::BareRepository) => {}
+        Ok(index) => {
+            for (path, hash) in index.hashtree() {
+                targets.push((format!("index entry {path}"), hash.clone()));
+            }
+        }
+        Err(error) => report.problems.push(Problem::Unreadable(error)),
+    }
+
+    for (name, hash) in targets {
+        if !hashes.contains(&hash) {
+            report.problems.push(Problem::MissingTarget { name, hash });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    pack::{self, PackStats},
+    reachable, reflog,
+    refs::Refs,
+    types::{Context, Error, FileService, Result},
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// How long something no longer in use is kept around, as given to
+/// `--prune` or in `gc.reflogExpire` and `gc.pruneExpire`: a number of
+/// days, `now` or `never`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    Never,
+    After(Duration),
+}
+
+/// What [`gc`] did.
+#[derive(Debug)]
+pub struct GcStats {
+    pub expired: usize,
+    /// Loose refs moved into the packed refs file.
+    pub refs: usize,
+    pub pack: Option<PackStats>,
+    pub pruned: usize,
+}
+
+impl Expiry {
+    pub fn parse(value: &str) -> Option<Expiry> {
+        match value {
+            "now" => Some(Expiry::After(Duration::ZERO)),
+            "never" => Some(Expiry::Never),
+            _ => {
+                let days: u64 = value.strip_suffix(".days").unwrap_or(value).parse().ok()?;
+                Some(Expiry::After(Duration::from_secs(days * DAY)))
+            }
+        }
+    }
+
+    /// Reads `name` from the config, falling back to `default` days.
+    fn from_config(file_service: &FileService, name: &str, default: u64) -> Result<Expiry> {
+        match file_service.config()?.get(name) {
+            Some(value) => Expiry::parse(&value)
+                .ok_or_else(|| Error::InvalidConfig(format!("bad expiry '{value}' for '{name}'"))),
+            None => Ok(Expiry::After(Duration::from_secs(default * DAY))),
+        }
+    }
+
+    /// The time before which things have expired, or `None` if they never
+    /// do.
+    pub fn cutoff(self) -> Option<SystemTime> {
+        match self {
+            Expiry::Never => None,
+            Expiry::After(age) => Some(SystemTime::now() - age),
+        }
+    }
+}
+
+/// Cleans up the repository: expires old reflog and stash entries, packs
+/// refs and everything reachable into a single pack and deletes unreachable
+/// objects that are older than `prune`, which defaults to `gc.pruneExpire`
+/// or two weeks. Unreachable objects from packs younger than that are kept
+/// as loose objects so that a later run can still prune them.
+pub fn gc(file_service: &FileService, prune: Option<Expiry>) -> Result<GcStats> {
+    let reflog = Expiry::from_config(file_service, "gc.reflogExpire", 90)?;
+    let prune = match prune {
+        Some(prune) => prune,
+        None => Expiry::from_config(file_service, "gc.pruneExpire", 14)?,
+    };
+
+    let expired = match reflog.cutoff() {
+        Some(cutoff) => reflog::expire(file_service, seconds(cutoff))?,
+        None => 0,
+    };
+
+    let refs = Refs::new(file_service).pack(true)?;
+
+    let reachable = reachable::reachable(file_service)?;
+    loosen_unreachable(file_service, &reachable, prune.cutoff())?;
+    let pack = pack::pack_objects(file_service, &reachable)?;
+    let pruned = prune_loose(file_service, &reachable, prune.cutoff(), false)?.len();
+
+    Ok(GcStats {
+        expired,
+        refs,
+        pack,
+        pruned,
+    })
+}
+
+/// Deletes the loose objects that are neither reachable nor younger than
+/// `expire`, which defaults to `gc.pruneExpire` or two weeks, returning their
+/// hashes. With `dry_run` nothing is deleted. Packed objects are left to
+/// [`gc`].
+pub fn prune(
+    file_service: &FileService,
+    expire: Option<Expiry>,
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let expire = match expire {
+        Some(expire) => expire,
+        None => Expiry::from_config(file_service, "gc.pruneExpire", 14)?,
+    };
+    let reachable = reachable::reachable(file_service)?;
+    prune_loose(file_service, &reachable, expire.cutoff(), dry_run)
+}
+
+/// Deletes the loose objects outside of `reachable` that were last written
+/// before `cutoff`, returning their hashes. With `dry_run` nothing is
+/// deleted.
+pub fn prune_loose(
+    file_service: &FileService,
+    reachable: &BTreeSet<String>,
+    cutoff: Option<SystemTime>,
+    dry_run: bool,
+) -> Result<Vec<String>> {
+    let Some(cutoff) = cutoff else {
+        return Ok(Vec::new());
+    };
+
+    let mut pruned = Vec::new();
+    for hash in file_service.loose_objects()? {
+        if reachable.contains(&hash) {
+            continue;
+        }
+        let path = file_service.object_path(&hash);
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .context("read", &path)?;
+        if modified >= cutoff {
+            continue;
+        }
+        if !dry_run {
+            file_service.remove_loose(&hash)?;
+        }
+        pruned.push(hash);
+    }
+    Ok(pruned)
+}
+
+/// Writes out the unreachable objects of packs that are too young to prune
+/// as loose objects, dated like the pack they came from.
+fn loosen_unreachable(
+    file_service: &FileService,
+    reachable: &BTreeSet<String>,
+    cutoff: Option<SystemTime>,
+) -> Result<()> {
+    for pack in file_service.packs()? {
+        let modified = fs::metadata(pack.path())
+            .and_then(|metadata| metadata.modified())
+            .context("read", pack.path())?;
+        if cutoff.is_some_and(|cutoff| modified < cutoff) {
+            continue;
+        }
+
+        for hash in pack.hashes() {
+            if reachable.contains(hash) || file_service.object_path(hash).is_file() {
+                continue;
+            }
+            let Some((kind, data)) = pack.read(hash)? else {
+                continue;
+            };
+            let path = file_service.write_loose(hash, kind, &data)?;
+            File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(modified))
+                .context("write", &path)?;
+        }
+    }
+    Ok(())
+}
+
+fn seconds(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64)
+}
@@ -0,0 +1,142 @@
+//! The ASCII art `log --graph` draws left of the history: a column for
+//! every line of descent being followed, a `*` on the commit shown, and
+//! `/`, `\` and `_` where lines join or branch out of each other.
+//!
+//! ```text
+//! *   commit 3
+//! |\  Merge: 1 2
+//! | * commit 2
+//! * | commit 1
+//! |/
+//! * commit 0
+//! ```
+
+/// The columns of a graph being drawn, each holding the hash of the commit
+/// its line leads to next.
+#[derive(Debug, Default)]
+pub struct Graph {
+    columns: Vec<String>,
+}
+
+impl Graph {
+    pub fn new() -> Graph {
+        Graph::default()
+    }
+
+    /// Draws the commit `hash` with `parents`, prefixing the lines of `text`
+    /// that describe it: the first with the commit's row, the second with
+    /// the lines leading on to its parents if they move, the rest with the
+    /// columns left afterwards. The commits have to be drawn newest first.
+    pub fn draw(&mut self, hash: &str, parents: &[String], text: &str) -> String {
+        let column = match self.columns.iter().position(|line| line == hash) {
+            Some(column) => column,
+            None => {
+                self.columns.push(hash.to_string());
+                self.columns.len() - 1
+            }
+        };
+        let mut row = Graph::lines(self.columns.len());
+        row[column * 2] = '*';
+
+        // the first parent carries on in the commit's column unless another
+        // line already leads to it; the others branch out right of it
+        let mut next = self.columns.clone();
+        let mut branch_at = match parents.first() {
+            Some(first) if !self.columns.contains(first) => {
+                next[column] = first.clone();
+                column + 1
+            }
+            _ => {
+                next.remove(column);
+                column
+            }
+        };
+        for parent in parents.iter().skip(1) {
+            if !next.contains(parent) {
+                next.insert(branch_at, parent.clone());
+                branch_at += 1;
+            }
+        }
+
+        let position = |hash: &String| next.iter().position(|line| line == hash);
+        let mut moves = Vec::new();
+        for (i, line) in self.columns.iter().enumerate() {
+            if i == column {
+                moves.extend(parents.iter().filter_map(position).map(|to| (i, to)));
+            } else {
+                moves.extend(position(line).map(|to| (i, to)));
+            }
+        }
+        let moving = moves.iter().any(|(from, to)| from != to);
+        let connector = moving.then(|| Graph::connector(&moves));
+        self.columns = next;
+
+        let padding = Graph::lines(self.columns.len());
+        let width = [row.len(), padding.len()]
+            .into_iter()
+            .chain(connector.iter().map(Vec::len))
+            .max()
+            .unwrap_or_default();
+
+        let mut prefixes = vec![row];
+        prefixes.extend(connector);
+        let mut lines: Vec<&str> = text.lines().collect();
+        if lines.len() < prefixes.len() {
+            lines.resize(prefixes.len(), "");
+        }
+        let mut out = String::new();
+        for (i, line) in lines.into_iter().enumerate() {
+            let prefix: String = prefixes.get(i).unwrap_or(&padding).iter().collect();
+            let line = format!("{prefix:width$} {line}");
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The columns carrying on into the next commit, for the blank line
+    /// between two commits.
+    pub fn padding(&self) -> String {
+        Graph::lines(self.columns.len()).into_iter().collect()
+    }
+
+    /// `count` lines going straight down.
+    fn lines(count: usize) -> Vec<char> {
+        let mut chars = vec![' '; (count * 2).saturating_sub(1)];
+        for i in (0..chars.len()).step_by(2) {
+            chars[i] = '|';
+        }
+        chars
+    }
+
+    /// Draws lines moving from column to column: `|` for one that stays,
+    /// `\` or `/` leaving for the column next to it and `_` on the way to
+    /// one further off.
+    fn connector(moves: &[(usize, usize)]) -> Vec<char> {
+        let width = moves
+            .iter()
+            .map(|&(from, to)| from.max(to) * 2 + 1)
+            .max()
+            .unwrap_or_default();
+        let mut chars = vec![' '; width];
+        for &(from, to) in moves {
+            if to == from {
+                chars[from * 2] = '|';
+            } else if to > from {
+                chars[from * 2 + 1] = '\\';
+                for i in (from * 2 + 3..to * 2).step_by(2) {
+                    chars[i] = '_';
+                }
+            } else {
+                chars[from * 2 - 1] = '/';
+                for i in (to * 2 + 1..from * 2 - 2).step_by(2) {
+                    chars[i] = '_';
+                }
+            }
+        }
+        while chars.last() == Some(&' ') {
+            chars.pop();
+        }
+        chars
+    }
+}
@@ -0,0 +1,129 @@
+use crypto::{digest::Digest, sha1::Sha1, sha2::Sha256};
+
+use crate::{
+    config::ConfigFile,
+    types::{Error, Result},
+};
+
+/// Matches an object hash of any supported algorithm inside a regex.
+pub const HEX_PATTERN: &str = "[0-9a-f]{40}(?:[0-9a-f]{24})?";
+
+/// The function a repository names its objects with. SHA-1 repositories
+/// use format version 0; others need version 1 and record the algorithm as
+/// `extensions.objectformat`, so that older versions of blip refuse to
+/// open them rather than misreading them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+/// A hash being computed piece by piece, for content too large to hold in
+/// memory at once.
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl HashAlgorithm {
+    pub fn parse(name: &str) -> Option<HashAlgorithm> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "sha256" => Some(HashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// Length of a hash written out in hex.
+    pub fn hex_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 40,
+            HashAlgorithm::Sha256 => 64,
+        }
+    }
+
+    /// Hashes the concatenation of `parts` into lowercase hex.
+    pub fn digest(self, parts: &[&[u8]]) -> String {
+        let mut hasher = self.hasher();
+        for part in parts {
+            hasher.update(part);
+        }
+        hasher.finish()
+    }
+
+    pub fn hasher(self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    /// Whether `name` looks like a full hash of this algorithm.
+    pub fn is_hash(self, name: &str) -> bool {
+        name.len() == self.hex_len() && name.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// Reads the algorithm from a repository's config, checking that the
+    /// format version is one this build understands.
+    pub fn from_config(config: &ConfigFile) -> Result<HashAlgorithm> {
+        let last = |name: &str| config.get_all(name).pop();
+
+        let version = match last("core.repositoryformatversion") {
+            Some(version) => version.trim().parse().map_err(|_| {
+                Error::InvalidConfig(format!("invalid repository format version '{version}'"))
+            })?,
+            None => 0,
+        };
+        let algorithm = match last("extensions.objectformat") {
+            Some(name) => HashAlgorithm::parse(&name)
+                .ok_or_else(|| Error::InvalidConfig(format!("unknown object format '{name}'")))?,
+            None => HashAlgorithm::Sha1,
+        };
+
+        match version {
+            0 if algorithm != HashAlgorithm::Sha1 => Err(Error::InvalidConfig(
+                "extensions.objectformat requires repository format version 1".into(),
+            )),
+            0 | 1 => Ok(algorithm),
+            _ => Err(Error::InvalidConfig(format!(
+                "unsupported repository format version {version}"
+            ))),
+        }
+    }
+
+    /// Records the algorithm in a new repository's config.
+    pub fn write_config(self, config: &mut ConfigFile) -> Result<()> {
+        match self {
+            HashAlgorithm::Sha1 => config.set("core.repositoryformatversion", "0"),
+            _ => {
+                config.set("core.repositoryformatversion", "1")?;
+                config.set("extensions.objectformat", self.name())
+            }
+        }
+    }
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.input(data),
+            Hasher::Sha256(hasher) => hasher.input(data),
+        }
+    }
+
+    /// The hash of everything passed to [`Hasher::update`], in lowercase hex.
+    pub fn finish(mut self) -> String {
+        match &mut self {
+            Hasher::Sha1(hasher) => hasher.result_str(),
+            Hasher::Sha256(hasher) => hasher.result_str(),
+        }
+    }
+}
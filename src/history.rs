@@ -0,0 +1,374 @@
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use regex::Regex;
+
+use crate::{
+    diff,
+    pathspec::Pathspec,
+    types::{days_from_civil, Commit, FileService, Result},
+};
+
+/// Iterates over every commit reachable from a starting point, newest
+/// first by commit time, following all parents of merge commits unless
+/// told otherwise and visiting each commit only once.
+pub struct CommitWalk<'a> {
+    file_service: &'a FileService,
+    queue: Vec<String>,
+    pending: Vec<Commit>,
+    seen: HashSet<String>,
+    first_parent: bool,
+    simplify: Option<Pathspec>,
+}
+
+impl<'a> CommitWalk<'a> {
+    pub fn new(file_service: &'a FileService, start: Option<String>) -> CommitWalk<'a> {
+        let mut walk = CommitWalk {
+            file_service,
+            queue: Vec::new(),
+            pending: Vec::new(),
+            seen: HashSet::new(),
+            first_parent: false,
+            simplify: None,
+        };
+        if let Some(start) = start {
+            walk.push(start);
+        }
+        walk
+    }
+
+    pub fn from_head(file_service: &'a FileService) -> Result<CommitWalk<'a>> {
+        let head = file_service.read_head_hash()?;
+        Ok(CommitWalk::new(file_service, head))
+    }
+
+    /// Adds another starting point to the walk.
+    pub fn push(&mut self, hash: String) {
+        if self.seen.insert(hash.clone()) {
+            self.queue.push(hash);
+        }
+    }
+
+    /// Follows only the first parent of merge commits: the branch they
+    /// were made on, without the branches merged into it.
+    pub fn first_parent(&mut self) {
+        self.first_parent = true;
+    }
+
+    /// Follows only one parent of a merge that has the same files matching
+    /// `pathspec` as it, as the history of those files went through that
+    /// parent alone.
+    pub fn simplify(&mut self, pathspec: Pathspec) {
+        self.simplify = Some(pathspec);
+    }
+
+    /// The parents of `commit` the walk goes on to.
+    fn followed(&self, commit: &Commit) -> Result<Vec<String>> {
+        let parents = commit.parents();
+        if self.first_parent {
+            return Ok(parents.iter().take(1).cloned().collect());
+        }
+        let Some(pathspec) = self.simplify.as_ref().filter(|_| parents.len() > 1) else {
+            return Ok(parents.to_vec());
+        };
+        for parent in parents {
+            let files = self.file_service.read_commit(parent)?.files().clone();
+            let same = diff::diff_files(&files, commit.files())
+                .iter()
+                .all(|(_, path)| !pathspec.matches(path));
+            if same {
+                return Ok(vec![parent.clone()]);
+            }
+        }
+        Ok(parents.to_vec())
+    }
+
+    /// Leaves `hash` and every commit reachable from it out of the walk.
+    pub fn hide(&mut self, hash: &str) -> Result<()> {
+        let hidden = ancestors(self.file_service, hash)?;
+        self.queue.retain(|queued| !hidden.contains(queued));
+        self.pending
+            .retain(|commit| !hidden.contains(commit.hash().unwrap_or_default()));
+        self.seen.extend(hidden);
+        Ok(())
+    }
+
+    fn load_queued(&mut self) -> Result<()> {
+        while let Some(hash) = self.queue.pop() {
+            let commit = self.file_service.read_commit(&hash)?;
+            self.pending.push(commit);
+        }
+        Ok(())
+    }
+
+    fn time(commit: &Commit) -> i64 {
+        commit.committer().map_or(0, |committer| committer.time)
+    }
+}
+
+impl<'a> Iterator for CommitWalk<'a> {
+    type Item = Result<Commit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.load_queued() {
+            return Some(Err(e));
+        }
+
+        // the most recent pending commit goes first; ties keep insertion order
+        let (i, _) = self
+            .pending
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, commit)| CommitWalk::time(commit))?;
+        let commit = self.pending.remove(i);
+
+        match self.followed(&commit) {
+            Ok(parents) => parents.into_iter().for_each(|parent| self.push(parent)),
+            Err(e) => return Some(Err(e)),
+        }
+        Some(Ok(commit))
+    }
+}
+
+/// Which commits `log` shows besides those a pathspec picks: any of them
+/// when nothing is set.
+#[derive(Debug, Default)]
+pub struct CommitFilter {
+    /// Patterns of which one has to match the author's `Name <email>`.
+    pub authors: Vec<Regex>,
+    /// Patterns of which one has to match the message.
+    pub greps: Vec<Regex>,
+    /// The earliest commit time shown, in seconds since the epoch.
+    pub since: Option<i64>,
+    /// The latest commit time shown, in seconds since the epoch.
+    pub until: Option<i64>,
+    /// Leaves out the branches merged into the one being walked, see
+    /// [`CommitWalk::first_parent`].
+    pub first_parent: bool,
+    /// Keeps the merges that took a pathspec's files from one parent from
+    /// leaving out the other parents' history, see
+    /// [`CommitWalk::simplify`].
+    pub full_history: bool,
+    /// Follows the one file of the pathspec back through the commits that
+    /// renamed or copied it.
+    pub follow: bool,
+}
+
+impl CommitFilter {
+    pub fn matches(&self, commit: &Commit) -> bool {
+        let time = CommitWalk::time(commit);
+        let author = commit
+            .author()
+            .map(|author| format!("{} <{}>", author.name, author.email))
+            .unwrap_or_default();
+        self.since.is_none_or(|since| time >= since)
+            && self.until.is_none_or(|until| time <= until)
+            && (self.authors.is_empty() || self.authors.iter().any(|re| re.is_match(&author)))
+            && (self.greps.is_empty() || self.greps.iter().any(|re| re.is_match(commit.message())))
+    }
+
+    /// Whether every commit the walk yields after `commit`, which comes
+    /// no earlier by commit time, is too old to be shown.
+    pub fn past(&self, commit: &Commit) -> bool {
+        self.since
+            .is_some_and(|since| CommitWalk::time(commit) < since)
+    }
+}
+
+/// Reads a point in time given to `--since` or `--until`, in seconds since
+/// the epoch: `now`, `yesterday`, `@<seconds>`, an age such as
+/// `2 weeks ago` or `3.days.ago`, or a UTC date such as `2026-10-15` with
+/// an optional `12:30[:00]` after it.
+pub fn parse_time(value: &str, now: i64) -> Option<i64> {
+    const UNITS: [(&str, i64); 7] = [
+        ("second", 1),
+        ("minute", 60),
+        ("hour", 3600),
+        ("day", 86400),
+        ("week", 7 * 86400),
+        ("month", 30 * 86400),
+        ("year", 365 * 86400),
+    ];
+
+    let value = value.trim();
+    match value {
+        "now" => return Some(now),
+        "yesterday" => return Some(now - 86400),
+        _ => {}
+    }
+    if let Some(seconds) = value.strip_prefix('@') {
+        return seconds.parse().ok();
+    }
+
+    let words: Vec<&str> = value
+        .split([' ', '.'])
+        .filter(|word| !word.is_empty())
+        .collect();
+    if let [count, unit, ref rest @ ..] = words[..] {
+        if let Ok(count) = count.parse::<i64>() {
+            if !matches!(rest, [] | ["ago"]) {
+                return None;
+            }
+            let unit = unit.strip_suffix('s').unwrap_or(unit);
+            let (_, seconds) = UNITS.iter().find(|(name, _)| *name == unit)?;
+            return Some(now - count * seconds);
+        }
+    }
+
+    let (date, clock) = match value.split_once([' ', 'T']) {
+        Some((date, clock)) => (date, Some(clock)),
+        None => (value, None),
+    };
+    let mut fields = date.split('-').map(str::parse::<i64>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day)), None) =
+        (fields.next(), fields.next(), fields.next(), fields.next())
+    else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut seconds = 0;
+    if let Some(clock) = clock {
+        let clock = clock
+            .split(':')
+            .map(str::parse::<i64>)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .ok()?;
+        let (hours, minutes, secs) = match clock[..] {
+            [hours, minutes] => (hours, minutes, 0),
+            [hours, minutes, secs] => (hours, minutes, secs),
+            _ => return None,
+        };
+        if !(0..24).contains(&hours) || !(0..60).contains(&minutes) || !(0..60).contains(&secs) {
+            return None;
+        }
+        seconds = hours * 3600 + minutes * 60 + secs;
+    }
+    Some(days_from_civil(year, month, day) * 86400 + seconds)
+}
+
+/// Collects `hash` and every commit reachable from it through parent links.
+pub fn ancestors(file_service: &FileService, hash: &str) -> Result<HashSet<String>> {
+    let mut seen = HashSet::new();
+    let mut queue = vec![hash.to_string()];
+    while let Some(hash) = queue.pop() {
+        if seen.insert(hash.clone()) {
+            let commit = file_service.read_commit_headers(&hash)?;
+            queue.extend(commit.parents().iter().cloned());
+        }
+    }
+    Ok(seen)
+}
+
+/// Returns whether `ancestor` is reachable from `descendant`; a commit
+/// counts as its own ancestor.
+pub fn is_ancestor(file_service: &FileService, ancestor: &str, descendant: &str) -> Result<bool> {
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::from([descendant.to_string()]);
+    while let Some(hash) = queue.pop_front() {
+        if hash == ancestor {
+            return Ok(true);
+        }
+        if seen.insert(hash.clone()) {
+            let commit = file_service.read_commit_headers(&hash)?;
+            queue.extend(commit.parents().iter().cloned());
+        }
+    }
+    Ok(false)
+}
+
+/// Counts the commits reachable from `ours` but not from `theirs`, and the
+/// other way around: how far each has moved on since their merge bases.
+///
+/// Both histories are walked together, newest commit first, marking each
+/// commit with the sides that reach it, and the walk stops once every
+/// commit left to visit is reached from both, below the merge bases.
+pub fn ahead_behind(
+    file_service: &FileService,
+    ours: &str,
+    theirs: &str,
+) -> Result<(usize, usize)> {
+    const OURS: u8 = 1;
+    const THEIRS: u8 = 2;
+    const BOTH: u8 = OURS | THEIRS;
+
+    // commits to visit by commit time, with their parents
+    let entry = |hash: &str| -> Result<(i64, String, Vec<String>)> {
+        let commit = file_service.read_commit_headers(hash)?;
+        let time = commit.committer().map_or(0, |committer| committer.time);
+        Ok((time, hash.to_string(), commit.parents().to_vec()))
+    };
+    let mut sides: HashMap<String, u8> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    for (hash, side) in [(ours, OURS), (theirs, THEIRS)] {
+        *sides.entry(hash.to_string()).or_default() |= side;
+        queue.push(entry(hash)?);
+    }
+
+    while queue.iter().any(|(_, hash, _)| sides[hash] != BOTH) {
+        let Some((_, hash, parents)) = queue.pop() else {
+            break;
+        };
+        let side = sides[&hash];
+        for parent in parents {
+            let marked = sides.entry(parent.clone()).or_default();
+            if *marked | side != *marked {
+                *marked |= side;
+                queue.push(entry(&parent)?);
+            }
+        }
+    }
+    let count = |side: u8| sides.values().filter(|&&marked| marked == side).count();
+    Ok((count(OURS), count(THEIRS)))
+}
+
+/// Finds every best common ancestor of two commits: the common ancestors
+/// that are not themselves ancestors of another common ancestor. There is
+/// usually one, but criss-cross merges can produce several. The result is
+/// ordered newest first.
+pub fn merge_bases(file_service: &FileService, a: &str, b: &str) -> Result<Vec<String>> {
+    let ours = ancestors(file_service, a)?;
+    let theirs = ancestors(file_service, b)?;
+    let common: HashSet<&String> = ours.intersection(&theirs).collect();
+
+    // anything reachable from a parent of a common ancestor is dominated by it
+    let mut dominated = HashSet::new();
+    let mut queue = Vec::new();
+    for hash in common.iter() {
+        queue.extend(
+            file_service
+                .read_commit_headers(hash)?
+                .parents()
+                .iter()
+                .cloned(),
+        );
+    }
+    while let Some(hash) = queue.pop() {
+        if dominated.insert(hash.clone()) {
+            queue.extend(
+                file_service
+                    .read_commit_headers(&hash)?
+                    .parents()
+                    .iter()
+                    .cloned(),
+            );
+        }
+    }
+
+    let mut bases = Vec::new();
+    for hash in common {
+        if !dominated.contains(hash) {
+            let commit = file_service.read_commit_headers(hash)?;
+            bases.push((CommitWalk::time(&commit), hash.clone()));
+        }
+    }
+    bases.sort_by(|x, y| y.cmp(x));
+    Ok(bases.into_iter().map(|(_, hash)| hash).collect())
+}
+
+/// The best common ancestor of two commits, or `None` if their histories
+/// are unrelated.
+pub fn merge_base(file_service: &FileService, a: &str, b: &str) -> Result<Option<String>> {
+    Ok(merge_bases(file_service, a, b)?.into_iter().next())
+}
@@ -0,0 +1,45 @@
+use std::{os::unix::fs::PermissionsExt, path::PathBuf, process::Command};
+
+use crate::types::{Context, Error, FileService, Result};
+
+/// Executables in `.blip/hooks` that are run at fixed points of a command,
+/// named after the point they hook into, e.g. `pre-commit`.
+#[derive(Debug)]
+pub struct Hooks {
+    dir: PathBuf,
+    root_dir: PathBuf,
+    blip_dir: PathBuf,
+}
+
+impl Hooks {
+    pub fn new(file_service: &FileService) -> Hooks {
+        Hooks {
+            dir: file_service.common_dir.join("hooks"),
+            root_dir: file_service.root_dir.clone(),
+            blip_dir: file_service.blip_dir.clone(),
+        }
+    }
+
+    /// Runs the hook `name` from the root of the working tree, failing when
+    /// it exits non-zero. Missing and non-executable hooks are skipped.
+    pub fn run(&self, name: &str, args: &[&str]) -> Result<()> {
+        let path = self.dir.join(name);
+        let executable = path
+            .metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0);
+        if !executable {
+            return Ok(());
+        }
+
+        let status = Command::new(&path)
+            .args(args)
+            .current_dir(&self.root_dir)
+            .env("BLIP_DIR", &self.blip_dir)
+            .status()
+            .context("run", &path)?;
+        if !status.success() {
+            return Err(Error::HookFailed(name.to_string()));
+        }
+        Ok(())
+    }
+}
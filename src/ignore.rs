@@ -0,0 +1,247 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config,
+    types::{Context, Result},
+};
+
+/// Name of the per-directory ignore files.
+pub const IGNORE_FILE: &str = ".blipignore";
+
+/// One line of an ignore file.
+#[derive(Debug)]
+struct Pattern {
+    glob: String,
+    /// Directory the pattern was read from, relative to the root, whose
+    /// contents it applies to. Empty for the root and the global file.
+    base: String,
+    negated: bool,
+    dir_only: bool,
+    /// Patterns containing a slash match the whole path below `base`,
+    /// others only the final component at any depth.
+    anchored: bool,
+}
+
+/// Decides which untracked paths the working tree should pretend are not
+/// there, following the gitignore rules: the user-global ignore file
+/// first, then `.blipignore` files from the root down, with later and
+/// deeper patterns taking precedence.
+#[derive(Debug, Default)]
+pub struct Ignore {
+    patterns: Vec<Pattern>,
+}
+
+impl Pattern {
+    fn parse(line: &str, base: &str) -> Option<Pattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line);
+        if glob.is_empty() {
+            return None;
+        }
+
+        Some(Pattern {
+            glob: glob.to_string(),
+            base: base.to_string(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(&self.base)
+                .and_then(|p| p.strip_prefix('/'))
+            {
+                Some(relative) => relative,
+                None => return false,
+            }
+        };
+
+        if self.anchored {
+            glob_match(&self.glob, relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            glob_match(&self.glob, name)
+        }
+    }
+}
+
+impl Ignore {
+    /// Reads the global ignore file and every `.blipignore` in the working
+    /// tree, skipping directories that are themselves ignored.
+    pub fn load(root_dir: &Path, blip_dir: &Path) -> Result<Ignore> {
+        let mut ignore = Ignore::default();
+        if let Some(global) = Ignore::global_path().filter(|path| path.is_file()) {
+            ignore.add_file(&global, "")?;
+        }
+        ignore.load_dir(root_dir, "", blip_dir)?;
+        Ok(ignore)
+    }
+
+    /// `$XDG_CONFIG_HOME/blip/ignore`, which defaults to
+    /// `~/.config/blip/ignore`.
+    fn global_path() -> Option<PathBuf> {
+        config::user_dir().map(|dir| dir.join("ignore"))
+    }
+
+    fn load_dir(&mut self, dir: &Path, relative: &str, blip_dir: &Path) -> Result<()> {
+        let file = dir.join(IGNORE_FILE);
+        if file.is_file() {
+            self.add_file(&file, relative)?;
+        }
+
+        let mut subdirs: Vec<PathBuf> = fs::read_dir(dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|entry| entry.path()))
+                    .collect()
+            })
+            .context("read", dir)?;
+        subdirs.retain(|path| path.is_dir() && path != blip_dir);
+        subdirs.sort();
+
+        for subdir in subdirs {
+            let Some(name) = subdir.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            let path = if relative.is_empty() {
+                name.to_string()
+            } else {
+                format!("{relative}/{name}")
+            };
+            if !self.is_ignored(&path, true) {
+                self.load_dir(&subdir, &path, blip_dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, file: &Path, base: &str) -> Result<()> {
+        let contents = fs::read_to_string(file).context("read", file)?;
+        self.patterns.extend(
+            contents
+                .lines()
+                .filter_map(|line| Pattern::parse(line, base)),
+        );
+        Ok(())
+    }
+
+    /// Whether `path`, relative to the root, is ignored. A file inside an
+    /// ignored directory is always ignored, as with git.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let mut end = 0;
+        while let Some(slash) = path[end..].find('/') {
+            end += slash;
+            if self.matches(&path[..end], true) {
+                return true;
+            }
+            end += 1;
+        }
+        self.matches(path, is_dir)
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+}
+
+/// Matches `text` against a shell glob where `*` and `?` stop at slashes,
+/// `**` spans directories and `[...]` is a character class.
+pub fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&glob, &text)
+}
+
+fn match_from(glob: &[char], text: &[char]) -> bool {
+    match glob {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            // `**/` may also match no directory at all
+            let rest = match rest {
+                ['/', after @ ..] if match_from(after, text) => return true,
+                _ => rest,
+            };
+            (0..=text.len()).any(|skip| match_from(rest, &text[skip..]))
+        }
+        ['*', rest @ ..] => {
+            let limit = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=limit).any(|skip| match_from(rest, &text[skip..]))
+        }
+        ['?', rest @ ..] => match text {
+            [c, tail @ ..] if *c != '/' => match_from(rest, tail),
+            _ => false,
+        },
+        ['[', rest @ ..] => match (text, class(rest)) {
+            ([c, tail @ ..], Some((matcher, after))) if *c != '/' => {
+                matcher(*c) && match_from(after, tail)
+            }
+            (_, Some(_)) => false,
+            // an unterminated class is a literal bracket
+            ([c, tail @ ..], None) => *c == '[' && match_from(rest, tail),
+            ([], None) => false,
+        },
+        ['\\', literal, rest @ ..] | [literal, rest @ ..] => match text {
+            [c, tail @ ..] if c == literal => match_from(rest, tail),
+            _ => false,
+        },
+    }
+}
+
+/// Parses a character class body following `[`, returning a matcher for it
+/// and the remainder of the glob after the closing `]`.
+fn class(glob: &[char]) -> Option<(impl Fn(char) -> bool, &[char])> {
+    let (negated, body) = match glob {
+        ['!' | '^', rest @ ..] => (true, rest),
+        _ => (false, glob),
+    };
+    // a `]` straight after the opening bracket is part of the class
+    let close = body
+        .iter()
+        .skip(1)
+        .position(|&c| c == ']')
+        .map(|pos| pos + 1)?;
+
+    let mut ranges = Vec::new();
+    let items = &body[..close];
+    let mut i = 0;
+    while i < items.len() {
+        if i + 2 < items.len() && items[i + 1] == '-' {
+            ranges.push((items[i], items[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((items[i], items[i]));
+            i += 1;
+        }
+    }
+
+    let matcher = move |c: char| ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != negated;
+    Some((matcher, &body[close + 1..]))
+}
@@ -0,0 +1,300 @@
+use std::{fs::Metadata, os::unix::fs::MetadataExt};
+
+use crate::{
+    hash::HashAlgorithm,
+    types::{FileMode, Index},
+};
+
+/// Start of a binary index. Indexes without it are in the original text
+/// format of `<path> <hash>` lines, which are read and then rewritten as
+/// binary.
+const MAGIC: &[u8] = b"BIDX";
+const VERSION: u32 = 3;
+/// Versions read besides the current one. Version 2 has no modes.
+const OLD_VERSIONS: [u32; 1] = [2];
+
+/// Entry flags.
+const STAGED: u8 = 0;
+const CONFLICT: u8 = 1;
+const REMOVED: u8 = 2;
+/// An entry that only carries stat data for a tracked file with nothing
+/// staged.
+const CACHED: u8 = 3;
+/// Set along with the flag of a staged entry for a symbolic link.
+const SYMLINK: u8 = 0x10;
+/// Set along with the flag of a staged entry for an executable file.
+const EXECUTABLE: u8 = 0x20;
+
+/// What the file system said about a working tree file when it was last
+/// hashed. As long as it says the same, the file is taken to be unchanged
+/// and its hash is reused.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stat {
+    pub mtime: i64,
+    pub mtime_nsec: u32,
+    pub ctime: i64,
+    pub ctime_nsec: u32,
+    pub size: u64,
+    pub mode: u32,
+}
+
+impl Stat {
+    pub fn from_metadata(metadata: &Metadata) -> Stat {
+        Stat {
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec() as u32,
+            ctime: metadata.ctime(),
+            ctime_nsec: metadata.ctime_nsec() as u32,
+            size: metadata.size(),
+            mode: metadata.mode(),
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.mtime.to_be_bytes());
+        out.extend_from_slice(&self.mtime_nsec.to_be_bytes());
+        out.extend_from_slice(&self.ctime.to_be_bytes());
+        out.extend_from_slice(&self.ctime_nsec.to_be_bytes());
+        out.extend_from_slice(&self.size.to_be_bytes());
+        out.extend_from_slice(&self.mode.to_be_bytes());
+    }
+
+    fn decode(reader: &mut Reader) -> Option<Stat> {
+        Some(Stat {
+            mtime: i64::from_be_bytes(reader.array()?),
+            mtime_nsec: u32::from_be_bytes(reader.array()?),
+            ctime: i64::from_be_bytes(reader.array()?),
+            ctime_nsec: u32::from_be_bytes(reader.array()?),
+            size: u64::from_be_bytes(reader.array()?),
+            mode: u32::from_be_bytes(reader.array()?),
+        })
+    }
+}
+
+pub(crate) fn is_binary(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Serializes `index` as `BIDX`, the version and the entry count, then one
+/// entry per path and a hex checksum of everything before it. An entry is
+/// a flag byte, with [`SYMLINK`] or [`EXECUTABLE`] set for files of those
+/// modes, the stat data, then the hash and the path, each preceded by its
+/// length. Staged entries carry stat data when the file was hashed to the
+/// staged content; other cached stat data gets an entry of its own.
+pub(crate) fn encode(index: &Index, algorithm: HashAlgorithm) -> Vec<u8> {
+    let mut entries: Vec<(u8, &str, &str, Stat)> = Vec::new();
+    for (path, hash) in index.hashtree.iter() {
+        let flag = if index.conflicts.contains(path) {
+            CONFLICT
+        } else {
+            match index.mode(path) {
+                FileMode::Regular => STAGED,
+                FileMode::Executable => STAGED | EXECUTABLE,
+                FileMode::Symlink => STAGED | SYMLINK,
+            }
+        };
+        let stat = match index.stat_cache.get(path) {
+            Some((stat, cached)) if cached == hash => *stat,
+            _ => Stat::default(),
+        };
+        entries.push((flag, path, hash, stat));
+    }
+    for path in index.removed.iter() {
+        entries.push((REMOVED, path, "", Stat::default()));
+    }
+    for (path, (stat, hash)) in index.stat_cache.iter() {
+        if index.hashtree.get(path) != Some(hash) {
+            entries.push((CACHED, path, hash, *stat));
+        }
+    }
+    entries.sort_by(|a, b| a.1.cmp(b.1).then(a.0.cmp(&b.0)));
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&VERSION.to_be_bytes());
+    out.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for (flag, path, hash, stat) in entries {
+        out.push(flag);
+        stat.encode(&mut out);
+        out.push(hash.len() as u8);
+        out.extend_from_slice(hash.as_bytes());
+        out.extend_from_slice(&(path.len() as u16).to_be_bytes());
+        out.extend_from_slice(path.as_bytes());
+    }
+    let checksum = algorithm.digest(&[&out]);
+    out.extend_from_slice(checksum.as_bytes());
+    out
+}
+
+/// Fills `index` from a binary index, or returns `None` if it is corrupt.
+pub(crate) fn decode(index: &mut Index, data: &[u8], algorithm: HashAlgorithm) -> Option<()> {
+    let (body, checksum) = data.split_at(data.len().checked_sub(algorithm.hex_len())?);
+    if algorithm.digest(&[body]).as_bytes() != checksum {
+        return None;
+    }
+
+    let mut reader = Reader { data: body, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return None;
+    }
+    let version = u32::from_be_bytes(reader.array()?);
+    if version != VERSION && !OLD_VERSIONS.contains(&version) {
+        return None;
+    }
+    let count = u32::from_be_bytes(reader.array()?);
+
+    for _ in 0..count {
+        let flag = reader.take(1)?[0];
+        let stat = Stat::decode(&mut reader)?;
+        let hash_len = reader.take(1)?[0] as usize;
+        let hash = String::from_utf8(reader.take(hash_len)?.to_vec()).ok()?;
+        let path_len = u16::from_be_bytes(reader.array()?) as usize;
+        let path = String::from_utf8(reader.take(path_len)?.to_vec()).ok()?;
+
+        match flag {
+            STAGED => index.update(&path, &hash),
+            flag if flag == STAGED | EXECUTABLE => {
+                index.update_with_mode(&path, &hash, FileMode::Executable)
+            }
+            flag if flag == STAGED | SYMLINK => {
+                index.update_with_mode(&path, &hash, FileMode::Symlink)
+            }
+            CONFLICT => index.mark_conflict(&path, &hash),
+            REMOVED => {
+                index.remove(&path);
+                continue;
+            }
+            CACHED => {}
+            _ => return None,
+        }
+        if stat != Stat::default() {
+            index.record_stat(&path, stat, &hash);
+        }
+    }
+
+    (reader.pos == body.len()).then_some(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use super::*;
+
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Sha1;
+
+    fn empty() -> Index {
+        Index::new(PathBuf::from("index"), Duration::ZERO, ALGORITHM)
+    }
+
+    fn stat(mtime: i64) -> Stat {
+        Stat {
+            mtime,
+            mtime_nsec: 1,
+            ctime: mtime,
+            ctime_nsec: 2,
+            size: 3,
+            mode: 0o100644,
+        }
+    }
+
+    fn decoded(data: &[u8]) -> Option<Index> {
+        let mut index = empty();
+        decode(&mut index, data, ALGORITHM).map(|()| index)
+    }
+
+    /// Replaces the checksum at the end of `data` with that of the rest.
+    fn reseal(data: &mut Vec<u8>) {
+        data.truncate(data.len() - ALGORITHM.hex_len());
+        let checksum = ALGORITHM.digest(&[data]);
+        data.extend_from_slice(checksum.as_bytes());
+    }
+
+    #[test]
+    fn indexes_round_trip() {
+        let mut index = empty();
+        index.update("regular", "1111");
+        index.update_with_mode("bin/tool", "2222", FileMode::Executable);
+        index.update_with_mode("link", "3333", FileMode::Symlink);
+        index.mark_conflict("conflicted", "4444");
+        index.remove("deleted");
+        index.record_stat("regular", stat(10), "1111");
+        // stat data of a file hashed to something other than what is staged
+        index.record_stat("link", stat(20), "5555");
+        index.record_stat("untouched", stat(30), "6666");
+
+        let data = encode(&index, ALGORITHM);
+        assert!(is_binary(&data));
+        let read = decoded(&data).unwrap();
+        assert_eq!(read.hashtree, index.hashtree);
+        assert_eq!(read.modes, index.modes);
+        assert_eq!(read.conflicts, index.conflicts);
+        assert_eq!(read.removed, index.removed);
+        assert_eq!(read.stat_cache, index.stat_cache);
+        assert_eq!(encode(&read, ALGORITHM), data);
+    }
+
+    #[test]
+    fn an_empty_index_round_trips() {
+        let data = encode(&empty(), ALGORITHM);
+        let read = decoded(&data).unwrap();
+        assert!(read.hashtree.is_empty() && read.stat_cache.is_empty());
+    }
+
+    #[test]
+    fn corrupt_indexes_are_refused() {
+        let mut index = empty();
+        index.update("file", "1111");
+        let data = encode(&index, ALGORITHM);
+
+        let mut flipped = data.clone();
+        flipped[MAGIC.len() + 9] ^= 1;
+        assert!(decoded(&flipped).is_none());
+        assert!(decoded(&data[..data.len() - 1]).is_none());
+        assert!(decoded(b"").is_none());
+
+        // well sealed, but not an index this version reads
+        let mut version = data.clone();
+        version[MAGIC.len() + 3] = 9;
+        reseal(&mut version);
+        assert!(decoded(&version).is_none());
+
+        let mut flag = data.clone();
+        flag[MAGIC.len() + 8] = 0x7f;
+        reseal(&mut flag);
+        assert!(decoded(&flag).is_none());
+
+        // a path longer than what is left, and bytes after the last entry
+        let mut long = data.clone();
+        let path_len = long.len() - ALGORITHM.hex_len() - "file".len() - 2;
+        long[path_len] = 0xff;
+        reseal(&mut long);
+        assert!(decoded(&long).is_none());
+
+        let mut trailing = data.clone();
+        trailing.insert(data.len() - ALGORITHM.hex_len(), 0);
+        reseal(&mut trailing);
+        assert!(decoded(&trailing).is_none());
+
+        let mut count = data;
+        count[MAGIC.len() + 7] = 2;
+        reseal(&mut count);
+        assert!(decoded(&count).is_none());
+    }
+}
@@ -0,0 +1,66 @@
+//! blip is a small version control system modelled on git.
+//!
+//! Besides the `blip` binary, the crate can be used as a library through
+//! [`Repository`]:
+//!
+//! ```no_run
+//! use blip::{cli::AddMode, Repository};
+//!
+//! let repository = Repository::init("project", None, None)?;
+//! repository.add(&["README".to_string()], AddMode::Paths, false, None)?;
+//! let commit = repository.commit("Add a README", false, false)?;
+//!
+//! for commit in repository.log()? {
+//!     println!("{}", commit?.message());
+//! }
+//! # Ok::<(), blip::Error>(())
+//! ```
+
+pub mod archive;
+pub mod attributes;
+pub mod binary;
+pub mod bisect;
+pub mod blame;
+pub mod bundle;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod conventional;
+pub mod diff;
+pub mod editor;
+pub mod fast_export;
+pub mod fast_import;
+pub mod fsck;
+pub mod gc;
+pub mod graph;
+pub mod hash;
+pub mod history;
+pub mod hooks;
+pub mod ignore;
+pub mod index;
+pub mod lock;
+pub mod mail;
+pub mod merge;
+pub mod pack;
+pub mod patch;
+pub mod pathspec;
+pub mod reachable;
+pub mod rebase;
+pub mod reflog;
+pub mod refs;
+pub mod remote;
+pub mod repository;
+pub mod revision;
+pub mod sequencer;
+pub mod server;
+pub mod sign;
+pub mod stash;
+pub mod submodule;
+pub mod trailer;
+pub mod transport;
+pub mod types;
+pub mod worktree;
+pub mod worktrees;
+
+pub use repository::Repository;
+pub use types::{Commit, Error, Result};
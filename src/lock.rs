@@ -0,0 +1,112 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::types::{Context, Error, Result};
+
+/// How long to wait for another process to release a lock unless
+/// `core.lockTimeout` says otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Longest pause between attempts to take a lock.
+const MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Exclusive access to a file that is about to be rewritten. Holding it
+/// means having created `<file>.lock`, which receives the new contents and
+/// replaces the file on [`LockFile::commit`], so readers only ever see the
+/// old or the new contents in full. Dropping the lock without committing
+/// leaves the file as it was.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: File,
+    committed: bool,
+}
+
+impl LockFile {
+    /// Takes the lock on `path`, retrying for up to `timeout` while another
+    /// process holds it.
+    pub fn acquire(path: &Path, timeout: Duration) -> Result<LockFile> {
+        let mut lock_path = OsString::from(path.as_os_str());
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let start = Instant::now();
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            match File::options()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+            {
+                Ok(file) => {
+                    return Ok(LockFile {
+                        path: path.to_path_buf(),
+                        lock_path,
+                        file,
+                        committed: false,
+                    })
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    let waited = start.elapsed();
+                    if waited >= timeout {
+                        return Err(Error::Locked(lock_path));
+                    }
+                    thread::sleep(backoff.min(timeout - waited));
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+                Err(err) => return Err(err).context("create", &lock_path),
+            }
+        }
+    }
+
+    pub fn write_all(&mut self, data: &[u8]) -> Result<()> {
+        self.file.write_all(data).context("write", &self.lock_path)
+    }
+
+    /// Flushes the new contents to disk and moves them into place.
+    pub fn commit(mut self) -> Result<()> {
+        self.file.sync_all().context("write", &self.lock_path)?;
+        fs::rename(&self.lock_path, &self.path).context("rename", &self.lock_path)?;
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// Replaces the contents of `path` under its lock, for contents that do not
+/// depend on the old ones. Those that do are read with a [`LockFile`]
+/// already taken, so that nothing written in between is lost.
+pub fn write(path: &Path, data: &[u8], timeout: Duration) -> Result<()> {
+    let mut lock = LockFile::acquire(path, timeout)?;
+    lock.write_all(data)?;
+    lock.commit()
+}
+
+/// Reads `core.lockTimeout`, in milliseconds with a negative value meaning
+/// to wait forever.
+pub fn timeout(value: Option<String>) -> Result<Duration> {
+    let Some(value) = value else {
+        return Ok(DEFAULT_TIMEOUT);
+    };
+    match value.trim().parse::<i64>() {
+        Ok(ms) if ms < 0 => Ok(Duration::MAX),
+        Ok(ms) => Ok(Duration::from_millis(ms as u64)),
+        Err(_) => Err(Error::InvalidConfig(format!(
+            "bad lock timeout '{value}' for 'core.lockTimeout'"
+        ))),
+    }
+}
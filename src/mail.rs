@@ -0,0 +1,344 @@
+//! Commits as emails, for sending changes to a project without sharing a
+//! remote with it: `blip format-patch` writes each commit as a message in
+//! mbox format, with its patch, and `blip am` makes commits from them
+//! again. The messages are the ones `git format-patch` writes and
+//! `git am` reads, so patches go both ways between blip and git.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    history, patch,
+    repository::Repository,
+    sign,
+    types::{Blob, Commit, Error, FileService, Result, Signature},
+    worktree::{is_safe_path, WorkTree},
+};
+
+/// The date git gives the `From` line starting each message, which mail
+/// programs use to recognise patches.
+const MAGIC_DATE: &str = "Mon Sep 17 00:00:00 2001";
+
+/// A commit as an email.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Email {
+    pub author: Signature,
+    /// The first line of the commit message.
+    pub subject: String,
+    /// The rest of the commit message.
+    pub body: String,
+    /// The changes of the commit, as `blip show` renders them.
+    pub patch: String,
+}
+
+impl Email {
+    pub fn from_commit(repository: &Repository, commit: &Commit) -> Result<Email> {
+        let (subject, body) = commit
+            .message()
+            .split_once('\n')
+            .unwrap_or((commit.message(), ""));
+        let author = match commit.author().or(commit.committer()) {
+            Some(author) => author.clone(),
+            None => Signature::parse("unknown <unknown> 0 +0000").expect("a valid signature"),
+        };
+        Ok(Email {
+            author,
+            subject: subject.trim().to_string(),
+            body: body.trim().to_string(),
+            patch: repository.commit_patch(commit, None)?,
+        })
+    }
+
+    /// The commit message the email carries.
+    pub fn message(&self) -> String {
+        match self.body.is_empty() {
+            true => self.subject.clone(),
+            false => format!("{}\n\n{}", self.subject, self.body),
+        }
+    }
+
+    /// Renders the email for the commit `hash` as the `number`th of
+    /// `total` messages of an mbox file.
+    pub fn format(&self, hash: &str, number: usize, total: usize) -> String {
+        let prefix = match total {
+            1 => "[PATCH]".to_string(),
+            _ => {
+                let width = total.to_string().len();
+                format!("[PATCH {number:0width$}/{total}]")
+            }
+        };
+        let mut out = format!("From {hash} {MAGIC_DATE}\n");
+        out.push_str(&format!(
+            "From: {} <{}>\n",
+            self.author.name, self.author.email
+        ));
+        out.push_str(&format!("Date: {}\n", self.author.rfc2822_date()));
+        out.push_str(&format!("Subject: {prefix} {}\n", self.subject));
+        let text = [&self.author.name, &self.subject, &self.body, &self.patch];
+        if text.iter().any(|text| !text.is_ascii()) {
+            out.push_str("MIME-Version: 1.0\n");
+            out.push_str("Content-Type: text/plain; charset=UTF-8\n");
+            out.push_str("Content-Transfer-Encoding: 8bit\n");
+        }
+        out.push('\n');
+        if !self.body.is_empty() {
+            out.push_str(&format!("{}\n\n", self.body));
+        }
+        out.push_str("---\n");
+        out.push_str(&self.patch);
+        out.push_str(&format!("-- \nblip {}\n\n", env!("CARGO_PKG_VERSION")));
+        out
+    }
+
+    /// Reads every message of an mbox file, or a single message without
+    /// its `From` line.
+    pub fn parse_mbox(text: &str) -> Result<Vec<Email>> {
+        let lines: Vec<&str> = text.split_inclusive('\n').collect();
+        let mut starts = vec![0];
+        for i in 1..lines.len() {
+            let follows_header = lines.get(i + 1).is_some_and(|next| is_header(next));
+            if lines[i].starts_with("From ") && lines[i - 1] == "\n" && follows_header {
+                starts.push(i);
+            }
+        }
+        starts.push(lines.len());
+        starts
+            .windows(2)
+            .map(|bounds| Email::parse(&lines[bounds[0]..bounds[1]].concat()))
+            .collect()
+    }
+
+    fn parse(text: &str) -> Result<Email> {
+        let invalid = |message: &str| Error::Usage(message.to_string());
+        let mut lines = text.split_inclusive('\n');
+        let mut headers: Vec<String> = Vec::new();
+        for line in lines.by_ref() {
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                break;
+            }
+            // folded headers continue on indented lines
+            if let (Some(header), true) = (headers.last_mut(), line.starts_with([' ', '\t'])) {
+                header.push(' ');
+                header.push_str(line.trim_start());
+            } else if !(headers.is_empty() && line.starts_with("From ")) {
+                headers.push(line.to_string());
+            }
+        }
+        let header = |name: &str| {
+            headers.iter().find_map(|header| {
+                let (key, value) = header.split_once(':')?;
+                key.eq_ignore_ascii_case(name)
+                    .then(|| decode_header(value.trim()))
+            })
+        };
+
+        let from = header("From").ok_or_else(|| invalid("the email has no From header"))?;
+        let (name, email) = match from.rsplit_once('<') {
+            Some((name, email)) => (
+                name.trim().trim_matches('"').to_string(),
+                email.trim_end_matches('>').to_string(),
+            ),
+            None => (from.clone(), from.clone()),
+        };
+        let date = header("Date").unwrap_or_default();
+        let author = Signature::from_rfc2822(&name, &email, &date)
+            .ok_or_else(|| invalid(&format!("the email has a malformed date '{date}'")))?;
+
+        let mut subject = header("Subject").unwrap_or_default();
+        while let Some(rest) = subject.strip_prefix('[') {
+            let Some((_, rest)) = rest.split_once(']') else {
+                break;
+            };
+            subject = rest.trim_start().to_string();
+        }
+
+        let mut body = String::new();
+        let mut patch = String::new();
+        for line in lines.by_ref() {
+            if line.trim_end() == "---" {
+                break;
+            }
+            if line.starts_with("diff --git ") {
+                patch.push_str(line);
+                break;
+            }
+            body.push_str(line);
+        }
+        patch.extend(lines);
+        Ok(Email {
+            author,
+            subject,
+            body: body.trim().to_string(),
+            patch,
+        })
+    }
+}
+
+/// The commits from `<since>` to HEAD, or from `<since>` to `<until>`
+/// when written `<since>..<until>`, that `<since>` cannot reach, oldest
+/// first. Only first parents are followed and merges are left out.
+pub fn commits(file_service: &FileService, range: &str) -> Result<Vec<Commit>> {
+    let (since, until) = range.split_once("..").unwrap_or((range, ""));
+    let resolve = |name: &str| file_service.resolve(if name.is_empty() { "HEAD" } else { name });
+    let (since, until) = (resolve(since)?, resolve(until)?);
+
+    let excluded = history::ancestors(file_service, &since)?;
+    let mut commits = Vec::new();
+    let mut next = Some(until);
+    while let Some(hash) = next.filter(|hash| !excluded.contains(hash)) {
+        let commit = file_service.read_commit(&hash)?;
+        next = commit.parent().map(str::to_string);
+        if commit.parents().len() <= 1 {
+            commits.push(commit);
+        }
+    }
+    commits.reverse();
+    Ok(commits)
+}
+
+/// Makes a commit on top of HEAD from each email in turn, with the author,
+/// message and changes the email records.
+///
+/// The commits are made without touching the working tree, which is only
+/// updated once every patch applies. A patch that does not apply leaves
+/// everything as it was.
+pub fn am(file_service: &FileService, emails: &[Email]) -> Result<Vec<Commit>> {
+    let worktree = WorkTree::new(file_service);
+    let mut index = file_service.lock_index()?;
+    let head = file_service.read_head_commit()?;
+    let status = worktree.status(&index, head.as_ref())?;
+    if !status.is_clean() {
+        let paths = status.staged.into_iter().chain(status.unstaged);
+        return Err(Error::DirtyWorkTree(paths.map(|(_, path)| path).collect()));
+    }
+
+    let empty = BTreeMap::new();
+    let mut commits: Vec<Commit> = Vec::new();
+    for (i, email) in emails.iter().enumerate() {
+        let tip = commits.last().or(head.as_ref());
+        let mut files = tip.map_or(&empty, |tip| tip.files()).clone();
+        let failed = |path: &str| {
+            Error::Usage(format!(
+                "patch {} '{}' does not apply to {path}; nothing was changed",
+                i + 1,
+                email.subject
+            ))
+        };
+
+        let patches = patch::parse(&email.patch)?;
+        if patches.is_empty() {
+            return Err(Error::Usage(format!(
+                "patch {} '{}' is empty",
+                i + 1,
+                email.subject
+            )));
+        }
+        for file in patches {
+            // nothing is committed that checking it out would refuse
+            for path in file.old_path.iter().chain(&file.new_path) {
+                if !is_safe_path(path) {
+                    return Err(Error::UnsafePath(path.clone()));
+                }
+                if worktree.is_beyond_symlink(path) {
+                    return Err(Error::BeyondSymlink(path.clone()));
+                }
+            }
+            let old = match (&file.old_path, &file.new_path) {
+                (Some(path), _) => {
+                    let Some(hash) = files.get(path) else {
+                        return Err(failed(path));
+                    };
+                    file_service.read_blob(hash)?
+                }
+                (None, Some(path)) if files.contains_key(path) => return Err(failed(path)),
+                (None, _) => Vec::new(),
+            };
+            let Some(new) = file.apply_data(&old) else {
+                return Err(failed(file.path()));
+            };
+            if let Some(path) = &file.old_path {
+                files.remove(path);
+            }
+            if let Some(path) = file.new_path {
+                let blob = Blob::from_data(new, file_service.hash_algorithm);
+                file_service.write_blob(&blob)?;
+                files.insert(path, blob.hash().clone());
+            }
+        }
+
+        let mut commit = Commit::new(tip, &email.message());
+        commit.set_files(files);
+        commit.set_author(email.author.clone());
+        commit.set_committer(file_service.signature("COMMITTER")?);
+        file_service.write_commit_object(&mut commit)?;
+        commits.push(commit);
+    }
+
+    if let Some(tip) = commits.last() {
+        worktree.checkout(
+            file_service,
+            head.as_ref().map_or(&empty, |head| head.files()),
+            tip.files(),
+            tip.modes(),
+        )?;
+        file_service.update_head(tip.hash().unwrap_or_default())?;
+        index.clear()?;
+    }
+    Ok(commits)
+}
+
+/// Whether `line` starts like an email header, `Name: value`.
+fn is_header(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// Decodes the `=?charset?q|b?text?=` words mail programs write non-ASCII
+/// headers as. Only UTF-8 and its subsets are understood.
+fn decode_header(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..]
+            .split_once("?=")
+            .and_then(|(word, after)| {
+                let mut parts = word.splitn(3, '?');
+                let (_, encoding, text) = (parts.next()?, parts.next()?, parts.next()?);
+                let bytes = match encoding {
+                    "q" | "Q" => decode_q(text)?,
+                    "b" | "B" => sign::base64_decode(text)?,
+                    _ => return None,
+                };
+                Some((String::from_utf8(bytes).ok()?, after))
+            });
+        let Some((word, after)) = decoded else {
+            break;
+        };
+        let between = &rest[..start];
+        // whitespace between two encoded words is not part of the text
+        if out.is_empty() || !between.trim().is_empty() {
+            out.push_str(between);
+        }
+        out.push_str(&word);
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+fn decode_q(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut chars = text.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'_' => bytes.push(b' '),
+            b'=' => {
+                let hex = [chars.next()?, chars.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    Some(bytes)
+}
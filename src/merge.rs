@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    attributes::{Attributes, MergeDriver},
+    diff::{self, Edit},
+    types::{Blob, Commit, FileMode, FileService, Index, Result},
+};
+
+/// One of the file sets a tree merge takes, with the modes of the files
+/// that are not regular ones.
+#[derive(Debug, Clone, Copy)]
+pub struct Files<'a> {
+    pub files: &'a BTreeMap<String, String>,
+    pub modes: &'a BTreeMap<String, FileMode>,
+}
+
+/// Outcome of merging three versions of a text file.
+#[derive(Debug)]
+pub struct TextMerge {
+    pub text: String,
+    pub conflicts: usize,
+}
+
+/// Outcome of merging three file sets. Conflicted paths are present in
+/// `files`, pointing at a blob that holds the conflict markers.
+#[derive(Debug, Default)]
+pub struct TreeMerge {
+    pub files: BTreeMap<String, String>,
+    /// Modes of the merged files that are not regular ones.
+    pub modes: BTreeMap<String, FileMode>,
+    pub conflicts: Vec<String>,
+}
+
+impl Files<'_> {
+    /// No files at all, for a side that has no commit.
+    pub const EMPTY: Files<'static> = Files {
+        files: &BTreeMap::new(),
+        modes: &BTreeMap::new(),
+    };
+
+    /// The mode of `path`, or `None` if it is not among the files.
+    fn mode(&self, path: &str) -> Option<FileMode> {
+        self.files
+            .contains_key(path)
+            .then(|| self.modes.get(path).copied().unwrap_or_default())
+    }
+}
+
+impl TreeMerge {
+    /// Stages a merge onto `head` that has conflicts: resolved paths that
+    /// differ from `head` as they are, conflicted ones as conflicts to
+    /// resolve, and paths the merge removed as removed.
+    pub fn stage(&self, index: &mut Index, head: &Commit) {
+        for path in head.files().keys() {
+            if !self.files.contains_key(path) {
+                index.remove(path);
+            }
+        }
+        for (path, hash) in self.files.iter() {
+            if self.conflicts.contains(path) {
+                index.mark_conflict(path, hash);
+            } else {
+                let mode = self.modes.get(path).copied().unwrap_or_default();
+                if head.files().get(path) != Some(hash) || head.mode(path) != mode {
+                    index.update_with_mode(path, hash, mode);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> From<&'a Commit> for Files<'a> {
+    fn from(commit: &'a Commit) -> Files<'a> {
+        Files {
+            files: commit.files(),
+            modes: commit.modes(),
+        }
+    }
+}
+
+/// A contiguous change to the base: lines `start..end` replaced by `lines`.
+#[derive(Debug)]
+struct Change<'a> {
+    start: usize,
+    end: usize,
+    lines: Vec<&'a str>,
+}
+
+fn changes<'a>(base: &[&'a str], other: &[&'a str]) -> Vec<Change<'a>> {
+    let mut changes: Vec<Change> = Vec::new();
+    let mut pos = 0;
+    let mut open = false;
+
+    for edit in diff::diff_lines(base, other) {
+        match edit {
+            Edit::Equal { old, .. } => {
+                pos = old + 1;
+                open = false;
+            }
+            Edit::Delete { old } => {
+                if !open {
+                    changes.push(Change {
+                        start: old,
+                        end: old,
+                        lines: Vec::new(),
+                    });
+                    open = true;
+                }
+                let change = changes.last_mut().unwrap();
+                change.end = old + 1;
+                pos = old + 1;
+            }
+            Edit::Insert { new } => {
+                if !open {
+                    changes.push(Change {
+                        start: pos,
+                        end: pos,
+                        lines: Vec::new(),
+                    });
+                    open = true;
+                }
+                changes.last_mut().unwrap().lines.push(other[new]);
+            }
+        }
+    }
+    changes
+}
+
+/// Applies the changes that fall within `start..end` of the base.
+fn apply<'a>(base: &[&'a str], changes: &[&Change<'a>], start: usize, end: usize) -> Vec<&'a str> {
+    let mut lines = Vec::new();
+    let mut pos = start;
+    for change in changes {
+        lines.extend_from_slice(&base[pos..change.start]);
+        lines.extend_from_slice(&change.lines);
+        pos = change.end;
+    }
+    lines.extend_from_slice(&base[pos..end]);
+    lines
+}
+
+fn push_lines(out: &mut String, lines: &[&str]) {
+    for line in lines {
+        out.push_str(line);
+    }
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Three-way merges `ours` and `theirs` against their common `base`,
+/// surrounding overlapping changes with conflict markers.
+pub fn merge_text(base: &str, ours: &str, theirs: &str, labels: (&str, &str)) -> TextMerge {
+    merge_lines(base, ours, theirs, labels, false)
+}
+
+/// Like [`merge_text`], but keeps the lines of both sides where their
+/// changes overlap, ours first, so that there is never a conflict. Suits
+/// files such as changelogs, where both sides add lines.
+pub fn union_text(base: &str, ours: &str, theirs: &str) -> String {
+    merge_lines(base, ours, theirs, ("", ""), true).text
+}
+
+fn merge_lines(
+    base: &str,
+    ours: &str,
+    theirs: &str,
+    labels: (&str, &str),
+    union: bool,
+) -> TextMerge {
+    let base: Vec<&str> = base.split_inclusive('\n').collect();
+    let ours: Vec<&str> = ours.split_inclusive('\n').collect();
+    let theirs: Vec<&str> = theirs.split_inclusive('\n').collect();
+
+    let ours = changes(&base, &ours);
+    let theirs = changes(&base, &theirs);
+
+    let mut merge = TextMerge {
+        text: String::new(),
+        conflicts: 0,
+    };
+    let (mut a, mut b, mut pos) = (0, 0, 0);
+
+    while a < ours.len() || b < theirs.len() {
+        let start = match (ours.get(a), theirs.get(b)) {
+            (Some(x), Some(y)) => x.start.min(y.start),
+            (Some(x), None) => x.start,
+            (None, Some(y)) => y.start,
+            (None, None) => unreachable!(),
+        };
+
+        // grow the region until no change from either side touches it
+        let (mut region_a, mut region_b) = (Vec::new(), Vec::new());
+        let mut end = start;
+        loop {
+            if let Some(change) = ours.get(a).filter(|c| c.start <= end) {
+                end = end.max(change.end);
+                region_a.push(change);
+                a += 1;
+            } else if let Some(change) = theirs.get(b).filter(|c| c.start <= end) {
+                end = end.max(change.end);
+                region_b.push(change);
+                b += 1;
+            } else {
+                break;
+            }
+        }
+
+        merge.text.extend(base[pos..start].iter().copied());
+        let mine = apply(&base, &region_a, start, end);
+        let other = apply(&base, &region_b, start, end);
+
+        if region_b.is_empty() || mine == other {
+            merge.text.extend(mine);
+        } else if region_a.is_empty() {
+            merge.text.extend(other);
+        } else if union {
+            push_lines(&mut merge.text, &mine);
+            push_lines(&mut merge.text, &other);
+        } else {
+            merge.conflicts += 1;
+            merge.text.push_str(&format!("<<<<<<< {}\n", labels.0));
+            push_lines(&mut merge.text, &mine);
+            merge.text.push_str("=======\n");
+            push_lines(&mut merge.text, &other);
+            merge.text.push_str(&format!(">>>>>>> {}\n", labels.1));
+        }
+        pos = end;
+    }
+
+    merge.text.extend(base[pos..].iter().copied());
+    merge
+}
+
+/// Three-way merges whole file sets, writing any newly merged blobs to the
+/// object store. Files changed on both sides are merged by the driver
+/// their `merge` attribute names, see [`MergeDriver`]. Modes are merged on
+/// their own, the same way.
+pub fn merge_trees(
+    file_service: &FileService,
+    attributes: &Attributes,
+    base: Files,
+    ours: Files,
+    theirs: Files,
+    labels: (&str, &str),
+) -> Result<TreeMerge> {
+    let paths: BTreeSet<&String> = base
+        .files
+        .keys()
+        .chain(ours.files.keys())
+        .chain(theirs.files.keys())
+        .collect();
+    let mut merge = TreeMerge::default();
+
+    for path in paths {
+        let (b, o, t) = (
+            base.files.get(path),
+            ours.files.get(path),
+            theirs.files.get(path),
+        );
+
+        let result = if o == t || b == t {
+            o
+        } else if b == o {
+            t
+        } else {
+            match (o, t) {
+                (Some(o), Some(_)) if attributes.merge_driver(path) == MergeDriver::Binary => {
+                    merge.conflicts.push(path.clone());
+                    Some(o)
+                }
+                (Some(o), Some(t)) => {
+                    let base_data = match b {
+                        Some(b) => file_service.read_blob(b)?,
+                        None => Vec::new(),
+                    };
+                    let ours_data = file_service.read_blob(o)?;
+                    let theirs_data = file_service.read_blob(t)?;
+
+                    let driver = attributes.merge_driver(path);
+                    let (data, clean) = if let MergeDriver::Custom { command, .. } = driver {
+                        let contents = (&base_data[..], &ours_data[..], &theirs_data[..]);
+                        attributes.run_merge_driver(&command, path, contents)?
+                    } else {
+                        let (Ok(base_text), Ok(ours_text), Ok(theirs_text)) = (
+                            String::from_utf8(base_data),
+                            String::from_utf8(ours_data),
+                            String::from_utf8(theirs_data),
+                        ) else {
+                            // binary files cannot be merged line by line, keep ours
+                            merge.conflicts.push(path.clone());
+                            merge.files.insert(path.clone(), o.clone());
+                            continue;
+                        };
+                        let text = match driver {
+                            MergeDriver::Union => TextMerge {
+                                text: union_text(&base_text, &ours_text, &theirs_text),
+                                conflicts: 0,
+                            },
+                            _ => merge_text(&base_text, &ours_text, &theirs_text, labels),
+                        };
+                        (text.text.into_bytes(), text.conflicts == 0)
+                    };
+
+                    if !clean {
+                        merge.conflicts.push(path.clone());
+                    }
+                    let blob = Blob::from_data(data, file_service.hash_algorithm);
+                    file_service.write_blob(&blob)?;
+                    merge.files.insert(path.clone(), blob.hash().clone());
+                    continue;
+                }
+                // modified on one side and deleted on the other, keep the modification
+                (o, t) => {
+                    merge.conflicts.push(path.clone());
+                    o.or(t)
+                }
+            }
+        };
+
+        if let Some(hash) = result {
+            merge.files.insert(path.clone(), hash.clone());
+        }
+    }
+
+    for path in merge.files.keys() {
+        let (b, o, t) = (base.mode(path), ours.mode(path), theirs.mode(path));
+        // a side that deleted the file has no say in its mode
+        let mode = if b == o { t.or(o) } else { o.or(t) };
+        if let Some(mode) = mode.filter(|mode| *mode != FileMode::Regular) {
+            merge.modes.insert(path.clone(), mode);
+        }
+    }
+
+    Ok(merge)
+}
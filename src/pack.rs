@@ -0,0 +1,704 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{self, File},
+    io::Write,
+    os::unix::prelude::FileExt,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::{
+    hash::HashAlgorithm,
+    types::{Context, Error, FileService, ObjectKind, Result},
+};
+
+const MAGIC: &[u8] = b"BPCK";
+const VERSION: u8 = 1;
+
+/// Entry type of objects stored before object headers existed.
+const UNTYPED: u8 = 0;
+/// Entry type of an object stored as a delta against an earlier entry.
+const DELTA: u8 = 7;
+
+/// How many preceding objects of the same kind are tried as delta bases.
+const WINDOW: usize = 10;
+/// Longest chain of deltas a read may have to follow.
+const MAX_DEPTH: usize = 50;
+/// Length of the blocks the delta search matches on.
+const BLOCK: usize = 16;
+
+/// Delta instructions: insert the following bytes, or copy a range of the
+/// base object.
+const INSERT: u8 = 0;
+const COPY: u8 = 1;
+
+/// A packfile, `objects/pack/pack-<hash>.pack`, together with the offsets
+/// of its objects from the matching `.idx` file.
+///
+/// The pack is `BPCK`, a version byte and the object count, then one entry
+/// per object and a hex checksum of everything before it. An entry is a
+/// type byte, the length of its payload and the payload, which is either
+/// the object content or, for deltas, the distance back to the base entry
+/// followed by the delta.
+#[derive(Debug)]
+pub struct Pack {
+    path: PathBuf,
+    file: File,
+    offsets: BTreeMap<String, u64>,
+}
+
+/// What [`repack`] wrote.
+#[derive(Debug)]
+pub struct PackStats {
+    pub name: String,
+    pub objects: usize,
+    pub deltas: usize,
+}
+
+/// An object on its way into a pack.
+struct Entry {
+    hash: String,
+    kind: Option<ObjectKind>,
+    data: Vec<u8>,
+}
+
+impl Pack {
+    /// Opens every pack in `pack_dir`, which need not exist.
+    pub fn load_all(pack_dir: &Path) -> Result<Vec<Pack>> {
+        let entries = match fs::read_dir(pack_dir) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            result => result.context("read", pack_dir)?,
+        };
+
+        let mut indexes = Vec::new();
+        for entry in entries {
+            let path = entry.context("read", pack_dir)?.path();
+            if path.extension().is_some_and(|ext| ext == "idx") {
+                indexes.push(path);
+            }
+        }
+        indexes.sort();
+        indexes.iter().map(|index| Pack::open(index)).collect()
+    }
+
+    /// Opens a pack from its index, a `<hash> <offset>` line per object.
+    fn open(index: &Path) -> Result<Pack> {
+        let path = index.with_extension("pack");
+        let contents = fs::read_to_string(index).context("read", index)?;
+
+        let mut offsets = BTreeMap::new();
+        for line in contents.lines() {
+            let (hash, offset) = line
+                .split_once(' ')
+                .and_then(|(hash, offset)| Some((hash, offset.parse().ok()?)))
+                .ok_or_else(|| Error::InvalidPack(index.to_path_buf()))?;
+            offsets.insert(hash.to_string(), offset);
+        }
+
+        let file = File::open(&path).context("open", &path)?;
+        Ok(Pack {
+            path,
+            file,
+            offsets,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.offsets.contains_key(hash)
+    }
+
+    pub fn hashes(&self) -> impl Iterator<Item = &String> {
+        self.offsets.keys()
+    }
+
+    /// Reads an object's kind and content, or `None` if it is not in this
+    /// pack.
+    pub fn read(&self, hash: &str) -> Result<Option<(Option<ObjectKind>, Vec<u8>)>> {
+        match self.offsets.get(hash) {
+            Some(&offset) => self.read_at(offset, 0).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn read_at(&self, offset: u64, depth: usize) -> Result<(Option<ObjectKind>, Vec<u8>)> {
+        let corrupt = || Error::InvalidPack(self.path.clone());
+        if depth > MAX_DEPTH {
+            return Err(corrupt());
+        }
+
+        // a type byte and at most two varints
+        let mut header = [0; 21];
+        let read = self
+            .file
+            .read_at(&mut header, offset)
+            .context("read", &self.path)?;
+        let header = &header[..read];
+        let mut pos = 1;
+        let kind = *header.first().ok_or_else(corrupt)?;
+        let len = read_varint(header, &mut pos).ok_or_else(corrupt)?;
+        let base = match kind {
+            DELTA => Some(read_varint(header, &mut pos).ok_or_else(corrupt)?),
+            _ => None,
+        };
+
+        // the length is only trusted as far as the file goes
+        let size = self.file.metadata().context("read", &self.path)?.len();
+        let start = offset + pos as u64;
+        if len > size.saturating_sub(start) {
+            return Err(corrupt());
+        }
+        let mut payload = vec![0; len as usize];
+        self.file
+            .read_exact_at(&mut payload, start)
+            .context("read", &self.path)?;
+
+        match base {
+            Some(distance) => {
+                let base_offset = offset.checked_sub(distance).ok_or_else(corrupt)?;
+                let (kind, base) = self.read_at(base_offset, depth + 1)?;
+                let data = apply_delta(&base, &payload).ok_or_else(corrupt)?;
+                Ok((kind, data))
+            }
+            None if kind == UNTYPED => Ok((None, payload)),
+            None => Ok((Some(kind_from_code(kind).ok_or_else(corrupt)?), payload)),
+        }
+    }
+}
+
+/// Moves every loose object and every object of the existing packs into a
+/// single new pack, then deletes what it replaced. Returns `None` when there
+/// is nothing to pack.
+pub fn repack(file_service: &FileService) -> Result<Option<PackStats>> {
+    let mut hashes: BTreeSet<String> = file_service.loose_objects()?.into_iter().collect();
+    for pack in file_service.packs()? {
+        hashes.extend(pack.hashes().cloned());
+    }
+    pack_objects(file_service, &hashes)
+}
+
+/// Writes `hashes` into a single new pack, then deletes the existing packs
+/// and the loose copies of the packed objects. Packed objects left out of
+/// `hashes` are lost.
+pub fn pack_objects(
+    file_service: &FileService,
+    hashes: &BTreeSet<String>,
+) -> Result<Option<PackStats>> {
+    if hashes.is_empty() {
+        return Ok(None);
+    }
+
+    let mut entries = Vec::new();
+    for hash in hashes {
+        let (kind, data) = file_service.read_raw(hash)?;
+        entries.push(Entry {
+            hash: hash.clone(),
+            kind,
+            data,
+        });
+    }
+
+    let pack_dir = file_service.pack_dir();
+    let stats = write_pack(&pack_dir, file_service.hash_algorithm, entries)?;
+
+    for pack in file_service.packs()? {
+        if pack.path().file_stem().and_then(|stem| stem.to_str()) == Some(stats.name.as_str()) {
+            continue;
+        }
+        let index = pack.path().with_extension("idx");
+        fs::remove_file(&index).context("remove", &index)?;
+        fs::remove_file(pack.path()).context("remove", pack.path())?;
+    }
+    for hash in file_service.loose_objects()? {
+        if !hashes.contains(&hash) {
+            continue;
+        }
+        file_service.remove_loose(&hash)?;
+    }
+
+    Ok(Some(stats))
+}
+
+/// Builds a pack of `hashes` in memory, to send to another repository.
+pub fn build(file_service: &FileService, hashes: &BTreeSet<String>) -> Result<Vec<u8>> {
+    let mut entries = Vec::new();
+    for hash in hashes {
+        let (kind, data) = file_service.read_raw(hash)?;
+        entries.push(Entry {
+            hash: hash.clone(),
+            kind,
+            data,
+        });
+    }
+    Ok(encode(file_service.hash_algorithm, &mut entries).0)
+}
+
+/// Reads every object of a pack received from another repository, checking
+/// its checksum and resolving deltas, or returns `None` if it is malformed.
+pub fn unpack(algorithm: HashAlgorithm, pack: &[u8]) -> Option<Vec<(Option<ObjectKind>, Vec<u8>)>> {
+    if !is_intact(algorithm, pack) {
+        return None;
+    }
+    let pack = &pack[..pack.len() - algorithm.hex_len()];
+    if pack.get(..MAGIC.len())? != MAGIC || *pack.get(MAGIC.len())? != VERSION {
+        return None;
+    }
+    let mut pos = MAGIC.len() + 1;
+    let count = read_varint(pack, &mut pos)?;
+
+    let mut objects: Vec<(Option<ObjectKind>, Vec<u8>)> = Vec::new();
+    let mut offsets: HashMap<u64, usize> = HashMap::new();
+    for i in 0..count as usize {
+        let offset = pos as u64;
+        let kind = *pack.get(pos)?;
+        pos += 1;
+        let len = read_varint(pack, &mut pos)? as usize;
+        let base = match kind {
+            DELTA => Some(read_varint(pack, &mut pos)?),
+            _ => None,
+        };
+        let payload = pack.get(pos..pos.checked_add(len)?)?;
+        pos += len;
+
+        let object = match base {
+            Some(distance) => {
+                let base = *offsets.get(&offset.checked_sub(distance)?)?;
+                let (kind, base) = &objects[base];
+                (*kind, apply_delta(base, payload)?)
+            }
+            None if kind == UNTYPED => (None, payload.to_vec()),
+            None => (Some(kind_from_code(kind)?), payload.to_vec()),
+        };
+        objects.push(object);
+        offsets.insert(offset, i);
+    }
+    (pos == pack.len()).then_some(objects)
+}
+
+/// Whether a pack ends with the checksum of everything before it.
+pub fn is_intact(algorithm: HashAlgorithm, pack: &[u8]) -> bool {
+    match pack.len().checked_sub(algorithm.hex_len()) {
+        Some(end) => algorithm.digest(&[&pack[..end]]).as_bytes() == &pack[end..],
+        None => false,
+    }
+}
+
+fn write_pack(
+    pack_dir: &Path,
+    algorithm: HashAlgorithm,
+    mut entries: Vec<Entry>,
+) -> Result<PackStats> {
+    let (pack, offsets, deltas) = encode(algorithm, &mut entries);
+
+    let mut index: Vec<(&str, u64)> = entries
+        .iter()
+        .map(|entry| entry.hash.as_str())
+        .zip(offsets)
+        .collect();
+    index.sort();
+    let names: Vec<&[u8]> = index.iter().map(|(hash, _)| hash.as_bytes()).collect();
+    let name = format!("pack-{}", algorithm.digest(&names));
+
+    fs::create_dir_all(pack_dir).context("create", pack_dir)?;
+    let pack_path = pack_dir.join(format!("{name}.pack"));
+    write_durably(&pack_path, &pack)?;
+    // the index goes last, a pack is not used until it has one
+    let index_path = pack_dir.join(format!("{name}.idx"));
+    let index: String = index
+        .iter()
+        .map(|(hash, offset)| format!("{hash} {offset}\n"))
+        .collect();
+    write_durably(&index_path, index.as_bytes())?;
+    // and both are on disk before anything they replace is deleted
+    File::open(pack_dir)
+        .and_then(|dir| dir.sync_all())
+        .context("write", pack_dir)?;
+
+    Ok(PackStats {
+        name,
+        objects: entries.len(),
+        deltas,
+    })
+}
+
+/// Replaces `path` with `contents` through a temporary file synced to disk
+/// first, so that a pack rewritten under the same name is never seen, or
+/// left, half written.
+fn write_durably(path: &Path, contents: &[u8]) -> Result<()> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!("tmp_{}_{name}", process::id()));
+    let written = File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .context("write", &temp)
+        .and_then(|()| fs::rename(&temp, path).context("rename", &temp));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Lays out `entries` as a pack, returning it with the offset of each entry
+/// in their new order and the number stored as deltas.
+fn encode(algorithm: HashAlgorithm, entries: &mut [Entry]) -> (Vec<u8>, Vec<u64>, usize) {
+    // similar objects are most likely of the same kind and size; bigger ones
+    // go first so that deltas mostly remove data rather than add it
+    entries.sort_by(|a, b| {
+        let code = |entry: &Entry| entry.kind.map_or(UNTYPED, kind_code);
+        code(a)
+            .cmp(&code(b))
+            .then(b.data.len().cmp(&a.data.len()))
+            .then(a.hash.cmp(&b.hash))
+    });
+
+    let mut pack = MAGIC.to_vec();
+    pack.push(VERSION);
+    write_varint(&mut pack, entries.len() as u64);
+
+    let mut offsets = Vec::with_capacity(entries.len());
+    let mut depths = Vec::with_capacity(entries.len());
+    let mut deltas = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        let offset = pack.len() as u64;
+        let best = (i.saturating_sub(WINDOW)..i)
+            .filter(|&base| entries[base].kind == entry.kind && depths[base] < MAX_DEPTH)
+            .map(|base| (base, compute_delta(&entries[base].data, &entry.data)))
+            .min_by_key(|(_, delta)| delta.len())
+            .filter(|(_, delta)| delta.len() < entry.data.len() / 2);
+
+        match best {
+            Some((base, delta)) => {
+                pack.push(DELTA);
+                write_varint(&mut pack, delta.len() as u64);
+                write_varint(&mut pack, offset - offsets[base]);
+                pack.extend_from_slice(&delta);
+                depths.push(depths[base] + 1);
+                deltas += 1;
+            }
+            None => {
+                pack.push(entry.kind.map_or(UNTYPED, kind_code));
+                write_varint(&mut pack, entry.data.len() as u64);
+                pack.extend_from_slice(&entry.data);
+                depths.push(0);
+            }
+        }
+        offsets.push(offset);
+    }
+    let checksum = algorithm.digest(&[&pack]);
+    pack.extend_from_slice(checksum.as_bytes());
+    (pack, offsets, deltas)
+}
+
+fn kind_code(kind: ObjectKind) -> u8 {
+    match kind {
+        ObjectKind::Blob => 1,
+        ObjectKind::Tree => 2,
+        ObjectKind::Commit => 3,
+        ObjectKind::Tag => 4,
+    }
+}
+
+fn kind_from_code(code: u8) -> Option<ObjectKind> {
+    match code {
+        1 => Some(ObjectKind::Blob),
+        2 => Some(ObjectKind::Tree),
+        3 => Some(ObjectKind::Commit),
+        4 => Some(ObjectKind::Tag),
+        _ => None,
+    }
+}
+
+/// Encodes `target` as the target length followed by instructions that
+/// copy blocks found in `base` and insert everything else.
+fn compute_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for start in (0..base.len().saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        blocks.entry(&base[start..start + BLOCK]).or_insert(start);
+    }
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, target.len() as u64);
+
+    let mut pending = 0;
+    let mut i = 0;
+    while i + BLOCK <= target.len() {
+        let Some(&start) = blocks.get(&target[i..i + BLOCK]) else {
+            i += 1;
+            continue;
+        };
+        let mut len = BLOCK;
+        while start + len < base.len()
+            && i + len < target.len()
+            && base[start + len] == target[i + len]
+        {
+            len += 1;
+        }
+
+        write_insert(&mut delta, &target[pending..i]);
+        delta.push(COPY);
+        write_varint(&mut delta, start as u64);
+        write_varint(&mut delta, len as u64);
+        i += len;
+        pending = i;
+    }
+    write_insert(&mut delta, &target[pending..]);
+    delta
+}
+
+fn write_insert(delta: &mut Vec<u8>, data: &[u8]) {
+    if !data.is_empty() {
+        delta.push(INSERT);
+        write_varint(delta, data.len() as u64);
+        delta.extend_from_slice(data);
+    }
+}
+
+/// Rebuilds an object from its base and a delta made by [`compute_delta`],
+/// or returns `None` if the delta does not fit the base or makes something
+/// other than the length it declares.
+fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos)? as usize;
+    // the declared length may be anything, the data grows as it is made
+    let mut data = Vec::with_capacity(len.min(base.len() + delta.len()));
+
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        match op {
+            INSERT => {
+                let n = read_varint(delta, &mut pos)? as usize;
+                data.extend_from_slice(delta.get(pos..pos.checked_add(n)?)?);
+                pos += n;
+            }
+            COPY => {
+                let start = read_varint(delta, &mut pos)? as usize;
+                let n = read_varint(delta, &mut pos)? as usize;
+                data.extend_from_slice(base.get(start..start.checked_add(n)?)?);
+            }
+            _ => return None,
+        }
+        if data.len() > len {
+            return None;
+        }
+    }
+
+    (data.len() == len).then_some(data)
+}
+
+/// Writes `value` seven bits at a time, lowest first, with the high bit
+/// set on every byte but the last.
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(hash: &str, kind: Option<ObjectKind>, data: &[u8]) -> Entry {
+        Entry {
+            hash: hash.to_string(),
+            kind,
+            data: data.to_vec(),
+        }
+    }
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, value);
+        out
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for value in [0, 1, 0x7f, 0x80, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut pos = 0;
+            assert_eq!(read_varint(&varint(value), &mut pos), Some(value));
+        }
+        assert_eq!(read_varint(&[0x80, 0x80], &mut 0), None);
+    }
+
+    #[test]
+    fn deltas_round_trip() {
+        let base: Vec<u8> = (0..2000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut target = base.clone();
+        target.splice(100..120, b"something new".iter().copied());
+        target.extend_from_slice(&base[..64]);
+
+        let delta = compute_delta(&base, &target);
+        assert!(delta.len() < target.len() / 10, "{}", delta.len());
+        assert_eq!(apply_delta(&base, &delta), Some(target));
+        assert_eq!(
+            apply_delta(b"", &compute_delta(b"", b"new")).unwrap(),
+            b"new"
+        );
+    }
+
+    #[test]
+    fn deltas_that_do_not_fit_are_refused() {
+        let base = b"0123456789abcdef0123456789abcdef";
+        let copy = |start: u64, len: u64| {
+            let mut delta = varint(len);
+            delta.push(COPY);
+            delta.extend(varint(start));
+            delta.extend(varint(len));
+            delta
+        };
+        assert_eq!(apply_delta(base, &copy(4, 8)).unwrap(), b"456789ab");
+        // past the end of the base, or overflowing on the way there
+        assert_eq!(apply_delta(base, &copy(30, 8)), None);
+        assert_eq!(apply_delta(base, &copy(u64::MAX, 2)), None);
+
+        // an insert longer than the delta itself
+        let mut delta = varint(4);
+        delta.push(INSERT);
+        delta.extend(varint(100));
+        delta.extend_from_slice(b"abcd");
+        assert_eq!(apply_delta(base, &delta), None);
+
+        // more or less data than declared
+        let mut delta = copy(0, 8);
+        delta[0] = 4;
+        assert_eq!(apply_delta(base, &delta), None);
+        let mut delta = copy(0, 8);
+        delta[0] = 9;
+        assert_eq!(apply_delta(base, &delta), None);
+        assert_eq!(apply_delta(base, &[2]), None);
+        assert_eq!(apply_delta(base, &[]), None);
+    }
+
+    #[test]
+    fn an_oversized_delta_length_is_refused_without_allocating_it() {
+        let mut delta = varint(i64::MAX as u64);
+        delta.push(INSERT);
+        delta.extend(varint(3));
+        delta.extend_from_slice(b"abc");
+        assert_eq!(apply_delta(b"base", &delta), None);
+    }
+
+    #[test]
+    fn packs_round_trip() {
+        let algorithm = HashAlgorithm::Sha1;
+        let text: Vec<u8> = (0..500)
+            .flat_map(|i| format!("line {i}\n").into_bytes())
+            .collect();
+        let mut edited = text.clone();
+        edited.extend_from_slice(b"one more line\n");
+        let mut entries = vec![
+            entry("a", Some(ObjectKind::Blob), &text),
+            entry("b", Some(ObjectKind::Blob), &edited),
+            entry("c", Some(ObjectKind::Tree), b"tree"),
+            entry("d", None, b"from before headers"),
+        ];
+        let (pack, offsets, deltas) = encode(algorithm, &mut entries);
+        assert_eq!(deltas, 1);
+        assert_eq!(offsets.len(), 4);
+        assert!(is_intact(algorithm, &pack));
+
+        let objects = unpack(algorithm, &pack).unwrap();
+        let expected: Vec<(Option<ObjectKind>, Vec<u8>)> = entries
+            .iter()
+            .map(|entry| (entry.kind, entry.data.clone()))
+            .collect();
+        assert_eq!(objects, expected);
+    }
+
+    #[test]
+    fn malformed_packs_are_refused() {
+        let algorithm = HashAlgorithm::Sha1;
+        let seal = |mut body: Vec<u8>| {
+            let checksum = algorithm.digest(&[&body]);
+            body.extend_from_slice(checksum.as_bytes());
+            body
+        };
+        let header = |count: u64| {
+            let mut pack = MAGIC.to_vec();
+            pack.push(VERSION);
+            pack.extend(varint(count));
+            pack
+        };
+
+        let (mut pack, _, _) = encode(algorithm, &mut [entry("a", None, b"content")]);
+        assert!(unpack(algorithm, &pack).is_some());
+        let last = pack.len() - 1;
+        pack[last] ^= 1;
+        assert_eq!(unpack(algorithm, &pack), None);
+        assert_eq!(unpack(algorithm, b"short"), None);
+
+        // an entry claiming more than the pack holds
+        let mut body = header(1);
+        body.push(kind_code(ObjectKind::Blob));
+        body.extend(varint(i64::MAX as u64));
+        body.extend_from_slice(b"content");
+        assert_eq!(unpack(algorithm, &seal(body)), None);
+
+        // more entries than there are
+        let mut body = header(u64::MAX);
+        body.push(UNTYPED);
+        body.extend(varint(1));
+        body.push(b'x');
+        assert_eq!(unpack(algorithm, &seal(body)), None);
+
+        // a delta against an offset with no entry
+        let mut body = header(1);
+        body.push(DELTA);
+        body.extend(varint(1));
+        body.extend(varint(3));
+        body.push(0);
+        assert_eq!(unpack(algorithm, &seal(body)), None);
+    }
+
+    #[test]
+    fn reading_an_oversized_entry_from_disk_fails() {
+        let dir = std::env::temp_dir().join(format!("blip_pack_test_{}", process::id()));
+        let stats = write_pack(
+            &dir,
+            HashAlgorithm::Sha1,
+            vec![entry("a", Some(ObjectKind::Blob), b"content")],
+        )
+        .unwrap();
+        let pack = Pack::load_all(&dir).unwrap().remove(0);
+        assert_eq!(
+            pack.read("a").unwrap(),
+            Some((Some(ObjectKind::Blob), b"content".to_vec()))
+        );
+
+        // the entry's length replaced by one far past the end of the file
+        let path = dir.join(format!("{}.pack", stats.name));
+        let contents = fs::read(&path).unwrap();
+        let offset = pack.offsets["a"] as usize;
+        let mut forged = contents[..=offset].to_vec();
+        forged.extend(varint(i64::MAX as u64));
+        forged.extend_from_slice(&contents[offset + 2..]);
+        fs::write(&path, forged).unwrap();
+        let result = Pack::load_all(&dir).unwrap()[0].read("a");
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(result, Err(Error::InvalidPack(_))), "{result:?}");
+    }
+}
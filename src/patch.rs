@@ -0,0 +1,787 @@
+//! Patches in the format `blip diff` writes, which is git's: reading them
+//! back, whether bare or inside an email, and applying them to the
+//! contents of files.
+//!
+//! Hunks are applied where their context lines match, which need not be
+//! the line their header names: the nearest match is taken, so a patch
+//! still applies to a file that has since changed elsewhere. With fuzz,
+//! a hunk may also leave out some of its outermost context lines to find a
+//! match, for files that have changed right next to it.
+
+use std::fmt;
+
+use crate::{
+    binary,
+    diff::{self, Edit, NO_NEWLINE},
+    fast_import,
+    types::{Error, Result},
+    worktree::is_safe_path,
+};
+
+/// The changes a patch makes to one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    /// The path before the change, `None` for a file the patch creates.
+    pub old_path: Option<String>,
+    /// The path after the change, `None` for a file the patch deletes.
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+    /// The contents of a binary file the patch replaces in full, in place
+    /// of hunks.
+    pub binary: Option<BinaryPatch>,
+}
+
+/// A `GIT binary patch`: the whole file before and after.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinaryPatch {
+    /// What the file has to be for the patch to apply, `None` when the
+    /// patch does not say.
+    pub old: Option<Vec<u8>>,
+    pub new: Vec<u8>,
+}
+
+/// One `@@` section of a file patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// The line of the old file the hunk starts at, counted from 1, or the
+    /// line it inserts after when it has no old lines.
+    pub old_start: usize,
+    /// The same for the new file.
+    pub new_start: usize,
+    /// Each line with its newline, if it had one.
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Context(String),
+    Delete(String),
+    Insert(String),
+}
+
+impl FilePatch {
+    /// The path the patch is for, the new one when it renames.
+    pub fn path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Applies the patch to `old`, the contents of the file before it, or
+    /// returns `None` if a hunk does not match.
+    pub fn apply(&self, old: &str) -> Option<String> {
+        let (new, rejected) = self.apply_fuzzy(old, 0);
+        rejected.is_empty().then_some(new)
+    }
+
+    /// Like [`FilePatch::apply`], for contents that need not be text: a
+    /// binary patch applies to what it says the file was, hunks to text.
+    pub fn apply_data(&self, old: &[u8]) -> Option<Vec<u8>> {
+        match &self.binary {
+            Some(binary) => binary
+                .old
+                .as_ref()
+                .is_none_or(|expected| expected == old)
+                .then(|| binary.new.clone()),
+            None => self
+                .apply(std::str::from_utf8(old).ok()?)
+                .map(String::into_bytes),
+        }
+    }
+
+    /// Applies the hunks of the patch that match `old`, each allowed to
+    /// leave out up to `fuzz` context lines at either end, and returns the
+    /// result with the hunks that did not match. A deletion that would
+    /// leave something behind rejects every hunk.
+    pub fn apply_fuzzy(&self, old: &str, fuzz: usize) -> (String, Vec<&Hunk>) {
+        let lines: Vec<&str> = old.split_inclusive('\n').collect();
+        let mut out = String::new();
+        let mut rejected = Vec::new();
+        let mut next = 0;
+        // how far the hunks so far were from where their headers put them
+        let mut drift = 0isize;
+        for hunk in self.hunks.iter() {
+            let Some((at, before, after, moved)) = hunk.locate(&lines, next, drift, fuzz) else {
+                rejected.push(hunk);
+                continue;
+            };
+            drift = moved;
+            for line in &lines[next..at] {
+                out.push_str(line);
+            }
+            for line in after {
+                out.push_str(line);
+            }
+            next = at + before.len();
+        }
+        for line in &lines[next..] {
+            out.push_str(line);
+        }
+        if self.new_path.is_none() && !out.is_empty() {
+            return (old.to_string(), self.hunks.iter().collect());
+        }
+        (out, rejected)
+    }
+
+    /// The hunks in `hunks` as a patch of their own, for a reject file.
+    pub fn reject(&self, hunks: &[&Hunk]) -> String {
+        let name = |path: &Option<String>, prefix: &str| match path {
+            Some(path) => format!("{prefix}{path}"),
+            None => "/dev/null".to_string(),
+        };
+        let mut out = format!(
+            "--- {}\n+++ {}\n",
+            name(&self.old_path, "a/"),
+            name(&self.new_path, "b/")
+        );
+        for hunk in hunks {
+            out.push_str(&hunk.to_string());
+        }
+        out
+    }
+}
+
+impl Hunk {
+    /// The lines the hunk expects to find.
+    pub fn before(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Delete(text) => Some(text.as_str()),
+                Line::Insert(_) => None,
+            })
+            .collect()
+    }
+
+    /// The lines the hunk leaves in their place.
+    pub fn after(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Context(text) | Line::Insert(text) => Some(text.as_str()),
+                Line::Delete(_) => None,
+            })
+            .collect()
+    }
+
+    /// Splits the hunk where context lines come between its changes, into
+    /// hunks with one run of changes each. The context between two runs
+    /// goes to both of the hunks around it, see [`apply_hunks`].
+    pub fn split(&self) -> Vec<Hunk> {
+        let mut runs = Vec::new();
+        let mut i = 0;
+        while i < self.lines.len() {
+            if matches!(self.lines[i], Line::Context(_)) {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < self.lines.len() && !matches!(self.lines[i], Line::Context(_)) {
+                i += 1;
+            }
+            runs.push((start, i));
+        }
+        if runs.len() < 2 {
+            return vec![self.clone()];
+        }
+
+        let mut hunks = Vec::new();
+        for k in 0..runs.len() {
+            let from = if k == 0 { 0 } else { runs[k - 1].1 };
+            let to = runs.get(k + 1).map_or(self.lines.len(), |&(next, _)| next);
+            let before = &self.lines[..from];
+            let old = before
+                .iter()
+                .filter(|line| !matches!(line, Line::Insert(_)));
+            let new = before
+                .iter()
+                .filter(|line| !matches!(line, Line::Delete(_)));
+            hunks.push(Hunk {
+                old_start: self.old_start + old.count(),
+                new_start: self.new_start + new.count(),
+                lines: self.lines[from..to].to_vec(),
+            });
+        }
+        hunks
+    }
+
+    /// Reads the hunk back from `text`, a copy of it as it displays that
+    /// was edited by hand, keeping where it starts. Its header and lines
+    /// starting with `#` are left out, and an empty line is taken for an
+    /// empty context line.
+    pub fn edited(&self, text: &str) -> Result<Hunk> {
+        let mut lines: Vec<Line> = Vec::new();
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            if line.starts_with('#') || line.starts_with("@@") {
+                continue;
+            }
+            if line.trim_end_matches('\n') == NO_NEWLINE {
+                if let Some(Line::Context(text) | Line::Delete(text) | Line::Insert(text)) =
+                    lines.last_mut()
+                {
+                    if text.ends_with('\n') {
+                        text.pop();
+                    }
+                }
+                continue;
+            }
+            if line == "\n" {
+                lines.push(Line::Context(line.to_string()));
+                continue;
+            }
+            let mut chars = line.chars();
+            let marker = chars.next();
+            let rest = chars.as_str().to_string();
+            lines.push(match marker {
+                Some(' ') => Line::Context(rest),
+                Some('-') => Line::Delete(rest),
+                Some('+') => Line::Insert(rest),
+                _ => return Err(invalid(i + 1, "line does not start with ' ', '-' or '+'")),
+            });
+        }
+        Ok(Hunk {
+            old_start: self.old_start,
+            new_start: self.new_start,
+            lines,
+        })
+    }
+
+    /// Finds where in `lines`, at or after `from`, the hunk applies,
+    /// `drift` lines from where its header says. Returns the line it
+    /// starts at with the lines it replaces there, those it puts in their
+    /// place, which are short of the context fuzz left out, and how far it
+    /// was from where its header says.
+    fn locate<'a>(
+        &'a self,
+        lines: &[&str],
+        from: usize,
+        drift: isize,
+        fuzz: usize,
+    ) -> Option<(usize, Vec<&'a str>, Vec<&'a str>, isize)> {
+        let leading = self
+            .lines
+            .iter()
+            .take_while(|line| matches!(line, Line::Context(_)))
+            .count();
+        let trailing = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|line| matches!(line, Line::Context(_)))
+            .count();
+        let (before, after) = (self.before(), self.after());
+        let start = match before.len() {
+            0 => self.old_start,
+            _ => self.old_start.saturating_sub(1),
+        };
+        for fuzz in 0..=fuzz {
+            let (lead, trail) = (fuzz.min(leading), fuzz.min(trailing));
+            // more fuzz than there is context to leave out changes nothing,
+            // and a hunk left with no lines to match would match anywhere
+            let exhausted = fuzz > leading && fuzz > trailing;
+            if fuzz > 0 && (exhausted || lead + trail >= before.len()) {
+                break;
+            }
+            let before = &before[lead..before.len() - trail];
+            let after = &after[lead..after.len() - trail];
+            let expected = (start + lead).saturating_add_signed(drift).max(from);
+            if let Some(at) = find(lines, before, from, expected) {
+                let drift = at as isize - (start + lead) as isize;
+                return Some((at, before.to_vec(), after.to_vec(), drift));
+            }
+        }
+        None
+    }
+}
+
+impl fmt::Display for Hunk {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            fmt,
+            "@@ -{} +{} @@",
+            diff::range(self.old_start, self.before().len()),
+            diff::range(self.new_start, self.after().len())
+        )?;
+        for line in self.lines.iter() {
+            let (marker, text) = match line {
+                Line::Context(text) => (' ', text),
+                Line::Delete(text) => ('-', text),
+                Line::Insert(text) => ('+', text),
+            };
+            match text.strip_suffix('\n') {
+                Some(text) => writeln!(fmt, "{marker}{text}")?,
+                None => writeln!(fmt, "{marker}{text}\n{NO_NEWLINE}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The hunks of the differences between two texts, with `context` lines
+/// around each change.
+pub fn diff(old: &str, new: &str, context: usize) -> Vec<Hunk> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff::diff_lines(&old_lines, &new_lines);
+    diff::hunks(&edits, context)
+        .into_iter()
+        .map(|hunk| Hunk {
+            old_start: hunk.old_start,
+            new_start: hunk.new_start,
+            lines: hunk
+                .edits
+                .iter()
+                .map(|edit| match *edit {
+                    Edit::Equal { old, .. } => Line::Context(old_lines[old].to_string()),
+                    Edit::Delete { old } => Line::Delete(old_lines[old].to_string()),
+                    Edit::Insert { new } => Line::Insert(new_lines[new].to_string()),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Applies `hunks`, in order, to `old`, the text [`diff`] took them from,
+/// perhaps split or edited since, exactly where their headers say. Only the
+/// lines a hunk changes are replaced, so that hunks split from one can
+/// share context. Returns `None` if a hunk does not match.
+pub fn apply_hunks(old: &str, hunks: &[&Hunk]) -> Option<String> {
+    let lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let mut out = String::new();
+    let mut next = 0;
+    for hunk in hunks {
+        let (before, after) = (hunk.before(), hunk.after());
+        let start = match before.len() {
+            0 => hunk.old_start,
+            _ => hunk.old_start.checked_sub(1)?,
+        };
+        if lines.get(start..start + before.len())? != before {
+            return None;
+        }
+        // the context around the lines the hunk changes
+        let lead = before
+            .iter()
+            .zip(&after)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let trail = before[lead..]
+            .iter()
+            .rev()
+            .zip(after[lead..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        if start + lead < next {
+            return None;
+        }
+        for line in &lines[next..start + lead] {
+            out.push_str(line);
+        }
+        for line in &after[lead..after.len() - trail] {
+            out.push_str(line);
+        }
+        next = start + before.len() - trail;
+    }
+    for line in &lines[next..] {
+        out.push_str(line);
+    }
+    Some(out)
+}
+
+/// Finds `wanted` in `lines` at or after `from`, as near to `expected` as
+/// possible.
+fn find(lines: &[&str], wanted: &[&str], from: usize, expected: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(wanted.len())?;
+    let matches = |at: usize| at >= from && at <= last && lines[at..at + wanted.len()] == *wanted;
+    (0..=last.max(expected)).find_map(|distance| {
+        [
+            expected.checked_add(distance),
+            expected.checked_sub(distance),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|&at| matches(at))
+    })
+}
+
+/// Reads the file patches in `text`, skipping anything around them such as
+/// the headers and message of an email. Binary patches are refused unless
+/// they hold the files' contents, as `diff --binary` writes them.
+pub fn parse(text: &str) -> Result<Vec<FilePatch>> {
+    let lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let mut patches: Vec<FilePatch> = Vec::new();
+    // whether the last patch is still reading its extended header lines
+    let mut in_header = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i].strip_suffix('\n').unwrap_or(lines[i]);
+        i += 1;
+        if let Some(names) = line.strip_prefix("diff --git ") {
+            let (old, new) = git_names(names).ok_or_else(|| invalid(i, "malformed diff header"))?;
+            patches.push(FilePatch {
+                old_path: Some(old),
+                new_path: Some(new),
+                hunks: Vec::new(),
+                binary: None,
+            });
+            in_header = true;
+            continue;
+        }
+        if line.starts_with("--- ") && lines.get(i).is_some_and(|next| next.starts_with("+++ ")) {
+            let old =
+                name(&line[4..]).ok_or_else(|| invalid(i, "malformed or unsafe file name"))?;
+            let new = lines[i].trim_end_matches('\n');
+            let new =
+                name(&new[4..]).ok_or_else(|| invalid(i + 1, "malformed or unsafe file name"))?;
+            i += 1;
+            // a patch without a git header starts here
+            if !in_header {
+                patches.push(FilePatch {
+                    old_path: old.clone(),
+                    new_path: new.clone(),
+                    hunks: Vec::new(),
+                    binary: None,
+                });
+            }
+            let patch = patches.last_mut().expect("a patch was just started");
+            if old.is_none() {
+                patch.old_path = None;
+            }
+            if new.is_none() {
+                patch.new_path = None;
+            }
+            in_header = false;
+            continue;
+        }
+        if line.starts_with("@@ ") {
+            let Some(patch) = patches.last_mut() else {
+                return Err(invalid(i, "hunk outside of a file patch"));
+            };
+            let (hunk, read) =
+                read_hunk(&lines[i - 1..]).ok_or_else(|| invalid(i, "malformed hunk"))?;
+            patch.hunks.push(hunk);
+            in_header = false;
+            i += read - 1;
+            continue;
+        }
+        if !in_header {
+            continue;
+        }
+        let patch = patches.last_mut().expect("a header belongs to a patch");
+        if line.starts_with("new file") {
+            patch.old_path = None;
+        } else if line.starts_with("deleted file") {
+            patch.new_path = None;
+        } else if let Some(old) = line
+            .strip_prefix("rename from ")
+            .or(line.strip_prefix("copy from "))
+        {
+            let old = unquote(old).filter(|old| is_safe_path(old));
+            patch.old_path = Some(old.ok_or_else(|| invalid(i, "malformed or unsafe file name"))?);
+        } else if let Some(new) = line
+            .strip_prefix("rename to ")
+            .or(line.strip_prefix("copy to "))
+        {
+            let new = unquote(new).filter(|new| is_safe_path(new));
+            patch.new_path = Some(new.ok_or_else(|| invalid(i, "malformed or unsafe file name"))?);
+        } else if line == "GIT binary patch" {
+            let (binary, read) = read_binary(&lines[i..]).map_err(|message| {
+                invalid(
+                    i + 1,
+                    &format!("{message} in binary patch for '{}'", patch.path()),
+                )
+            })?;
+            patch.binary = Some(binary);
+            in_header = false;
+            i += read;
+        } else if line.starts_with("Binary files ") {
+            return Err(invalid(
+                i,
+                &format!(
+                    "binary patch for '{}' has no contents, make it with diff --binary",
+                    patch.path()
+                ),
+            ));
+        }
+    }
+    Ok(patches)
+}
+
+/// Reads the hunk starting at the `@@` line `lines[0]`, returning it and
+/// how many lines it took up.
+fn read_hunk(lines: &[&str]) -> Option<(Hunk, usize)> {
+    let header = lines[0].strip_prefix("@@ -")?;
+    let (ranges, _) = header.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let (old_start, mut old_left) = range(old)?;
+    let (new_start, mut new_left) = range(new)?;
+
+    let mut hunk = Hunk {
+        old_start,
+        new_start,
+        lines: Vec::new(),
+    };
+    let mut read = 1;
+    while old_left > 0 || new_left > 0 {
+        let line = *lines.get(read)?;
+        read += 1;
+        // mail programs sometimes strip the space off empty context lines
+        let (marker, text) = match line {
+            "\n" => (" ", line),
+            _ => line.split_at_checked(1)?,
+        };
+        let text = text.to_string();
+        match marker {
+            " " if old_left > 0 && new_left > 0 => {
+                hunk.lines.push(Line::Context(text));
+                old_left -= 1;
+                new_left -= 1;
+            }
+            "-" if old_left > 0 => {
+                hunk.lines.push(Line::Delete(text));
+                old_left -= 1;
+            }
+            "+" if new_left > 0 => {
+                hunk.lines.push(Line::Insert(text));
+                new_left -= 1;
+            }
+            _ => return None,
+        }
+        if lines
+            .get(read)
+            .is_some_and(|next| next.trim_end_matches('\n') == NO_NEWLINE)
+        {
+            read += 1;
+            match hunk.lines.last_mut()? {
+                Line::Context(text) | Line::Delete(text) | Line::Insert(text) => {
+                    if text.ends_with('\n') {
+                        text.pop();
+                    }
+                }
+            }
+        }
+    }
+    Some((hunk, read))
+}
+
+/// Reads the `literal` blocks following a `GIT binary patch` line, the new
+/// contents and then, if given, the old ones, returning them with how many
+/// lines they took up or what is wrong with them.
+fn read_binary(lines: &[&str]) -> std::result::Result<(BinaryPatch, usize), &'static str> {
+    let mut contents = Vec::new();
+    let mut read = 0;
+    while contents.len() < 2 {
+        let Some(header) = lines.get(read).map(|line| line.trim_end_matches('\n')) else {
+            break;
+        };
+        let size = match header.split_once(' ') {
+            Some(("literal", size)) => size.parse::<usize>().map_err(|_| "malformed size")?,
+            Some(("delta", _)) => return Err("deltas are not supported"),
+            _ if contents.is_empty() => return Err("missing contents"),
+            _ => break,
+        };
+        let data: Vec<&str> = lines[read + 1..]
+            .iter()
+            .map(|line| line.trim_end_matches('\n'))
+            .take_while(|line| !line.is_empty())
+            .collect();
+        read += data.len() + 2;
+        let data = binary::decode(&data).ok_or("malformed or compressed data")?;
+        if data.len() != size {
+            return Err("wrong size");
+        }
+        contents.push(data);
+    }
+    let mut contents = contents.into_iter();
+    let new = contents.next().ok_or("missing contents")?;
+    Ok((
+        BinaryPatch {
+            old: contents.next(),
+            new,
+        },
+        read.min(lines.len()),
+    ))
+}
+
+/// Reads an `<start>,<len>` hunk range, the length defaulting to 1.
+fn range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// Splits the `a/<old> b/<new>` of a `diff --git` line. Unquoted names
+/// with spaces can only be told apart when they are the same.
+fn git_names(names: &str) -> Option<(String, String)> {
+    if let Some(quoted) = names.strip_prefix('"') {
+        let (old, rest) = fast_import::unquote(quoted)?;
+        let new = unquote(rest.strip_prefix(' ')?)?;
+        return Some((strip_prefix(&old)?, strip_prefix(&new)?));
+    }
+    let half = names.len() / 2;
+    let (old, new) = match names.as_bytes().get(half) {
+        Some(b' ') if names[..half].get(2..) == names.get(half + 3..) => {
+            (&names[..half], &names[half + 1..])
+        }
+        _ => names
+            .split_once(" b/")
+            .map(|(old, _)| (old, &names[old.len() + 1..]))?,
+    };
+    Some((strip_prefix(old)?, strip_prefix(&unquote(new)?)?))
+}
+
+/// Reads the name on a `---` or `+++` line, `None` for `/dev/null`.
+fn name(name: &str) -> Option<Option<String>> {
+    // plain diff follows the name with a tab and a timestamp
+    let name = match name.starts_with('"') {
+        true => name,
+        false => name.split('\t').next()?,
+    };
+    let name = unquote(name.trim_end())?;
+    if name == "/dev/null" {
+        return Some(None);
+    }
+    Some(Some(strip_prefix(&name)?))
+}
+
+/// Drops the leading `a/` or `b/`, or whatever directory stands in for
+/// them.
+fn strip_prefix(name: &str) -> Option<String> {
+    name.split_once('/')
+        .map(|(_, path)| path.to_string())
+        .filter(|path| is_safe_path(path))
+}
+
+/// A name, C-quoted or not.
+fn unquote(name: &str) -> Option<String> {
+    match name.strip_prefix('"') {
+        Some(quoted) => match fast_import::unquote(quoted)? {
+            (name, "") => Some(name),
+            _ => None,
+        },
+        None => Some(name.to_string()),
+    }
+}
+
+fn invalid(line: usize, message: &str) -> Error {
+    Error::InvalidPatch {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The lines `1` to `n`, one per line.
+    fn numbered(n: usize) -> String {
+        (1..=n).map(|i| format!("{i}\n")).collect()
+    }
+
+    /// `text` with the whole line `from` replaced by `to`, which may be empty.
+    fn replace(text: &str, from: &str, to: &str) -> String {
+        text.split_inclusive('\n')
+            .map(|line| if line == from { to } else { line })
+            .collect()
+    }
+
+    /// A patch of `path` from `old` to `new`, as `blip diff` writes it.
+    fn patch(path: &str, old: &str, new: &str) -> FilePatch {
+        let text = format!(
+            "diff --git a/{path} b/{path}\n--- a/{path}\n+++ b/{path}\n{}",
+            diff::unified(old, new, 3)
+        );
+        let mut patches = parse(&text).unwrap();
+        assert_eq!(patches.len(), 1);
+        patches.remove(0)
+    }
+
+    #[test]
+    fn patches_apply_to_what_they_were_made_from() {
+        let old = numbered(30);
+        let new = replace(
+            &replace(&replace(&old, "3\n", "three\n"), "20\n", ""),
+            "30\n",
+            "30\n31\n",
+        );
+        let file = patch("f", &old, &new);
+        assert_eq!(file.hunks.len(), 3);
+        assert_eq!(file.apply(&old).as_deref(), Some(new.as_str()));
+    }
+
+    #[test]
+    fn hunks_apply_where_their_context_moved() {
+        let old = numbered(20);
+        let file = patch("f", &old, &replace(&old, "15\n", "fifteen\n"));
+        let moved = format!("new\nlines\n{old}");
+        let expected = replace(&moved, "15\n", "fifteen\n");
+        assert_eq!(file.apply(&moved), Some(expected));
+    }
+
+    #[test]
+    fn fuzz_leaves_out_outer_context_lines() {
+        let old = numbered(20);
+        let file = patch("f", &old, &replace(&old, "10\n", "ten\n"));
+        // the first and last context lines changed since
+        let changed = replace(&replace(&old, "7\n", "seven\n"), "13\n", "thirteen\n");
+        let (_, rejected) = file.apply_fuzzy(&changed, 0);
+        assert_eq!(rejected.len(), 1);
+
+        let (new, rejected) = file.apply_fuzzy(&changed, 1);
+        assert!(rejected.is_empty());
+        assert_eq!(new, replace(&changed, "10\n", "ten\n"));
+    }
+
+    #[test]
+    fn hunks_that_do_not_apply_are_rejected_alone() {
+        let old = numbered(30);
+        let file = patch(
+            "f",
+            &old,
+            &replace(&replace(&old, "5\n", "five\n"), "25\n", "twenty-five\n"),
+        );
+        let changed = replace(&replace(&old, "24\n", ""), "26\n", "");
+        let (new, rejected) = file.apply_fuzzy(&changed, 0);
+        assert_eq!(rejected, [&file.hunks[1]]);
+        assert_eq!(new, replace(&changed, "5\n", "five\n"));
+
+        // the reject file is a patch of the rejected hunk
+        let reject = file.reject(&rejected);
+        assert!(
+            reject.starts_with("--- a/f\n+++ b/f\n@@ -22,7 +22,7 @@"),
+            "{reject}"
+        );
+        let reread = parse(&reject).unwrap();
+        assert_eq!(reread[0].hunks, [file.hunks[1].clone()]);
+    }
+
+    #[test]
+    fn a_deletion_must_take_everything() {
+        let text = "diff --git a/f b/f\ndeleted file mode 100644\n--- a/f\n+++ /dev/null\n\
+                    @@ -1,2 +0,0 @@\n-1\n-2\n";
+        let file = parse(text).unwrap().remove(0);
+        assert_eq!(file.new_path, None);
+        assert_eq!(file.apply("1\n2\n").as_deref(), Some(""));
+        let (kept, rejected) = file.apply_fuzzy("1\n2\n3\n", 0);
+        assert_eq!((kept.as_str(), rejected.len()), ("1\n2\n3\n", 1));
+    }
+
+    #[test]
+    fn a_missing_newline_at_the_end_round_trips() {
+        let file = patch("f", "a\nb", "a\nc");
+        assert_eq!(file.apply("a\nb").as_deref(), Some("a\nc"));
+    }
+
+    #[test]
+    fn malformed_patches_are_refused() {
+        let hunk = "@@ -1,2 +1,2 @@\n-a\n+b\n";
+        assert!(parse(&format!("--- a/f\n+++ b/f\n{hunk}")).is_err());
+        assert!(parse("@@ -1 +1 @@\n-a\n+b\n").is_err());
+        assert!(parse("--- a/.blip/config\n+++ b/.blip/config\n").is_err());
+        assert!(parse("--- a/../f\n+++ b/../f\n").is_err());
+        assert!(parse("diff --git a/f b/f\nBinary files a/f and b/f differ\n").is_err());
+        assert_eq!(parse("not a patch\n").unwrap(), []);
+    }
+}
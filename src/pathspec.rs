@@ -0,0 +1,116 @@
+use crate::{
+    ignore::glob_match,
+    types::{Error, FileService, Result},
+};
+
+/// Prefixes marking a pattern as an exclusion.
+const EXCLUDE_MAGIC: [&str; 3] = [":(exclude)", ":!", ":^"];
+
+/// One pattern of a [`Pathspec`].
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    text: String,
+    glob: bool,
+}
+
+/// The paths a command is limited to, as given on its command line. Each
+/// pattern is either a path, matching that file and everything below it, or
+/// a glob in which `*` and `?` stop at slashes and `**` spans directories.
+/// Patterns written as `:(exclude)<pattern>`, or `:!<pattern>` for short,
+/// take paths away from those the others match. With no other patterns,
+/// everything is matched.
+#[derive(Debug, Clone, Default)]
+pub struct Pathspec {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl Pattern {
+    fn new(text: &str) -> Pattern {
+        Pattern {
+            text: text.to_string(),
+            glob: text.contains(['*', '?', '[']),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_glob(&self) -> bool {
+        self.glob
+    }
+
+    /// Whether `path` is the pattern or lies below it. A glob also matches
+    /// everything inside the directories it matches.
+    pub fn matches(&self, path: &str) -> bool {
+        if self.text.is_empty() || path == self.text {
+            return true;
+        }
+        if !self.glob {
+            return path
+                .strip_prefix(self.text.as_str())
+                .is_some_and(|rest| rest.starts_with('/'));
+        }
+        path.match_indices('/')
+            .map(|(end, _)| &path[..end])
+            .chain([path])
+            .any(|prefix| glob_match(&self.text, prefix))
+    }
+}
+
+impl Pathspec {
+    /// Parses patterns relative to the root of the working tree.
+    pub fn new(specs: &[String]) -> Result<Pathspec> {
+        let mut pathspec = Pathspec::default();
+        for spec in specs {
+            match split_magic(spec)? {
+                (true, pattern) => pathspec.exclude.push(Pattern::new(pattern)),
+                (false, pattern) => pathspec.include.push(Pattern::new(pattern)),
+            }
+        }
+        Ok(pathspec)
+    }
+
+    /// Rewrites a pattern given relative to the current directory to be
+    /// relative to the root, keeping any magic in front of it.
+    pub fn from_cwd(file_service: &FileService, spec: &str) -> Result<String> {
+        let (excluded, pattern) = split_magic(spec)?;
+        let pattern = file_service.repo_path(pattern)?;
+        Ok(if excluded {
+            format!(":(exclude){pattern}")
+        } else {
+            pattern
+        })
+    }
+
+    /// The patterns selecting paths, as opposed to excluding them.
+    pub fn includes(&self) -> &[Pattern] {
+        &self.include
+    }
+
+    pub fn is_excluded(&self, path: &str) -> bool {
+        self.exclude.iter().any(|pattern| pattern.matches(path))
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(path)))
+            && !self.is_excluded(path)
+    }
+}
+
+/// Separates the magic at the start of a pattern from the pattern itself,
+/// returning whether it excludes.
+fn split_magic(spec: &str) -> Result<(bool, &str)> {
+    if let Some(pattern) = EXCLUDE_MAGIC
+        .iter()
+        .find_map(|magic| spec.strip_prefix(magic))
+    {
+        return Ok((true, pattern));
+    }
+    if let Some(rest) = spec.strip_prefix(":(") {
+        let magic = rest.split_once(')').map_or(rest, |(magic, _)| magic);
+        return Err(Error::Usage(format!("unknown pathspec magic '{magic}'")));
+    }
+    Ok((false, spec))
+}
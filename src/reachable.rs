@@ -0,0 +1,223 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    reflog,
+    refs::Refs,
+    stash::Stash,
+    types::{Commit, Error, FileService, ObjectKind, Result, Tag, Tree},
+    worktrees,
+};
+
+/// Refs outside of `refs/` that still keep history alive.
+const SPECIAL_REFS: [&str; 5] = [
+    "ORIG_HEAD",
+    "MERGE_HEAD",
+    "CHERRY_PICK_HEAD",
+    "REVERT_HEAD",
+    "REBASE_HEAD",
+];
+
+/// The objects everything else hangs off: every ref, every stash and reflog
+/// entry and, in each working tree, HEAD, its reflog and the blobs staged
+/// in the index.
+pub fn roots(file_service: &FileService) -> Result<Vec<(String, Option<ObjectKind>)>> {
+    let refs = Refs::new(file_service);
+    let mut roots = Vec::new();
+
+    for worktree in worktrees::list(file_service)? {
+        if worktree.blip_dir == file_service.blip_dir {
+            worktree_roots(file_service, &mut roots)?;
+        } else {
+            worktree_roots(&worktree.open()?, &mut roots)?;
+        }
+    }
+    for (_, hash) in refs.all()? {
+        roots.push((hash, None));
+    }
+    for entry in Stash::new(file_service).entries()? {
+        roots.push((entry.hash, Some(ObjectKind::Commit)));
+    }
+    for name in reflog::names(file_service)? {
+        reflog_roots(file_service, &name, &mut roots)?;
+    }
+    Ok(roots)
+}
+
+/// The roots only one working tree has: HEAD and its reflog, the refs
+/// outside of `refs/` and the index.
+fn worktree_roots(
+    file_service: &FileService,
+    roots: &mut Vec<(String, Option<ObjectKind>)>,
+) -> Result<()> {
+    let refs = Refs::new(file_service);
+    if let Some(head) = file_service.read_head_hash()? {
+        roots.push((head, None));
+    }
+    reflog_roots(file_service, "HEAD", roots)?;
+    for name in SPECIAL_REFS {
+        if let Some(hash) = refs.read(name) {
+            roots.push((hash, None));
+        }
+    }
+    if file_service.bare {
+        return Ok(());
+    }
+    for hash in file_service.read_index()?.hashtree().values() {
+        roots.push((hash.clone(), Some(ObjectKind::Blob)));
+    }
+    Ok(())
+}
+
+/// Adds the commits the reflog of `name` has seen it point at.
+fn reflog_roots(
+    file_service: &FileService,
+    name: &str,
+    roots: &mut Vec<(String, Option<ObjectKind>)>,
+) -> Result<()> {
+    for entry in reflog::entries(file_service, name)? {
+        roots.extend(entry.old.map(|old| (old, None)));
+        roots.push((entry.new, None));
+    }
+    Ok(())
+}
+
+/// Collects every object reachable from the roots, failing on the first
+/// one that is missing or corrupt.
+pub fn reachable(file_service: &FileService) -> Result<BTreeSet<String>> {
+    let mut walk = Walk::new(file_service, roots(file_service)?);
+    for (_, kind) in walk.by_ref() {
+        kind?;
+    }
+    Ok(walk.seen)
+}
+
+/// Visits the starting objects and everything reachable from them: the
+/// parents and tree of commits, the entries of trees and the targets of
+/// tags. Each object is yielded once with its kind, or with the error that
+/// kept it from being read, in which case what it refers to is skipped. A
+/// `None` kind is for refs, which point at commits or tags.
+pub struct Walk<'a> {
+    file_service: &'a FileService,
+    stack: Vec<(String, Option<ObjectKind>)>,
+    seen: BTreeSet<String>,
+}
+
+impl<'a> Walk<'a> {
+    pub fn new(
+        file_service: &'a FileService,
+        start: Vec<(String, Option<ObjectKind>)>,
+    ) -> Walk<'a> {
+        Walk {
+            file_service,
+            stack: start,
+            seen: BTreeSet::new(),
+        }
+    }
+
+    fn visit(&mut self, hash: &str, kind: Option<ObjectKind>) -> Result<ObjectKind> {
+        let kind = match kind {
+            Some(kind) => kind,
+            None => ref_kind(self.file_service, hash)?,
+        };
+
+        match kind {
+            ObjectKind::Commit => {
+                let commit = self.file_service.read_commit_headers(hash)?;
+                if let Some(tree) = commit.tree() {
+                    self.stack.push((tree.to_string(), Some(ObjectKind::Tree)));
+                }
+                for parent in commit.parents() {
+                    self.stack.push((parent.clone(), Some(ObjectKind::Commit)));
+                }
+                // commits from before trees list their blobs directly
+                for blob in commit.files().values() {
+                    self.stack.push((blob.clone(), Some(ObjectKind::Blob)));
+                }
+            }
+            ObjectKind::Tree => {
+                for entry in self.file_service.read_tree(hash)?.entries().values() {
+                    // the commits of submodules are in their own repository
+                    if entry.kind != ObjectKind::Commit {
+                        self.stack.push((entry.hash.clone(), Some(entry.kind)));
+                    }
+                }
+            }
+            ObjectKind::Tag => {
+                let tag = self.file_service.read_tag(hash)?;
+                self.stack
+                    .push((tag.object().to_string(), Some(tag.kind())));
+            }
+            ObjectKind::Blob => {
+                if !self.file_service.has_object(hash)? {
+                    return Err(Error::MissingObject(hash.to_string()));
+                }
+            }
+        }
+        Ok(kind)
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = (String, Result<ObjectKind>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (hash, kind) = self.stack.pop()?;
+            if self.seen.insert(hash.clone()) {
+                let kind = self.visit(&hash, kind);
+                return Some((hash, kind));
+            }
+        }
+    }
+}
+
+/// Parses an object, returning the objects it refers to with the kind each
+/// should be.
+pub fn links(hash: &str, kind: ObjectKind, data: &[u8]) -> Result<Vec<(String, ObjectKind)>> {
+    if kind == ObjectKind::Blob {
+        return Ok(Vec::new());
+    }
+    let text = std::str::from_utf8(data).map_err(|_| Error::InvalidObject {
+        hash: hash.to_string(),
+        kind,
+    })?;
+
+    let mut links = Vec::new();
+    match kind {
+        ObjectKind::Tree => {
+            for entry in Tree::from(hash, text)?.entries().values() {
+                if entry.kind != ObjectKind::Commit {
+                    links.push((entry.hash.clone(), entry.kind));
+                }
+            }
+        }
+        ObjectKind::Commit => {
+            let commit = Commit::from(hash, text)?;
+            if let Some(tree) = commit.tree() {
+                links.push((tree.to_string(), ObjectKind::Tree));
+            }
+            for parent in commit.parents() {
+                links.push((parent.clone(), ObjectKind::Commit));
+            }
+            for blob in commit.files().values() {
+                links.push((blob.clone(), ObjectKind::Blob));
+            }
+        }
+        ObjectKind::Tag => {
+            let tag = Tag::from(hash, text)?;
+            links.push((tag.object().to_string(), tag.kind()));
+        }
+        ObjectKind::Blob => {}
+    }
+    Ok(links)
+}
+
+/// Tells whether a ref points at a tag or a commit.
+fn ref_kind(file_service: &FileService, hash: &str) -> Result<ObjectKind> {
+    match file_service.read_raw(hash)? {
+        (Some(kind), _) => Ok(kind),
+        // objects without a header are told apart by reading them as a tag
+        (None, _) if file_service.read_tag(hash).is_ok() => Ok(ObjectKind::Tag),
+        (None, _) => Ok(ObjectKind::Commit),
+    }
+}
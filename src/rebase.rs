@@ -0,0 +1,130 @@
+use std::fs;
+
+use crate::{
+    editor, history,
+    revision::Abbrev,
+    sequencer::{self, Action, Operation, Outcome, Step},
+    types::{Error, FileService, Result},
+};
+
+/// The file of `.blip` an interactive rebase has the todo list edited in.
+const TODO_FILE: &str = "REBASE_TODO";
+
+/// The help that follows the todo list of an interactive rebase.
+const TODO_HELP: &str = "\
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# e, edit <commit> = use commit, but stop for amending
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup <commit> = like \"squash\", but discard this commit's message
+# d, drop <commit> = remove commit
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+# If you remove a line here that commit will be lost.
+# If you remove everything, the rebase will be aborted.
+";
+
+/// What [`rebase`] did to the current branch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rebased {
+    /// The branch already contained the upstream.
+    UpToDate,
+    /// The branch had no commits of its own and now points at the upstream.
+    FastForward,
+    /// The branch's own commits were replayed on top of the upstream; the
+    /// number of them that still made a change there.
+    Applied(usize),
+    /// The rebase stopped halfway, for the sequencer to carry it on.
+    Stopped(Outcome),
+}
+
+impl From<Outcome> for Rebased {
+    fn from(outcome: Outcome) -> Rebased {
+        match outcome {
+            Outcome::Done(commits) => Rebased::Applied(commits.len()),
+            outcome => Rebased::Stopped(outcome),
+        }
+    }
+}
+
+/// Replays the commits of the current branch that `upstream` cannot reach
+/// on top of it, along first parents and leaving out merges, then moves
+/// the branch to the result, see [`sequencer::start`].
+///
+/// An `interactive` rebase first has the todo list of those commits edited,
+/// see [`sequencer::parse_todo`], and carries out whatever it ends up with,
+/// even when the branch already contains the upstream.
+pub fn rebase(file_service: &FileService, upstream: &str, interactive: bool) -> Result<Rebased> {
+    let head = sequencer::prepare(file_service, Operation::Rebase)?;
+    let ours = head.hash().unwrap_or_default().to_string();
+    if !interactive && history::is_ancestor(file_service, upstream, &ours)? {
+        return Ok(Rebased::UpToDate);
+    }
+
+    let upstream_history = history::ancestors(file_service, upstream)?;
+    let mut todo = Vec::new();
+    let mut next = Some(ours.clone());
+    while let Some(hash) = next.filter(|hash| !upstream_history.contains(hash)) {
+        let commit = file_service.read_commit(&hash)?;
+        next = commit.parent().map(str::to_string);
+        if commit.parents().len() <= 1 {
+            todo.push(Step {
+                action: Action::Pick,
+                hash,
+            });
+        }
+    }
+    todo.reverse();
+    let fast_forward = todo.is_empty();
+    if interactive {
+        todo = edit_todo(file_service, upstream, &ours, &todo)?;
+        if todo.is_empty() {
+            return Err(Error::Usage("nothing to do".into()));
+        }
+    }
+
+    let onto = file_service.read_commit(upstream)?;
+    match sequencer::start(file_service, Operation::Rebase, &head, todo, onto)?.into() {
+        Rebased::Applied(_) if fast_forward => Ok(Rebased::FastForward),
+        rebased => Ok(rebased),
+    }
+}
+
+/// Has the todo list `todo` of rebasing `head` onto `upstream` edited and
+/// returns what is left in it.
+fn edit_todo(
+    file_service: &FileService,
+    upstream: &str,
+    head: &str,
+    todo: &[Step],
+) -> Result<Vec<Step>> {
+    let abbrev = Abbrev::new(file_service)?;
+    let mut text = String::new();
+    for step in todo {
+        let commit = file_service.read_commit(&step.hash)?;
+        let subject = commit.message().lines().next().unwrap_or_default();
+        let short = abbrev.shorten(&step.hash);
+        text.push_str(&format!("{} {short} {subject}\n", step.action.name()));
+    }
+    let (upstream, head) = (abbrev.shorten(upstream), abbrev.shorten(head));
+    text.push_str(&format!(
+        "\n# Rebase {upstream}..{head} onto {upstream} ({} commands)\n#\n{TODO_HELP}",
+        todo.len()
+    ));
+
+    let path = file_service.blip_dir.join(TODO_FILE);
+    fs::write(&path, text)?;
+    let edited = editor::edit(file_service, &path).and_then(|()| Ok(fs::read_to_string(&path)?));
+    let _ = fs::remove_file(&path);
+    let todo = sequencer::parse_todo(file_service, &edited?)?;
+    if let Some(step) = todo.first() {
+        if matches!(step.action, Action::Squash | Action::Fixup) {
+            return Err(Error::Usage(format!(
+                "cannot '{}' without a previous commit",
+                step.action.name()
+            )));
+        }
+    }
+    Ok(todo)
+}
@@ -0,0 +1,156 @@
+//! Reflogs: where a ref pointed before, so that commits a ref moved away
+//! from, such as the tip [`Repository::amend`](crate::Repository::amend)
+//! replaces, can still be found.
+//!
+//! The reflog of a ref is kept in `.blip/logs/<ref>`, one line per move,
+//! oldest first, in the format the stash keeps its entries in:
+//!
+//! ```text
+//! <old hash> <new hash> <name> <<email>> <time> <offset>\t<message>
+//! ```
+//!
+//! HEAD's reflog belongs to its working tree, the others are shared. The
+//! stash keeps its own, see [`Stash`].
+
+use std::{fs, path::PathBuf};
+
+use crate::{
+    lock::LockFile,
+    stash::{Stash, NULL_HASH, STASH_REF},
+    types::{Context, Error, FileService, Result, Signature},
+};
+
+/// One move of a ref.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// Where the ref pointed before, `None` if it did not exist.
+    pub old: Option<String>,
+    pub new: String,
+    pub signature: Option<Signature>,
+    pub message: String,
+}
+
+/// Records that the ref `name` moved from `old` to `new`, by the committer
+/// and for the reason `message`.
+pub fn append(
+    file_service: &FileService,
+    name: &str,
+    old: Option<&str>,
+    new: &str,
+    message: &str,
+) -> Result<()> {
+    let path = path(file_service, name);
+    let signature = file_service.signature("COMMITTER")?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("create", parent)?;
+    }
+    let mut lock = LockFile::acquire(&path, file_service.lock_timeout)?;
+    let mut log = fs::read_to_string(&path).unwrap_or_default();
+    log.push_str(&format!(
+        "{} {new} {signature}\t{}\n",
+        old.unwrap_or(NULL_HASH),
+        message.lines().next().unwrap_or_default()
+    ));
+    lock.write_all(log.as_bytes())?;
+    lock.commit()
+}
+
+/// The moves of the ref `name`, newest first.
+pub fn entries(file_service: &FileService, name: &str) -> Result<Vec<ReflogEntry>> {
+    let path = path(file_service, name);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let log = fs::read_to_string(&path).context("read", &path)?;
+    let mut entries = Vec::new();
+    for (number, line) in log.lines().enumerate() {
+        let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut fields = head.splitn(3, ' ');
+        let (Some(old), Some(new), Some(signature)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(Error::InvalidLog {
+                path,
+                line: number + 1,
+            });
+        };
+        entries.push(ReflogEntry {
+            old: (old != NULL_HASH).then(|| old.to_string()),
+            new: new.to_string(),
+            signature: Signature::parse(signature),
+            message: message.to_string(),
+        });
+    }
+    entries.reverse();
+    Ok(entries)
+}
+
+/// The shared refs with a reflog, all but the stash's.
+pub fn names(file_service: &FileService) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    let logs = file_service.common_dir.join("logs");
+    let mut dirs = vec![logs.join("refs")];
+    while let Some(dir) = dirs.pop() {
+        let Ok(read) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in read {
+            let path = entry.context("read", &dir)?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Some(name) = path.strip_prefix(&logs).ok().and_then(|name| name.to_str())
+            {
+                if name != STASH_REF {
+                    names.push(name.replace('\\', "/"));
+                }
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Drops the entries made before `cutoff`, in seconds since the epoch,
+/// from the reflogs, the stash's and this working tree's HEAD's included,
+/// returning how many there were.
+pub fn expire(file_service: &FileService, cutoff: i64) -> Result<usize> {
+    let mut expired = Stash::new(file_service).expire(cutoff)?;
+    let mut names = names(file_service)?;
+    names.push("HEAD".to_string());
+    for name in names {
+        let path = path(file_service, &name);
+        if !path.is_file() {
+            continue;
+        }
+        let mut lock = LockFile::acquire(&path, file_service.lock_timeout)?;
+        let log = fs::read_to_string(&path).context("read", &path)?;
+        let kept: Vec<&str> = log
+            .lines()
+            .filter(|line| {
+                let head = line.split_once('\t').map_or(*line, |(head, _)| head);
+                let signature = head.splitn(3, ' ').nth(2).and_then(Signature::parse);
+                signature.is_none_or(|signature| signature.time >= cutoff)
+            })
+            .collect();
+        let dropped = log.lines().count() - kept.len();
+        if dropped == 0 {
+            continue;
+        }
+        expired += dropped;
+        if kept.is_empty() {
+            fs::remove_file(&path).context("remove", &path)?;
+        } else {
+            let log: String = kept.iter().map(|line| format!("{line}\n")).collect();
+            lock.write_all(log.as_bytes())?;
+            lock.commit()?;
+        }
+    }
+    Ok(expired)
+}
+
+fn path(file_service: &FileService, name: &str) -> PathBuf {
+    let dir = match name {
+        "HEAD" => &file_service.blip_dir,
+        _ => &file_service.common_dir,
+    };
+    dir.join("logs").join(name)
+}
@@ -0,0 +1,390 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    hash::HashAlgorithm,
+    lock::{self, LockFile},
+    types::{Context, Error, FileService, Result},
+};
+
+/// File under `.blip` holding refs packed together by [`Refs::pack`].
+pub const PACKED_REFS: &str = "packed-refs";
+
+/// What HEAD holds: `ref: <name>` for a symbolic ref to a branch, or the
+/// hash of the commit it is detached at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Head {
+    /// A full ref name such as `refs/heads/master`.
+    Symbolic(String),
+    Detached(String),
+}
+
+/// Named pointers to commits stored as files under `.blip/refs`, or as
+/// lines of `.blip/packed-refs` once packed. A loose ref file takes
+/// precedence over a packed entry of the same name, so refs are always
+/// updated by writing loose files.
+#[derive(Debug)]
+pub struct Refs {
+    blip_dir: PathBuf,
+    common_dir: PathBuf,
+    heads_dir: PathBuf,
+    tags_dir: PathBuf,
+    lock_timeout: Duration,
+}
+
+impl Head {
+    /// Parses the contents of HEAD, or returns `None` if they are neither a
+    /// valid ref under `refs/` nor a hash.
+    pub fn parse(contents: &str, algorithm: HashAlgorithm) -> Option<Head> {
+        let contents = contents.trim_end();
+        if let Some(target) = contents.strip_prefix("ref:") {
+            let target = target.trim_start();
+            let name = target.strip_prefix("refs/")?;
+            return Refs::is_valid_name(name).then(|| Head::Symbolic(target.to_string()));
+        }
+        algorithm
+            .is_hash(contents)
+            .then(|| Head::Detached(contents.to_string()))
+    }
+}
+
+impl fmt::Display for Head {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Head::Symbolic(name) => write!(fmt, "ref: {name}"),
+            Head::Detached(hash) => fmt.write_str(hash),
+        }
+    }
+}
+
+impl Refs {
+    pub fn new(file_service: &FileService) -> Refs {
+        Refs {
+            blip_dir: file_service.blip_dir.clone(),
+            common_dir: file_service.common_dir.clone(),
+            heads_dir: file_service.common_dir.join("refs").join("heads"),
+            tags_dir: file_service.common_dir.join("refs").join("tags"),
+            lock_timeout: file_service.lock_timeout,
+        }
+    }
+
+    /// The file holding the ref `name`. Refs under `refs/` are shared by
+    /// every working tree, others such as `ORIG_HEAD` belong to one.
+    pub(crate) fn path(&self, name: &str) -> PathBuf {
+        match name.starts_with("refs/") {
+            true => self.common_dir.join(name),
+            false => self.blip_dir.join(name),
+        }
+    }
+
+    /// Reads the hash a ref such as `refs/heads/master` points at.
+    pub fn read(&self, name: &str) -> Option<String> {
+        FileService::get_hash_from_ref(&self.path(name))
+            .or_else(|| self.packed().ok()?.remove(name))
+    }
+
+    pub fn write(&self, name: &str, hash: &str) -> Result<()> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("create", parent)?;
+        }
+        lock::write(&path, hash.as_bytes(), self.lock_timeout)
+    }
+
+    /// Points `name` at `hash` provided it still points at `expected`,
+    /// `None` meaning that it must not exist, and returns whether it did.
+    /// The check and the write happen under the ref's lock.
+    pub fn compare_and_write(
+        &self,
+        name: &str,
+        expected: Option<&str>,
+        hash: &str,
+    ) -> Result<bool> {
+        let path = self.path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("create", parent)?;
+        }
+        let mut lock = LockFile::acquire(&path, self.lock_timeout)?;
+        if self.read(name).as_deref() != expected {
+            return Ok(false);
+        }
+        lock.write_all(hash.as_bytes())?;
+        lock.commit()?;
+        Ok(true)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        let path = self.path(name);
+        let packed = self.remove_packed(name)?;
+        if packed && !path.is_file() {
+            return Ok(());
+        }
+        fs::remove_file(&path).context("remove", &path)?;
+        self.remove_empty_dirs(&path);
+        Ok(())
+    }
+
+    /// Drops directories left behind by the removal of the ref file at
+    /// `path`.
+    fn remove_empty_dirs(&self, path: &Path) {
+        // drop directories left empty by hierarchical names like `feature/x`
+        let refs_dir = self.common_dir.join("refs");
+        let mut dir = path.parent();
+        while let Some(parent) = dir {
+            if parent == refs_dir
+                || parent == self.heads_dir
+                || parent == self.tags_dir
+                || fs::remove_dir(parent).is_err()
+            {
+                break;
+            }
+            dir = parent.parent();
+        }
+    }
+
+    /// The refs in the packed refs file, by full name. Lines of git's
+    /// format that blip does not write, comments and peeled hashes, are
+    /// skipped.
+    pub fn packed(&self) -> Result<BTreeMap<String, String>> {
+        let path = self.common_dir.join(PACKED_REFS);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).context("read", &path),
+        };
+        Ok(text
+            .lines()
+            .filter(|line| !line.starts_with(['#', '^']))
+            .filter_map(|line| line.split_once(' '))
+            .map(|(hash, name)| (name.to_string(), hash.to_string()))
+            .collect())
+    }
+
+    /// Rewrites the packed refs file to hold `refs`, under a lock already
+    /// taken on it.
+    fn write_packed(mut lock: LockFile, refs: &BTreeMap<String, String>) -> Result<()> {
+        let mut text = String::new();
+        for (name, hash) in refs {
+            text.push_str(&format!("{hash} {name}\n"));
+        }
+        lock.write_all(text.as_bytes())?;
+        lock.commit()
+    }
+
+    /// Takes `name` out of the packed refs file, returning whether it was
+    /// there.
+    fn remove_packed(&self, name: &str) -> Result<bool> {
+        if !self.packed()?.contains_key(name) {
+            return Ok(false);
+        }
+        let lock = LockFile::acquire(&self.common_dir.join(PACKED_REFS), self.lock_timeout)?;
+        // read again now that nobody else can change it
+        let mut packed = self.packed()?;
+        let removed = packed.remove(name).is_some();
+        Refs::write_packed(lock, &packed)?;
+        Ok(removed)
+    }
+
+    /// Moves every loose ref into the packed refs file, returning how many
+    /// there were. Unless `prune` is false the loose files are then
+    /// deleted, except for any changed in the meantime.
+    pub fn pack(&self, prune: bool) -> Result<usize> {
+        let lock = LockFile::acquire(&self.common_dir.join(PACKED_REFS), self.lock_timeout)?;
+        let mut packed = self.packed()?;
+        let mut loose = Vec::new();
+        for name in self.names()? {
+            if let Some(hash) = FileService::get_hash_from_ref(&self.path(&name)) {
+                packed.insert(name.clone(), hash.clone());
+                loose.push((name, hash));
+            }
+        }
+        Refs::write_packed(lock, &packed)?;
+
+        if prune {
+            for (name, hash) in loose.iter() {
+                let path = self.path(name);
+                let ref_lock = LockFile::acquire(&path, self.lock_timeout)?;
+                if FileService::get_hash_from_ref(&path).as_ref() == Some(hash) {
+                    fs::remove_file(&path).context("remove", &path)?;
+                }
+                drop(ref_lock);
+                self.remove_empty_dirs(&path);
+            }
+        }
+        Ok(loose.len())
+    }
+
+    /// Name of the branch HEAD points at, or `None` when HEAD is detached.
+    pub fn current_branch(&self, file_service: &FileService) -> Result<Option<String>> {
+        Ok(match file_service.read_head()? {
+            Head::Symbolic(name) => name.strip_prefix("refs/heads/").map(str::to_string),
+            Head::Detached(_) => None,
+        })
+    }
+
+    pub fn branch_ref(name: &str) -> String {
+        format!("refs/heads/{name}")
+    }
+
+    pub fn tag_ref(name: &str) -> String {
+        format!("refs/tags/{name}")
+    }
+
+    /// The ref tracking branch `name` of `remote`.
+    pub fn remote_ref(remote: &str, name: &str) -> String {
+        format!("refs/remotes/{remote}/{name}")
+    }
+
+    /// The full name of the ref `name` stands for, trying it as given and
+    /// then as a branch, a tag and a remote-tracking branch.
+    pub fn full_name(&self, name: &str) -> Option<String> {
+        let candidates = match name {
+            _ if name.starts_with("refs/") => vec![name.to_string()],
+            _ => vec![
+                Refs::branch_ref(name),
+                Refs::tag_ref(name),
+                format!("refs/remotes/{name}"),
+            ],
+        };
+        candidates
+            .into_iter()
+            .find(|candidate| self.read(candidate).is_some())
+    }
+
+    pub fn is_branch(&self, name: &str) -> bool {
+        Refs::is_valid_name(name) && self.read(&Refs::branch_ref(name)).is_some()
+    }
+
+    pub fn is_tag(&self, name: &str) -> bool {
+        Refs::is_valid_name(name) && self.read(&Refs::tag_ref(name)).is_some()
+    }
+
+    /// Lists every branch with the commit it points at, sorted by name.
+    pub fn branches(&self) -> Result<Vec<(String, String)>> {
+        self.list("refs/heads/")
+    }
+
+    /// Lists every tag with the object it points at, sorted by name.
+    pub fn tags(&self) -> Result<Vec<(String, String)>> {
+        self.list("refs/tags/")
+    }
+
+    /// Lists the branches last fetched from `remote` with the commits they
+    /// pointed at, sorted by name.
+    pub fn remote_branches(&self, remote: &str) -> Result<Vec<(String, String)>> {
+        self.list(&Refs::remote_ref(remote, ""))
+    }
+
+    /// Lists every ref under `refs/`, such as `refs/heads/master`, with
+    /// the object it points at, sorted by name.
+    pub fn all(&self) -> Result<Vec<(String, String)>> {
+        let refs = self.list("refs/")?;
+        Ok(refs
+            .into_iter()
+            .map(|(name, hash)| (format!("refs/{name}"), hash))
+            .collect())
+    }
+
+    /// Names every loose ref file under `refs/`, including unreadable ones.
+    pub fn names(&self) -> Result<Vec<String>> {
+        let refs_dir = self.common_dir.join("refs");
+        let mut names = Vec::new();
+        if refs_dir.is_dir() {
+            Refs::collect(&refs_dir, &refs_dir, &mut names)?;
+        }
+        names.sort();
+        Ok(names.iter().map(|name| format!("refs/{name}")).collect())
+    }
+
+    /// Lists the refs whose full name starts with `prefix`, loose or
+    /// packed, named without it.
+    fn list(&self, prefix: &str) -> Result<Vec<(String, String)>> {
+        let mut refs: BTreeMap<String, String> = self
+            .packed()?
+            .into_iter()
+            .filter_map(|(name, hash)| Some((name.strip_prefix(prefix)?.to_string(), hash)))
+            .collect();
+
+        let base = self.common_dir.join(prefix);
+        let mut names = Vec::new();
+        if base.is_dir() {
+            Refs::collect(&base, &base, &mut names)?;
+        }
+        for name in names {
+            if let Some(hash) = FileService::get_hash_from_ref(&base.join(&name)) {
+                refs.insert(name, hash);
+            }
+        }
+        Ok(refs.into_iter().collect())
+    }
+
+    fn collect(base: &Path, dir: &Path, names: &mut Vec<String>) -> Result<()> {
+        for entry in fs::read_dir(dir).context("read", dir)? {
+            let path = entry.context("read", dir)?.path();
+            if path.is_dir() {
+                Refs::collect(base, &path, names)?;
+                continue;
+            }
+            let name = path.strip_prefix(base).ok().and_then(|p| p.to_str());
+            // lock files of refs being written are not refs themselves
+            if let Some(name) = name.filter(|name| !name.ends_with(".lock")) {
+                names.push(name.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn create_branch(&self, name: &str, hash: &str) -> Result<()> {
+        if !Refs::is_valid_name(name) {
+            return Err(Error::InvalidRefName(name.to_string()));
+        }
+        if self.heads_dir.join(name).exists() || self.is_branch(name) {
+            return Err(Error::RefExists(name.to_string()));
+        }
+        self.write(&Refs::branch_ref(name), hash)
+    }
+
+    pub fn delete_branch(&self, name: &str) -> Result<()> {
+        if !self.is_branch(name) {
+            return Err(Error::UnknownRevision(name.to_string()));
+        }
+        self.delete(&Refs::branch_ref(name))
+    }
+
+    pub fn create_tag(&self, name: &str, hash: &str, force: bool) -> Result<()> {
+        if !Refs::is_valid_name(name) {
+            return Err(Error::InvalidRefName(name.to_string()));
+        }
+        if !force && (self.tags_dir.join(name).exists() || self.is_tag(name)) {
+            return Err(Error::RefExists(name.to_string()));
+        }
+        self.write(&Refs::tag_ref(name), hash)
+    }
+
+    pub fn delete_tag(&self, name: &str) -> Result<()> {
+        if !self.is_tag(name) {
+            return Err(Error::UnknownRevision(name.to_string()));
+        }
+        self.delete(&Refs::tag_ref(name))
+    }
+
+    /// Checks a ref name against the rules git applies to branch names.
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && !name.starts_with('-')
+            && !name.contains("..")
+            && !name.contains("//")
+            && !name.ends_with(".lock")
+            && name != "HEAD"
+            && name
+                .split('/')
+                .all(|part| !part.is_empty() && !part.starts_with('.'))
+            && !name
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || "~^:?*[\\".contains(c))
+    }
+}
@@ -0,0 +1,830 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+use crate::{
+    config::ConfigFile,
+    hash::HashAlgorithm,
+    history, pack, reachable,
+    refs::{Head, Refs},
+    repository::Repository,
+    transport::{self, Negotiation, Transport},
+    types::{Context, Error, FileService, ObjectKind, Result, Tag},
+    worktree::WorkTree,
+};
+
+/// Name a clone gives the repository it was made from.
+pub const DEFAULT_REMOTE: &str = "origin";
+
+/// Maps refs of a remote to local refs, written `[+]<src>:<dst>` as in
+/// `+refs/heads/*:refs/remotes/origin/*`. A `*` in both sides matches any
+/// part of a name, and a leading `+` allows updates that are not fast
+/// forwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    pub force: bool,
+    pub src: String,
+    pub dst: String,
+}
+
+/// Another repository this one exchanges history with, as configured in
+/// `[remote "<name>"]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remote {
+    pub name: String,
+    pub url: String,
+    /// The refs a fetch brings in, and where it puts them.
+    pub fetch: Vec<Refspec>,
+}
+
+/// The branch of a remote a local branch integrates with, as configured in
+/// `branch.<name>.remote` and `branch.<name>.merge`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    pub remote: String,
+    /// The full name of the branch on the remote, e.g. `refs/heads/main`.
+    pub merge: String,
+}
+
+/// How a fetch changed a local ref.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateStatus {
+    New,
+    FastForward,
+    /// Moved to a commit that does not descend from the old one, which the
+    /// refspec allowed.
+    Forced,
+    UpToDate,
+    /// Left alone because the update was not a fast forward.
+    Rejected,
+    /// Left alone because it did not point where a push expected it to.
+    Stale,
+}
+
+/// When [`push`] may move a branch to a commit that does not descend from
+/// the one it points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Force {
+    Never,
+    Always,
+    /// Only if the branch still points at the given revision, by default
+    /// the remote-tracking ref for it.
+    WithLease(Option<String>),
+}
+
+/// How much history [`fetch`] brings in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    /// All of it, down to what is already here.
+    Full,
+    /// Only this many commits along each path from the fetched tips,
+    /// making a shallow clone.
+    Limit(usize),
+    /// Also this many commits past the boundary of a shallow clone.
+    Deepen(usize),
+}
+
+/// A ref a fetch or push transferred, and what became of the ref it
+/// updated: the local one for a fetch and the remote one for a push.
+#[derive(Debug, Clone)]
+pub struct RefUpdate {
+    pub remote: String,
+    pub local: String,
+    pub old: Option<String>,
+    pub new: String,
+    pub status: UpdateStatus,
+}
+
+/// What [`clone`] made.
+#[derive(Debug)]
+pub struct Cloned {
+    pub repository: Repository,
+    /// The branch checked out, or `None` if the source had a detached HEAD
+    /// or no commits.
+    pub branch: Option<String>,
+    /// Whether the source had no commits, leaving nothing to check out.
+    pub empty: bool,
+}
+
+impl Refspec {
+    pub fn parse(spec: &str) -> Option<Refspec> {
+        let (force, spec) = match spec.strip_prefix('+') {
+            Some(spec) => (true, spec),
+            None => (false, spec),
+        };
+        let (src, dst) = spec.split_once(':')?;
+        let globs = (src.matches('*').count(), dst.matches('*').count());
+        if src.is_empty() || dst.is_empty() || !matches!(globs, (0, 0) | (1, 1)) {
+            return None;
+        }
+        Some(Refspec {
+            force,
+            src: src.to_string(),
+            dst: dst.to_string(),
+        })
+    }
+
+    /// Fetches every branch of `remote` into `refs/remotes/<remote>/`.
+    pub fn default_fetch(remote: &str) -> Refspec {
+        Refspec {
+            force: true,
+            src: Refs::branch_ref("*"),
+            dst: Refs::remote_ref(remote, "*"),
+        }
+    }
+
+    /// The local ref the remote ref `name` maps to, if it matches.
+    pub fn map(&self, name: &str) -> Option<String> {
+        let Some((prefix, suffix)) = self.src.split_once('*') else {
+            return (name == self.src).then(|| self.dst.clone());
+        };
+        let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+        Some(self.dst.replacen('*', matched, 1))
+    }
+
+    /// Whether the local ref `name` is one this refspec writes to.
+    pub fn tracks(&self, name: &str) -> bool {
+        match self.dst.split_once('*') {
+            Some((prefix, suffix)) => name
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.ends_with(suffix)),
+            None => name == self.dst,
+        }
+    }
+}
+
+impl fmt::Display for Refspec {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.force {
+            fmt.write_str("+")?;
+        }
+        write!(fmt, "{}:{}", self.src, self.dst)
+    }
+}
+
+impl Remote {
+    /// Reads the remote `name` from the config, or `None` if it has no URL.
+    pub fn load(file_service: &FileService, name: &str) -> Result<Option<Remote>> {
+        let config = file_service.config()?;
+        let Some(url) = config.get(&format!("remote.{name}.url")) else {
+            return Ok(None);
+        };
+        let fetch = config
+            .get_all(&format!("remote.{name}.fetch"))
+            .into_iter()
+            .map(|spec| {
+                Refspec::parse(&spec).ok_or_else(|| {
+                    Error::InvalidConfig(format!("bad refspec '{spec}' for remote '{name}'"))
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(Remote {
+            name: name.to_string(),
+            url,
+            fetch,
+        }))
+    }
+
+    /// Every configured remote, sorted by name.
+    pub fn list(file_service: &FileService) -> Result<Vec<Remote>> {
+        let mut names: Vec<String> = file_service
+            .config()?
+            .list()
+            .into_iter()
+            .filter_map(|(_, name, _)| {
+                let name = name.strip_prefix("remote.")?.strip_suffix(".url")?;
+                Some(name.to_string())
+            })
+            .collect();
+        names.sort();
+        names.dedup();
+
+        let mut remotes = Vec::new();
+        for name in names {
+            remotes.extend(Remote::load(file_service, &name)?);
+        }
+        Ok(remotes)
+    }
+
+    /// Records a new remote in the repository config, fetching all of its
+    /// branches into `refs/remotes/<name>/`.
+    pub fn add(file_service: &FileService, name: &str, url: &str) -> Result<Remote> {
+        if !Refs::is_valid_name(name) {
+            return Err(Error::Usage(format!("'{name}' is not a valid remote name")));
+        }
+        if Remote::load(file_service, name)?.is_some() {
+            return Err(Error::Usage(format!("remote {name} already exists")));
+        }
+
+        let remote = Remote {
+            name: name.to_string(),
+            url: url.to_string(),
+            fetch: vec![Refspec::default_fetch(name)],
+        };
+        let mut config = ConfigFile::load(file_service.common_dir.join("config"))?;
+        config.set(&format!("remote.{name}.url"), url)?;
+        for spec in remote.fetch.iter() {
+            config.add(&format!("remote.{name}.fetch"), &spec.to_string())?;
+        }
+        config.save()?;
+        Ok(remote)
+    }
+
+    /// Forgets the remote `name`: its config, its remote-tracking refs and
+    /// the upstream settings of branches tracking it.
+    pub fn remove(file_service: &FileService, name: &str) -> Result<()> {
+        let Some(remote) = Remote::load(file_service, name)? else {
+            return Err(Error::Usage(format!("no such remote: '{name}'")));
+        };
+
+        let mut config = ConfigFile::load(file_service.common_dir.join("config"))?;
+        config.remove_section(&format!("remote.{name}"))?;
+        for (key, value) in config.list() {
+            let Some(branch) = key
+                .strip_prefix("branch.")
+                .and_then(|key| key.strip_suffix(".remote"))
+            else {
+                continue;
+            };
+            if value == name {
+                config.unset(&format!("branch.{branch}.remote"))?;
+                config.unset(&format!("branch.{branch}.merge"))?;
+            }
+        }
+        config.save()?;
+
+        let refs = Refs::new(file_service);
+        for (name, _) in refs.all()? {
+            if remote.fetch.iter().any(|spec| spec.tracks(&name)) {
+                refs.delete(&name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Brings in the refs `remote` advertises that its fetch refspecs map, with
+/// the objects they need, and updates the local refs they map to.
+///
+/// The objects come as a single pack holding what the remote can reach
+/// from the wanted refs but not from the tips of the refs here, which it
+/// is told about. Anything still missing after that, or everything when
+/// the remote cannot send packs, is downloaded one object at a time,
+/// stopping at those already present. `depth` bounds the history sent,
+/// which only remotes that send packs can do.
+pub fn fetch(file_service: &FileService, remote: &Remote, depth: Depth) -> Result<Vec<RefUpdate>> {
+    let mut transport = transport::connect(&remote.url)?;
+
+    let advertised = transport.list_refs()?;
+    let mut wanted = Vec::new();
+    for (name, hash) in advertised.iter() {
+        let mapped = remote
+            .fetch
+            .iter()
+            .find_map(|spec| Some((spec.map(name)?, spec.force)));
+        if let Some((local, force)) = mapped {
+            wanted.push((name.clone(), hash.clone(), local, force));
+        }
+    }
+
+    let mut wants = Vec::new();
+    for (_, hash, _, _) in wanted.iter() {
+        if !wants.contains(hash) && !file_service.has_object(hash)? {
+            wants.push(hash.clone());
+        }
+    }
+    let deepen = matches!(depth, Depth::Deepen(_)) && !file_service.shallow().is_empty();
+    if !wants.is_empty() || deepen {
+        negotiate(file_service, remote, transport.as_mut(), wants, depth)?;
+    }
+
+    let mut seen = HashSet::new();
+    for (_, hash, _, _) in wanted.iter() {
+        download(file_service, remote, transport.as_mut(), hash, &mut seen)?;
+    }
+
+    let refs = Refs::new(file_service);
+    let mut updates = Vec::new();
+    for (name, new, local, force) in wanted {
+        let old = refs.read(&local);
+        let status = match old {
+            None => UpdateStatus::New,
+            Some(ref old) if *old == new => UpdateStatus::UpToDate,
+            Some(ref old) => {
+                let old = file_service.peel(old)?;
+                if history::is_ancestor(file_service, &old, &file_service.peel(&new)?)? {
+                    UpdateStatus::FastForward
+                } else if force {
+                    UpdateStatus::Forced
+                } else {
+                    UpdateStatus::Rejected
+                }
+            }
+        };
+        if !matches!(status, UpdateStatus::UpToDate | UpdateStatus::Rejected) {
+            refs.write(&local, &new)?;
+        }
+        updates.push(RefUpdate {
+            remote: name,
+            local,
+            old,
+            new,
+            status,
+        });
+    }
+
+    // tags pointing into history that is now here come along
+    for (name, hash) in advertised {
+        let fetched = updates.iter().any(|update| update.local == name);
+        if !name.starts_with("refs/tags/") || fetched || refs.read(&name).is_some() {
+            continue;
+        }
+        if !file_service.has_object(&hash)? {
+            let (kind, data) = transport.read_object(&hash)?;
+            let tag = std::str::from_utf8(&data)
+                .ok()
+                .and_then(|text| Tag::from(&hash, text).ok());
+            match tag {
+                Some(tag) if file_service.has_object(tag.object())? => {
+                    store(file_service, remote, &hash, kind, &data)?
+                }
+                _ => continue,
+            }
+        }
+        refs.write(&name, &hash)?;
+        updates.push(RefUpdate {
+            remote: name.clone(),
+            local: name,
+            old: None,
+            new: hash,
+            status: UpdateStatus::New,
+        });
+    }
+    Ok(updates)
+}
+
+/// Asks `remote` for a pack of `wants` and what they need, telling it the
+/// tips of the refs here as what is already present, then stores the
+/// objects it holds and moves the shallow boundary to where the history
+/// here now ends.
+fn negotiate(
+    file_service: &FileService,
+    remote: &Remote,
+    transport: &mut dyn Transport,
+    wants: Vec<String>,
+    depth: Depth,
+) -> Result<()> {
+    let mut haves: Vec<String> = file_service.read_head_hash()?.into_iter().collect();
+    for (_, hash) in Refs::new(file_service).all()? {
+        if !haves.contains(&hash) {
+            haves.push(hash);
+        }
+    }
+    let mut shallow = file_service.shallow();
+    let request = Negotiation {
+        wants,
+        haves,
+        shallow: shallow.iter().cloned().collect(),
+        depth: match depth {
+            Depth::Limit(depth) => Some(depth),
+            Depth::Full | Depth::Deepen(_) => None,
+        },
+        deepen: match depth {
+            Depth::Deepen(deepen) => Some(deepen),
+            Depth::Full | Depth::Limit(_) => None,
+        },
+    };
+    let error = |message: &str| Error::Remote {
+        url: remote.url.clone(),
+        message: message.to_string(),
+    };
+    let Some(packed) = transport.fetch_pack(&request)? else {
+        if depth != Depth::Full {
+            return Err(error("does not support shallow fetches"));
+        }
+        return Ok(());
+    };
+    let mut missing = Vec::new();
+    for hash in packed.requires.iter() {
+        if !file_service.has_object(hash)? {
+            missing.push(hash.as_str());
+        }
+    }
+    if !missing.is_empty() {
+        return Err(error(&format!(
+            "needs commits that are missing here: {}",
+            missing.join(", ")
+        )));
+    }
+    let objects = pack::unpack(file_service.hash_algorithm, &packed.pack)
+        .ok_or_else(|| error("sent a malformed pack"))?;
+    for (kind, data) in objects {
+        let hash = file_service.hash_raw(kind, &data);
+        if !file_service.has_object(&hash)? {
+            file_service.write_loose(&hash, kind, &data)?;
+        }
+    }
+
+    shallow.extend(packed.shallow);
+    let mut boundary = BTreeSet::new();
+    for hash in shallow {
+        if !file_service.has_object(&hash)? {
+            continue;
+        }
+        let (_, data) = file_service.read_raw(&hash)?;
+        for (parent, kind) in reachable::links(&hash, ObjectKind::Commit, &data)? {
+            if kind == ObjectKind::Commit && !file_service.has_object(&parent)? {
+                boundary.insert(hash.clone());
+            }
+        }
+    }
+    file_service.set_shallow(boundary)
+}
+
+/// Stores an object downloaded from `remote`, checking it against its hash
+/// first.
+fn store(
+    file_service: &FileService,
+    remote: &Remote,
+    hash: &str,
+    kind: Option<ObjectKind>,
+    data: &[u8],
+) -> Result<()> {
+    if file_service.hash_raw(kind, data) != hash {
+        return Err(Error::Remote {
+            url: remote.url.clone(),
+            message: format!("object {hash} does not match its hash"),
+        });
+    }
+    file_service.write_loose(hash, kind, data)?;
+    Ok(())
+}
+
+/// Downloads `hash` and everything it refers to that is not already
+/// stored, checking each object against its hash.
+fn download(
+    file_service: &FileService,
+    remote: &Remote,
+    transport: &mut dyn Transport,
+    hash: &str,
+    seen: &mut HashSet<String>,
+) -> Result<()> {
+    let mut pending = vec![(hash.to_string(), None)];
+    while let Some((hash, expected)) = pending.pop() {
+        if !seen.insert(hash.clone()) || file_service.has_object(&hash)? {
+            continue;
+        }
+        let (kind, data) = transport.read_object(&hash)?;
+        store(file_service, remote, &hash, kind, &data)?;
+
+        let kind = object_kind(file_service, &hash, kind, expected);
+        for (link, kind) in reachable::links(&hash, kind, &data)? {
+            pending.push((link, Some(kind)));
+        }
+    }
+    Ok(())
+}
+
+/// The kind of a stored object. Objects from before headers take the kind
+/// they are referred to as, and refs to them point at commits unless they
+/// read as a tag.
+pub(crate) fn object_kind(
+    file_service: &FileService,
+    hash: &str,
+    stored: Option<ObjectKind>,
+    expected: Option<ObjectKind>,
+) -> ObjectKind {
+    match stored.or(expected) {
+        Some(kind) => kind,
+        None if file_service.read_tag(hash).is_ok() => ObjectKind::Tag,
+        None => ObjectKind::Commit,
+    }
+}
+
+/// Sends the branch `branch` to `remote`, updating the branch of the same
+/// name there and then the remote-tracking ref for it here.
+///
+/// The remote is sent every object reachable from the branch that is not
+/// reachable from a ref it already has. Its branch is only moved if it
+/// still points where it did when its refs were listed, and unless `force`
+/// allows otherwise only if the move is a fast forward.
+pub fn push(
+    file_service: &FileService,
+    remote: &Remote,
+    branch: &str,
+    force: &Force,
+) -> Result<RefUpdate> {
+    let local = Refs::branch_ref(branch);
+    let refs = Refs::new(file_service);
+    let new = refs
+        .read(&local)
+        .ok_or_else(|| Error::UnknownRevision(branch.to_string()))?;
+
+    let mut transport = transport::connect(&remote.url)?;
+    let advertised: BTreeMap<String, String> = transport.list_refs()?.into_iter().collect();
+    let old = advertised.get(&local).cloned();
+    let tracking = remote.fetch.iter().find_map(|spec| spec.map(&local));
+
+    let lease_broken = match force {
+        Force::WithLease(Some(expected)) => {
+            old.as_deref() != Some(file_service.resolve(expected)?.as_str())
+        }
+        Force::WithLease(None) => old != tracking.as_deref().and_then(|name| refs.read(name)),
+        Force::Never | Force::Always => false,
+    };
+    let status = match old {
+        Some(ref old) if *old == new => UpdateStatus::UpToDate,
+        _ if lease_broken => UpdateStatus::Stale,
+        None => UpdateStatus::New,
+        Some(ref old)
+            if file_service.has_object(old)? && history::is_ancestor(file_service, old, &new)? =>
+        {
+            UpdateStatus::FastForward
+        }
+        Some(_) if *force != Force::Never => UpdateStatus::Forced,
+        Some(_) => UpdateStatus::Rejected,
+    };
+    let update = RefUpdate {
+        remote: local.clone(),
+        local: local.clone(),
+        old,
+        new,
+        status,
+    };
+    if matches!(
+        status,
+        UpdateStatus::UpToDate | UpdateStatus::Stale | UpdateStatus::Rejected
+    ) {
+        return Ok(update);
+    }
+
+    let mut haves = Vec::new();
+    for hash in advertised.values() {
+        if file_service.has_object(hash)? {
+            haves.push(hash.clone());
+        }
+    }
+    let none = BTreeSet::new();
+    let have = closure(file_service, haves, &HashSet::new(), &none)?;
+    for hash in closure(file_service, vec![update.new.clone()], &have, &none)? {
+        let (kind, data) = file_service.read_raw(&hash)?;
+        transport.write_object(&hash, kind, &data)?;
+    }
+    transport.update_ref(&local, update.old.as_deref(), &update.new)?;
+
+    if let Some(tracking) = tracking {
+        refs.write(&tracking, &update.new)?;
+    }
+    Ok(update)
+}
+
+/// The objects reachable from `roots`, not following any in `exclude`, nor
+/// the parents of commits in `shallow` or at the shallow boundary here.
+pub(crate) fn closure(
+    file_service: &FileService,
+    roots: Vec<String>,
+    exclude: &HashSet<String>,
+    shallow: &BTreeSet<String>,
+) -> Result<HashSet<String>> {
+    let mut seen = HashSet::new();
+    let mut pending: Vec<_> = roots.into_iter().map(|hash| (hash, None)).collect();
+    while let Some((hash, expected)) = pending.pop() {
+        if exclude.contains(&hash) || !seen.insert(hash.clone()) {
+            continue;
+        }
+        let (kind, data) = file_service.read_raw(&hash)?;
+        let kind = object_kind(file_service, &hash, kind, expected);
+        let grafted = shallow.contains(&hash) || file_service.is_shallow(&hash);
+        for (link, kind) in reachable::links(&hash, kind, &data)? {
+            if !(grafted && kind == ObjectKind::Commit) {
+                pending.push((link, Some(kind)));
+            }
+        }
+    }
+    Ok(seen)
+}
+
+impl Upstream {
+    /// Reads the upstream of the branch `branch`, if it has one.
+    pub fn load(file_service: &FileService, branch: &str) -> Result<Option<Upstream>> {
+        let config = file_service.config()?;
+        let remote = config.get(&format!("branch.{branch}.remote"));
+        let merge = config.get(&format!("branch.{branch}.merge"));
+        Ok(remote
+            .zip(merge)
+            .map(|(remote, merge)| Upstream { remote, merge }))
+    }
+
+    /// Parses a remote-tracking branch such as `origin/main` into the
+    /// upstream it stands for.
+    pub fn parse(file_service: &FileService, name: &str) -> Result<Upstream> {
+        let name = name.strip_prefix("refs/remotes/").unwrap_or(name);
+        let unknown = || {
+            Error::Usage(format!(
+                "the requested upstream branch '{name}' does not exist"
+            ))
+        };
+        let (remote, branch) = name.split_once('/').ok_or_else(unknown)?;
+        if Refs::new(file_service)
+            .read(&Refs::remote_ref(remote, branch))
+            .is_none()
+        {
+            return Err(unknown());
+        }
+        Ok(Upstream {
+            remote: remote.to_string(),
+            merge: Refs::branch_ref(branch),
+        })
+    }
+
+    /// Records `self` as the upstream of the branch `branch`.
+    pub fn set(&self, file_service: &FileService, branch: &str) -> Result<()> {
+        let mut config = ConfigFile::load(file_service.common_dir.join("config"))?;
+        config.set(&format!("branch.{branch}.remote"), &self.remote)?;
+        config.set(&format!("branch.{branch}.merge"), &self.merge)?;
+        config.save()
+    }
+
+    /// Forgets the upstream of the branch `branch`.
+    pub fn unset(file_service: &FileService, branch: &str) -> Result<()> {
+        let mut config = ConfigFile::load(file_service.common_dir.join("config"))?;
+        config.unset(&format!("branch.{branch}.remote"))?;
+        config.unset(&format!("branch.{branch}.merge"))?;
+        config.save()
+    }
+
+    /// The remote-tracking ref a fetch of the upstream's remote updates for
+    /// it, such as `refs/remotes/origin/main`.
+    pub fn tracking_ref(&self, file_service: &FileService) -> Result<Option<String>> {
+        let Some(remote) = Remote::load(file_service, &self.remote)? else {
+            return Ok(None);
+        };
+        Ok(remote.fetch.iter().find_map(|spec| spec.map(&self.merge)))
+    }
+
+    /// The remote-tracking branch for the upstream as it is usually
+    /// written, such as `origin/main`.
+    pub fn short_name(&self, file_service: &FileService) -> Result<String> {
+        Ok(match self.tracking_ref(file_service)? {
+            Some(name) => name
+                .strip_prefix("refs/remotes/")
+                .unwrap_or(&name)
+                .to_string(),
+            None => format!(
+                "{}/{}",
+                self.remote,
+                self.merge
+                    .strip_prefix("refs/heads/")
+                    .unwrap_or(&self.merge)
+            ),
+        })
+    }
+
+    /// How many commits the branch at `hash` has that its remote-tracking
+    /// branch does not, and how many the other way around, or `None` when
+    /// the remote-tracking branch is gone.
+    pub fn ahead_behind(
+        &self,
+        file_service: &FileService,
+        hash: &str,
+    ) -> Result<Option<(usize, usize)>> {
+        let tracking = self.tracking_ref(file_service)?;
+        let Some(theirs) = tracking.and_then(|name| Refs::new(file_service).read(&name)) else {
+            return Ok(None);
+        };
+        history::ahead_behind(file_service, hash, &theirs).map(Some)
+    }
+}
+
+/// Copies the repository at `url` into a new repository at `destination`,
+/// which must not exist or be an empty directory.
+///
+/// The source is recorded as the remote `origin` and fetched, so that its
+/// branches become remote-tracking refs under `refs/remotes/origin/`; its
+/// tags are copied as they are. The branch its HEAD is on is then created,
+/// set to track its counterpart there and checked out. A source on this
+/// machine has its objects copied as they are stored, loose or packed,
+/// rather than one at a time.
+///
+/// With a `depth`, only that many commits of each branch are fetched and
+/// only the tags pointing into them are copied.
+pub fn clone(url: &str, destination: &Path, depth: Option<usize>) -> Result<Cloned> {
+    let source = match transport::local_path(url) {
+        Some(path) => Some(FileService::open(path)?),
+        None => None,
+    };
+    let mut transport = transport::connect(url)?;
+    let not_empty = destination
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some());
+    if not_empty || destination.is_file() {
+        return Err(Error::Usage(format!(
+            "destination path '{}' already exists and is not an empty directory",
+            destination.display()
+        )));
+    }
+
+    let branch = match transport.head()? {
+        Head::Symbolic(name) => name.strip_prefix("refs/heads/").map(str::to_string),
+        Head::Detached(_) => None,
+    };
+    let advertised: BTreeMap<String, String> = transport.list_refs()?.into_iter().collect();
+    let algorithm = match source {
+        Some(ref source) => Some(source.hash_algorithm),
+        // the hash function of a repository elsewhere shows in its hashes
+        None => advertised.values().next().map(|hash| match hash.len() {
+            len if len == HashAlgorithm::Sha256.hex_len() => HashAlgorithm::Sha256,
+            _ => HashAlgorithm::Sha1,
+        }),
+    };
+    let repository = Repository::init(destination, branch.as_deref(), algorithm)?;
+    let file_service = repository.file_service();
+
+    let remote = match source {
+        Some(ref source) => {
+            // a shallow clone takes only the objects it needs
+            if depth.is_none() {
+                copy_objects(source, file_service)?;
+                file_service.set_shallow(source.shallow())?;
+            }
+            Remote::add(
+                file_service,
+                DEFAULT_REMOTE,
+                &source.root_dir.to_string_lossy(),
+            )?
+        }
+        None => Remote::add(file_service, DEFAULT_REMOTE, url)?,
+    };
+    let depth = depth.map_or(Depth::Full, Depth::Limit);
+    fetch(file_service, &remote, depth)?;
+
+    let refs = Refs::new(file_service);
+    let mut seen = HashSet::new();
+    for (name, hash) in advertised.iter() {
+        if depth == Depth::Full && name.starts_with("refs/tags/") && refs.read(name).is_none() {
+            download(file_service, &remote, transport.as_mut(), hash, &mut seen)?;
+            refs.write(name, hash)?;
+        }
+    }
+
+    if let Some(ref branch) = branch {
+        let upstream = Upstream {
+            remote: DEFAULT_REMOTE.to_string(),
+            merge: Refs::branch_ref(branch),
+        };
+        upstream.set(file_service, branch)?;
+    }
+
+    let Some(hash) = advertised.get("HEAD").cloned() else {
+        return Ok(Cloned {
+            repository,
+            branch,
+            empty: true,
+        });
+    };
+    let commit = file_service.read_commit(&hash)?;
+    WorkTree::new(file_service).checkout(
+        file_service,
+        &BTreeMap::new(),
+        commit.files(),
+        commit.modes(),
+    )?;
+    match branch {
+        Some(ref branch) => refs.write(&Refs::branch_ref(branch), &hash)?,
+        None => file_service.set_head(None, &hash)?,
+    }
+
+    Ok(Cloned {
+        repository,
+        branch,
+        empty: false,
+    })
+}
+
+/// Copies every loose object and pack of `source` into `target`.
+fn copy_objects(source: &FileService, target: &FileService) -> Result<()> {
+    for hash in source.loose_objects()? {
+        let to = target.object_path(&hash);
+        if let Some(dir) = to.parent() {
+            fs::create_dir_all(dir).context("create", dir)?;
+        }
+        let from = source.object_path(&hash);
+        fs::copy(&from, &to).context("copy", &from)?;
+    }
+
+    let pack_dir = source.pack_dir();
+    if !pack_dir.is_dir() {
+        return Ok(());
+    }
+    let target_dir = target.pack_dir();
+    fs::create_dir_all(&target_dir).context("create", &target_dir)?;
+    for entry in fs::read_dir(&pack_dir).context("read", &pack_dir)? {
+        let from = entry.context("read", &pack_dir)?.path();
+        if let Some(name) = from.file_name().filter(|_| from.is_file()) {
+            fs::copy(&from, target_dir.join(name)).context("copy", &from)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1313 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    num::NonZeroUsize,
+    panic,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use regex::Regex;
+
+use crate::{
+    cli::AddMode,
+    config::Config,
+    conventional,
+    diff::{self, DiffOptions, FileStat, Rename, RenameDetection},
+    editor,
+    hash::HashAlgorithm,
+    history::{CommitFilter, CommitWalk},
+    hooks::Hooks,
+    index::Stat,
+    pathspec::Pathspec,
+    reflog,
+    refs::Refs,
+    sequencer::{self, Operation},
+    sign::{self, SigningKey},
+    submodule, trailer,
+    types::{Blob, Commit, Context, Error, FileMode, FileService, Index, Result},
+    worktree::{Change, Status, WorkTree},
+};
+
+/// A blip repository, for driving blip from other programs without going
+/// through the command line. Paths given to its methods are relative to the
+/// root of the working tree.
+#[derive(Debug)]
+pub struct Repository {
+    file_service: FileService,
+}
+
+/// A line [`Repository::grep`] found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    pub path: String,
+    /// The number of the line, from 1.
+    pub number: usize,
+    pub line: String,
+}
+
+/// A tracked text file whose working tree copy differs from the staged
+/// one, see [`Repository::unstaged_changes`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnstagedChange {
+    pub path: String,
+    pub staged: String,
+    /// The working tree's contents as they would be stored, `None` when
+    /// the file is deleted there.
+    pub current: Option<String>,
+}
+
+/// One side of a comparison: a set of files and where to read them from,
+/// with the modes of a commit's files that are not regular ones.
+enum Side {
+    Commit(BTreeMap<String, String>, BTreeMap<String, FileMode>),
+    WorkTree(BTreeMap<String, String>),
+}
+
+impl Side {
+    fn files(&self) -> &BTreeMap<String, String> {
+        match self {
+            Side::Commit(files, _) | Side::WorkTree(files) => files,
+        }
+    }
+
+    /// The mode of the file at `path`, or `None` if there is none.
+    fn mode(&self, worktree: &WorkTree, path: &str) -> Option<FileMode> {
+        if !self.files().contains_key(path) {
+            return None;
+        }
+        match self {
+            Side::Commit(_, modes) => Some(modes.get(path).copied().unwrap_or_default()),
+            Side::WorkTree(_) => Some(worktree.mode(path).unwrap_or_default()),
+        }
+    }
+
+    /// The paths of the submodules among the files.
+    fn submodules(
+        &self,
+        file_service: &FileService,
+        worktree: &WorkTree,
+    ) -> Result<BTreeSet<String>> {
+        match self {
+            Side::Commit(files, _) => submodule::paths(file_service, files),
+            Side::WorkTree(_) => worktree.submodules(),
+        }
+    }
+
+    /// Reads the file at `path`, or renders the commit a submodule is at
+    /// the way git does.
+    fn read(
+        &self,
+        file_service: &FileService,
+        worktree: &WorkTree,
+        submodules: &BTreeSet<String>,
+        path: &str,
+    ) -> Result<Option<String>> {
+        let data = self.read_data(file_service, worktree, submodules, path)?;
+        Ok(data.map(|data| String::from_utf8_lossy(&data).into_owned()))
+    }
+
+    /// Like [`Side::read`], but the bytes as they are.
+    fn read_data(
+        &self,
+        file_service: &FileService,
+        worktree: &WorkTree,
+        submodules: &BTreeSet<String>,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(hash) = self.files().get(path) else {
+            return Ok(None);
+        };
+        if submodules.contains(path) {
+            return Ok(Some(format!("Subproject commit {hash}\n").into_bytes()));
+        }
+        Ok(Some(match self {
+            Side::Commit(..) => file_service.read_blob(hash)?,
+            Side::WorkTree(_) => worktree.read_converted(path)?,
+        }))
+    }
+}
+
+/// The paths whose contents or mode differ between two sides.
+fn changed_paths(old: &Side, new: &Side, worktree: &WorkTree) -> BTreeSet<String> {
+    let mut paths: BTreeSet<String> = diff::diff_files(old.files(), new.files())
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    // files whose contents are the same but not their mode
+    for path in old.files().keys() {
+        let (before, after) = (old.mode(worktree, path), new.mode(worktree, path));
+        if after.is_some() && before != after {
+            paths.insert(path.clone());
+        }
+    }
+    paths
+}
+
+impl Repository {
+    /// Opens the repository containing `path`, searching upwards from it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Repository> {
+        Ok(Repository {
+            file_service: FileService::open(path.as_ref())?,
+        })
+    }
+
+    /// Creates an empty repository at `path`, or reinitializes an existing
+    /// one. The initial branch defaults to `init.defaultBranch`.
+    pub fn init<P: AsRef<Path>>(
+        path: P,
+        branch: Option<&str>,
+        object_format: Option<HashAlgorithm>,
+    ) -> Result<Repository> {
+        Repository::create(path.as_ref(), branch, object_format, false)
+    }
+
+    /// Like [`Repository::init`], but makes `path` itself the repository,
+    /// without a working tree, as repositories only pushed to and served
+    /// usually are.
+    pub fn init_bare<P: AsRef<Path>>(
+        path: P,
+        branch: Option<&str>,
+        object_format: Option<HashAlgorithm>,
+    ) -> Result<Repository> {
+        Repository::create(path.as_ref(), branch, object_format, true)
+    }
+
+    fn create(
+        path: &Path,
+        branch: Option<&str>,
+        object_format: Option<HashAlgorithm>,
+        bare: bool,
+    ) -> Result<Repository> {
+        fs::create_dir_all(path)?;
+
+        let blip_dir = match bare {
+            true => path.to_path_buf(),
+            false => path.join(".blip"),
+        };
+        let config = Config::load(Some(&blip_dir))?;
+        let branch = match branch {
+            Some(branch) => branch.to_string(),
+            None => config
+                .get("init.defaultbranch")
+                .unwrap_or_else(|| "master".to_string()),
+        };
+        if !Refs::is_valid_name(&branch) {
+            return Err(Error::InvalidRefName(branch));
+        }
+        let object_format = match object_format {
+            Some(object_format) => object_format,
+            None => match config.get("init.defaultobjectformat") {
+                Some(name) => HashAlgorithm::parse(&name).ok_or_else(|| {
+                    Error::InvalidConfig(format!("unknown object format '{name}'"))
+                })?,
+                None => HashAlgorithm::default(),
+            },
+        };
+        FileService::init_blip(path, &branch, object_format, bare)?;
+        Repository::open(path)
+    }
+
+    pub fn file_service(&self) -> &FileService {
+        &self.file_service
+    }
+
+    pub fn root_dir(&self) -> &Path {
+        &self.file_service.root_dir
+    }
+
+    /// Stages the files matching `pathspecs`, see [`Pathspec`]. With no
+    /// pathspecs, `AddMode::All` and `AddMode::Update` apply to the whole
+    /// tree.
+    ///
+    /// Files are hashed and stored on `jobs` threads, which defaults to
+    /// `add.jobs` or the number of CPUs; the index is updated once they are
+    /// all done.
+    pub fn add(
+        &self,
+        pathspecs: &[String],
+        mode: AddMode,
+        force: bool,
+        jobs: Option<usize>,
+    ) -> Result<()> {
+        let worktree = WorkTree::new(&self.file_service);
+        let mut index = self.file_service.lock_index()?;
+        let head = self.file_service.read_head_commit()?;
+        let tracked = WorkTree::tracked_files(&index, head.as_ref());
+        let modes = WorkTree::tracked_modes(&index, head.as_ref());
+        let present = worktree.files()?;
+        let ignore = worktree.ignore()?;
+
+        let pathspec = Pathspec::new(pathspecs)?;
+        let whole_tree = Pathspec::new(&[String::new()])?;
+        let includes = match (pathspec.includes().is_empty(), mode) {
+            (true, AddMode::Paths) if pathspecs.is_empty() => return Ok(()),
+            (true, _) => whole_tree.includes(),
+            (false, _) => pathspec.includes(),
+        };
+
+        let mut paths = BTreeSet::new();
+        for pattern in includes {
+            let matches = |path: &&String| pattern.matches(path) && !pathspec.is_excluded(path);
+            let spec = pattern.as_str();
+            let before = paths.len();
+
+            paths.extend(tracked.keys().filter(matches).cloned());
+            if mode != AddMode::Update {
+                let untracked = present.iter().filter(matches).filter(|path| {
+                    force || tracked.contains_key(*path) || !ignore.is_ignored(path, false)
+                });
+                paths.extend(untracked.cloned());
+            }
+
+            if paths.len() == before && !pattern.is_glob() && present.contains(spec) && !force {
+                return Err(Error::Usage(format!(
+                    "the following paths are ignored by a .blipignore file:\n{spec}\nUse -f if you really want to add them."
+                )));
+            }
+            if paths.len() == before && !spec.is_empty() && !tracked.contains_key(spec) {
+                return Err(Error::Usage(format!(
+                    "pathspec '{spec}' did not match any files"
+                )));
+            }
+        }
+
+        let submodules = worktree.submodules()?;
+        let mut changed = Vec::new();
+        for path in paths {
+            if !present.contains(&path) {
+                if tracked.contains_key(&path) {
+                    index.remove(&path);
+                }
+                continue;
+            }
+
+            let full_path = self.file_service.root_dir.join(&path);
+            // nested repositories are staged at the commit they have checked
+            // out, once `blip submodule add` has made them submodules
+            if full_path.is_dir() && !full_path.is_symlink() {
+                let hash = worktree.hash(&path)?;
+                if let Some(hash) = hash.filter(|_| submodules.contains(&path)) {
+                    if tracked.get(&path) != Some(&hash) {
+                        index.update(&path, &hash);
+                    }
+                }
+                continue;
+            }
+            // stat before reading, so that a change made meanwhile shows
+            // up as a stat mismatch later
+            let metadata = fs::symlink_metadata(&full_path).context("read", &full_path)?;
+            let stat = Stat::from_metadata(&metadata);
+            let mode = FileMode::from_metadata(&metadata);
+            let unchanged = index
+                .cached_hash(&path, &stat)
+                .is_some_and(|hash| tracked.get(&path).is_some_and(|tracked| tracked == hash))
+                && modes.get(&path).copied().unwrap_or_default() == mode;
+            if unchanged && !index.conflicts().contains(&path) {
+                continue;
+            }
+            changed.push((path, stat, mode));
+        }
+
+        let jobs = self.jobs(jobs, "add.jobs")?;
+        let conflicts = index.conflicts();
+        // loaded once up front rather than by every thread
+        worktree.attributes()?;
+        let hashes = parallel_map(&changed, jobs, |(path, _, mode)| {
+            let hash = worktree.hash_file(path)?;
+            let same_mode = modes.get(path).copied().unwrap_or_default() == *mode;
+            if tracked.get(path) == Some(&hash) && same_mode && !conflicts.contains(path) {
+                return Ok((hash, false));
+            }
+            Ok((worktree.write_blob(&self.file_service, path)?, true))
+        })?;
+
+        for ((path, stat, mode), (hash, staged)) in changed.into_iter().zip(hashes) {
+            if staged {
+                index.update_with_mode(&path, &hash, mode);
+            }
+            index.record_stat(&path, stat, &hash);
+        }
+        self.file_service.write_index(&index)
+    }
+
+    /// The number of threads a command uses: `jobs` if given, else the
+    /// config `key`, with 0 or no setting meaning one per CPU.
+    fn jobs(&self, jobs: Option<usize>, key: &str) -> Result<usize> {
+        let jobs = match (jobs, self.file_service.config()?.get(key)) {
+            (Some(jobs), _) => jobs,
+            (None, Some(value)) => value.trim().parse().map_err(|_| {
+                Error::InvalidConfig(format!("bad job count '{value}' for '{key}'"))
+            })?,
+            (None, None) => 0,
+        };
+        Ok(match jobs {
+            0 => thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            jobs => jobs,
+        })
+    }
+
+    /// The tracked files matching `pathspecs` whose contents in the working
+    /// tree differ from the staged ones, for staging part of the
+    /// difference, in path order. Binary files, those that are not UTF-8
+    /// and would not survive being staged line by line among them, symlinks
+    /// and submodules are left out, as are changes to modes alone.
+    pub fn unstaged_changes(&self, pathspecs: &[String]) -> Result<Vec<UnstagedChange>> {
+        let pathspec = Pathspec::new(pathspecs)?;
+        let worktree = WorkTree::new(&self.file_service);
+        let index = self.file_service.read_index()?;
+        let head = self.file_service.read_head_commit()?;
+        let tracked = WorkTree::tracked_files(&index, head.as_ref());
+        let modes = WorkTree::tracked_modes(&index, head.as_ref());
+        let status = worktree.status_matching(&index, head.as_ref(), &pathspec)?;
+        let submodules = worktree.submodules()?;
+
+        let mut changes = Vec::new();
+        for (change, path) in status.unstaged {
+            let regular = modes.get(&path).copied().unwrap_or_default() != FileMode::Symlink
+                && worktree.mode(&path) != Some(FileMode::Symlink);
+            if submodules.contains(&path) || !regular {
+                continue;
+            }
+            let staged = self.file_service.read_blob(&tracked[&path])?;
+            let current = match change {
+                Change::Deleted => None,
+                _ => Some(worktree.read_converted(&path)?),
+            };
+            if current.as_ref() == Some(&staged) {
+                continue;
+            }
+            let text = |data: Vec<u8>| {
+                String::from_utf8(data)
+                    .ok()
+                    .filter(|text| !text.contains('\0'))
+            };
+            let Some(staged) = text(staged) else {
+                continue;
+            };
+            let current = match current.map(text) {
+                Some(None) => continue,
+                current => current.flatten(),
+            };
+            changes.push(UnstagedChange {
+                path,
+                staged,
+                current,
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Stages `contents` for the tracked file at `path`, keeping its staged
+    /// mode, or its deletion for `None`.
+    pub fn stage_contents(&self, path: &str, contents: Option<&str>) -> Result<()> {
+        let mut index = self.file_service.lock_index()?;
+        let Some(contents) = contents else {
+            index.remove(path);
+            return self.file_service.write_index(&index);
+        };
+        let head = self.file_service.read_head_commit()?;
+        let modes = WorkTree::tracked_modes(&index, head.as_ref());
+        let blob = Blob::from_data(
+            contents.as_bytes().to_vec(),
+            self.file_service.hash_algorithm,
+        );
+        self.file_service.write_blob(&blob)?;
+        let mode = modes.get(path).copied().unwrap_or_default();
+        index.update_with_mode(path, blob.hash(), mode);
+        self.file_service.write_index(&index)
+    }
+
+    /// Unstages whatever is staged for `paths`, going back to HEAD's
+    /// version of each.
+    pub fn unstage(&self, paths: &[String]) -> Result<()> {
+        let mut index = self.file_service.lock_index()?;
+        for path in paths {
+            index.unstage(path);
+        }
+        self.file_service.write_index(&index)
+    }
+
+    /// Throws away the changes in the working tree to the tracked files
+    /// `paths` by checking out their staged versions. Submodules and files
+    /// whose deletion is staged are left alone.
+    pub fn discard_changes(&self, paths: &[String]) -> Result<()> {
+        let worktree = WorkTree::new(&self.file_service);
+        let index = self.file_service.read_index()?;
+        let head = self.file_service.read_head_commit()?;
+        let tracked = WorkTree::tracked_files(&index, head.as_ref());
+        let modes = WorkTree::tracked_modes(&index, head.as_ref());
+        let submodules = worktree.submodules()?;
+        for path in paths {
+            if let Some(hash) = tracked.get(path).filter(|_| !submodules.contains(path)) {
+                let mode = modes.get(path).copied().unwrap_or_default();
+                worktree.checkout_file(&self.file_service, path, hash, mode)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores the tracked files matching `pathspecs`: their staged
+    /// versions in the working tree, or with `staged`, HEAD's versions in
+    /// the index.
+    pub fn restore(&self, pathspecs: &[String], staged: bool) -> Result<()> {
+        let index = self.file_service.read_index()?;
+        let head = self.file_service.read_head_commit()?;
+        let mut known: BTreeSet<String> = WorkTree::tracked_files(&index, head.as_ref())
+            .into_keys()
+            .collect();
+        // a deletion can only be unstaged
+        if staged {
+            known.extend(head.iter().flat_map(|head| head.files().keys().cloned()));
+        }
+
+        let pathspec = Pathspec::new(pathspecs)?;
+        for pattern in pathspec.includes() {
+            if !known.iter().any(|path| pattern.matches(path)) {
+                return Err(Error::Usage(format!(
+                    "pathspec '{}' did not match any files",
+                    pattern.as_str()
+                )));
+            }
+        }
+        let paths: Vec<String> = known
+            .into_iter()
+            .filter(|path| pathspec.matches(path))
+            .collect();
+        if staged {
+            self.unstage(&paths)
+        } else {
+            self.discard_changes(&paths)
+        }
+    }
+
+    /// Records the staged changes as a new commit on the current branch,
+    /// running the commit hooks unless `no_verify` is set. With `sign`, or
+    /// `commit.gpgSign`, the commit is signed with `user.signingKey`. With
+    /// `commit.conventional` set, a message that is not a conventional
+    /// commit is refused, see [`conventional`].
+    pub fn commit(&self, msg: &str, no_verify: bool, sign: bool) -> Result<Commit> {
+        self.record(Some(msg), false, &[], no_verify, sign, false)
+    }
+
+    /// Replaces the commit at HEAD with one that has the staged changes on
+    /// top of its files and `msg`, or else its message, keeping its parents
+    /// and author, the way [`Repository::commit`] records a new one. The
+    /// replaced commit is recorded in the reflogs of HEAD and the branch,
+    /// see [`reflog`].
+    pub fn amend(&self, msg: Option<&str>, no_verify: bool, sign: bool) -> Result<Commit> {
+        self.record(msg, false, &[], no_verify, sign, true)
+    }
+
+    /// Like [`Repository::commit`], or [`Repository::amend`] with `amend`,
+    /// but has the message written in the editor, see [`editor`]. It starts
+    /// out as the amended commit's message, a conflicted merge's or the
+    /// `commit.template` file, with `trailers` added, over a commented-out
+    /// summary of the changes; comment lines are dropped afterwards. An
+    /// empty message, or an unedited template, aborts the commit.
+    pub fn commit_edited(
+        &self,
+        amend: bool,
+        trailers: &[(String, String)],
+        no_verify: bool,
+        sign: bool,
+    ) -> Result<Commit> {
+        self.record(None, true, trailers, no_verify, sign, amend)
+    }
+
+    fn record(
+        &self,
+        msg: Option<&str>,
+        edit: bool,
+        trailers: &[(String, String)],
+        no_verify: bool,
+        sign: bool,
+        amend: bool,
+    ) -> Result<Commit> {
+        let refs = Refs::new(&self.file_service);
+        let config = self.file_service.config()?;
+        let signing_key = if sign || config.get_bool("commit.gpgSign")?.unwrap_or(false) {
+            Some(SigningKey::from_config(&self.file_service)?)
+        } else {
+            None
+        };
+        let hooks = Hooks::new(&self.file_service);
+        let parent = self.file_service.read_head_commit()?;
+        let amended = match parent {
+            Some(ref head) if amend => Some(head),
+            None if amend => return Err(Error::Usage("there is no commit to amend".into())),
+            _ => None,
+        };
+
+        let conflicts = self.file_service.read_index()?.conflicts().clone();
+        if !conflicts.is_empty() {
+            return Err(Error::UnmergedPaths(conflicts.into_iter().collect()));
+        }
+        if amended.is_some() {
+            // the commit a stopped step waits for is not there yet to amend
+            let stopped = Operation::ALL
+                .into_iter()
+                .find(|operation| refs.read(operation.head_ref()).is_some());
+            if let Some(operation) = stopped {
+                return Err(Error::Usage(format!(
+                    "cannot amend in the middle of a {}",
+                    operation.name()
+                )));
+            }
+        }
+        if !no_verify {
+            hooks.run("pre-commit", &[])?;
+        }
+        // the pre-commit hook may have staged more changes
+        let index = self.file_service.read_index()?;
+
+        // concluding a conflicted merge records the merged branch as a parent
+        let merge_head = refs.read("MERGE_HEAD");
+        // and a conflicted cherry-pick keeps the author of the picked commit
+        let cherry_pick_head = refs.read("CHERRY_PICK_HEAD");
+
+        // the message hooks receive the message in a file they are free to edit
+        let given = msg.is_some();
+        let (merge_msg, template);
+        let msg = match (msg, amended) {
+            (Some(msg), _) => msg,
+            (None, Some(amended)) => amended.message(),
+            (None, None) if merge_head.is_some() => {
+                let path = self.file_service.blip_dir.join("MERGE_MSG");
+                merge_msg = fs::read_to_string(path).unwrap_or_default();
+                &merge_msg
+            }
+            (None, None) if edit => {
+                template = self.template()?;
+                &template
+            }
+            (None, None) => "",
+        };
+        let templated = edit && !given && amended.is_none() && merge_head.is_none();
+        let templated = templated && !msg.trim().is_empty();
+        let msg = trailer::append(msg, trailers);
+        let unedited = editor::strip_comments(&msg);
+        let mut contents = format!("{}\n", msg.trim_end());
+        if edit {
+            // a blank line to write the message on, else one after it
+            if !msg.trim().is_empty() {
+                contents.push('\n');
+            }
+            contents.push_str(&self.status_comment(&index, parent.as_ref())?);
+        }
+        let message_file = self.file_service.blip_dir.join("COMMIT_EDITMSG");
+        fs::write(&message_file, contents)?;
+        let message_path = message_file.to_string_lossy();
+        let mut hook_args = vec![message_path.as_ref()];
+        match amended {
+            Some(amended) => hook_args.extend(["commit", amended.hash().unwrap_or_default()]),
+            None if merge_head.is_some() => hook_args.push("merge"),
+            None if given => hook_args.push("message"),
+            None if templated => hook_args.push("template"),
+            None => {}
+        }
+        hooks.run("prepare-commit-msg", &hook_args)?;
+        if edit {
+            editor::edit(&self.file_service, &message_file)?;
+            let mut edited = editor::strip_comments(&fs::read_to_string(&message_file)?);
+            if templated && edited == unedited {
+                return Err(Error::Usage(
+                    "aborting commit; you did not edit the message".into(),
+                ));
+            }
+            // trailers alone are no message either
+            if trailer::append("", trailers).trim() == edited {
+                edited.clear();
+            }
+            fs::write(&message_file, format!("{edited}\n"))?;
+        }
+        if !no_verify {
+            hooks.run("commit-msg", &[&message_path])?;
+        }
+        let msg = fs::read_to_string(&message_file)?;
+        if msg.trim().is_empty() {
+            return Err(Error::Usage(
+                "aborting commit due to empty commit message".into(),
+            ));
+        }
+        // a merge keeps the message it was given
+        if merge_head.is_none() && config.get_bool("commit.conventional")?.unwrap_or(false) {
+            let types = config.get("commit.conventionalTypes");
+            let types: Vec<&str> = match types {
+                Some(ref types) => types
+                    .split([',', ' '])
+                    .filter(|kind| !kind.is_empty())
+                    .collect(),
+                None => conventional::TYPES.to_vec(),
+            };
+            let problems = conventional::check(&msg, &types);
+            if !problems.is_empty() {
+                return Err(Error::NotConventional(problems));
+            }
+        }
+
+        // from here on nobody else may change what is committed
+        let mut index = self.file_service.lock_index()?;
+        let mut commit = match amended {
+            Some(amended) => {
+                let mut commit = Commit::new(None, &msg);
+                for parent in amended.parents() {
+                    commit.add_parent(parent);
+                }
+                commit.set_files(amended.files().clone());
+                commit.set_modes(amended.modes().clone());
+                commit
+            }
+            None => Commit::new(parent.as_ref(), &msg),
+        };
+        commit.add_from_index(&index);
+        let picked = match cherry_pick_head {
+            Some(ref hash) => Some(self.file_service.read_commit(hash)?),
+            None => None,
+        };
+        let author = amended
+            .or(picked.as_ref())
+            .and_then(|commit| commit.author());
+        match author {
+            Some(author) => commit.set_author(author.clone()),
+            None => commit.set_author(self.file_service.signature("AUTHOR")?),
+        }
+        commit.set_committer(self.file_service.signature("COMMITTER")?);
+        if let Some(ref merge_head) = merge_head {
+            commit.add_parent(merge_head);
+        }
+        if let Some(key) = signing_key {
+            commit.set_signing_key(key);
+        }
+
+        let old = parent.as_ref().and_then(Commit::hash);
+        self.file_service.write_commit(&mut commit, old)?;
+        index.clear()?;
+
+        let hash = commit.hash().unwrap_or_default();
+        match amended {
+            Some(amended) => {
+                let old = amended.hash();
+                let subject = commit.message().lines().next().unwrap_or_default();
+                let message = format!("commit (amend): {subject}");
+                reflog::append(&self.file_service, "HEAD", old, hash, &message)?;
+                if let Some(branch) = refs.current_branch(&self.file_service)? {
+                    let name = Refs::branch_ref(&branch);
+                    reflog::append(&self.file_service, &name, old, hash, &message)?;
+                }
+                sequencer::amended(&self.file_service, old.unwrap_or_default(), hash)?;
+            }
+            None => sequencer::committed(&self.file_service, hash)?,
+        }
+
+        // too late to abort anything, so a failing post-commit hook is ignored
+        let _ = hooks.run("post-commit", &[]);
+        Ok(commit)
+    }
+
+    /// The contents of the `commit.template` file, if one is set, for a new
+    /// commit's message to start from.
+    fn template(&self) -> Result<String> {
+        let Some(path) = self.file_service.config()?.get("commit.template") else {
+            return Ok(String::new());
+        };
+        let path = self.file_service.root_dir.join(sign::expand_home(&path));
+        fs::read_to_string(&path).context("read", &path)
+    }
+
+    /// The files among `staged`, the changes between `head` and `index`,
+    /// that were renamed or copied, as `diff.renames` has them found.
+    pub fn staged_renames(
+        &self,
+        index: &Index,
+        head: Option<&Commit>,
+        staged: &[(Change, String)],
+    ) -> Result<Vec<Rename>> {
+        let detection = RenameDetection::from_config(self.file_service.config()?)?;
+        let worktree = WorkTree::new(&self.file_service);
+        let old = match head {
+            Some(head) => Side::Commit(head.files().clone(), head.modes().clone()),
+            None => Side::Commit(BTreeMap::new(), BTreeMap::new()),
+        };
+        let new = Side::Commit(
+            WorkTree::tracked_files(index, head),
+            WorkTree::tracked_modes(index, head),
+        );
+        let paths = staged.iter().map(|(_, path)| path.clone()).collect();
+        self.renames(&old, &new, &worktree, &paths, detection)
+    }
+
+    /// What a commit of `index` on top of `head` would record, and what it
+    /// would leave out, as comment lines to go under a message being
+    /// edited.
+    fn status_comment(&self, index: &Index, head: Option<&Commit>) -> Result<String> {
+        let status = WorkTree::new(&self.file_service).status(index, head)?;
+        let mut comment = String::from(
+            "# Please enter the commit message for your changes. Lines starting\n\
+             # with '#' will be ignored, and an empty message aborts the commit.\n#\n",
+        );
+        match Refs::new(&self.file_service).current_branch(&self.file_service)? {
+            Some(branch) => comment.push_str(&format!("# On branch {branch}\n")),
+            None => comment.push_str("# HEAD detached\n"),
+        }
+        let renames = self.staged_renames(index, head, &status.staged)?;
+        for (header, changes, renames) in [
+            ("Changes to be committed:", &status.staged, &renames[..]),
+            ("Changes not staged for commit:", &status.unstaged, &[]),
+        ] {
+            if !changes.is_empty() {
+                comment.push_str(&format!("#\n# {header}\n"));
+            }
+            for line in Status::describe(changes, renames) {
+                comment.push_str(&format!("#\t{line}\n"));
+            }
+        }
+        if !status.untracked.is_empty() {
+            comment.push_str("#\n# Untracked files:\n");
+        }
+        for path in &status.untracked {
+            comment.push_str(&format!("#\t{path}\n"));
+        }
+        Ok(comment)
+    }
+
+    /// Walks the history from HEAD, newest commit first.
+    pub fn log(&self) -> Result<CommitWalk<'_>> {
+        CommitWalk::from_head(&self.file_service)
+    }
+
+    /// Goes on with `walk`, from [`Repository::log`] or elsewhere, leaving
+    /// out commits that change no file matching `pathspecs`, see
+    /// [`Repository::touches`], and those `filter` does not match. The walk
+    /// ends at the first commit older than the filter's `since`.
+    ///
+    /// Unless the filter asks for the full history, only one parent of a
+    /// merge that took the files matching `pathspecs` from it is followed,
+    /// see [`CommitWalk::simplify`].
+    pub fn log_matching<'a>(
+        &'a self,
+        mut walk: CommitWalk<'a>,
+        pathspecs: &[String],
+        filter: &'a CommitFilter,
+    ) -> Result<impl Iterator<Item = Result<Commit>> + 'a> {
+        let mut pathspec = (!pathspecs.is_empty())
+            .then(|| Pathspec::new(pathspecs))
+            .transpose()?;
+        let follow = match (filter.follow, pathspecs) {
+            (false, _) => None,
+            (true, [path]) => {
+                let detection = RenameDetection::from_config(self.file_service.config()?)?;
+                Some((path.clone(), detection.unwrap_or(RenameDetection::DEFAULT)))
+            }
+            (true, _) => {
+                return Err(Error::Usage("--follow takes exactly one file".into()));
+            }
+        };
+        if filter.first_parent {
+            walk.first_parent();
+        }
+        // the files followed change on the way, so no parent can be skipped
+        if let Some(pathspec) = pathspec.clone().filter(|_| !filter.full_history) {
+            if follow.is_none() {
+                walk.simplify(pathspec);
+            }
+        }
+        let walk =
+            walk.take_while(|commit| !commit.as_ref().is_ok_and(|commit| filter.past(commit)));
+        let mut follow = follow;
+        Ok(walk.filter_map(move |commit| {
+            if commit.as_ref().is_ok_and(|commit| !filter.matches(commit)) {
+                return None;
+            }
+            let Some(spec) = &pathspec else {
+                return Some(commit);
+            };
+            let commit = match commit {
+                Ok(commit) => commit,
+                Err(error) => return Some(Err(error)),
+            };
+            match self.touches(&commit, spec, filter.first_parent) {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(error) => return Some(Err(error)),
+            }
+            if let Some((path, detection)) = &mut follow {
+                match self.renamed_from(&commit, path, *detection) {
+                    Ok(Some(from)) => match Pathspec::new(std::slice::from_ref(&from)) {
+                        Ok(spec) => {
+                            pathspec = Some(spec);
+                            *path = from;
+                        }
+                        Err(error) => return Some(Err(error)),
+                    },
+                    Ok(None) => {}
+                    Err(error) => return Some(Err(error)),
+                }
+            }
+            Some(Ok(commit))
+        }))
+    }
+
+    /// The file `commit` renamed or copied to `path`, if it added it as
+    /// one.
+    fn renamed_from(
+        &self,
+        commit: &Commit,
+        path: &str,
+        detection: RenameDetection,
+    ) -> Result<Option<String>> {
+        let parent = self.parent_side(commit)?;
+        if !commit.files().contains_key(path) || parent.files().contains_key(path) {
+            return Ok(None);
+        }
+        let side = Side::Commit(commit.files().clone(), commit.modes().clone());
+        let worktree = WorkTree::new(&self.file_service);
+        let paths = changed_paths(&parent, &side, &worktree);
+        let renames = self.renames(&parent, &side, &worktree, &paths, Some(detection))?;
+        Ok(renames
+            .into_iter()
+            .find(|rename| rename.to == path)
+            .map(|rename| rename.from))
+    }
+
+    /// Whether `commit` changes a file matching `pathspec`. A merge only
+    /// does if it differs from every parent there, as one that took those
+    /// files from a parent adds nothing the parent's history does not show,
+    /// or with `first_parent` from the first one.
+    fn touches(&self, commit: &Commit, pathspec: &Pathspec, first_parent: bool) -> Result<bool> {
+        let changes = |parent: &BTreeMap<String, String>| {
+            diff::diff_files(parent, commit.files())
+                .iter()
+                .any(|(_, path)| pathspec.matches(path))
+        };
+        if commit.parents().is_empty() {
+            return Ok(changes(&BTreeMap::new()));
+        }
+        let parents = match commit.parents() {
+            [first, ..] if first_parent => std::slice::from_ref(first),
+            parents => parents,
+        };
+        for parent in parents {
+            if !changes(self.file_service.read_commit(parent)?.files()) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Renders the patch between up to two commits and the index or the
+    /// working tree, the same comparisons `blip diff` makes, limited to the
+    /// files matching `pathspecs`, rendered as `options` says.
+    pub fn diff(
+        &self,
+        commits: &[String],
+        cached: bool,
+        pathspecs: &[String],
+        options: DiffOptions,
+    ) -> Result<String> {
+        let pathspec = Pathspec::new(pathspecs)?;
+        let worktree = WorkTree::new(&self.file_service);
+        let (old, new) = self.diff_sides(commits, cached, &pathspec, &worktree)?;
+        self.patch(&old, &new, &worktree, &pathspec, options)
+    }
+
+    /// Like [`Repository::diff`], but only says how each file changed: its
+    /// path with `A`, `M` or `D`, or for a rename or copy, the similarity
+    /// after `R` or `C` and both paths, see [`Rename::status`].
+    pub fn diff_names(
+        &self,
+        commits: &[String],
+        cached: bool,
+        pathspecs: &[String],
+        renames: Option<RenameDetection>,
+    ) -> Result<Vec<(String, String)>> {
+        let pathspec = Pathspec::new(pathspecs)?;
+        let worktree = WorkTree::new(&self.file_service);
+        let (old, new) = self.diff_sides(commits, cached, &pathspec, &worktree)?;
+        let mut paths = changed_paths(&old, &new, &worktree);
+        paths.retain(|path| pathspec.matches(path));
+        let renames = self.renames(&old, &new, &worktree, &paths, renames)?;
+
+        let mut names = Vec::new();
+        for path in paths {
+            if let Some(rename) = renames.iter().find(|rename| rename.to == path) {
+                let name = format!("{} -> {}", rename.from, rename.to);
+                names.push((rename.status(), name));
+                continue;
+            }
+            if renames
+                .iter()
+                .any(|rename| !rename.copy && rename.from == path)
+            {
+                continue;
+            }
+            let status = match (
+                old.files().contains_key(&path),
+                new.files().contains_key(&path),
+            ) {
+                (false, _) => "A",
+                (_, false) => "D",
+                _ => "M",
+            };
+            names.push((status.to_string(), path));
+        }
+        Ok(names)
+    }
+
+    /// The two sides `blip diff` compares for `commits` and `cached`.
+    fn diff_sides(
+        &self,
+        commits: &[String],
+        cached: bool,
+        pathspec: &Pathspec,
+        worktree: &WorkTree,
+    ) -> Result<(Side, Side)> {
+        let index = self.file_service.read_index()?;
+        let head = self.file_service.read_head_commit()?;
+        let mut tracked = WorkTree::tracked_files(&index, head.as_ref());
+        tracked.retain(|path, _| pathspec.matches(path));
+        let modes = WorkTree::tracked_modes(&index, head.as_ref());
+
+        let commit_files = |name: &str| -> Result<Side> {
+            let hash = self.file_service.resolve(name)?;
+            let commit = self.file_service.read_commit(&hash)?;
+            Ok(Side::Commit(commit.files().clone(), commit.modes().clone()))
+        };
+
+        let (old, new) = match (commits, cached) {
+            ([], false) => (
+                Side::Commit(tracked.clone(), modes),
+                Side::WorkTree(worktree.snapshot(&tracked)?),
+            ),
+            ([], true) => {
+                let committed = match head {
+                    Some(ref head) => Side::Commit(head.files().clone(), head.modes().clone()),
+                    None => Side::Commit(BTreeMap::new(), BTreeMap::new()),
+                };
+                (committed, Side::Commit(tracked, modes))
+            }
+            ([commit], false) => (
+                commit_files(commit)?,
+                Side::WorkTree(worktree.snapshot(&tracked)?),
+            ),
+            ([commit], true) => (commit_files(commit)?, Side::Commit(tracked, modes)),
+            ([a, b], _) => (commit_files(a)?, commit_files(b)?),
+            _ => return Err(Error::Usage("diff takes at most two commits".into())),
+        };
+        Ok((old, new))
+    }
+
+    /// Renders the patch a commit makes on top of its first parent, or
+    /// that adds all of its files if it has none, with the files it
+    /// renames shown as such when `renames` says how to find them.
+    pub fn commit_patch(
+        &self,
+        commit: &Commit,
+        renames: Option<RenameDetection>,
+    ) -> Result<String> {
+        let parent = self.parent_side(commit)?;
+        let worktree = WorkTree::new(&self.file_service);
+        self.patch(
+            &parent,
+            &Side::Commit(commit.files().clone(), commit.modes().clone()),
+            &worktree,
+            &Pathspec::default(),
+            DiffOptions {
+                renames,
+                ..DiffOptions::default()
+            },
+        )
+    }
+
+    /// Counts the lines added and deleted in each file a commit changes,
+    /// compared the way [`Repository::commit_patch`] compares them. A
+    /// renamed file goes by `<from> => <to>`.
+    pub fn commit_stat(
+        &self,
+        commit: &Commit,
+        renames: Option<RenameDetection>,
+    ) -> Result<Vec<FileStat>> {
+        let parent = self.parent_side(commit)?;
+        let side = Side::Commit(commit.files().clone(), commit.modes().clone());
+        let worktree = WorkTree::new(&self.file_service);
+        let old_submodules = parent.submodules(&self.file_service, &worktree)?;
+        let new_submodules = side.submodules(&self.file_service, &worktree)?;
+        let attributes = worktree.attributes()?;
+        let paths = changed_paths(&parent, &side, &worktree);
+        let renames = self.renames(&parent, &side, &worktree, &paths, renames)?;
+
+        let mut stats = Vec::new();
+        for path in paths {
+            let rename = renames.iter().find(|rename| rename.to == path);
+            if rename.is_none()
+                && renames
+                    .iter()
+                    .any(|rename| !rename.copy && rename.from == path)
+            {
+                continue;
+            }
+            let from = rename.map_or(&path, |rename| &rename.from);
+            let before = parent.read_data(&self.file_service, &worktree, &old_submodules, from)?;
+            let after = side.read_data(&self.file_service, &worktree, &new_submodules, &path)?;
+            let (before, after) = (before.unwrap_or_default(), after.unwrap_or_default());
+            let binary = attributes
+                .is_binary_diff(&path, [&before, &after])
+                .then_some((before.len(), after.len()));
+            let (added, deleted) = match binary {
+                Some(_) => (0, 0),
+                None => diff::line_counts(
+                    &String::from_utf8_lossy(&before),
+                    &String::from_utf8_lossy(&after),
+                ),
+            };
+            stats.push(FileStat {
+                path: match rename {
+                    Some(rename) => format!("{} => {}", rename.from, rename.to),
+                    None => path,
+                },
+                added,
+                deleted,
+                binary,
+            });
+        }
+        Ok(stats)
+    }
+
+    /// The files of a commit's first parent, none if it has no parent.
+    fn parent_side(&self, commit: &Commit) -> Result<Side> {
+        Ok(match commit.parents().first() {
+            Some(parent) => {
+                let parent = self.file_service.read_commit(parent)?;
+                Side::Commit(parent.files().clone(), parent.modes().clone())
+            }
+            None => Side::Commit(BTreeMap::new(), BTreeMap::new()),
+        })
+    }
+
+    /// Finds the lines matching `pattern` in the files of `commit`, read
+    /// from the object store, or in the working tree's copies of the
+    /// tracked files when there is none, in path order.
+    ///
+    /// Files are searched on `grep.threads` threads, or one per CPU.
+    /// Binary files, those with a NUL byte, symlinks and submodules are
+    /// left out.
+    pub fn grep(&self, pattern: &Regex, commit: Option<&str>) -> Result<Vec<GrepMatch>> {
+        let (files, modes) = match commit {
+            Some(name) => {
+                let commit = self
+                    .file_service
+                    .read_commit(&self.file_service.resolve(name)?)?;
+                (commit.files().clone(), commit.modes().clone())
+            }
+            None => {
+                let index = self.file_service.read_index()?;
+                let head = self.file_service.read_head_commit()?;
+                (
+                    WorkTree::tracked_files(&index, head.as_ref()),
+                    WorkTree::tracked_modes(&index, head.as_ref()),
+                )
+            }
+        };
+        let submodules = submodule::paths(&self.file_service, &files)?;
+        let searched: Vec<(&String, &String)> = files
+            .iter()
+            .filter(|(path, _)| {
+                !submodules.contains(*path) && modes.get(*path) != Some(&FileMode::Symlink)
+            })
+            .collect();
+
+        let jobs = self.jobs(None, "grep.threads")?;
+        let found = parallel_map(&searched, jobs, |(path, hash)| {
+            let data = match commit {
+                Some(_) => self.file_service.read_blob(hash)?,
+                None => {
+                    let full_path = self.file_service.root_dir.join(path);
+                    match fs::symlink_metadata(&full_path) {
+                        Ok(metadata) if metadata.is_file() => {
+                            fs::read(&full_path).context("read", &full_path)?
+                        }
+                        // deleted or replaced since it was staged
+                        _ => return Ok(Vec::new()),
+                    }
+                }
+            };
+            if data.contains(&0) {
+                return Ok(Vec::new());
+            }
+            Ok(String::from_utf8_lossy(&data)
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| pattern.is_match(line))
+                .map(|(i, line)| GrepMatch {
+                    path: path.to_string(),
+                    number: i + 1,
+                    line: line.to_string(),
+                })
+                .collect())
+        })?;
+        Ok(found.into_iter().flatten().collect())
+    }
+
+    fn patch(
+        &self,
+        old: &Side,
+        new: &Side,
+        worktree: &WorkTree,
+        pathspec: &Pathspec,
+        options: DiffOptions,
+    ) -> Result<String> {
+        let old_submodules = old.submodules(&self.file_service, worktree)?;
+        let new_submodules = new.submodules(&self.file_service, worktree)?;
+        let attributes = worktree.attributes()?;
+        let mut paths = changed_paths(old, new, worktree);
+        paths.retain(|path| pathspec.matches(path));
+        let renames = self.renames(old, new, worktree, &paths, options.renames)?;
+
+        let mut patch = String::new();
+        for path in paths {
+            if let Some(rename) = renames.iter().find(|rename| rename.to == path) {
+                let from = &rename.from;
+                let before = old.read_data(&self.file_service, worktree, &old_submodules, from)?;
+                let after = new.read_data(&self.file_service, worktree, &new_submodules, &path)?;
+                let (before, after) = (before.unwrap_or_default(), after.unwrap_or_default());
+                patch.push_str(&diff::rename_patch(
+                    rename,
+                    &before,
+                    &after,
+                    (
+                        old.mode(worktree, from).unwrap_or_default(),
+                        new.mode(worktree, &path).unwrap_or_default(),
+                    ),
+                    attributes.is_binary_diff(&path, [&before, &after]),
+                    options,
+                ));
+                continue;
+            }
+            if renames
+                .iter()
+                .any(|rename| !rename.copy && rename.from == path)
+            {
+                continue;
+            }
+            let (old_mode, new_mode) = (old.mode(worktree, &path), new.mode(worktree, &path));
+            let modes = (old_mode.unwrap_or_default(), new_mode.unwrap_or_default());
+            let before = old.read_data(&self.file_service, worktree, &old_submodules, &path)?;
+            let after = new.read_data(&self.file_service, worktree, &new_submodules, &path)?;
+            let contents = [before.as_deref(), after.as_deref()].map(Option::unwrap_or_default);
+            if attributes.is_binary_diff(&path, contents) {
+                patch.push_str(&diff::binary_file_patch(
+                    &path,
+                    before.as_deref(),
+                    after.as_deref(),
+                    modes,
+                    options.binary,
+                ));
+                continue;
+            }
+            let text = |data: Option<Vec<u8>>| {
+                data.map(|data| String::from_utf8_lossy(&data).into_owned())
+            };
+            let git_mode =
+                |mode: FileMode, submodules: &BTreeSet<String>| match submodules.contains(&path) {
+                    true => diff::GITLINK_MODE,
+                    false => mode.git_mode(),
+                };
+            patch.push_str(&diff::file_patch(
+                &path,
+                text(before).as_deref(),
+                text(after).as_deref(),
+                (
+                    git_mode(modes.0, &old_submodules),
+                    git_mode(modes.1, &new_submodules),
+                ),
+                options.whitespace,
+            ));
+        }
+        Ok(patch)
+    }
+
+    /// The files among the changed `paths` that turn up under another
+    /// path between two sides, none without `detection`.
+    fn renames(
+        &self,
+        old: &Side,
+        new: &Side,
+        worktree: &WorkTree,
+        paths: &BTreeSet<String>,
+        detection: Option<RenameDetection>,
+    ) -> Result<Vec<Rename>> {
+        let Some(detection) = detection else {
+            return Ok(Vec::new());
+        };
+        let changes: Vec<(Change, String)> = paths
+            .iter()
+            .map(|path| {
+                let change = match (
+                    old.files().contains_key(path),
+                    new.files().contains_key(path),
+                ) {
+                    (false, _) => Change::Added,
+                    (_, false) => Change::Deleted,
+                    _ => Change::Modified,
+                };
+                (change, path.clone())
+            })
+            .collect();
+        let old_submodules = old.submodules(&self.file_service, worktree)?;
+        let new_submodules = new.submodules(&self.file_service, worktree)?;
+        diff::find_renames(
+            &changes,
+            detection,
+            |path| old.read(&self.file_service, worktree, &old_submodules, path),
+            |path| new.read(&self.file_service, worktree, &new_submodules, path),
+        )
+    }
+}
+
+/// Runs `f` on every item on up to `jobs` threads, returning the results in
+/// the order of `items`, or the first error after which no new items are
+/// started.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    f: impl Fn(&T) -> Result<R> + Sync,
+) -> Result<Vec<R>> {
+    let jobs = jobs.min(items.len());
+    if jobs <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let worker = || {
+        let mut done = Vec::new();
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(item) = items.get(i) else {
+                return Ok(done);
+            };
+            match f(item) {
+                Ok(result) => done.push((i, result)),
+                Err(error) => {
+                    next.store(items.len(), Ordering::Relaxed);
+                    return Err(error);
+                }
+            }
+        }
+    };
+    let batches = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs).map(|_| scope.spawn(worker)).collect();
+        workers
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| panic::resume_unwind(panic))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut results: Vec<(usize, R)> = batches.into_iter().flatten().collect();
+    results.sort_by_key(|(i, _)| *i);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
@@ -0,0 +1,197 @@
+use crate::types::{Error, FileService, ObjectKind, Result};
+
+/// Shortest hash prefix accepted as a revision.
+pub const MIN_PREFIX: usize = 4;
+
+/// Length hashes are abbreviated to unless `core.abbrev` says otherwise.
+const DEFAULT_ABBREV: usize = 7;
+
+/// Shortens hashes for display to the fewest characters, but at least
+/// `core.abbrev`, that no other object in the repository shares.
+#[derive(Debug)]
+pub struct Abbrev {
+    hashes: Vec<String>,
+    min_len: usize,
+}
+
+/// A move from one commit to another at the end of a revision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// `^<n>`: the nth parent, or the commit itself for 0.
+    Parent(usize),
+    /// `~<n>`: n generations back along first parents.
+    Ancestor(usize),
+    /// `^{}` peels tags off, and `^{commit}` also insists on a commit.
+    Peel(Option<ObjectKind>),
+}
+
+/// Resolves a revision to a commit hash. A revision is a name, which is
+/// `HEAD`, a branch, a tag, a remote-tracking branch such as `origin/main`,
+/// a full ref name, a full hash or an unambiguous prefix of one at least
+/// [`MIN_PREFIX`] long, followed by any number of `^<n>` and `~<n>`
+/// steps where `n` defaults to 1. `HEAD~2` is HEAD's grandparent and
+/// `main^2` the second parent of the merge at `main`. Annotated tags are
+/// peeled down to the object they point at, which `^{}` spells out and
+/// `^{commit}` requires to be a commit.
+pub fn resolve(file_service: &FileService, revision: &str) -> Result<String> {
+    let unknown = || Error::UnknownRevision(revision.to_string());
+    let (name, steps) = parse(revision).ok_or_else(unknown)?;
+
+    let mut hash = file_service.peel(&file_service.resolve_name(name)?)?;
+    for step in steps {
+        match step {
+            // the name was peeled already, leaving only the kind to check
+            Step::Peel(None) => {}
+            Step::Peel(kind) => {
+                if file_service.read_raw(&hash)?.0 != kind {
+                    return Err(unknown());
+                }
+            }
+            Step::Parent(n) => {
+                let commit = file_service.read_commit_headers(&hash)?;
+                if n > 0 {
+                    hash = commit.parents().get(n - 1).ok_or_else(unknown)?.clone();
+                }
+            }
+            Step::Ancestor(n) => {
+                let mut commit = file_service.read_commit_headers(&hash)?;
+                for _ in 0..n {
+                    hash = commit.parent().ok_or_else(unknown)?.to_string();
+                    commit = file_service.read_commit_headers(&hash)?;
+                }
+            }
+        }
+    }
+    Ok(hash)
+}
+
+/// Resolves a revision to any object rather than a commit: a bare name is
+/// not peeled, so it can name a tag object, and `<revision>:<path>` names
+/// the blob or tree at `path` in a commit.
+pub fn resolve_object(file_service: &FileService, revision: &str) -> Result<String> {
+    if let Some((revision, path)) = revision.split_once(':') {
+        let commit = file_service.read_commit_headers(&resolve(file_service, revision)?)?;
+        let Some(mut hash) = commit.tree().map(str::to_string) else {
+            return Err(Error::UnknownRevision(format!("{revision}:{path}")));
+        };
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let tree = file_service.read_tree(&hash)?;
+            let entry = tree.entries().get(name).ok_or_else(|| {
+                Error::Usage(format!("path '{path}' does not exist in '{revision}'"))
+            })?;
+            hash = entry.hash.clone();
+        }
+        return Ok(hash);
+    }
+
+    match parse(revision) {
+        Some((name, steps)) if steps.is_empty() => file_service.resolve_name(name),
+        _ => resolve(file_service, revision),
+    }
+}
+
+/// Resolves a revision to a tree: a tree itself, or the tree of the commit
+/// it names.
+pub fn resolve_tree(file_service: &FileService, revision: &str) -> Result<String> {
+    let hash = file_service.peel(&resolve_object(file_service, revision)?)?;
+    match file_service.read_raw(&hash)?.0 {
+        Some(ObjectKind::Tree) => Ok(hash),
+        Some(ObjectKind::Commit) => {
+            let commit = file_service.read_commit_headers(&hash)?;
+            commit
+                .tree()
+                .map(str::to_string)
+                .ok_or_else(|| Error::UnknownRevision(revision.to_string()))
+        }
+        _ => Err(Error::InvalidObject {
+            hash,
+            kind: ObjectKind::Tree,
+        }),
+    }
+}
+
+/// Splits a revision into its name and steps, or returns `None` if it is
+/// malformed.
+fn parse(revision: &str) -> Option<(&str, Vec<Step>)> {
+    let end = revision.find(['^', '~']).unwrap_or(revision.len());
+    let (name, mut rest) = revision.split_at(end);
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut steps = Vec::new();
+    while let Some(op) = rest.chars().next() {
+        rest = &rest[1..];
+        if op == '^' && rest.starts_with('{') {
+            let (kind, after) = rest[1..].split_once('}')?;
+            steps.push(Step::Peel(match kind {
+                "" => None,
+                "commit" => Some(ObjectKind::Commit),
+                _ => return None,
+            }));
+            rest = after;
+            continue;
+        }
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let n = match &rest[..digits] {
+            "" => 1,
+            n => n.parse().ok()?,
+        };
+        rest = &rest[digits..];
+        steps.push(match op {
+            '^' => Step::Parent(n),
+            '~' => Step::Ancestor(n),
+            _ => return None,
+        });
+    }
+    Some((name, steps))
+}
+
+impl Abbrev {
+    /// Lists every object in the repository, so that any number of hashes
+    /// can then be shortened without going back to the object store.
+    pub fn new(file_service: &FileService) -> Result<Abbrev> {
+        let max_len = file_service.hash_algorithm.hex_len();
+        let min_len = match file_service.config()?.get("core.abbrev") {
+            Some(value) => match value.trim().parse() {
+                Ok(len) if (MIN_PREFIX..=max_len).contains(&len) => len,
+                _ => {
+                    return Err(Error::InvalidConfig(format!(
+                        "bad abbrev length '{value}' for 'core.abbrev'"
+                    )))
+                }
+            },
+            None => DEFAULT_ABBREV,
+        };
+
+        let mut hashes = file_service.loose_objects()?;
+        for pack in file_service.packs()? {
+            hashes.extend(pack.hashes().cloned());
+        }
+        hashes.sort();
+        hashes.dedup();
+        Ok(Abbrev { hashes, min_len })
+    }
+
+    /// The shortest unique prefix of `hash`, which need not be stored.
+    pub fn shorten<'a>(&self, hash: &'a str) -> &'a str {
+        // only the neighbours in sorted order can share a longer prefix
+        let at = self.hashes.partition_point(|other| other.as_str() < hash);
+        let neighbours = [at.checked_sub(1), Some(at), Some(at + 1)];
+        let shared = neighbours
+            .into_iter()
+            .flatten()
+            .filter_map(|i| self.hashes.get(i))
+            .filter(|other| other.as_str() != hash)
+            .map(|other| common_prefix(other, hash))
+            .max()
+            .unwrap_or(0);
+        &hash[..(shared + 1).max(self.min_len).min(hash.len())]
+    }
+}
+
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count()
+}
@@ -0,0 +1,681 @@
+//! Operations that replay commits a step at a time and may stop halfway:
+//! rebase, cherry-pick, revert, and a merge that is left with conflicts.
+//!
+//! An operation that stops keeps its state in `.blip/sequencer/` until it
+//! is continued, skipped past or aborted, one file per field:
+//!
+//! - `operation`: which of them it is, see [`Operation`];
+//! - `head-name`: the branch it works on, empty for a detached HEAD;
+//! - `orig-head`: where HEAD was before it, also kept in `ORIG_HEAD`;
+//! - `stopped`: the step whose conflicts are left to resolve, if any;
+//! - `todo`: the steps left after it, an `<action> <commit>` per line;
+//! - `done`: the commits it made so far.
+//!
+//! The commit a stopped step was replaying is also in the pseudo-ref of
+//! the operation, see [`Operation::head_ref`], for revisions and for
+//! [`Repository::commit`](crate::Repository::commit) to conclude the step.
+//! Only one operation can be in progress at a time.
+
+use std::{collections::BTreeMap, fs};
+
+use crate::{
+    editor,
+    merge::{self, Files},
+    refs::Refs,
+    revision::Abbrev,
+    types::{Commit, Error, FileMode, FileService, Result},
+    worktree::WorkTree,
+};
+
+/// The directory of `.blip` that keeps the state of a stopped operation.
+const STATE_DIR: &str = "sequencer";
+
+/// A multi-step operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Merge,
+    CherryPick,
+    Revert,
+    Rebase,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 4] = [
+        Operation::Merge,
+        Operation::CherryPick,
+        Operation::Revert,
+        Operation::Rebase,
+    ];
+
+    /// The command that carries out the operation.
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Merge => "merge",
+            Operation::CherryPick => "cherry-pick",
+            Operation::Revert => "revert",
+            Operation::Rebase => "rebase",
+        }
+    }
+
+    /// The pseudo-ref holding the commit the operation stopped at.
+    pub fn head_ref(self) -> &'static str {
+        match self {
+            Operation::Merge => "MERGE_HEAD",
+            Operation::CherryPick => "CHERRY_PICK_HEAD",
+            Operation::Revert => "REVERT_HEAD",
+            Operation::Rebase => "REBASE_HEAD",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Operation> {
+        Operation::ALL
+            .into_iter()
+            .find(|operation| operation.name() == name)
+    }
+}
+
+/// What a step does with its commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Pick,
+    /// Commits the opposite of the changes of the commit.
+    Revert,
+    /// Picks the commit and edits its message.
+    Reword,
+    /// Picks the commit and stops for it to be amended.
+    Edit,
+    /// Melds the commit into the one before, editing the two messages
+    /// together.
+    Squash,
+    /// Melds the commit into the one before, keeping that one's message.
+    Fixup,
+    Drop,
+}
+
+impl Action {
+    fn parse(name: &str) -> Option<Action> {
+        Some(match name {
+            "p" | "pick" => Action::Pick,
+            "revert" => Action::Revert,
+            "r" | "reword" => Action::Reword,
+            "e" | "edit" => Action::Edit,
+            "s" | "squash" => Action::Squash,
+            "f" | "fixup" => Action::Fixup,
+            "d" | "drop" => Action::Drop,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Pick => "pick",
+            Action::Revert => "revert",
+            Action::Reword => "reword",
+            Action::Edit => "edit",
+            Action::Squash => "squash",
+            Action::Fixup => "fixup",
+            Action::Drop => "drop",
+        }
+    }
+}
+
+/// One step of an operation: a line of its todo list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub action: Action,
+    pub hash: String,
+}
+
+/// What carrying out the steps of an operation came to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every step was carried out; the commits they made, oldest first.
+    Done(Vec<String>),
+    /// Replaying `hash` conflicted. The conflicts are left in the working
+    /// tree and the index, HEAD is at the commits made so far and the rest
+    /// waits for [`resume`], [`skip`] or [`abort`].
+    Stopped {
+        hash: String,
+        conflicts: Vec<String>,
+    },
+    /// An `edit` step made `hash` and stopped there for it to be amended
+    /// before [`resume`].
+    Editing { hash: String },
+}
+
+/// Reads a todo list, resolving the commit of each step. Comments, blank
+/// lines and `drop` steps are left out.
+pub fn parse_todo(file_service: &FileService, text: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let action = Action::parse(name)
+            .ok_or_else(|| Error::Usage(format!("unknown command '{name}' in the todo list")))?;
+        let Some(commit) = words.next() else {
+            return Err(Error::Usage(format!("missing commit after '{name}'")));
+        };
+        if action != Action::Drop {
+            let hash = file_service.resolve(commit)?;
+            steps.push(Step { action, hash });
+        }
+    }
+    Ok(steps)
+}
+
+/// A stopped operation, as kept in [`STATE_DIR`].
+#[derive(Debug)]
+struct State {
+    operation: Operation,
+    /// The branch the operation works on, `None` for a detached HEAD.
+    branch: Option<String>,
+    /// Where HEAD was before the operation.
+    orig_head: String,
+    /// The step whose conflicts are left to resolve, `None` when stopped
+    /// at an `edit` step or between steps.
+    stopped: Option<Step>,
+    /// The steps left after it.
+    todo: Vec<Step>,
+    /// The commits made so far.
+    done: Vec<String>,
+}
+
+impl State {
+    fn load(file_service: &FileService) -> Result<Option<State>> {
+        let dir = file_service.blip_dir.join(STATE_DIR);
+        if !dir.exists() {
+            return Ok(None);
+        }
+        let read = |name: &str| -> Result<String> {
+            Ok(fs::read_to_string(dir.join(name))?.trim_end().to_string())
+        };
+        let operation = read("operation")?;
+        let operation = Operation::parse(&operation)
+            .ok_or_else(|| Error::Usage(format!("unknown operation '{operation}' in progress")))?;
+        let branch = read("head-name")?;
+        Ok(Some(State {
+            operation,
+            branch: (!branch.is_empty()).then_some(branch),
+            orig_head: read("orig-head")?,
+            stopped: parse_todo(file_service, &read("stopped")?)?.pop(),
+            todo: parse_todo(file_service, &read("todo")?)?,
+            done: read("done")?.lines().map(str::to_string).collect(),
+        }))
+    }
+
+    fn save(&self, file_service: &FileService) -> Result<()> {
+        let dir = file_service.blip_dir.join(STATE_DIR);
+        fs::create_dir_all(&dir)?;
+        let line = |step: &Step| format!("{} {}\n", step.action.name(), step.hash);
+        fs::write(
+            dir.join("operation"),
+            format!("{}\n", self.operation.name()),
+        )?;
+        fs::write(
+            dir.join("head-name"),
+            format!("{}\n", self.branch.as_deref().unwrap_or_default()),
+        )?;
+        fs::write(dir.join("orig-head"), format!("{}\n", self.orig_head))?;
+        fs::write(
+            dir.join("stopped"),
+            self.stopped.as_ref().map(line).unwrap_or_default(),
+        )?;
+        fs::write(
+            dir.join("todo"),
+            self.todo.iter().map(line).collect::<String>(),
+        )?;
+        fs::write(
+            dir.join("done"),
+            self.done
+                .iter()
+                .map(|hash| format!("{hash}\n"))
+                .collect::<String>(),
+        )?;
+        Ok(())
+    }
+
+    /// Drops the state along with the pseudo-ref and message of the step
+    /// the operation stopped at.
+    fn remove(file_service: &FileService) -> Result<()> {
+        clear_stop(file_service)?;
+        let dir = file_service.blip_dir.join(STATE_DIR);
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// The operation in progress, if one is stopped.
+pub fn in_progress(file_service: &FileService) -> Result<Option<Operation>> {
+    Ok(State::load(file_service)?.map(|state| state.operation))
+}
+
+/// Fails if an operation is in progress, as only one can be at a time.
+pub fn check_idle(file_service: &FileService) -> Result<()> {
+    match in_progress(file_service)? {
+        Some(operation) => Err(Error::Usage(format!(
+            "a {0} is already in progress; use 'blip {0} --continue' or 'blip {0} --abort'",
+            operation.name()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Checks that `operation` can start: none is in progress, there is a
+/// commit to start from and no changes in the working tree or the index
+/// that it could clobber. Returns HEAD's commit.
+pub fn prepare(file_service: &FileService, operation: Operation) -> Result<Commit> {
+    check_idle(file_service)?;
+    let Some(head) = file_service.read_head_commit()? else {
+        return Err(Error::Usage(format!(
+            "cannot {} on an empty branch",
+            operation.name()
+        )));
+    };
+    let worktree = WorkTree::new(file_service);
+    let index = file_service.read_index()?;
+    let status = worktree.status(&index, Some(&head))?;
+    if !status.is_clean() {
+        let paths = status.staged.into_iter().chain(status.unstaged);
+        return Err(Error::DirtyWorkTree(paths.map(|(_, path)| path).collect()));
+    }
+    Ok(head)
+}
+
+/// Carries out `todo` on top of `onto` from `head`, as [`prepare`]
+/// returned it, and moves the current branch, or a detached HEAD, to the
+/// result. HEAD's commit is kept in `ORIG_HEAD`.
+///
+/// Commits are made without touching the working tree, which is only
+/// updated once every step is carried out or the operation stops. Steps
+/// whose changes are already there are dropped. A rebase keeps picked
+/// commits that already sit where they would be replayed to as they are,
+/// and detaches HEAD while it is stopped.
+pub fn start(
+    file_service: &FileService,
+    operation: Operation,
+    head: &Commit,
+    todo: Vec<Step>,
+    onto: Commit,
+) -> Result<Outcome> {
+    let refs = Refs::new(file_service);
+    let orig_head = head.hash().unwrap_or_default().to_string();
+    refs.write("ORIG_HEAD", &orig_head)?;
+    let state = State {
+        operation,
+        branch: refs.current_branch(file_service)?,
+        orig_head,
+        stopped: None,
+        todo,
+        done: Vec::new(),
+    };
+    replay(file_service, state, head, onto)
+}
+
+/// Records a merge of `theirs` into HEAD that stopped at conflicts, which
+/// the merge already left in the working tree and the index along with
+/// its message in `MERGE_MSG`. A commit concludes it.
+pub fn stop_merge(file_service: &FileService, theirs: &str) -> Result<()> {
+    let refs = Refs::new(file_service);
+    let orig_head = file_service.read_head_hash()?.unwrap_or_default();
+    refs.write("ORIG_HEAD", &orig_head)?;
+    refs.write(Operation::Merge.head_ref(), theirs)?;
+    State {
+        operation: Operation::Merge,
+        branch: refs.current_branch(file_service)?,
+        orig_head,
+        stopped: None,
+        todo: Vec::new(),
+        done: Vec::new(),
+    }
+    .save(file_service)
+}
+
+/// The state of the stopped `operation`, failing if it is not the one in
+/// progress.
+fn load_stopped(file_service: &FileService, operation: Operation) -> Result<State> {
+    match State::load(file_service)? {
+        Some(state) if state.operation == operation => Ok(state),
+        _ => Err(Error::Usage(format!("no {} in progress", operation.name()))),
+    }
+}
+
+/// Goes on with the stopped `operation`. After a conflict, the resolution is
+/// committed the way the step that conflicted calls for, unless it was
+/// committed already, a resolution that leaves HEAD as it is dropping the
+/// commit. After an `edit` step, staged changes are amended into HEAD.
+/// The steps left are then carried out.
+pub fn resume(file_service: &FileService, operation: Operation) -> Result<Outcome> {
+    let mut state = load_stopped(file_service, operation)?;
+    if operation == Operation::Merge {
+        return Err(Error::Usage("a merge is concluded by committing it".into()));
+    }
+    let worktree = WorkTree::new(file_service);
+    let mut index = file_service.lock_index()?;
+    if !index.conflicts().is_empty() {
+        return Err(Error::UnmergedPaths(
+            index.conflicts().iter().cloned().collect(),
+        ));
+    }
+    let head = file_service
+        .read_head_commit()?
+        .ok_or_else(|| Error::Usage(format!("no {} in progress", operation.name())))?;
+    let status = worktree.status(&index, Some(&head))?;
+    if !status.unstaged.is_empty() {
+        return Err(Error::DirtyWorkTree(
+            status.unstaged.into_iter().map(|(_, path)| path).collect(),
+        ));
+    }
+
+    let files = WorkTree::tracked_files(&index, Some(&head));
+    let modes = WorkTree::tracked_modes(&index, Some(&head));
+    let stopped = state.stopped.take();
+    let tip = match stopped {
+        Some(ref step) => {
+            let commit = file_service.read_commit(&step.hash)?;
+            let (tip, changed) = commit_step(
+                file_service,
+                state.operation,
+                step,
+                &commit,
+                head,
+                files,
+                modes,
+            )?;
+            if changed {
+                state.done.push(tip.hash().unwrap_or_default().to_string());
+            }
+            tip
+        }
+        None if status.staged.is_empty() => head,
+        None => {
+            let amended = meld(file_service, &head, head.message(), files, modes)?;
+            if let Some(last) = state.done.last_mut() {
+                *last = amended.hash().unwrap_or_default().to_string();
+            }
+            amended
+        }
+    };
+    let hash = tip.hash().unwrap_or_default();
+    file_service.update_head(hash)?;
+    index.clear()?;
+    clear_stop(file_service)?;
+    if stopped.is_some_and(|step| step.action == Action::Edit) {
+        state.save(file_service)?;
+        return Ok(Outcome::Editing {
+            hash: hash.to_string(),
+        });
+    }
+    let current = file_service.read_commit(hash)?;
+    replay(file_service, state, &current, tip)
+}
+
+/// Drops the step the stopped `operation` conflicted at, putting the
+/// working tree and the index back as HEAD has them, and carries out the
+/// steps left.
+pub fn skip(file_service: &FileService, operation: Operation) -> Result<Outcome> {
+    let mut state = load_stopped(file_service, operation)?;
+    if state.stopped.take().is_none() {
+        return Err(Error::Usage(format!(
+            "the {} did not stop at a commit to skip",
+            operation.name()
+        )));
+    }
+    let worktree = WorkTree::new(file_service);
+    let mut index = file_service.lock_index()?;
+    let head = file_service
+        .read_head_commit()?
+        .ok_or_else(|| Error::Usage(format!("no {} in progress", operation.name())))?;
+    let tracked = WorkTree::tracked_files(&index, Some(&head));
+    let current = worktree.snapshot(&tracked)?;
+    worktree.checkout(file_service, &current, head.files(), head.modes())?;
+    index.clear()?;
+    clear_stop(file_service)?;
+
+    let tip = file_service.read_commit(head.hash().unwrap_or_default())?;
+    replay(file_service, state, &head, tip)
+}
+
+/// Gives up on the stopped `operation`, putting the branch, HEAD, the
+/// working tree and the index back as they were before it.
+pub fn abort(file_service: &FileService, operation: Operation) -> Result<()> {
+    let state = load_stopped(file_service, operation)?;
+    let worktree = WorkTree::new(file_service);
+    let mut index = file_service.lock_index()?;
+    let head = file_service.read_head_commit()?;
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+    let current = worktree.snapshot(&tracked)?;
+
+    let orig_head = file_service.read_commit(&state.orig_head)?;
+    worktree.checkout(file_service, &current, orig_head.files(), orig_head.modes())?;
+    if let Some(ref branch) = state.branch {
+        Refs::new(file_service).write(&Refs::branch_ref(branch), &state.orig_head)?;
+    }
+    file_service.set_head(state.branch.as_deref(), &state.orig_head)?;
+    index.clear()?;
+    State::remove(file_service)
+}
+
+/// Records that `hash` was committed on top of a stopped operation,
+/// concluding the step it stopped at. A merge, and an operation with no
+/// steps left but a rebase, which has its branch to move, are then over.
+pub fn committed(file_service: &FileService, hash: &str) -> Result<()> {
+    let Some(mut state) = State::load(file_service)? else {
+        return clear_stop(file_service);
+    };
+    let over = state.operation == Operation::Merge
+        || (state.todo.is_empty() && state.operation != Operation::Rebase);
+    if over {
+        return State::remove(file_service);
+    }
+    clear_stop(file_service)?;
+    state.stopped = None;
+    state.done.push(hash.to_string());
+    state.save(file_service)
+}
+
+/// Records that the commit `old` the operation in progress made, such as
+/// the one an `edit` step stopped at, was amended into `new`.
+pub fn amended(file_service: &FileService, old: &str, new: &str) -> Result<()> {
+    let Some(mut state) = State::load(file_service)? else {
+        return Ok(());
+    };
+    for hash in state.done.iter_mut().filter(|hash| *hash == old) {
+        *hash = new.to_string();
+    }
+    state.save(file_service)
+}
+
+/// Drops the pseudo-refs and message of a stopped step.
+fn clear_stop(file_service: &FileService) -> Result<()> {
+    let refs = Refs::new(file_service);
+    for operation in Operation::ALL {
+        if refs.read(operation.head_ref()).is_some() {
+            refs.delete(operation.head_ref())?;
+        }
+    }
+    let _ = fs::remove_file(file_service.blip_dir.join("MERGE_MSG"));
+    Ok(())
+}
+
+/// Carries out the steps `state` has left on top of `tip`, from a working
+/// tree that has the files of `current`.
+fn replay(
+    file_service: &FileService,
+    mut state: State,
+    current: &Commit,
+    mut tip: Commit,
+) -> Result<Outcome> {
+    let worktree = WorkTree::new(file_service);
+    let mut index = file_service.lock_index()?;
+    let abbrev = Abbrev::new(file_service)?;
+    let detach = state.operation == Operation::Rebase;
+    while !state.todo.is_empty() {
+        let step = state.todo.remove(0);
+        let commit = file_service.read_commit(&step.hash)?;
+        let picks = matches!(step.action, Action::Pick | Action::Edit);
+        if detach && picks && commit.parent() == tip.hash() {
+            tip = commit;
+            state.done.push(step.hash.clone());
+        } else {
+            let parent = match commit.parent() {
+                Some(parent) => Some(file_service.read_commit(parent)?),
+                None => None,
+            };
+            let parent = parent.as_ref().map_or(Files::EMPTY, Files::from);
+            let subject = commit.message().lines().next().unwrap_or_default();
+            let label = format!("{} ({subject})", abbrev.shorten(&step.hash));
+            let (base, theirs, label) = match step.action {
+                Action::Revert => ((&commit).into(), parent, format!("parent of {label}")),
+                _ => (parent, (&commit).into(), label),
+            };
+            let merged = merge::merge_trees(
+                file_service,
+                worktree.attributes()?,
+                base,
+                (&tip).into(),
+                theirs,
+                ("HEAD", &label),
+            )?;
+            if !merged.conflicts.is_empty() {
+                worktree.checkout(file_service, current.files(), &merged.files, &merged.modes)?;
+                merged.stage(&mut index, &tip);
+                file_service.write_index(&index)?;
+                let hash = tip.hash().unwrap_or_default();
+                match detach {
+                    true => file_service.set_head(None, hash)?,
+                    false => file_service.update_head(hash)?,
+                }
+                Refs::new(file_service).write(state.operation.head_ref(), &step.hash)?;
+                let hash = step.hash.clone();
+                state.stopped = Some(step);
+                state.save(file_service)?;
+                return Ok(Outcome::Stopped {
+                    hash,
+                    conflicts: merged.conflicts,
+                });
+            }
+            let changed;
+            (tip, changed) = commit_step(
+                file_service,
+                state.operation,
+                &step,
+                &commit,
+                tip,
+                merged.files,
+                merged.modes,
+            )?;
+            if changed {
+                state.done.push(tip.hash().unwrap_or_default().to_string());
+            }
+        }
+
+        if step.action == Action::Edit {
+            let hash = tip.hash().unwrap_or_default();
+            worktree.checkout(file_service, current.files(), tip.files(), tip.modes())?;
+            file_service.set_head(None, hash)?;
+            index.clear()?;
+            state.save(file_service)?;
+            return Ok(Outcome::Editing {
+                hash: hash.to_string(),
+            });
+        }
+    }
+
+    let hash = tip.hash().unwrap_or_default();
+    worktree.checkout(file_service, current.files(), tip.files(), tip.modes())?;
+    if let Some(ref branch) = state.branch {
+        Refs::new(file_service).write(&Refs::branch_ref(branch), hash)?;
+    }
+    file_service.set_head(state.branch.as_deref(), hash)?;
+    index.clear()?;
+    State::remove(file_service)?;
+    Ok(Outcome::Done(state.done))
+}
+
+/// Makes the commit `step` calls for with `files` and `modes`, the result
+/// of replaying `commit` onto `tip`: one on top of `tip`, or one replacing
+/// `tip` when melding. Returns the new tip and whether it differs from
+/// `tip`; a commit that makes no change is dropped.
+fn commit_step(
+    file_service: &FileService,
+    operation: Operation,
+    step: &Step,
+    commit: &Commit,
+    tip: Commit,
+    files: BTreeMap<String, String>,
+    modes: BTreeMap<String, FileMode>,
+) -> Result<(Commit, bool)> {
+    let unchanged = files == *tip.files() && modes == *tip.modes();
+    let message = match step.action {
+        Action::Squash => {
+            let message = format!("{}\n\n{}", tip.message(), commit.message());
+            let message = edit_message(file_service, operation, &message)?;
+            return Ok((meld(file_service, &tip, &message, files, modes)?, true));
+        }
+        Action::Fixup if !unchanged => {
+            return Ok((meld(file_service, &tip, tip.message(), files, modes)?, true));
+        }
+        _ if unchanged => return Ok((tip, false)),
+        Action::Revert => {
+            let subject = commit.message().lines().next().unwrap_or_default();
+            let hash = commit.hash().unwrap_or_default();
+            format!("Revert \"{subject}\"\n\nThis reverts commit {hash}.")
+        }
+        Action::Reword => edit_message(file_service, operation, commit.message())?,
+        _ => commit.message().to_string(),
+    };
+
+    let mut replayed = Commit::new(Some(&tip), &message);
+    replayed.set_files(files);
+    replayed.set_modes(modes);
+    match (step.action, commit.author()) {
+        (Action::Revert, _) | (_, None) => replayed.set_author(file_service.signature("AUTHOR")?),
+        (_, Some(author)) => replayed.set_author(author.clone()),
+    }
+    replayed.set_committer(file_service.signature("COMMITTER")?);
+    file_service.write_commit_object(&mut replayed)?;
+    Ok((replayed, true))
+}
+
+/// Makes a commit that replaces `tip`, with its parents and author but
+/// `message`, `files` and `modes`.
+fn meld(
+    file_service: &FileService,
+    tip: &Commit,
+    message: &str,
+    files: BTreeMap<String, String>,
+    modes: BTreeMap<String, FileMode>,
+) -> Result<Commit> {
+    let mut melded = Commit::new(None, message);
+    for parent in tip.parents() {
+        melded.add_parent(parent);
+    }
+    melded.set_files(files);
+    melded.set_modes(modes);
+    if let Some(author) = tip.author() {
+        melded.set_author(author.clone());
+    }
+    melded.set_committer(file_service.signature("COMMITTER")?);
+    file_service.write_commit_object(&mut melded)?;
+    Ok(melded)
+}
+
+/// Has `message` edited for a commit `operation` makes.
+fn edit_message(file_service: &FileService, operation: Operation, message: &str) -> Result<String> {
+    let path = file_service.blip_dir.join("COMMIT_EDITMSG");
+    fs::write(&path, format!("{}\n", message.trim_end()))?;
+    editor::edit(file_service, &path)?;
+    let message = editor::strip_comments(&fs::read_to_string(&path)?);
+    if message.is_empty() {
+        return Err(Error::Usage(format!(
+            "aborting {} due to empty commit message",
+            operation.name()
+        )));
+    }
+    Ok(message)
+}
@@ -0,0 +1,344 @@
+//! `blip serve`: a small HTTP server answering the requests of
+//! [`HttpTransport`](crate::transport::HttpTransport), so that other
+//! repositories can clone, fetch from and push to the ones it serves.
+//!
+//! Each repository is served under `/<name>`, its directory's name, and the
+//! only one served also under `/`. There is no authentication, so pushing
+//! is refused unless the server is made writable: then anyone who can reach
+//! it can push to it. Connections are served a request each, on threads of
+//! their own up to [`MAX_CONNECTIONS`] at a time, and dropped when the
+//! client stalls for longer than [`TIMEOUT`].
+//!
+//! The same requests reach `blip upload-pack` and `blip receive-pack`
+//! through the standard input and output of an SSH session, see
+//! [`serve_pipe`]. There each request is a `<method> <endpoint> <len>` line
+//! followed by `<len>` bytes of body, and is answered by a `<status> <len>`
+//! line followed by the body of the response.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    transport::{self, Negotiation},
+    types::{self, Error, FileService, Result},
+};
+
+/// Largest request body accepted, which bounds the size of a pushed object.
+/// Bodies are read as they arrive rather than allocated up front.
+const MAX_BODY: usize = 100 << 20;
+
+/// Largest request line and headers accepted together.
+const MAX_HEAD: u64 = 64 << 10;
+
+/// Most connections answered at once; more are turned away.
+const MAX_CONNECTIONS: usize = 32;
+
+/// Longest a connection may go without sending or taking any data.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The repositories a server answers for, by the name they are served
+/// under, and whether they may be pushed to.
+#[derive(Debug)]
+pub struct Server {
+    repositories: BTreeMap<String, PathBuf>,
+    writable: bool,
+}
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// What a request is answered with.
+struct Response {
+    status: u16,
+    body: Vec<u8>,
+}
+
+impl Server {
+    /// Prepares to serve the repositories containing each of `paths`. Unless
+    /// `writable`, only requests that read from them are allowed.
+    pub fn new(paths: &[PathBuf], writable: bool) -> Result<Server> {
+        let mut repositories = BTreeMap::new();
+        for path in paths {
+            let root = FileService::open(path)?.root_dir;
+            let name = root
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if repositories.insert(name.clone(), root).is_some() {
+                return Err(Error::Usage(format!(
+                    "more than one repository is named '{name}'"
+                )));
+            }
+        }
+        Ok(Server {
+            repositories,
+            writable,
+        })
+    }
+
+    /// The names the repositories are served under.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.repositories.keys().map(String::as_str)
+    }
+
+    /// Answers connections on `listener` until the process is stopped, each
+    /// on a thread of its own.
+    pub fn run(self, listener: TcpListener) -> Result<()> {
+        let server = Arc::new(self);
+        let busy = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let timeouts = stream
+                .set_read_timeout(Some(TIMEOUT))
+                .and_then(|_| stream.set_write_timeout(Some(TIMEOUT)));
+            if timeouts.is_err() {
+                continue;
+            }
+            if busy.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                busy.fetch_sub(1, Ordering::SeqCst);
+                write_response(&mut stream, &Response::text(503, "too many connections"));
+                continue;
+            }
+            let server = Arc::clone(&server);
+            let busy = Arc::clone(&busy);
+            thread::spawn(move || {
+                server.handle(stream);
+                busy.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        Ok(())
+    }
+
+    fn handle(&self, mut stream: TcpStream) {
+        let response = match read_request(&mut stream) {
+            Some(request) => self.respond(&request),
+            None => Response::text(400, "malformed request"),
+        };
+        write_response(&mut stream, &response);
+    }
+
+    /// Finds the repository a request is for and what it asks of it.
+    fn respond(&self, request: &Request) -> Response {
+        let path = request.path.trim_start_matches('/');
+        let (root, endpoint) = match path.split_once('/') {
+            Some((name, endpoint)) if self.repositories.contains_key(name) => {
+                (&self.repositories[name], endpoint)
+            }
+            _ => match self.repositories.values().next() {
+                Some(root) if self.repositories.len() == 1 => (root, path),
+                _ => return Response::text(404, "no such repository"),
+            },
+        };
+        if !self.writable && !transport::is_read(&request.method, endpoint) {
+            return Response::text(405, "pushing is not enabled on this server");
+        }
+        // opened afresh for each request to see the repository as it is now
+        let result = FileService::open(root).and_then(|file_service| {
+            answer(&file_service, &request.method, endpoint, &request.body)
+        });
+        Response::from_result(result)
+    }
+}
+
+/// Answers requests for the repository containing `path` read from the
+/// standard input, on the standard output, until the input ends. Unless
+/// `writable`, only requests that read from the repository are allowed.
+pub fn serve_pipe(path: &Path, writable: bool) -> Result<()> {
+    let file_service = FileService::open(path)?;
+    let mut input = BufReader::new(io::stdin().lock());
+    let mut output = io::stdout().lock();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(endpoint), Some(Ok(length))) = (
+            parts.next(),
+            parts.next(),
+            parts.next().map(str::parse::<usize>),
+        ) else {
+            return Err(Error::Usage(format!(
+                "malformed request '{}'",
+                line.trim_end()
+            )));
+        };
+        if length > MAX_BODY {
+            return Err(Error::Usage("request too large".into()));
+        }
+        let body = read_body(&mut input, length)?;
+
+        let response = if writable || transport::is_read(method, endpoint) {
+            Response::from_result(answer(&file_service, method, endpoint, &body))
+        } else {
+            Response::text(405, "this session is read-only")
+        };
+        writeln!(output, "{} {}", response.status, response.body.len())?;
+        output.write_all(&response.body)?;
+        output.flush()?;
+    }
+}
+
+impl Response {
+    fn text(status: u16, text: &str) -> Response {
+        Response {
+            status,
+            body: format!("{text}\n").into_bytes(),
+        }
+    }
+
+    /// Answers with the outcome of a request, telling refusals apart from
+    /// failures.
+    fn from_result(result: Result<Response>) -> Response {
+        match result {
+            Ok(response) => response,
+            Err(err @ (Error::StaleRef(_) | Error::InvalidRefName(_) | Error::Usage(_))) => {
+                Response::text(409, &err.to_string())
+            }
+            Err(err @ Error::MissingObject(_)) => Response::text(404, &err.to_string()),
+            Err(err) => Response::text(500, &err.to_string()),
+        }
+    }
+}
+
+/// Answers a request for `endpoint` of a repository.
+fn answer(
+    file_service: &FileService,
+    method: &str,
+    endpoint: &str,
+    body: &[u8],
+) -> Result<Response> {
+    let ok = |body: Vec<u8>| Ok(Response { status: 200, body });
+    match (method, endpoint) {
+        ("GET", "info/refs") => {
+            let mut refs = String::new();
+            for (name, hash) in transport::advertise(file_service)? {
+                refs.push_str(&format!("{hash} {name}\n"));
+            }
+            ok(refs.into_bytes())
+        }
+        ("GET", "HEAD") => ok(format!("{}\n", file_service.read_head()?).into_bytes()),
+        ("POST", "upload-pack") => {
+            let Some(request) = Negotiation::parse(body) else {
+                return Ok(Response::text(400, "malformed negotiation"));
+            };
+            ok(transport::upload_pack(file_service, &request)?.to_bytes())
+        }
+        (_, "info/refs" | "HEAD" | "upload-pack") => Ok(Response::text(405, "method not allowed")),
+        ("POST", "update-ref") => {
+            let text = String::from_utf8_lossy(body);
+            let mut parts = text.split_whitespace();
+            let (Some(old), Some(new), Some(name)) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Ok(Response::text(400, "malformed ref update"));
+            };
+            let old = Some(old).filter(|old| old.chars().any(|c| c != '0'));
+            transport::receive_ref(file_service, name, old, new)?;
+            ok(Vec::new())
+        }
+        (method, endpoint) => {
+            let hash = endpoint
+                .strip_prefix("objects/")
+                .filter(|hash| file_service.hash_algorithm.is_hash(hash));
+            let Some(hash) = hash else {
+                return Ok(Response::text(404, "not found"));
+            };
+            match method {
+                "GET" => {
+                    let (kind, data) = file_service.read_raw(hash)?;
+                    let mut stored = kind
+                        .map(|kind| types::object_header(kind, data.len()).into_bytes())
+                        .unwrap_or_default();
+                    stored.extend_from_slice(&data);
+                    ok(stored)
+                }
+                "PUT" => {
+                    let (kind, data) = types::parse_object(hash, body.to_vec())?;
+                    transport::receive_object(file_service, hash, kind, &data)?;
+                    ok(Vec::new())
+                }
+                _ => Ok(Response::text(405, "method not allowed")),
+            }
+        }
+    }
+}
+
+/// Reads a request with its body, or `None` if it is not one.
+fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream).take(MAX_HEAD);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = (parts.next()?.to_string(), parts.next()?.to_string());
+
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    if length > MAX_BODY {
+        return None;
+    }
+    reader.set_limit(length as u64);
+    let body = read_body(&mut reader, length).ok()?;
+    Some(Request { method, path, body })
+}
+
+/// Reads a body of `length` bytes, growing the buffer only as they arrive.
+pub(crate) fn read_body(reader: &mut impl Read, length: usize) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    reader.take(length as u64).read_to_end(&mut body)?;
+    match body.len() == length {
+        true => Ok(body),
+        false => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+/// Sends `response` and closes the connection with it.
+fn write_response(stream: &mut TcpStream, response: &Response) {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {reason}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.body.len()
+    );
+    // the client going away is no concern of the server's
+    let _ = stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(&response.body));
+}
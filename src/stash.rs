@@ -0,0 +1,169 @@
+use std::{fs, path::PathBuf, time::Duration};
+
+use crate::{
+    lock::LockFile,
+    refs::Refs,
+    types::{Context, Error, FileService, Result, Signature},
+};
+
+pub(crate) const STASH_REF: &str = "refs/stash";
+/// The old hash of a reflog entry for a ref that did not exist before.
+pub(crate) const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+
+/// One stashed set of changes. `hash` names the commit holding the working
+/// tree, whose parents are the HEAD it was taken on and a commit holding
+/// the index at the time.
+#[derive(Debug, Clone)]
+pub struct StashEntry {
+    pub hash: String,
+    pub message: String,
+    signature: String,
+}
+
+/// The stash stack, kept as the reflog of `refs/stash` in
+/// `.blip/logs/refs/stash`, oldest entry first, with `refs/stash` itself
+/// pointing at the newest one.
+#[derive(Debug)]
+pub struct Stash {
+    refs: Refs,
+    log: PathBuf,
+    lock_timeout: Duration,
+}
+
+impl Stash {
+    pub fn new(file_service: &FileService) -> Stash {
+        Stash {
+            refs: Refs::new(file_service),
+            log: file_service.common_dir.join("logs").join(STASH_REF),
+            lock_timeout: file_service.lock_timeout,
+        }
+    }
+
+    /// Lists the stashed entries, newest first so that `stash@{n}` is the
+    /// entry at position `n`.
+    pub fn entries(&self) -> Result<Vec<StashEntry>> {
+        if !self.log.is_file() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        let log = fs::read_to_string(&self.log).context("read", &self.log)?;
+        for (number, line) in log.lines().enumerate() {
+            let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+            let mut fields = head.splitn(3, ' ');
+            let (Some(_), Some(hash), Some(signature)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(Error::InvalidLog {
+                    path: self.log.clone(),
+                    line: number + 1,
+                });
+            };
+            entries.push(StashEntry {
+                hash: hash.to_string(),
+                message: message.to_string(),
+                signature: signature.to_string(),
+            });
+        }
+        entries.reverse();
+        Ok(entries)
+    }
+
+    pub fn push(&self, hash: &str, message: &str, signature: &Signature) -> Result<()> {
+        let lock = self.lock()?;
+        let mut entries = self.entries()?;
+        entries.insert(
+            0,
+            StashEntry {
+                hash: hash.to_string(),
+                message: message.to_string(),
+                signature: signature.to_string(),
+            },
+        );
+        self.write(lock, &entries)
+    }
+
+    pub fn get(&self, index: usize) -> Result<StashEntry> {
+        self.entries()?
+            .get(index)
+            .cloned()
+            .ok_or_else(|| Error::UnknownRevision(Stash::name(index)))
+    }
+
+    /// Removes `stash@{index}`, renumbering the entries above it.
+    pub fn drop(&self, index: usize) -> Result<StashEntry> {
+        let lock = self.lock()?;
+        let mut entries = self.entries()?;
+        if index >= entries.len() {
+            return Err(Error::UnknownRevision(Stash::name(index)));
+        }
+        let entry = entries.remove(index);
+        self.write(lock, &entries)?;
+        Ok(entry)
+    }
+
+    /// Drops the entries made before `cutoff`, in seconds since the epoch,
+    /// returning how many there were.
+    pub fn expire(&self, cutoff: i64) -> Result<usize> {
+        let lock = self.lock()?;
+        let mut entries = self.entries()?;
+        let before = entries.len();
+        entries.retain(|entry| {
+            Signature::parse(&entry.signature).is_none_or(|signature| signature.time >= cutoff)
+        });
+        let expired = before - entries.len();
+        if expired > 0 {
+            self.write(lock, &entries)?;
+        }
+        Ok(expired)
+    }
+
+    /// Takes the lock on the log, to be held from reading the entries until
+    /// they are written back.
+    fn lock(&self) -> Result<LockFile> {
+        if let Some(parent) = self.log.parent() {
+            fs::create_dir_all(parent).context("create", parent)?;
+        }
+        LockFile::acquire(&self.log, self.lock_timeout)
+    }
+
+    fn write(&self, mut lock: LockFile, entries: &[StashEntry]) -> Result<()> {
+        let Some(newest) = entries.first() else {
+            if self.log.is_file() {
+                fs::remove_file(&self.log).context("remove", &self.log)?;
+            }
+            if self.refs.read(STASH_REF).is_some() {
+                self.refs.delete(STASH_REF)?;
+            }
+            return Ok(());
+        };
+
+        let mut log = String::new();
+        let mut previous = NULL_HASH;
+        for entry in entries.iter().rev() {
+            log.push_str(&format!(
+                "{previous} {} {}\t{}\n",
+                entry.hash, entry.signature, entry.message
+            ));
+            previous = &entry.hash;
+        }
+        lock.write_all(log.as_bytes())?;
+        lock.commit()?;
+        self.refs.write(STASH_REF, &newest.hash)
+    }
+
+    pub fn name(index: usize) -> String {
+        format!("stash@{{{index}}}")
+    }
+
+    /// Parses `stash@{n}` or a bare `n` into a position on the stack.
+    pub fn parse_name(name: &str) -> Result<usize> {
+        let number = name
+            .strip_prefix("stash@{")
+            .and_then(|rest| rest.strip_suffix('}'))
+            .unwrap_or(name);
+        number
+            .parse()
+            .map_err(|_| Error::UnknownRevision(name.to_string()))
+    }
+}
@@ -0,0 +1,234 @@
+//! Submodules: other repositories checked out inside the working tree, at
+//! a commit the repository containing them pins.
+//!
+//! Trees record a submodule as a `commit <hash> <name>` entry naming the
+//! commit it must be at. That commit lives in the submodule's own
+//! repository, so it is never looked for here. `.blipmodules` at the root
+//! of the working tree says which paths are submodules and where they are
+//! cloned from, in the format of a config file:
+//!
+//! ```text
+//! [submodule "libs/parser"]
+//!     path = libs/parser
+//!     url = ../parser
+//! ```
+//!
+//! Relative URLs are taken from the URL of `origin`, or from the root of
+//! the working tree for a repository without one.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use crate::{
+    config::ConfigFile,
+    remote::{self, Depth, Remote, DEFAULT_REMOTE},
+    types::{Error, FileService, Result},
+    worktree::WorkTree,
+};
+
+/// The file listing the submodules, at the root of the working tree.
+pub const MODULES_FILE: &str = ".blipmodules";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submodule {
+    pub name: String,
+    /// Where it is checked out, relative to the root of the working tree.
+    pub path: String,
+    pub url: String,
+}
+
+/// A submodule along with the commit it is pinned at and the one it has
+/// checked out, `None` until it is cloned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub submodule: Submodule,
+    pub recorded: String,
+    pub checked_out: Option<String>,
+}
+
+/// Reads the submodules listed in the contents of a `.blipmodules` file.
+pub fn parse(contents: &[u8]) -> Result<Vec<Submodule>> {
+    let text = String::from_utf8_lossy(contents);
+    let config = ConfigFile::parse(PathBuf::from(MODULES_FILE), &text)?;
+    let mut submodules = Vec::new();
+    for (key, path) in config.list() {
+        let name = key
+            .strip_prefix("submodule.")
+            .and_then(|key| key.strip_suffix(".path"));
+        if let Some(name) = name {
+            submodules.push(Submodule {
+                name: name.to_string(),
+                path,
+                url: config
+                    .get_all(&format!("submodule.{name}.url"))
+                    .pop()
+                    .unwrap_or_default(),
+            });
+        }
+    }
+    Ok(submodules)
+}
+
+/// The submodules `files`, a commit's or the index's, have, as the
+/// `.blipmodules` among them lists them.
+pub fn list(
+    file_service: &FileService,
+    files: &BTreeMap<String, String>,
+) -> Result<Vec<Submodule>> {
+    let Some(hash) = files.get(MODULES_FILE) else {
+        return Ok(Vec::new());
+    };
+    let mut submodules = parse(&file_service.read_blob(hash)?)?;
+    submodules.retain(|submodule| files.contains_key(&submodule.path));
+    Ok(submodules)
+}
+
+/// The paths of the submodules among `files`, see [`list`].
+pub fn paths(
+    file_service: &FileService,
+    files: &BTreeMap<String, String>,
+) -> Result<BTreeSet<String>> {
+    let submodules = list(file_service, files)?;
+    Ok(submodules
+        .into_iter()
+        .map(|submodule| submodule.path)
+        .collect())
+}
+
+/// Clones `url` into `path` of the working tree, unless a repository is
+/// already there, and stages it as a submodule at the commit it has checked
+/// out, adding it to `.blipmodules`. Returns that commit.
+pub fn add(file_service: &FileService, url: &str, path: &str) -> Result<String> {
+    let path = file_service.repo_path(path)?;
+    let mut index = file_service.lock_index()?;
+    let head = file_service.read_head_commit()?;
+    if path.is_empty() || WorkTree::tracked_files(&index, head.as_ref()).contains_key(&path) {
+        return Err(Error::Usage(format!(
+            "'{path}' already exists in the index"
+        )));
+    }
+
+    let full_path = file_service.root_dir.join(&path);
+    if !full_path.join(".blip").exists() {
+        remote::clone(&resolve_url(file_service, url)?, &full_path, None)?;
+    }
+    let nested = FileService::open(&full_path)?;
+    let hash = nested
+        .read_head_hash()?
+        .ok_or_else(|| Error::Usage(format!("'{path}' has no commit checked out")))?;
+
+    let modules_path = file_service.root_dir.join(MODULES_FILE);
+    let mut modules = ConfigFile::load(modules_path.clone())?;
+    modules.set(&format!("submodule.{path}.path"), &path)?;
+    modules.set(&format!("submodule.{path}.url"), url)?;
+    modules.save()?;
+
+    let modules_hash = file_service.write_blob_file(&modules_path)?;
+    index.update(MODULES_FILE, &modules_hash);
+    index.update(&path, &hash);
+    file_service.write_index(&index)?;
+    Ok(hash)
+}
+
+/// Every submodule of the files tracked in the working tree with the
+/// commit it is pinned at and the one it has checked out.
+pub fn states(file_service: &FileService) -> Result<Vec<State>> {
+    let index = file_service.read_index()?;
+    let head = file_service.read_head_commit()?;
+    let tracked = WorkTree::tracked_files(&index, head.as_ref());
+    let worktree = WorkTree::new(file_service);
+
+    let mut states = Vec::new();
+    for submodule in list(file_service, &tracked)? {
+        states.push(State {
+            recorded: tracked[&submodule.path].clone(),
+            checked_out: worktree.hash(&submodule.path)?,
+            submodule,
+        });
+    }
+    Ok(states)
+}
+
+/// Clones every submodule not cloned yet and checks out the commit each is
+/// pinned at, fetching it if needed, then does the same in the submodules
+/// of each submodule. Returns the path and commit of every submodule that
+/// was cloned or moved, nested ones with their full path.
+///
+/// A submodule with local changes is left alone and reported as an error.
+pub fn update(file_service: &FileService) -> Result<Vec<(String, String)>> {
+    let mut updated = Vec::new();
+    for state in states(file_service)? {
+        let path = &state.submodule.path;
+        let full_path = file_service.root_dir.join(path);
+        let cloned = state.checked_out.is_none();
+        if cloned {
+            let url = resolve_url(file_service, &state.submodule.url)?;
+            remote::clone(&url, &full_path, None)?;
+        }
+        let nested = FileService::open(&full_path)?;
+
+        let moved = nested.read_head_hash()?.as_ref() != Some(&state.recorded);
+        if moved {
+            if !nested.has_object(&state.recorded)? {
+                if let Some(origin) = Remote::load(&nested, DEFAULT_REMOTE)? {
+                    remote::fetch(&nested, &origin, Depth::Full)?;
+                }
+            }
+            detach(&nested, &state.recorded)?;
+        }
+        if cloned || moved {
+            updated.push((path.clone(), state.recorded.clone()));
+        }
+
+        for (nested_path, hash) in update(&nested)? {
+            updated.push((format!("{path}/{nested_path}"), hash));
+        }
+    }
+    Ok(updated)
+}
+
+/// Checks `hash` out in `file_service` with a detached HEAD, as submodules
+/// follow commits rather than branches.
+fn detach(file_service: &FileService, hash: &str) -> Result<()> {
+    let commit = file_service.read_commit(hash)?;
+    let head = file_service.read_head_commit()?;
+    let mut index = file_service.lock_index()?;
+    let worktree = WorkTree::new(file_service);
+    let status = worktree.status(&index, head.as_ref())?;
+    if !status.is_clean() {
+        let paths = status.staged.into_iter().chain(status.unstaged);
+        return Err(Error::DirtyWorkTree(paths.map(|(_, path)| path).collect()));
+    }
+
+    let empty = BTreeMap::new();
+    let current = head.as_ref().map_or(&empty, |head| head.files());
+    worktree.checkout(file_service, current, commit.files(), commit.modes())?;
+    file_service.set_head(None, hash)?;
+    index.clear()
+}
+
+/// Resolves a URL starting with `./` or `../` against the URL of `origin`,
+/// or the root of the working tree without one. Other URLs are returned as
+/// they are.
+fn resolve_url(file_service: &FileService, url: &str) -> Result<String> {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return Ok(url.to_string());
+    }
+    let base = match Remote::load(file_service, DEFAULT_REMOTE)? {
+        Some(origin) => origin.url,
+        None => file_service.root_dir.to_string_lossy().into_owned(),
+    };
+    let mut segments: Vec<&str> = base.trim_end_matches('/').split('/').collect();
+    for segment in url.split('/') {
+        match segment {
+            "." | "" => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Ok(segments.join("/"))
+}
@@ -0,0 +1,42 @@
+//! Trailers: the `Key: value` lines, such as `Signed-off-by:`, that end a
+//! commit message in a paragraph of their own.
+
+/// The trailers of `message` in order: its last paragraph, when every line
+/// of it is a trailer and it is not the only paragraph.
+pub fn parse(message: &str) -> Vec<(String, String)> {
+    let Some((_, last)) = message.trim_end().rsplit_once("\n\n") else {
+        return Vec::new();
+    };
+    last.lines()
+        .map(parse_line)
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default()
+}
+
+/// Splits a `Key: value` line, whose key is made of letters, digits and
+/// `-`, into its key and value.
+pub fn parse_line(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once(':')?;
+    let valid = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    valid.then(|| (key.to_string(), value.trim().to_string()))
+}
+
+/// Adds `trailers` to the end of `message`, in a paragraph of their own
+/// unless it already ends with one. A trailer the one before it already
+/// says is left out, so that signing off twice adds one line.
+pub fn append(message: &str, trailers: &[(String, String)]) -> String {
+    let mut message = message.trim_end().to_string();
+    let mut last = parse(&message).pop();
+    for (key, value) in trailers {
+        if last
+            .as_ref()
+            .is_some_and(|(last_key, last_value)| last_key == key && last_value == value)
+        {
+            continue;
+        }
+        message.push_str(if last.is_some() { "\n" } else { "\n\n" });
+        message.push_str(&format!("{key}: {value}"));
+        last = Some((key.clone(), value.clone()));
+    }
+    message
+}
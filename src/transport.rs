@@ -0,0 +1,839 @@
+//! Talking to other repositories. A remote's URL picks the [`Transport`]:
+//! `http://` URLs are served by `blip serve`, `[<user>@]<host>:<path>` and
+//! `ssh://[<user>@]<host>[:<port>]/<path>` are reached by running `blip
+//! upload-pack` or `blip receive-pack` over `ssh`, or the command in
+//! `BLIP_SSH_COMMAND`, and anything else is a path to a repository on this
+//! machine, optionally written as `file://<path>`, or to a bundle, see
+//! [`crate::bundle`], which can only be fetched from.
+//!
+//! Over HTTP, a repository at `<url>` answers the following requests. Over
+//! SSH the same requests are framed as described in [`crate::server`].
+//!
+//! - `GET <url>/info/refs` with one `<hash> <name>` line per ref, `HEAD`
+//!   first when it points at a commit;
+//! - `GET <url>/HEAD` with the contents of its HEAD, `ref: <name>` or a
+//!   hash;
+//! - `GET <url>/objects/<hash>` with the object as stored, that is its
+//!   `<type> <len>\0` header followed by its content;
+//! - `PUT <url>/objects/<hash>` by storing the object sent, as above;
+//! - `POST <url>/upload-pack` with a body of [`Negotiation`] lines by a
+//!   pack, see [`crate::pack`], of every object reachable from the wants
+//!   but not from the haves it has itself. The pack is preceded by a
+//!   `shallow <hash>` line for each commit sent without its parents;
+//! - `POST <url>/update-ref` with a body of `<old> <new> <name>` by
+//!   pointing the ref at `<new>` if it still points at `<old>`, all zeros
+//!   for a ref that must not exist yet. A refusal is answered with a 409
+//!   and the reason as the body.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    env, fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    path::Path,
+    process::{self, Child, ChildStdin, ChildStdout, Stdio},
+};
+
+use crate::{
+    bundle::Bundle,
+    hash::HashAlgorithm,
+    pack,
+    refs::{Head, Refs},
+    remote, server,
+    types::{self, Error, FileService, ObjectKind, Result},
+};
+
+/// The largest response taken from another repository, packs included;
+/// anything it claims beyond that is refused rather than waited for.
+const MAX_RESPONSE: usize = 1 << 30;
+
+/// A connection to another repository.
+pub trait Transport {
+    /// Lists the refs of the other repository with the hashes they point
+    /// at, including `HEAD` when it points at a commit.
+    fn list_refs(&mut self) -> Result<Vec<(String, String)>>;
+
+    /// Reads the HEAD of the other repository.
+    fn head(&mut self) -> Result<Head>;
+
+    /// Reads an object of the other repository, split into its kind and
+    /// content like [`FileService::read_raw`].
+    fn read_object(&mut self, hash: &str) -> Result<(Option<ObjectKind>, Vec<u8>)>;
+
+    /// Asks the other repository for a single pack of what `request`
+    /// wants, or returns `None` if it cannot build one and objects must be
+    /// read one at a time.
+    fn fetch_pack(&mut self, request: &Negotiation) -> Result<Option<Packed>>;
+
+    /// Stores an object in the other repository.
+    fn write_object(&mut self, hash: &str, kind: Option<ObjectKind>, data: &[u8]) -> Result<()>;
+
+    /// Points a ref of the other repository at `new` provided it still
+    /// points at `old`, `None` meaning that it must not exist yet.
+    fn update_ref(&mut self, name: &str, old: Option<&str>, new: &str) -> Result<()>;
+}
+
+/// What a fetch asks another repository for, sent as a `want <hash>`,
+/// `have <hash>`, `shallow <hash>`, `depth <n>` or `deepen <n>` line per
+/// item.
+#[derive(Debug, Default)]
+pub struct Negotiation {
+    /// The tips to fetch.
+    pub wants: Vec<String>,
+    /// The tips of the refs already here.
+    pub haves: Vec<String>,
+    /// The commits at the boundary of the history here, whose parents are
+    /// missing.
+    pub shallow: Vec<String>,
+    /// How many commits to send from each want, all of them when `None`.
+    pub depth: Option<usize>,
+    /// How many commits to send past each of the shallow commits.
+    pub deepen: Option<usize>,
+}
+
+/// The answer to a [`Negotiation`].
+#[derive(Debug)]
+pub struct Packed {
+    pub pack: Vec<u8>,
+    /// The commits sent without their parents, which the repository
+    /// receiving them must treat as having none.
+    pub shallow: Vec<String>,
+    /// The commits the pack builds on, which the repository receiving it
+    /// must already have. Only bundles have any.
+    pub requires: Vec<String>,
+}
+
+/// A repository on this machine.
+pub struct LocalTransport {
+    file_service: FileService,
+}
+
+/// A bundle, whose refs and objects are read into memory.
+pub struct BundleTransport {
+    url: String,
+    bundle: Bundle,
+    /// The objects of the bundle's pack, unpacked on first use.
+    objects: Option<Objects>,
+}
+
+/// Objects by hash, with their kind and content.
+type Objects = HashMap<String, (Option<ObjectKind>, Vec<u8>)>;
+
+/// A repository served over HTTP by `blip serve`.
+pub struct HttpTransport {
+    url: String,
+    host: String,
+    port: u16,
+    /// The path of the repository on the server, without a trailing slash.
+    path: String,
+}
+
+/// A repository on another machine reached over SSH, where `blip
+/// upload-pack` and `blip receive-pack` answer the same requests as over
+/// HTTP.
+pub struct SshTransport {
+    url: String,
+    /// The host with the user to log in as, if any.
+    host: String,
+    port: Option<u16>,
+    path: String,
+    upload: Option<Session>,
+    receive: Option<Session>,
+}
+
+/// A `blip upload-pack` or `blip receive-pack` running over SSH.
+struct Session {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: Option<BufReader<ChildStdout>>,
+}
+
+/// A response to a request sent over HTTP or SSH.
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+/// A way of sending the requests described above to another repository.
+trait Channel {
+    fn url(&self) -> &str;
+
+    fn request(&mut self, method: &str, endpoint: &str, body: &[u8]) -> Result<Response>;
+
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::Remote {
+            url: self.url().to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Sends a GET request, failing unless it succeeds.
+    fn get(&mut self, endpoint: &str) -> Result<Vec<u8>> {
+        let response = self.request("GET", endpoint, &[])?;
+        match response.status {
+            200 => Ok(response.body),
+            status => Err(self.error(format!("GET {endpoint} returned {status}"))),
+        }
+    }
+}
+
+/// Opens the transport `url` calls for.
+pub fn connect(url: &str) -> Result<Box<dyn Transport>> {
+    if url.starts_with("http://") {
+        return Ok(Box::new(HttpTransport::new(url)?));
+    }
+    if let Some(transport) = SshTransport::new(url) {
+        return Ok(Box::new(transport));
+    }
+    if let Some(path) = bundle_path(url) {
+        return Ok(Box::new(BundleTransport::new(url, path)?));
+    }
+    let Some(path) = local_path(url) else {
+        return Err(Error::Remote {
+            url: url.to_string(),
+            message: "unsupported protocol".into(),
+        });
+    };
+    Ok(Box::new(LocalTransport {
+        file_service: FileService::open(path)?,
+    }))
+}
+
+/// The path of the repository `url` stands for, if it is on this machine.
+pub fn local_path(url: &str) -> Option<&Path> {
+    match url.strip_prefix("file://") {
+        _ if bundle_path(url).is_some() => None,
+        Some(path) => Some(Path::new(path)),
+        None if url.contains("://") || SshTransport::new(url).is_some() => None,
+        None => Some(Path::new(url)),
+    }
+}
+
+/// The path of the bundle `url` stands for, if it is one.
+fn bundle_path(url: &str) -> Option<&Path> {
+    let path = Path::new(url.strip_prefix("file://").unwrap_or(url));
+    Bundle::is_bundle(path).then_some(path)
+}
+
+/// The refs a repository shows others, `HEAD` first when it points at a
+/// commit.
+pub fn advertise(file_service: &FileService) -> Result<Vec<(String, String)>> {
+    let mut refs = Vec::new();
+    if let Some(head) = file_service.read_head_hash()? {
+        refs.push(("HEAD".to_string(), head));
+    }
+    refs.extend(Refs::new(file_service).all()?);
+    Ok(refs)
+}
+
+impl Transport for LocalTransport {
+    fn list_refs(&mut self) -> Result<Vec<(String, String)>> {
+        advertise(&self.file_service)
+    }
+
+    fn head(&mut self) -> Result<Head> {
+        self.file_service.read_head()
+    }
+
+    fn read_object(&mut self, hash: &str) -> Result<(Option<ObjectKind>, Vec<u8>)> {
+        self.file_service.read_raw(hash)
+    }
+
+    fn fetch_pack(&mut self, request: &Negotiation) -> Result<Option<Packed>> {
+        upload_pack(&self.file_service, request).map(Some)
+    }
+
+    fn write_object(&mut self, hash: &str, kind: Option<ObjectKind>, data: &[u8]) -> Result<()> {
+        receive_object(&self.file_service, hash, kind, data)
+    }
+
+    fn update_ref(&mut self, name: &str, old: Option<&str>, new: &str) -> Result<()> {
+        receive_ref(&self.file_service, name, old, new)
+    }
+}
+
+impl BundleTransport {
+    /// Reads the bundle at `path`. One without a `HEAD` has it point at its
+    /// first branch, so that clones of it have something to check out.
+    pub fn new(url: &str, path: &Path) -> Result<BundleTransport> {
+        let mut bundle = Bundle::read(path)?;
+        if !bundle.refs.iter().any(|(name, _)| name == "HEAD") {
+            let branch = bundle
+                .refs
+                .iter()
+                .find(|(name, _)| name.starts_with("refs/heads/"));
+            if let Some((_, hash)) = branch {
+                bundle.refs.insert(0, ("HEAD".to_string(), hash.clone()));
+            }
+        }
+        Ok(BundleTransport {
+            url: url.to_string(),
+            bundle,
+            objects: None,
+        })
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Remote {
+            url: self.url.clone(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Transport for BundleTransport {
+    fn list_refs(&mut self) -> Result<Vec<(String, String)>> {
+        Ok(self.bundle.refs.clone())
+    }
+
+    /// Points at the first branch `HEAD` is at, or is detached there.
+    fn head(&mut self) -> Result<Head> {
+        let refs = &self.bundle.refs;
+        let Some((_, head)) = refs.iter().find(|(name, _)| name == "HEAD") else {
+            return Err(self.error("has no branches"));
+        };
+        let branch = refs
+            .iter()
+            .find(|(name, hash)| name.starts_with("refs/heads/") && hash == head);
+        Ok(match branch {
+            Some((name, _)) => Head::Symbolic(name.clone()),
+            None => Head::Detached(head.clone()),
+        })
+    }
+
+    fn read_object(&mut self, hash: &str) -> Result<(Option<ObjectKind>, Vec<u8>)> {
+        if self.objects.is_none() {
+            let algorithm = self.bundle.hash_algorithm();
+            let objects = pack::unpack(algorithm, &self.bundle.pack)
+                .ok_or_else(|| self.error("has a malformed pack"))?;
+            let objects = objects.into_iter().map(|(kind, data)| {
+                let hash = match kind {
+                    Some(kind) => types::hash_object(algorithm, kind, &data),
+                    None => algorithm.digest(&[&data]),
+                };
+                (hash, (kind, data))
+            });
+            self.objects = Some(objects.collect());
+        }
+        match self.objects.as_ref().and_then(|objects| objects.get(hash)) {
+            Some((kind, data)) => Ok((*kind, data.clone())),
+            None => Err(Error::MissingObject(hash.to_string())),
+        }
+    }
+
+    /// Offers the whole pack, since a bundle cannot leave anything out.
+    fn fetch_pack(&mut self, request: &Negotiation) -> Result<Option<Packed>> {
+        if request.depth.is_some() || request.deepen.is_some() {
+            return Ok(None);
+        }
+        Ok(Some(Packed {
+            pack: self.bundle.pack.clone(),
+            shallow: Vec::new(),
+            requires: self
+                .bundle
+                .prerequisites
+                .iter()
+                .map(|(hash, _)| hash.clone())
+                .collect(),
+        }))
+    }
+
+    fn write_object(&mut self, _: &str, _: Option<ObjectKind>, _: &[u8]) -> Result<()> {
+        Err(self.error("bundles cannot be pushed to"))
+    }
+
+    fn update_ref(&mut self, _: &str, _: Option<&str>, _: &str) -> Result<()> {
+        Err(self.error("bundles cannot be pushed to"))
+    }
+}
+
+impl HttpTransport {
+    pub fn new(url: &str) -> Result<HttpTransport> {
+        let invalid = || Error::Remote {
+            url: url.to_string(),
+            message: "not a valid http URL".into(),
+        };
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(HttpTransport {
+            url: url.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.trim_end_matches('/').to_string(),
+        })
+    }
+}
+
+impl Channel for HttpTransport {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Sends each request on a connection of its own.
+    fn request(&mut self, method: &str, endpoint: &str, body: &[u8]) -> Result<Response> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))
+            .map_err(|err| self.error(err.to_string()))?;
+        let head = format!(
+            "{method} {}/{endpoint} HTTP/1.1\r\nHost: {}:{}\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n",
+            self.path,
+            self.host,
+            self.port,
+            body.len()
+        );
+        let mut raw = Vec::new();
+        stream
+            .write_all(head.as_bytes())
+            .and_then(|_| stream.write_all(body))
+            .and_then(|_| {
+                (&mut stream)
+                    .take(MAX_RESPONSE as u64 + 1)
+                    .read_to_end(&mut raw)
+            })
+            .map_err(|err| self.error(err.to_string()))?;
+        if raw.len() > MAX_RESPONSE {
+            return Err(self.error("response too large"));
+        }
+        parse_response(&raw).ok_or_else(|| self.error("malformed HTTP response"))
+    }
+}
+
+impl SshTransport {
+    /// Parses `[<user>@]<host>:<path>` or `ssh://[<user>@]<host>[:<port>]/<path>`,
+    /// returning `None` for other URLs and for hosts ssh would take for an
+    /// option.
+    pub fn new(url: &str) -> Option<SshTransport> {
+        let (host, port, path) = match url.strip_prefix("ssh://") {
+            Some(rest) => {
+                let slash = rest.find('/')?;
+                let (authority, path) = rest.split_at(slash);
+                match authority.rsplit_once(':') {
+                    Some((host, port)) => (host, Some(port.parse().ok()?), path),
+                    None => (authority, None, path),
+                }
+            }
+            None if url.contains("://") => return None,
+            None => {
+                let (host, path) = url.split_once(':')?;
+                if host.contains('/') {
+                    return None;
+                }
+                (host, None, path)
+            }
+        };
+        if host.is_empty() || host.starts_with('-') || path.is_empty() {
+            return None;
+        }
+        Some(SshTransport {
+            url: url.to_string(),
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            upload: None,
+            receive: None,
+        })
+    }
+
+    /// Starts `blip <command>` for the repository on the other machine.
+    fn spawn(&self, command: &str) -> Result<Session> {
+        let ssh = env::var("BLIP_SSH_COMMAND").unwrap_or_else(|_| "ssh".to_string());
+        let mut words = ssh.split_whitespace();
+        let mut ssh = process::Command::new(words.next().unwrap_or("ssh"));
+        ssh.args(words);
+        if let Some(port) = self.port {
+            ssh.arg("-p").arg(port.to_string());
+        }
+        let quoted = format!("'{}'", self.path.replace('\'', "'\\''"));
+        let mut child = ssh
+            .arg("--")
+            .arg(&self.host)
+            .arg(format!("blip {command} {quoted}"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|err| self.error(err.to_string()))?;
+        Ok(Session {
+            stdin: child.stdin.take(),
+            stdout: child.stdout.take().map(BufReader::new),
+            child,
+        })
+    }
+}
+
+impl Channel for SshTransport {
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Reads go through `blip upload-pack` unless `blip receive-pack` is
+    /// already running, which writes need.
+    fn request(&mut self, method: &str, endpoint: &str, body: &[u8]) -> Result<Response> {
+        let session = if !is_read(method, endpoint) || self.receive.is_some() {
+            if self.receive.is_none() {
+                self.receive = Some(self.spawn("receive-pack")?);
+            }
+            self.receive.as_mut()
+        } else {
+            if self.upload.is_none() {
+                self.upload = Some(self.spawn("upload-pack")?);
+            }
+            self.upload.as_mut()
+        };
+        let response = session.and_then(|session| session.request(method, endpoint, body).ok());
+        response.ok_or_else(|| self.error("connection closed"))
+    }
+}
+
+impl Session {
+    fn request(&mut self, method: &str, endpoint: &str, body: &[u8]) -> io::Result<Response> {
+        let stdin = self.stdin.as_mut().ok_or(io::ErrorKind::BrokenPipe)?;
+        writeln!(stdin, "{method} {endpoint} {}", body.len())?;
+        stdin.write_all(body)?;
+        stdin.flush()?;
+
+        let stdout = self.stdout.as_mut().ok_or(io::ErrorKind::BrokenPipe)?;
+        let mut line = String::new();
+        stdout.read_line(&mut line)?;
+        let invalid = || io::Error::from(io::ErrorKind::InvalidData);
+        let (status, length) = line.trim_end().split_once(' ').ok_or_else(invalid)?;
+        let status = status.parse().map_err(|_| invalid())?;
+        let length = length.parse().map_err(|_| invalid())?;
+        if length > MAX_RESPONSE {
+            return Err(invalid());
+        }
+        let body = server::read_body(stdout, length)?;
+        Ok(Response { status, body })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // closing its input tells the other side to finish
+        self.stdin.take();
+        let _ = self.child.wait();
+    }
+}
+
+impl<C: Channel> Transport for C {
+    fn list_refs(&mut self) -> Result<Vec<(String, String)>> {
+        let body = self.get("info/refs")?;
+        let text = String::from_utf8(body).map_err(|_| self.error("malformed ref list"))?;
+        text.lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (hash, name) = line
+                    .split_once(' ')
+                    .ok_or_else(|| self.error("malformed ref list"))?;
+                Ok((name.to_string(), hash.to_string()))
+            })
+            .collect()
+    }
+
+    fn head(&mut self) -> Result<Head> {
+        let contents = String::from_utf8(self.get("HEAD")?).unwrap_or_default();
+        // the other repository's hash function is not known here
+        [HashAlgorithm::Sha1, HashAlgorithm::Sha256]
+            .into_iter()
+            .find_map(|algorithm| Head::parse(&contents, algorithm))
+            .ok_or_else(|| self.error("malformed HEAD"))
+    }
+
+    fn read_object(&mut self, hash: &str) -> Result<(Option<ObjectKind>, Vec<u8>)> {
+        let response = self.request("GET", &format!("objects/{hash}"), &[])?;
+        match response.status {
+            200 => types::parse_object(hash, response.body),
+            404 => Err(Error::MissingObject(hash.to_string())),
+            status => Err(self.error(format!("GET objects/{hash} returned {status}"))),
+        }
+    }
+
+    fn fetch_pack(&mut self, request: &Negotiation) -> Result<Option<Packed>> {
+        let response = self.request("POST", "upload-pack", request.to_string().as_bytes())?;
+        match response.status {
+            200 => Packed::parse(response.body)
+                .map(Some)
+                .ok_or_else(|| self.error("malformed pack")),
+            // servers from before packs were sent
+            404 | 405 => Ok(None),
+            status => Err(self.error(format!("POST upload-pack returned {status}"))),
+        }
+    }
+
+    fn write_object(&mut self, hash: &str, kind: Option<ObjectKind>, data: &[u8]) -> Result<()> {
+        let mut stored = kind
+            .map(|kind| types::object_header(kind, data.len()).into_bytes())
+            .unwrap_or_default();
+        stored.extend_from_slice(data);
+        let response = self.request("PUT", &format!("objects/{hash}"), &stored)?;
+        match response.status {
+            200 | 201 | 204 => Ok(()),
+            status => Err(self.error(format!("PUT objects/{hash} returned {status}"))),
+        }
+    }
+
+    fn update_ref(&mut self, name: &str, old: Option<&str>, new: &str) -> Result<()> {
+        let zeros = "0".repeat(new.len());
+        let body = format!("{} {new} {name}", old.unwrap_or(&zeros));
+        let response = self.request("POST", "update-ref", body.as_bytes())?;
+        match response.status {
+            200 | 204 => Ok(()),
+            409 => Err(self.error(String::from_utf8_lossy(&response.body).trim())),
+            status => Err(self.error(format!("POST update-ref returned {status}"))),
+        }
+    }
+}
+
+/// Whether a request only reads from the repository it is sent to.
+pub fn is_read(method: &str, endpoint: &str) -> bool {
+    method == "GET" || (method, endpoint) == ("POST", "upload-pack")
+}
+
+impl Negotiation {
+    /// Reads a negotiation from its lines, or returns `None` if one of them
+    /// is not understood.
+    pub fn parse(body: &[u8]) -> Option<Negotiation> {
+        let mut request = Negotiation::default();
+        for line in std::str::from_utf8(body).ok()?.lines() {
+            match line.split_once(' ')? {
+                ("want", hash) => request.wants.push(hash.to_string()),
+                ("have", hash) => request.haves.push(hash.to_string()),
+                ("shallow", hash) => request.shallow.push(hash.to_string()),
+                ("depth", depth) => request.depth = Some(depth.parse().ok()?),
+                ("deepen", deepen) => request.deepen = Some(deepen.parse().ok()?),
+                _ => return None,
+            }
+        }
+        Some(request)
+    }
+}
+
+impl fmt::Display for Negotiation {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, hashes) in [
+            ("want", &self.wants),
+            ("have", &self.haves),
+            ("shallow", &self.shallow),
+        ] {
+            for hash in hashes {
+                writeln!(fmt, "{name} {hash}")?;
+            }
+        }
+        if let Some(depth) = self.depth {
+            writeln!(fmt, "depth {depth}")?;
+        }
+        if let Some(deepen) = self.deepen {
+            writeln!(fmt, "deepen {deepen}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Packed {
+    /// Splits a response into its `shallow <hash>` lines and the pack
+    /// after them.
+    pub fn parse(mut body: Vec<u8>) -> Option<Packed> {
+        let mut shallow = Vec::new();
+        let mut start = 0;
+        while body[start..].starts_with(b"shallow ") {
+            let end = start + body[start..].iter().position(|&byte| byte == b'\n')?;
+            let hash = std::str::from_utf8(&body[start + b"shallow ".len()..end]).ok()?;
+            shallow.push(hash.to_string());
+            start = end + 1;
+        }
+        body.drain(..start);
+        Some(Packed {
+            pack: body,
+            shallow,
+            requires: Vec::new(),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self
+            .shallow
+            .iter()
+            .flat_map(|hash| format!("shallow {hash}\n").into_bytes())
+            .collect();
+        bytes.extend_from_slice(&self.pack);
+        bytes
+    }
+}
+
+/// Builds the pack another repository asks for: every object reachable
+/// from the wants, leaving out what is reachable from the haves this
+/// repository also has.
+///
+/// With a depth, only that many commits are sent along each path from the
+/// wants, and with a deepen that many more past each of the other side's
+/// shallow commits; the commits whose parents are cut off that way, or
+/// were never here to begin with, are reported back as shallow.
+pub fn upload_pack(file_service: &FileService, request: &Negotiation) -> Result<Packed> {
+    let mut common = Vec::new();
+    for hash in request.haves.iter() {
+        if file_service.has_object(hash)? {
+            common.push(hash.clone());
+        }
+    }
+    let theirs: BTreeSet<String> = request.shallow.iter().cloned().collect();
+    let have = remote::closure(file_service, common, &HashSet::new(), &theirs)?;
+
+    let mut roots = request.wants.clone();
+    let mut boundary = BTreeSet::new();
+    if request.depth.is_some() || request.deepen.is_some() {
+        let depth = request.depth.unwrap_or(usize::MAX);
+        let mut pending: Vec<_> = roots.iter().map(|hash| (hash.clone(), depth)).collect();
+        if let Some(deepen) = request.deepen {
+            for hash in theirs.iter() {
+                if !file_service.has_object(hash)? {
+                    continue;
+                }
+                for parent in file_service.read_commit_headers(hash)?.parents() {
+                    pending.push((parent.clone(), deepen));
+                    roots.push(parent.clone());
+                }
+            }
+        }
+
+        // each commit may be reached along paths of different lengths, and
+        // the one leaving the most commits below it wins
+        let mut below: HashMap<String, usize> = HashMap::new();
+        while let Some((hash, depth)) = pending.pop() {
+            let reached = below.get(&hash).is_some_and(|&below| below >= depth);
+            if depth == 0 || reached || have.contains(&hash) {
+                continue;
+            }
+            below.insert(hash.clone(), depth);
+            let (kind, _) = file_service.read_raw(&hash)?;
+            match remote::object_kind(file_service, &hash, kind, None) {
+                ObjectKind::Tag => {
+                    let tag = file_service.read_tag(&hash)?;
+                    pending.push((tag.object().to_string(), depth));
+                }
+                ObjectKind::Commit => {
+                    let commit = file_service.read_commit_headers(&hash)?;
+                    let parents = commit.parents();
+                    if depth > 1 {
+                        boundary.remove(&hash);
+                        pending.extend(parents.iter().map(|parent| (parent.clone(), depth - 1)));
+                    } else if parents.iter().any(|parent| !have.contains(parent)) {
+                        boundary.insert(hash);
+                    }
+                }
+                ObjectKind::Tree | ObjectKind::Blob => {}
+            }
+        }
+    }
+
+    let send = remote::closure(file_service, roots, &have, &boundary)?;
+    boundary.extend(
+        send.iter()
+            .filter(|hash| file_service.is_shallow(hash))
+            .cloned(),
+    );
+    Ok(Packed {
+        pack: pack::build(file_service, &send.into_iter().collect())?,
+        shallow: boundary.into_iter().collect(),
+        requires: Vec::new(),
+    })
+}
+
+/// Stores an object sent by another repository, checking it against its
+/// hash first.
+pub fn receive_object(
+    file_service: &FileService,
+    hash: &str,
+    kind: Option<ObjectKind>,
+    data: &[u8],
+) -> Result<()> {
+    if file_service.hash_raw(kind, data) != hash {
+        return Err(Error::Usage(format!(
+            "object {hash} does not match its hash"
+        )));
+    }
+    if !file_service.has_object(hash)? {
+        file_service.write_loose(hash, kind, data)?;
+    }
+    Ok(())
+}
+
+/// Applies a ref update sent by another repository. The ref must be under
+/// `refs/`, its new target must already have been received, and the branch
+/// checked out here is only updated when `receive.denyCurrentBranch` is
+/// `ignore` or `false`, since its files would no longer match it. Bare
+/// repositories have no files to protect.
+pub fn receive_ref(
+    file_service: &FileService,
+    name: &str,
+    old: Option<&str>,
+    new: &str,
+) -> Result<()> {
+    let valid = name
+        .strip_prefix("refs/")
+        .is_some_and(|rest| rest.contains('/') && Refs::is_valid_name(rest));
+    if !valid {
+        return Err(Error::InvalidRefName(name.to_string()));
+    }
+    if !file_service.has_object(new)? {
+        return Err(Error::MissingObject(new.to_string()));
+    }
+    if !file_service.bare && file_service.read_head()? == Head::Symbolic(name.to_string()) {
+        let deny = file_service.config()?.get("receive.denyCurrentBranch");
+        if !matches!(deny.as_deref(), Some("ignore" | "false")) {
+            return Err(Error::Usage(format!(
+                "refusing to update checked out branch {name}"
+            )));
+        }
+    }
+    if !Refs::new(file_service).compare_and_write(name, old, new)? {
+        return Err(Error::StaleRef(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Splits a complete HTTP response into its status and body, undoing
+/// chunked transfer encoding.
+fn parse_response(raw: &[u8]) -> Option<Response> {
+    let end = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&raw[..end]).ok()?;
+    let mut body = raw[end + 4..].to_vec();
+
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => body.truncate(value.parse().ok()?),
+            "transfer-encoding" if value.eq_ignore_ascii_case("chunked") => {
+                body = dechunk(&body)?;
+            }
+            _ => {}
+        }
+    }
+    Some(Response { status, body })
+}
+
+fn dechunk(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = data.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&data[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        if size == 0 {
+            return Some(body);
+        }
+        let chunk = data.get(line_end + 2..line_end + 2 + size)?;
+        body.extend_from_slice(chunk);
+        data = data.get(line_end + 4 + size..)?;
+    }
+}
@@ -1,30 +1,157 @@
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
     env, fmt,
     fs::{self, File},
     io::{self, BufRead, BufReader, Read, Write},
-    os::unix::prelude::FileExt,
-    path::{Path, PathBuf},
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, OnceLock, PoisonError, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use crypto::{digest::Digest, sha1::Sha1};
+use crate::{
+    config::{Config, ConfigFile},
+    hash::{HashAlgorithm, HEX_PATTERN},
+    index::{self, Stat},
+    lock::{self, LockFile},
+    pack::Pack,
+    refs::{Head, Refs},
+    revision,
+    sign::{self, SigningKey},
+    submodule,
+};
 use regex::Regex;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum Error {
-    IoError(io::Error),
+    /// An I/O failure without anything more specific to say about it.
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// An I/O failure while performing `op`, e.g. "read", on `path`.
+    #[error("Could not {op} {}: {source}", .path.display())]
+    File {
+        op: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[error("{0}")]
+    Usage(String),
+    #[error("No Directory Found")]
     NoDirectory,
-    InvalidIndex,
-    InvalidObjectStore,
+    /// An index that cannot be parsed, with the offending line if it is in
+    /// the old text format.
+    #[error("{}", corrupt_index(.path, .line))]
+    InvalidIndex { path: PathBuf, line: Option<usize> },
+    /// A reflog, such as the one backing the stash, that cannot be parsed.
+    #[error("Log is corrupt at line {line} of {}", .path.display())]
+    InvalidLog { path: PathBuf, line: usize },
+    #[error("Object {0} is missing")]
+    MissingObject(String),
+    /// A lock file, see [`LockFile`](crate::lock::LockFile), still held
+    /// after waiting for it.
+    #[error(
+        "{} is held by another blip process; remove it if none is running",
+        .0.display()
+    )]
+    Locked(PathBuf),
+    /// A packfile or pack index that cannot be parsed.
+    #[error("Pack {} is corrupt", .0.display())]
+    InvalidPack(PathBuf),
+    #[error("Bundle {} is corrupt", .0.display())]
+    InvalidBundle(PathBuf),
+    /// A `.blip` file of a linked working tree that does not point at a
+    /// repository.
+    #[error("{} does not point at a repository", .0.display())]
+    InvalidBlipFile(PathBuf),
+    /// A command that needs a working tree run in a bare repository.
+    #[error("This Operation Must Be Run in a Work Tree")]
+    BareRepository,
+    /// A fast-import stream that cannot be parsed, see
+    /// [`fast_import`](crate::fast_import).
+    #[error("Stream is corrupt at line {line}: {message}")]
+    InvalidStream { line: usize, message: String },
+    /// A patch that cannot be parsed, see [`patch`](crate::patch).
+    #[error("Patch is corrupt at line {line}: {message}")]
+    InvalidPatch { line: usize, message: String },
+    #[error("Object {hash} is not a valid {}", .kind.as_str())]
+    InvalidObject { hash: String, kind: ObjectKind },
+    #[error("No Files Staged for Commit")]
     EmptyCommit,
+    #[error("Unknown Revision '{0}'")]
+    UnknownRevision(String),
+    /// A hash prefix shared by more than one object, with a line
+    /// describing each of them.
+    #[error(
+        "Short hash '{prefix}' is ambiguous; candidates are:{}",
+        list(.candidates, "\n  ")
+    )]
+    AmbiguousRevision {
+        prefix: String,
+        candidates: Vec<String>,
+    },
+    #[error("'{0}' is Not a Valid Ref Name")]
+    InvalidRefName(String),
+    /// HEAD holding something other than a ref or a hash.
+    #[error("HEAD holds neither a ref nor a hash: '{0}'")]
+    InvalidHead(String),
+    #[error("Ref '{0}' Already Exists")]
+    RefExists(String),
+    #[error("Local Changes Would Be Overwritten:{}", list(.0, "\n\t"))]
+    DirtyWorkTree(Vec<String>),
+    #[error("Unmerged Paths Must Be Resolved First:{}", list(.0, "\n\t"))]
+    UnmergedPaths(Vec<String>),
+    #[error("Bad Config: {0}")]
+    InvalidConfig(String),
+    #[error("Hook '{0}' Failed")]
+    HookFailed(String),
+    /// A commit message that is not a conventional commit, with what is
+    /// wrong with it, see [`conventional`](crate::conventional).
+    #[error("Commit Message Is Not a Conventional Commit:{}", list(.0, "\n\t"))]
+    NotConventional(Vec<String>),
+    /// A path that would lead outside the working tree or into `.blip`,
+    /// see [`is_safe_path`](crate::worktree::is_safe_path).
+    #[error("Refusing to Touch Unsafe Path '{0}'")]
+    UnsafePath(String),
+    /// A path with a symbolic link among its parent directories, see
+    /// [`WorkTree::is_beyond_symlink`](crate::worktree::WorkTree::is_beyond_symlink).
+    #[error("'{0}' Is Beyond a Symbolic Link")]
+    BeyondSymlink(String),
+    /// The editor exiting non-zero, see [`editor`](crate::editor).
+    #[error("Editor '{0}' Failed")]
+    EditorFailed(String),
+    /// A filter command of a `filter` attribute that failed on `path` and
+    /// is required to succeed, see [`attributes`](crate::attributes).
+    #[error("Filter '{filter}' Failed on '{path}'")]
+    FilterFailed { filter: String, path: String },
+    /// A key that cannot be signed with, or none being configured.
+    #[error("Cannot sign: {0}")]
+    SigningKey(String),
+    /// A signature that is missing, malformed, does not match or was made
+    /// by an untrusted key.
+    #[error("Bad signature: {0}")]
+    BadSignature(String),
+    /// A remote repository that cannot be reached or answered with
+    /// something unexpected.
+    #[error("Remote {url}: {message}")]
+    Remote { url: String, message: String },
+    /// A ref that no longer points where an update expected it to.
+    #[error("Ref '{0}' has changed since it was read")]
+    StaleRef(String),
 }
 
+/// An object read back from the store, typed by its header.
 #[derive(Debug)]
 pub enum ObjectStore {
     Blob(Blob),
     Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
 }
 
 #[derive(Debug)]
@@ -33,122 +160,845 @@ pub struct Blob {
     data: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+/// How a file is checked out. Commits and the index only record the mode
+/// of files that are not regular ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileMode {
+    #[default]
+    Regular,
+    /// A regular file anyone who can read may also execute.
+    Executable,
+    /// A symbolic link, whose blob holds the path it points at.
+    Symlink,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub kind: ObjectKind,
+    pub hash: String,
+    /// Always regular for entries other than blobs.
+    pub mode: FileMode,
+}
+
+/// A single directory level, mapping entry names to blobs and subtrees.
+#[derive(Debug, Default)]
 pub struct Tree {
-    tree_type: String,
-    name: String,
-    hash: String,
-    children: Vec<ObjectStore>,
+    hash: Option<String>,
+    data: Option<Vec<u8>>,
+    entries: BTreeMap<String, TreeEntry>,
 }
 
 #[derive(Debug)]
 pub struct FileService {
     pub root_dir: PathBuf,
+    /// HEAD, the index and the other state of one working tree: `.blip`
+    /// itself, or for a linked working tree its directory under
+    /// `.blip/worktrees`, see [`worktrees`](crate::worktrees).
     pub blip_dir: PathBuf,
+    /// Objects, refs, config and hooks, shared by every working tree of
+    /// the repository. The same as `blip_dir` outside linked working trees.
+    pub common_dir: PathBuf,
     pub object_dir: PathBuf,
     pub index: PathBuf,
     pub head: PathBuf,
+    /// How objects in this repository are named, from its config.
+    pub hash_algorithm: HashAlgorithm,
+    /// How long to wait for the lock on a ref or the index, see
+    /// [`lock::timeout`].
+    pub lock_timeout: Duration,
+    /// Whether the repository has no working tree, `core.bare` in its
+    /// config. Its `root_dir` is then the repository directory itself.
+    pub bare: bool,
+    /// Loaded on first use, see [`FileService::config`].
+    config: OnceLock<Config>,
+    /// Loaded on first use, see [`FileService::packs`].
+    packs: OnceLock<Vec<Pack>>,
+    /// The boundary of a shallow clone, see [`FileService::shallow`].
+    shallow: RwLock<BTreeSet<String>>,
 }
 
 #[derive(Debug)]
 pub struct Index {
     pub path: PathBuf,
     pub hashtree: BTreeMap<String, String>,
+    /// Modes of the staged files that are not regular ones.
+    pub modes: BTreeMap<String, FileMode>,
+    /// Paths left unmerged by a conflicted merge.
+    pub conflicts: BTreeSet<String>,
+    /// Paths staged for deletion.
+    pub removed: BTreeSet<String>,
+    /// Stat data of working tree files when they were last hashed, with
+    /// the hash they had.
+    pub(crate) stat_cache: BTreeMap<String, (Stat, String)>,
+    /// Modification time of the index file when it was read. A file
+    /// modified at or after it may have changed again within the same
+    /// clock tick after being hashed, so its cached hash is not trusted.
+    pub(crate) written: Option<(i64, u32)>,
+    /// Taken by [`FileService::lock_index`] before reading, and given up by
+    /// the next write.
+    lock: Mutex<Option<LockFile>>,
+    lock_timeout: Duration,
+    hash_algorithm: HashAlgorithm,
+}
+
+/// Day names from Thursday, the weekday of the Unix epoch.
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Identity and timestamp of whoever authored or committed a change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    pub time: i64,
+    /// Offset from UTC in minutes.
+    pub offset: i32,
 }
 
 #[derive(Debug)]
 pub struct Commit {
     hash: Option<String>,
     data: Option<Vec<u8>>,
-    parent: Option<String>,
+    parents: Vec<String>,
+    tree: Option<String>,
     files: BTreeMap<String, String>,
+    /// Modes of the files that are not regular ones.
+    modes: BTreeMap<String, FileMode>,
+    author: Option<Signature>,
+    committer: Option<Signature>,
+    /// The armored signature of the commit without it, see [`sign`].
+    signature: Option<String>,
+    /// The key to sign with when the commit is written.
+    signing_key: Option<Box<SigningKey>>,
+    message: String,
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::IoError(e) => e.fmt(fmt),
-            Self::NoDirectory => fmt.write_str("No Directory Found"),
-            Self::InvalidIndex => fmt.write_str("Index is Corrupt"),
-            Self::InvalidObjectStore => fmt.write_str("Blip Repository is Corrupt"),
-            Self::EmptyCommit => fmt.write_str("No Files Staged for Commit"),
-        }
+/// An annotated tag object naming another object, usually a commit.
+#[derive(Debug)]
+pub struct Tag {
+    hash: Option<String>,
+    data: Option<Vec<u8>>,
+    object: String,
+    kind: ObjectKind,
+    name: String,
+    tagger: Option<Signature>,
+    message: String,
+    /// The armored signature following the message, see [`sign`].
+    signature: Option<String>,
+    /// The key to sign with when the tag is written.
+    signing_key: Option<Box<SigningKey>>,
+}
+
+/// The message of [`Error::InvalidIndex`].
+fn corrupt_index(path: &Path, line: &Option<usize>) -> String {
+    match line {
+        Some(line) => format!("Index is corrupt at line {line} of {}", path.display()),
+        None => format!("Index {} is corrupt", path.display()),
     }
 }
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Self {
-        Error::IoError(err)
+/// `items`, each after `separator`, for errors listing several things.
+fn list(items: &[String], separator: &str) -> String {
+    items
+        .iter()
+        .map(|item| format!("{separator}{item}"))
+        .collect()
+}
+
+/// Records what was being done, and to which file, when an I/O operation
+/// fails.
+pub trait Context<T> {
+    fn context(self, op: &'static str, path: &Path) -> Result<T>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn context(self, op: &'static str, path: &Path) -> Result<T> {
+        self.map_err(|source| Error::File {
+            op,
+            path: path.to_path_buf(),
+            source,
+        })
     }
 }
 
 impl Blob {
-    pub fn new(path: &PathBuf) -> Result<Blob> {
-        let mut file = File::open(path)?;
-        let mut data = Vec::new();
-
-        file.read_to_end(&mut data)?;
+    pub fn new(path: &PathBuf, algorithm: HashAlgorithm) -> Result<Blob> {
+        let data = fs::read(path).context("read", path)?;
+        Ok(Blob::from_data(data, algorithm))
+    }
 
-        let mut hash = Sha1::new();
-        hash.input(&data);
+    /// Hashes the file at `path` as a blob without reading it into memory.
+    pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<String> {
+        stream_blob(path, algorithm, |_| Ok(()))
+    }
 
-        Ok(Blob {
-            hash: hash.result_str(),
+    pub fn from_data(data: Vec<u8>, algorithm: HashAlgorithm) -> Blob {
+        Blob {
+            hash: hash_object(algorithm, ObjectKind::Blob, &data),
             data,
-        })
+        }
     }
 }
 
 impl Blob {
     pub fn hash(&self) -> &String {
-        return &self.hash;
+        &self.hash
     }
 
     pub fn data(&self) -> &Vec<u8> {
-        return &self.data;
+        &self.data
+    }
+}
+
+/// Size of the pieces large files are hashed and copied in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The `<type> <len>\0` header every stored object starts with.
+pub(crate) fn object_header(kind: ObjectKind, len: usize) -> String {
+    format!("{} {len}\0", kind.as_str())
+}
+
+/// Hashes an object's content together with its header, so that objects of
+/// different kinds never share a hash.
+pub(crate) fn hash_object(algorithm: HashAlgorithm, kind: ObjectKind, data: &[u8]) -> String {
+    algorithm.digest(&[object_header(kind, data.len()).as_bytes(), data])
+}
+
+/// Finds the header at the start of a stored object, returning where it
+/// ends, the object's kind and its content length.
+fn parse_header(stored: &[u8]) -> Option<(usize, ObjectKind, usize)> {
+    let end = stored.iter().position(|&b| b == 0)?;
+    let header = std::str::from_utf8(&stored[..end]).ok()?;
+    let (kind, len) = header.split_once(' ')?;
+    Some((end, ObjectKind::parse(kind)?, len.parse().ok()?))
+}
+
+/// Splits a stored object into its kind and content. Objects written before
+/// headers existed have none and come back with no kind.
+pub(crate) fn parse_object(hash: &str, stored: Vec<u8>) -> Result<(Option<ObjectKind>, Vec<u8>)> {
+    let Some((end, kind, len)) = parse_header(&stored) else {
+        return Ok((None, stored));
+    };
+
+    if stored.len() - end - 1 != len {
+        return Err(Error::InvalidObject {
+            hash: hash.to_string(),
+            kind,
+        });
+    }
+    Ok((Some(kind), stored[end + 1..].to_vec()))
+}
+
+/// Passes the file at `path` to `sink` as a stored blob, header first and
+/// then the content in chunks, and returns the blob's hash. Fails if the
+/// file changes size while it is read, as the header would then be wrong.
+fn stream_blob(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    mut sink: impl FnMut(&[u8]) -> Result<()>,
+) -> Result<String> {
+    let mut file = File::open(path).context("read", path)?;
+    let len = file.metadata().context("read", path)?.len();
+    let header = object_header(ObjectKind::Blob, len as usize);
+    let mut hasher = algorithm.hasher();
+    hasher.update(header.as_bytes());
+    sink(header.as_bytes())?;
+
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut read = 0;
+    loop {
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err).context("read", path),
+        };
+        read += n as u64;
+        hasher.update(&buf[..n]);
+        sink(&buf[..n])?;
+    }
+    if read != len {
+        return Err(io::Error::other("file changed while being read")).context("read", path);
+    }
+    Ok(hasher.finish())
+}
+
+/// The content of a blob, read from the object store as it is consumed. See
+/// [`FileService::open_blob`].
+#[derive(Debug)]
+pub struct BlobReader(BlobSource);
+
+#[derive(Debug)]
+enum BlobSource {
+    Loose(io::Take<BufReader<File>>),
+    Packed(io::Cursor<Vec<u8>>),
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            BlobSource::Loose(reader) => reader.read(buf),
+            BlobSource::Packed(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl ObjectKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Blob => "blob",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tag => "tag",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<ObjectKind> {
+        match name {
+            "blob" => Some(ObjectKind::Blob),
+            "tree" => Some(ObjectKind::Tree),
+            "commit" => Some(ObjectKind::Commit),
+            "tag" => Some(ObjectKind::Tag),
+            _ => None,
+        }
+    }
+}
+
+impl FileMode {
+    /// The mode of a file with `metadata`, as read without following
+    /// symbolic links.
+    pub fn from_metadata(metadata: &fs::Metadata) -> FileMode {
+        if metadata.is_symlink() {
+            FileMode::Symlink
+        } else if metadata.permissions().mode() & 0o111 != 0 {
+            FileMode::Executable
+        } else {
+            FileMode::Regular
+        }
+    }
+
+    /// The mode git gives files of this mode in trees and patches.
+    pub fn git_mode(&self) -> &'static str {
+        match self {
+            FileMode::Regular => "100644",
+            FileMode::Executable => "100755",
+            FileMode::Symlink => "120000",
+        }
+    }
+}
+
+impl Tree {
+    pub fn new() -> Tree {
+        Tree::default()
+    }
+
+    pub fn from(hash: &str, input: &str) -> Result<Tree> {
+        let mut tree = Tree::new();
+        tree.hash = Some(hash.to_string());
+
+        let entry = Regex::new(&format!(
+            r"^(blob|exec|link|tree|commit) ({HEX_PATTERN}) (.+)$"
+        ))
+        .unwrap();
+
+        for line in input.lines() {
+            let Some(caps) = entry.captures(line) else {
+                return Err(Error::InvalidObject {
+                    hash: hash.to_string(),
+                    kind: ObjectKind::Tree,
+                });
+            };
+            match &caps[1] {
+                "tree" => tree.insert(&caps[3], ObjectKind::Tree, &caps[2]),
+                "commit" => tree.insert(&caps[3], ObjectKind::Commit, &caps[2]),
+                "exec" => tree.insert_file(&caps[3], &caps[2], FileMode::Executable),
+                "link" => tree.insert_file(&caps[3], &caps[2], FileMode::Symlink),
+                _ => tree.insert_file(&caps[3], &caps[2], FileMode::Regular),
+            }
+        }
+
+        Ok(tree)
+    }
+}
+
+impl Tree {
+    pub fn hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    pub fn entries(&self) -> &BTreeMap<String, TreeEntry> {
+        &self.entries
+    }
+
+    pub fn insert(&mut self, name: &str, kind: ObjectKind, hash: &str) {
+        let entry = TreeEntry {
+            kind,
+            hash: hash.to_string(),
+            mode: FileMode::Regular,
+        };
+        self.entries.insert(name.to_string(), entry);
+    }
+
+    /// Inserts a blob entry for a file of mode `mode`.
+    pub fn insert_file(&mut self, name: &str, hash: &str, mode: FileMode) {
+        let entry = TreeEntry {
+            kind: ObjectKind::Blob,
+            hash: hash.to_string(),
+            mode,
+        };
+        self.entries.insert(name.to_string(), entry);
+    }
+
+    pub(crate) fn update(&mut self, algorithm: HashAlgorithm) {
+        let mut text = String::new();
+        for (name, entry) in self.entries.iter() {
+            let kind = match entry.mode {
+                FileMode::Executable => "exec",
+                FileMode::Symlink => "link",
+                FileMode::Regular => entry.kind.as_str(),
+            };
+            text.push_str(&format!("{kind} {} {name}\n", entry.hash));
+        }
+        let data = text.into_bytes();
+
+        self.hash = Some(hash_object(algorithm, ObjectKind::Tree, &data));
+        self.data = Some(data);
+    }
+}
+
+impl Signature {
+    /// Builds a signature for the current time from `BLIP_<ROLE>_NAME` and
+    /// `BLIP_<ROLE>_EMAIL`, where role is `AUTHOR` or `COMMITTER`, falling
+    /// back to `user.name` and `user.email` from the config and then to the
+    /// login name of the current user.
+    pub fn new(role: &str, config: &Config) -> Signature {
+        let name = env::var(format!("BLIP_{role}_NAME"))
+            .ok()
+            .or_else(|| config.get("user.name"))
+            .or_else(|| env::var("USER").ok())
+            .unwrap_or_else(|| "unknown".to_string());
+        let email = env::var(format!("BLIP_{role}_EMAIL"))
+            .ok()
+            .or_else(|| config.get("user.email"))
+            .unwrap_or_else(|| format!("{}@localhost", name.replace(' ', ".")));
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+
+        Signature {
+            name,
+            email,
+            time,
+            offset: 0,
+        }
+    }
+
+    pub fn parse(input: &str) -> Option<Signature> {
+        let re = Regex::new(r"^(.*) <(.*)> (-?\d+) ([+-])(\d{2})(\d{2})$").unwrap();
+        let caps = re.captures(input)?;
+
+        let hours: i32 = caps[5].parse().ok()?;
+        let minutes: i32 = caps[6].parse().ok()?;
+        let sign = if &caps[4] == "-" { -1 } else { 1 };
+
+        Some(Signature {
+            name: caps[1].to_string(),
+            email: caps[2].to_string(),
+            time: caps[3].parse().ok()?,
+            offset: sign * (hours * 60 + minutes),
+        })
+    }
+
+    /// Formats the timestamp in the signature's own timezone, e.g.
+    /// `Thu Oct 15 08:54:01 2026 +0000`.
+    pub fn date(&self) -> String {
+        let (weekday, year, month, day, secs) = self.civil();
+        format!(
+            "{} {} {} {:02}:{:02}:{:02} {} {}",
+            DAYS[weekday],
+            MONTHS[month - 1],
+            day,
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+            year,
+            self.offset_string(),
+        )
+    }
+
+    /// Formats the timestamp for an email `Date:` header, e.g.
+    /// `Thu, 15 Oct 2026 08:54:01 +0000`.
+    pub fn rfc2822_date(&self) -> String {
+        let (weekday, year, month, day, secs) = self.civil();
+        format!(
+            "{}, {} {} {} {:02}:{:02}:{:02} {}",
+            DAYS[weekday],
+            day,
+            MONTHS[month - 1],
+            year,
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+            self.offset_string(),
+        )
+    }
+
+    /// Formats the timestamp in the signature's own timezone the way
+    /// `blame` columns do, e.g. `2026-10-15 08:54:01 +0000`.
+    pub fn iso_date(&self) -> String {
+        let (_, year, month, day, secs) = self.civil();
+        format!(
+            "{year}-{month:02}-{day:02} {:02}:{:02}:{:02} {}",
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+            self.offset_string(),
+        )
+    }
+
+    /// Builds a signature from an email `From:` identity and `Date:`
+    /// header such as `Thu, 15 Oct 2026 08:54:01 +0000`, the weekday being
+    /// optional.
+    pub fn from_rfc2822(name: &str, email: &str, date: &str) -> Option<Signature> {
+        let date = date.split_once(", ").map_or(date, |(_, date)| date);
+        let parts: Vec<&str> = date.split_whitespace().collect();
+        let [day, month, year, time, offset] = parts[..] else {
+            return None;
+        };
+        let day: i64 = day.parse().ok()?;
+        let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+        let year: i64 = year.parse().ok()?;
+        let mut clock = time.split(':').map(str::parse::<i64>);
+        let (Some(Ok(hours)), Some(Ok(minutes)), Some(Ok(seconds))) =
+            (clock.next(), clock.next(), clock.next())
+        else {
+            return None;
+        };
+        let (sign, digits) = match offset.split_at_checked(1)? {
+            ("+", digits) => (1, digits),
+            ("-", digits) => (-1, digits),
+            _ => return None,
+        };
+        if digits.len() != 4 {
+            return None;
+        }
+        let offset: i32 =
+            digits[..2].parse::<i32>().ok()? * 60 + digits[2..].parse::<i32>().ok()?;
+
+        let days = days_from_civil(year, month, day);
+
+        let offset = sign * offset;
+        let local = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+        Some(Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            time: local - offset as i64 * 60,
+            offset,
+        })
+    }
+
+    /// The weekday, counted from Thursday, year, month, day and seconds
+    /// into the day of the timestamp in the signature's own timezone.
+    pub(crate) fn civil(&self) -> (usize, i64, usize, i64, i64) {
+        let local = self.time + self.offset as i64 * 60;
+        let days = local.div_euclid(86400);
+        let secs = local.rem_euclid(86400);
+
+        // civil date from days since the epoch, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+
+        (days.rem_euclid(7) as usize, year, month as usize, day, secs)
+    }
+
+    fn offset_string(&self) -> String {
+        let sign = if self.offset < 0 { '-' } else { '+' };
+        let offset = self.offset.abs();
+        format!("{sign}{:02}{:02}", offset / 60, offset % 60)
+    }
+}
+
+/// Days since the epoch of a civil date, see
+/// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            fmt,
+            "{} <{}> {} {}",
+            self.name,
+            self.email,
+            self.time,
+            self.offset_string()
+        )
     }
 }
 
 impl FileService {
+    /// Opens the repository containing the current directory.
     pub fn new() -> Result<FileService> {
-        let root_dir = FileService::find_root()?;
-        let blip_dir = root_dir.join(".blip");
-        let object_dir = blip_dir.join("objects");
+        FileService::open(&env::current_dir()?)
+    }
+
+    /// Opens the repository containing `dir`, searching upwards from it.
+    /// A bare repository is opened from its own directory or below it.
+    pub fn open(dir: &Path) -> Result<FileService> {
+        let mut dir = fs::canonicalize(dir)?;
+        loop {
+            if FileService::is_blip(&dir) {
+                let blip_dir = FileService::follow_blip_file(&dir.join(".blip"))?;
+                return FileService::at(dir, blip_dir);
+            }
+            if FileService::is_bare(&dir)? {
+                return FileService::at(dir.clone(), dir);
+            }
+            if !dir.pop() {
+                return Err(Error::NoDirectory);
+            }
+        }
+    }
+
+    /// Opens the working tree whose state is kept in `blip_dir`, even if
+    /// its directory is gone. Linked working trees name theirs in a
+    /// `blipdir` file, see [`worktrees`](crate::worktrees).
+    pub(crate) fn open_blip_dir(blip_dir: &Path) -> Result<FileService> {
+        let link = blip_dir.join("blipdir");
+        let root_dir = match fs::read_to_string(&link) {
+            Ok(contents) => Path::new(contents.trim_end())
+                .parent()
+                .map(Path::to_path_buf),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                blip_dir.parent().map(Path::to_path_buf)
+            }
+            Err(err) => return Err(err).context("read", &link),
+        };
+        let root_dir = root_dir.ok_or_else(|| Error::InvalidBlipFile(link.clone()))?;
+        FileService::at(root_dir, blip_dir.to_path_buf())
+    }
+
+    /// The directory the `.blip` at `path` stands for: itself, or where a
+    /// `.blip` file of a linked working tree points with its `blipdir:`
+    /// line.
+    fn follow_blip_file(path: &Path) -> Result<PathBuf> {
+        if path.is_dir() {
+            return Ok(path.to_path_buf());
+        }
+        let contents = fs::read_to_string(path).context("read", path)?;
+        let target = contents
+            .trim_end()
+            .strip_prefix("blipdir:")
+            .map(|target| path.parent().unwrap_or(path).join(target.trim()))
+            .filter(|target| target.is_dir())
+            .ok_or_else(|| Error::InvalidBlipFile(path.to_path_buf()))?;
+        fs::canonicalize(&target).context("read", &target)
+    }
+
+    fn at(root_dir: PathBuf, blip_dir: PathBuf) -> Result<FileService> {
+        // linked working trees name the shared directory in `commondir`
+        let common_path = blip_dir.join("commondir");
+        let common_dir = match fs::read_to_string(&common_path) {
+            Ok(contents) => {
+                let dir = blip_dir.join(contents.trim_end());
+                fs::canonicalize(&dir).context("read", &dir)?
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => blip_dir.clone(),
+            Err(err) => return Err(err).context("read", &common_path),
+        };
+        let object_dir = common_dir.join("objects");
         let index = blip_dir.join("index");
         let head = blip_dir.join("HEAD");
+        let hash_algorithm =
+            HashAlgorithm::from_config(&ConfigFile::load(common_dir.join("config"))?)?;
+        let shallow_path = common_dir.join("shallow");
+        let shallow = match fs::read_to_string(&shallow_path) {
+            Ok(contents) => contents.lines().map(str::to_string).collect(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(err) => return Err(err).context("read", &shallow_path),
+        };
 
-        Ok(FileService {
+        let mut file_service = FileService {
             root_dir,
             blip_dir,
+            common_dir,
             object_dir,
             index,
             head,
-        })
+            hash_algorithm,
+            lock_timeout: lock::DEFAULT_TIMEOUT,
+            bare: false,
+            config: OnceLock::new(),
+            packs: OnceLock::new(),
+            shallow: RwLock::new(shallow),
+        };
+        file_service.lock_timeout = lock::timeout(file_service.config()?.get("core.lockTimeout"))?;
+        // linked working trees of a bare repository are not bare themselves
+        file_service.bare = file_service.blip_dir == file_service.common_dir
+            && file_service
+                .config()?
+                .get_bool("core.bare")?
+                .unwrap_or(false);
+        if file_service.bare {
+            file_service.root_dir = file_service.blip_dir.clone();
+        }
+        file_service.migrate_objects()?;
+        Ok(file_service)
     }
 
-    pub fn init_blip(path: &str) -> Result<()> {
-        let path: PathBuf = [path, ".blip"].iter().collect();
+    /// Moves objects stored flat in `objects/`, as repositories created
+    /// before the fan-out layout have them, into their fan-out directory.
+    fn migrate_objects(&self) -> Result<()> {
+        let entries = fs::read_dir(&self.object_dir).context("read", &self.object_dir)?;
+        for entry in entries {
+            let path = entry.context("read", &self.object_dir)?.path();
+            let Some(hash) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if !self.hash_algorithm.is_hash(hash) || !path.is_file() {
+                continue;
+            }
+
+            let target = self.object_path(hash);
+            if let Some(dir) = target.parent() {
+                fs::create_dir_all(dir).context("create", dir)?;
+            }
+            fs::rename(&path, &target).context("move", &path)?;
+        }
+        Ok(())
+    }
 
-        fs::create_dir_all(path.join("objects"))?;
-        fs::create_dir_all(path.join("refs").join("heads"))?;
+    /// Where the object `hash` is stored: `objects/ab/cdef...`, spreading
+    /// objects over up to 256 directories named by their first byte.
+    pub fn object_path(&self, hash: &str) -> PathBuf {
+        let (dir, file) = hash.split_at(2.min(hash.len()));
+        self.object_dir.join(dir).join(file)
+    }
 
-        File::create(path.join("index"))?;
-        let mut head = File::create(path.join("HEAD"))?;
-        head.write_all("ref: refs/heads/master".as_bytes());
+    /// Where packfiles are kept, see [`Pack`].
+    pub fn pack_dir(&self) -> PathBuf {
+        self.object_dir.join("pack")
+    }
 
-        Ok(())
+    /// The packs of the repository, opened once per `FileService`.
+    pub fn packs(&self) -> Result<&[Pack]> {
+        if let Some(packs) = self.packs.get() {
+            return Ok(packs);
+        }
+        let packs = Pack::load_all(&self.pack_dir())?;
+        Ok(self.packs.get_or_init(|| packs))
     }
 
-    fn find_root() -> Result<PathBuf> {
-        let mut current_dir = env::current_dir()?;
-        loop {
-            if FileService::is_blip(&current_dir) {
-                return Ok(current_dir);
+    /// Lists the hashes of all objects stored outside of packs.
+    pub fn loose_objects(&self) -> Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        let dirs = fs::read_dir(&self.object_dir).context("read", &self.object_dir)?;
+        for dir in dirs {
+            let dir = dir.context("read", &self.object_dir)?.path();
+            let Some(prefix) = dir.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if prefix.len() != 2 || !dir.is_dir() {
+                continue;
             }
-            if !current_dir.pop() {
-                return Err(Error::NoDirectory);
+            for file in fs::read_dir(&dir).context("read", &dir)? {
+                let file = file.context("read", &dir)?.file_name();
+                let hash = format!("{prefix}{}", file.to_string_lossy());
+                if self.hash_algorithm.is_hash(&hash) {
+                    hashes.push(hash);
+                }
             }
         }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    /// Whether the object `hash` is stored, loose or packed.
+    pub fn has_object(&self, hash: &str) -> Result<bool> {
+        if self.object_path(hash).is_file() {
+            return Ok(true);
+        }
+        Ok(self.packs()?.iter().any(|pack| pack.contains(hash)))
+    }
+
+    /// Creates the `.blip` directory of a repository in `path`, or makes
+    /// `path` itself a repository without a working tree if `bare` is set.
+    pub fn init_blip(
+        path: &Path,
+        branch: &str,
+        algorithm: HashAlgorithm,
+        bare: bool,
+    ) -> Result<()> {
+        let path = match bare {
+            true => path.to_path_buf(),
+            false => path.join(".blip"),
+        };
+
+        for dir in [
+            path.join("objects"),
+            path.join("refs").join("heads"),
+            path.join("hooks"),
+        ] {
+            fs::create_dir_all(&dir).context("create", &dir)?;
+        }
+
+        if !bare {
+            let index = path.join("index");
+            File::create(&index).context("create", &index)?;
+        }
+        let head = path.join("HEAD");
+        let contents = Head::Symbolic(Refs::branch_ref(branch)).to_string();
+        fs::write(&head, contents).context("write", &head)?;
+
+        let mut config = ConfigFile::load(path.join("config"))?;
+        if config.get_all("core.repositoryformatversion").is_empty() {
+            algorithm.write_config(&mut config)?;
+        }
+        if bare {
+            config.set("core.bare", "true")?;
+        }
+        config.save()
+    }
+
+    /// The system, global and repository config merged together, read once
+    /// per `FileService`.
+    pub fn config(&self) -> Result<&Config> {
+        if let Some(config) = self.config.get() {
+            return Ok(config);
+        }
+        let config = Config::load(Some(&self.common_dir))?;
+        Ok(self.config.get_or_init(|| config))
+    }
+
+    /// The identity to record for `role`, see [`Signature::new`].
+    pub fn signature(&self, role: &str) -> Result<Signature> {
+        Ok(Signature::new(role, self.config()?))
     }
 
     fn is_blip<P>(path: P) -> bool
@@ -157,60 +1007,528 @@ impl FileService {
     {
         path.as_ref().join(".blip").exists()
     }
+
+    /// Whether `dir` is a bare repository: it holds what `.blip` would and
+    /// its config says it is bare.
+    fn is_bare(dir: &Path) -> Result<bool> {
+        if !dir.join("HEAD").is_file() || !dir.join("objects").is_dir() {
+            return Ok(false);
+        }
+        let config = ConfigFile::load(dir.join("config"))?;
+        let bare = config.get_all("core.bare").pop();
+        Ok(bare.is_some_and(|bare| bare.eq_ignore_ascii_case("true")))
+    }
+
+    /// Fails in a bare repository, which has no working tree or index to
+    /// work with.
+    pub fn check_work_tree(&self) -> Result<()> {
+        match self.bare {
+            true => Err(Error::BareRepository),
+            false => Ok(()),
+        }
+    }
 }
 
 impl FileService {
+    /// Turns a path given relative to the current directory into one
+    /// relative to the repository root, without touching the filesystem so
+    /// that deleted files can still be named.
+    pub fn repo_path(&self, path: &str) -> Result<String> {
+        let mut full_path = PathBuf::new();
+        for component in env::current_dir()?.join(path).components() {
+            match component {
+                Component::ParentDir => {
+                    full_path.pop();
+                }
+                Component::CurDir => {}
+                component => full_path.push(component),
+            }
+        }
+
+        full_path
+            .strip_prefix(&self.root_dir)
+            .ok()
+            .and_then(|path| path.to_str())
+            .map(|path| path.to_string())
+            .ok_or_else(|| Error::Usage(format!("'{path}' is outside repository")))
+    }
+
+    /// Reads HEAD, which must hold a ref under `refs/` or a hash.
+    pub fn read_head(&self) -> Result<Head> {
+        let contents = fs::read_to_string(&self.head).context("read", &self.head)?;
+        Head::parse(&contents, self.hash_algorithm)
+            .ok_or_else(|| Error::InvalidHead(contents.trim_end().to_string()))
+    }
+
+    /// Replaces the contents of HEAD.
+    pub fn write_head(&self, head: &Head) -> Result<()> {
+        lock::write(&self.head, head.to_string().as_bytes(), self.lock_timeout)
+    }
+
+    /// Returns the ref file HEAD points at, or HEAD itself when it is
+    /// detached and holds a commit hash directly.
     pub fn get_head_ref(&self) -> Result<PathBuf> {
-        let mut head_file = File::open(self.head.clone())?;
-        let mut ref_path = String::new();
-        head_file.read_to_string(&mut ref_path)?;
-        let ref_path = ref_path.split_off(5);
+        match self.read_head()? {
+            Head::Symbolic(name) => Ok(self.common_dir.join(name)),
+            Head::Detached(_) => Ok(self.head.clone()),
+        }
+    }
 
-        Ok(self.blip_dir.join(ref_path))
+    /// The commits at the boundary of a shallow clone, listed one per line
+    /// in `.blip/shallow`. Their parents were never fetched, and they are
+    /// read as if they had none.
+    pub fn shallow(&self) -> BTreeSet<String> {
+        self.shallow
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
     }
 
-    pub fn get_hash_from_ref(ref_path: &PathBuf) -> Option<String> {
-        match File::open(ref_path) {
-            Ok(mut f) => {
-                let mut hash = String::new();
-                f.read_to_string(&mut hash)
-                    .expect("Error: Ref File is Corrupt");
-                return Some(hash);
+    pub fn is_shallow(&self, hash: &str) -> bool {
+        self.shallow
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(hash)
+    }
+
+    /// Replaces the shallow boundary, removing `.blip/shallow` once history
+    /// is complete.
+    pub fn set_shallow(&self, commits: BTreeSet<String>) -> Result<()> {
+        let path = self.common_dir.join("shallow");
+        if commits.is_empty() {
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(err).context("remove", &path)
+                }
+                _ => {}
+            }
+        } else {
+            let contents: String = commits.iter().map(|hash| format!("{hash}\n")).collect();
+            fs::write(&path, contents).context("write", &path)?;
+        }
+        *self.shallow.write().unwrap_or_else(PoisonError::into_inner) = commits;
+        Ok(())
+    }
+
+    /// The commit HEAD is at, or `None` on a branch with no commits yet.
+    pub fn read_head_hash(&self) -> Result<Option<String>> {
+        Ok(match self.read_head()? {
+            Head::Symbolic(name) => Refs::new(self).read(&name),
+            Head::Detached(hash) => Some(hash),
+        })
+    }
+
+    /// Moves whatever HEAD points at, the current branch or a detached
+    /// HEAD, to `hash`.
+    pub fn update_head(&self, hash: &str) -> Result<()> {
+        match self.read_head()? {
+            Head::Symbolic(name) => Refs::new(self).write(&name, hash),
+            Head::Detached(_) => self.write_head(&Head::Detached(hash.to_string())),
+        }
+    }
+
+    /// Moves HEAD like [`FileService::update_head`], provided it is still at
+    /// `old`, `None` meaning an unborn branch. The check and the write
+    /// happen under the lock of the ref moved.
+    pub fn advance_head(&self, old: Option<&str>, hash: &str) -> Result<()> {
+        match self.read_head()? {
+            Head::Symbolic(name) => match Refs::new(self).compare_and_write(&name, old, hash)? {
+                true => Ok(()),
+                false => Err(Error::StaleRef(name)),
+            },
+            Head::Detached(_) => {
+                let mut lock = LockFile::acquire(&self.head, self.lock_timeout)?;
+                if self.read_head()? != Head::Detached(old.unwrap_or_default().to_string()) {
+                    return Err(Error::StaleRef("HEAD".to_string()));
+                }
+                lock.write_all(Head::Detached(hash.to_string()).to_string().as_bytes())?;
+                lock.commit()
+            }
+        }
+    }
+
+    /// Points HEAD at a branch, or detaches it at a commit if `branch` is `None`.
+    pub fn set_head(&self, branch: Option<&str>, hash: &str) -> Result<()> {
+        self.write_head(&match branch {
+            Some(branch) => Head::Symbolic(Refs::branch_ref(branch)),
+            None => Head::Detached(hash.to_string()),
+        })
+    }
+
+    /// Resolves a revision such as `main`, `HEAD~2` or an abbreviated hash
+    /// to a commit hash, see [`revision::resolve`].
+    pub fn resolve(&self, revision: &str) -> Result<String> {
+        revision::resolve(self, revision)
+    }
+
+    /// Resolves `HEAD`, a branch or tag name, a full hash or a unique
+    /// prefix of one to the hash it names, which may be an annotated tag.
+    pub(crate) fn resolve_name(&self, name: &str) -> Result<String> {
+        let refs = Refs::new(self);
+        let found = match name {
+            "HEAD" => self.read_head_hash()?,
+            _ if name.starts_with("refs/") => refs.read(name),
+            _ => refs
+                .read(&Refs::branch_ref(name))
+                .or_else(|| refs.read(&Refs::tag_ref(name)))
+                .or_else(|| refs.read(&format!("refs/remotes/{name}"))),
+        };
+        if let Some(hash) = found {
+            return Ok(hash);
+        }
+
+        if self.hash_algorithm.is_hash(name) && self.has_object(name)? {
+            return Ok(name.to_string());
+        }
+
+        let is_prefix = name.len() >= revision::MIN_PREFIX
+            && name.len() < self.hash_algorithm.hex_len()
+            && name.chars().all(|c| c.is_ascii_hexdigit());
+        if is_prefix {
+            match &self.find_prefix(&name.to_ascii_lowercase())?[..] {
+                [] => {}
+                [hash] => return Ok(hash.clone()),
+                hashes => {
+                    return Err(Error::AmbiguousRevision {
+                        prefix: name.to_string(),
+                        candidates: hashes.iter().map(|hash| self.describe(hash)).collect(),
+                    })
+                }
+            }
+        }
+
+        Err(Error::UnknownRevision(name.to_string()))
+    }
+
+    /// One line telling an object apart from others sharing a prefix: its
+    /// hash and kind, and the summary of a commit.
+    fn describe(&self, hash: &str) -> String {
+        match self.read_raw(hash) {
+            Ok((Some(ObjectKind::Commit), _)) => {
+                let summary = self.read_commit_headers(hash).map(|commit| {
+                    commit
+                        .message()
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string()
+                });
+                format!("{hash} commit {}", summary.unwrap_or_default())
+            }
+            Ok((Some(kind), _)) => format!("{hash} {}", kind.as_str()),
+            Ok((None, _)) => format!("{hash} object"),
+            Err(_) => format!("{hash} (unreadable)"),
+        }
+    }
+
+    /// Every stored object, loose or packed, whose hash starts with
+    /// `prefix`.
+    pub fn find_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut hashes = BTreeSet::new();
+        let dir = self.object_dir.join(&prefix[..2]);
+        if dir.is_dir() {
+            for file in fs::read_dir(&dir).context("read", &dir)? {
+                let file = file.context("read", &dir)?.file_name();
+                let hash = format!("{}{}", &prefix[..2], file.to_string_lossy());
+                if hash.starts_with(prefix) && self.hash_algorithm.is_hash(&hash) {
+                    hashes.insert(hash);
+                }
             }
-            Err(_) => None,
+        }
+        for pack in self.packs()? {
+            hashes.extend(
+                pack.hashes()
+                    .filter(|hash| hash.starts_with(prefix))
+                    .cloned(),
+            );
+        }
+        Ok(hashes.into_iter().collect())
+    }
+
+    /// Follows annotated tags until reaching an object that is not a tag.
+    pub(crate) fn peel(&self, hash: &str) -> Result<String> {
+        let mut hash = hash.to_string();
+        while let Some(tag) = self.try_read_tag(&hash)? {
+            hash = tag.object;
+        }
+        Ok(hash)
+    }
+
+    /// Reads the hash stored in a ref file, or `None` if the file is
+    /// missing or unreadable. `fsck` reports refs that are broken.
+    pub fn get_hash_from_ref(ref_path: &PathBuf) -> Option<String> {
+        fs::read_to_string(ref_path)
+            .ok()
+            .map(|hash| hash.trim().to_string())
+    }
+
+    pub fn read_head_commit(&self) -> Result<Option<Commit>> {
+        match self.read_head_hash()? {
+            Some(hash) => Ok(Some(self.read_commit(&hash)?)),
+            None => Ok(None),
         }
     }
 
     pub fn read_commit(&self, hash: &str) -> Result<Commit> {
-        Commit::from(hash, &self.read_object(hash)?)
+        let mut commit = self.read_commit_headers(hash)?;
+        if let Some(ref tree) = commit.tree {
+            (commit.files, commit.modes) = self.read_tree_entries(tree)?;
+        }
+        Ok(commit)
+    }
+
+    /// Reads a commit's headers and message without loading its tree,
+    /// which is all history traversals need. Commits at the boundary of a
+    /// shallow clone come without their parents.
+    pub fn read_commit_headers(&self, hash: &str) -> Result<Commit> {
+        let mut commit = Commit::from(hash, &self.read_text(hash, ObjectKind::Commit)?)?;
+        if self.is_shallow(hash) {
+            commit.parents.clear();
+        }
+        Ok(commit)
+    }
+
+    pub fn read_tag(&self, hash: &str) -> Result<Tag> {
+        self.try_read_tag(hash)?
+            .ok_or_else(|| Error::InvalidObject {
+                hash: hash.to_string(),
+                kind: ObjectKind::Tag,
+            })
+    }
+
+    /// Reads an object as a tag, or returns `None` if it is some other kind.
+    fn try_read_tag(&self, hash: &str) -> Result<Option<Tag>> {
+        let (kind, data) = self.read_raw(hash)?;
+        let Ok(data) = String::from_utf8(data) else {
+            return Ok(None);
+        };
+        // objects without a header can only be told apart by their content
+        match kind {
+            Some(ObjectKind::Tag) => Ok(Some(Tag::from(hash, &data)?)),
+            None if Tag::is_tag(&data) => Ok(Some(Tag::from(hash, &data)?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn write_tag(&self, tag: &mut Tag) -> Result<String> {
+        tag.update(self.hash_algorithm);
+        match tag {
+            Tag {
+                hash: Some(ref hash),
+                data: Some(ref data),
+                ..
+            } => {
+                self.write_obj(ObjectKind::Tag, hash, data)?;
+                Ok(hash.clone())
+            }
+            _ => unreachable!("update sets the hash and data"),
+        }
+    }
+
+    pub fn read_tree(&self, hash: &str) -> Result<Tree> {
+        Tree::from(hash, &self.read_text(hash, ObjectKind::Tree)?)
+    }
+
+    /// Reads a tree and all of its subtrees into a flat map of path to blob hash.
+    pub fn read_tree_files(&self, hash: &str) -> Result<BTreeMap<String, String>> {
+        Ok(self.read_tree_entries(hash)?.0)
+    }
+
+    /// Like [`FileService::read_tree_files`], along with the modes of the
+    /// files that are not regular ones.
+    pub fn read_tree_entries(
+        &self,
+        hash: &str,
+    ) -> Result<(BTreeMap<String, String>, BTreeMap<String, FileMode>)> {
+        let mut files = BTreeMap::new();
+        let mut modes = BTreeMap::new();
+        self.collect_tree_files(hash, "", &mut files, &mut modes)?;
+        Ok((files, modes))
+    }
+
+    fn collect_tree_files(
+        &self,
+        hash: &str,
+        prefix: &str,
+        files: &mut BTreeMap<String, String>,
+        modes: &mut BTreeMap<String, FileMode>,
+    ) -> Result<()> {
+        for (name, entry) in self.read_tree(hash)?.entries() {
+            let path = format!("{prefix}{name}");
+            match entry.kind {
+                ObjectKind::Tree => {
+                    self.collect_tree_files(&entry.hash, &format!("{path}/"), files, modes)?
+                }
+                _ => {
+                    if entry.mode != FileMode::Regular {
+                        modes.insert(path.clone(), entry.mode);
+                    }
+                    files.insert(path, entry.hash.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads and parses any object. Objects stored without a header are
+    /// returned as blobs since nothing tells what else they might be.
+    pub fn read_object(&self, hash: &str) -> Result<ObjectStore> {
+        let (kind, data) = self.read_raw(hash)?;
+        let text = || {
+            String::from_utf8(data.clone()).map_err(|_| Error::InvalidObject {
+                hash: hash.to_string(),
+                kind: kind.unwrap_or(ObjectKind::Blob),
+            })
+        };
+        Ok(match kind {
+            None | Some(ObjectKind::Blob) => ObjectStore::Blob(Blob {
+                hash: hash.to_string(),
+                data,
+            }),
+            Some(ObjectKind::Tree) => ObjectStore::Tree(Tree::from(hash, &text()?)?),
+            Some(ObjectKind::Commit) => {
+                let mut commit = Commit::from(hash, &text()?)?;
+                if let Some(ref tree) = commit.tree {
+                    commit.files = self.read_tree_files(tree)?;
+                }
+                ObjectStore::Commit(commit)
+            }
+            Some(ObjectKind::Tag) => ObjectStore::Tag(Tag::from(hash, &text()?)?),
+        })
+    }
+
+    pub fn read_blob(&self, hash: &str) -> Result<Vec<u8>> {
+        self.read_kind(hash, ObjectKind::Blob)
+    }
+
+    /// Opens a blob for reading without loading it into memory. Packed
+    /// blobs have to be rebuilt from their deltas first, so only loose ones
+    /// are actually streamed.
+    pub fn open_blob(&self, hash: &str) -> Result<BlobReader> {
+        let path = self.object_path(hash);
+        let file = match File::open(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let data = self.read_blob(hash)?;
+                return Ok(BlobReader(BlobSource::Packed(io::Cursor::new(data))));
+            }
+            result => result.context("read", &path)?,
+        };
+        let size = file.metadata().context("read", &path)?.len();
+        let invalid = |kind| Error::InvalidObject {
+            hash: hash.to_string(),
+            kind,
+        };
+
+        let mut reader = BufReader::with_capacity(CHUNK_SIZE, file);
+        let len = match parse_header(reader.fill_buf().context("read", &path)?) {
+            Some((_, kind, _)) if kind != ObjectKind::Blob => return Err(invalid(kind)),
+            Some((end, _, len)) => {
+                if size != (end + 1 + len) as u64 {
+                    return Err(invalid(ObjectKind::Blob));
+                }
+                reader.consume(end + 1);
+                len as u64
+            }
+            None => size,
+        };
+        Ok(BlobReader(BlobSource::Loose(reader.take(len))))
+    }
+
+    /// Reads the stored bytes of an object, split into its kind and content,
+    /// falling back to the packs when there is no loose copy.
+    pub(crate) fn read_raw(&self, hash: &str) -> Result<(Option<ObjectKind>, Vec<u8>)> {
+        let path = self.object_path(hash);
+        match fs::read(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            result => return parse_object(hash, result.context("read", &path)?),
+        }
+
+        for pack in self.packs()? {
+            if let Some(object) = pack.read(hash)? {
+                return Ok(object);
+            }
+        }
+        Err(Error::MissingObject(hash.to_string()))
     }
 
-    fn read_object(&self, hash: &str) -> Result<String> {
-        let mut data = String::new();
-        let mut object_file = File::open(self.object_dir.join(hash))?;
-        object_file.read_to_string(&mut data)?;
+    /// Reads the content of an object, which must be of `kind` unless it
+    /// predates object headers.
+    fn read_kind(&self, hash: &str, kind: ObjectKind) -> Result<Vec<u8>> {
+        match self.read_raw(hash)? {
+            (Some(actual), _) if actual != kind => Err(Error::InvalidObject {
+                hash: hash.to_string(),
+                kind,
+            }),
+            (_, data) => Ok(data),
+        }
+    }
 
-        Ok(data)
+    /// Reads an object of `kind` as text.
+    fn read_text(&self, hash: &str, kind: ObjectKind) -> Result<String> {
+        String::from_utf8(self.read_kind(hash, kind)?).map_err(|_| Error::InvalidObject {
+            hash: hash.to_string(),
+            kind,
+        })
     }
 
+    /// Reads the index, accepting the old text format of `<path> <hash>`,
+    /// `<path> <hash> U` for conflicts and `<path> -` for deletions, which
+    /// the next write replaces with the binary one.
     pub fn read_index(&self) -> Result<Index> {
-        let mut index_data = BTreeMap::new();
+        self.check_work_tree()?;
+        let mut index = Index::new(self.index.clone(), self.lock_timeout, self.hash_algorithm);
+        let data = fs::read(&self.index).context("read", &self.index)?;
+        if let Ok(metadata) = fs::metadata(&self.index) {
+            let stat = Stat::from_metadata(&metadata);
+            index.written = Some((stat.mtime, stat.mtime_nsec));
+        }
+        if index::is_binary(&data) {
+            index::decode(&mut index, &data, self.hash_algorithm).ok_or_else(|| {
+                Error::InvalidIndex {
+                    path: self.index.clone(),
+                    line: None,
+                }
+            })?;
+            return Ok(index);
+        }
 
-        let file = BufReader::new(File::open(&self.index)?);
-        for line in file.lines() {
-            let line = line?;
-            let blob: Vec<_> = line.split(' ').collect();
-            if blob.len() != 2 {
-                return Err(Error::InvalidIndex);
+        let invalid = |number: usize| Error::InvalidIndex {
+            path: self.index.clone(),
+            line: Some(number + 1),
+        };
+        let text = String::from_utf8(data).map_err(|_| Error::InvalidIndex {
+            path: self.index.clone(),
+            line: None,
+        })?;
+        for (number, line) in text.lines().enumerate() {
+            match line.split(' ').collect::<Vec<_>>()[..] {
+                [path, "-"] => index.remove(path),
+                [path, hash] => index.update(path, hash),
+                [path, hash, "U"] => index.mark_conflict(path, hash),
+                _ => return Err(invalid(number)),
             }
-            index_data.insert(blob[0].to_string(), blob[1].to_string());
         }
+        Ok(index)
+    }
 
-        Ok(Index::new(self.index.clone(), index_data))
+    /// Stores a commit and moves HEAD to it from `old`, the commit it was
+    /// made on.
+    pub(crate) fn write_commit(&self, commit: &mut Commit, old: Option<&str>) -> Result<()> {
+        let hash = self.write_commit_object(commit)?;
+        self.advance_head(old, &hash)
     }
 
-    pub(crate) fn write_commit(&self, commit: &mut Commit) -> Result<()> {
-        commit.update();
+    /// Stores a commit and its trees without moving HEAD.
+    pub(crate) fn write_commit_object(&self, commit: &mut Commit) -> Result<String> {
+        let tree = self.write_tree(&commit.files, &commit.modes)?;
+        self.write_commit_tree(commit, &tree)
+    }
+
+    /// Stores a commit of the existing tree `tree` without moving HEAD.
+    pub(crate) fn write_commit_tree(&self, commit: &mut Commit, tree: &str) -> Result<String> {
+        (commit.files, commit.modes) = self.read_tree_entries(tree)?;
+        commit.tree = Some(tree.to_string());
+        commit.update(self.hash_algorithm);
 
         match commit {
             &mut Commit {
@@ -218,122 +1536,433 @@ impl FileService {
                 data: Some(ref data),
                 ..
             } => {
-                self.write_obj(hash, data)?;
-                let mut head_file = File::create(self.get_head_ref()?)?;
-                head_file.write_all(hash.as_bytes())?;
-            }
-            _ => {
-                return Err(Error::EmptyCommit);
+                self.write_obj(ObjectKind::Commit, hash, data)?;
+                Ok(hash.clone())
             }
+            _ => Err(Error::EmptyCommit),
         }
+    }
 
-        Ok(())
+    /// Reads the index like [`FileService::read_index`], but under its lock,
+    /// held until the index is written back, so that nobody else can change
+    /// it in between and have the change lost.
+    pub fn lock_index(&self) -> Result<Index> {
+        self.check_work_tree()?;
+        let lock = LockFile::acquire(&self.index, self.lock_timeout)?;
+        let index = self.read_index()?;
+        *index.lock.lock().unwrap_or_else(PoisonError::into_inner) = Some(lock);
+        Ok(index)
     }
 
     pub fn write_index(&self, index: &Index) -> Result<()> {
-        let mut file = File::create(self.index.clone())?;
-        for (hash, path) in index.hashtree().iter() {
-            writeln!(&mut file, "{} {}", hash, path);
+        index.write()
+    }
+
+    /// Writes the tree hierarchy for a flat map of path to blob hash, one
+    /// tree object per directory, and returns the hash of the root tree.
+    /// Files missing from `modes` are regular ones. Submodules, see
+    /// [`submodule`](crate::submodule), map to the commit they are at
+    /// instead.
+    pub fn write_tree(
+        &self,
+        files: &BTreeMap<String, String>,
+        modes: &BTreeMap<String, FileMode>,
+    ) -> Result<String> {
+        let submodules = submodule::paths(self, files)?;
+        self.write_subtree(files, modes, &submodules, "")
+    }
+
+    /// Writes the tree of the directory `prefix`, whose files are `files`.
+    fn write_subtree(
+        &self,
+        files: &BTreeMap<String, String>,
+        modes: &BTreeMap<String, FileMode>,
+        submodules: &BTreeSet<String>,
+        prefix: &str,
+    ) -> Result<String> {
+        let mut tree = Tree::new();
+        let mut subdirs: BTreeMap<&str, BTreeMap<String, String>> = BTreeMap::new();
+
+        for (path, hash) in files.iter() {
+            match path.split_once('/') {
+                Some((dir, rest)) => {
+                    subdirs
+                        .entry(dir)
+                        .or_default()
+                        .insert(rest.to_string(), hash.clone());
+                }
+                None if submodules.contains(&format!("{prefix}{path}")) => {
+                    tree.insert(path, ObjectKind::Commit, hash)
+                }
+                None => {
+                    let mode = modes.get(&format!("{prefix}{path}")).copied();
+                    tree.insert_file(path, hash, mode.unwrap_or_default())
+                }
+            }
+        }
+
+        for (dir, files) in subdirs.iter() {
+            let hash = self.write_subtree(files, modes, submodules, &format!("{prefix}{dir}/"))?;
+            tree.insert(dir, ObjectKind::Tree, &hash);
+        }
+
+        tree.update(self.hash_algorithm);
+        match tree {
+            Tree {
+                hash: Some(ref hash),
+                data: Some(ref data),
+                ..
+            } => {
+                self.write_obj(ObjectKind::Tree, hash, data)?;
+                Ok(hash.clone())
+            }
+            _ => unreachable!("update sets the hash and data"),
         }
-        Ok(())
     }
 
     pub fn write_blob(&self, blob: &Blob) -> Result<()> {
-        self.write_obj(blob.hash(), blob.data())
+        self.write_obj(ObjectKind::Blob, blob.hash(), blob.data())
+    }
+
+    /// Stores the file at `path` as a blob and returns its hash. The file is
+    /// copied into the object store in chunks, so it never has to fit in
+    /// memory.
+    pub fn write_blob_file(&self, path: &Path) -> Result<String> {
+        let temp = self.temp_object_path();
+        let hash = File::create(&temp)
+            .context("create", &temp)
+            .and_then(|mut out| {
+                stream_blob(path, self.hash_algorithm, |chunk| {
+                    out.write_all(chunk).context("write", &temp)
+                })
+            });
+        let hash = match hash {
+            Ok(hash) => hash,
+            Err(error) => {
+                let _ = fs::remove_file(&temp);
+                return Err(error);
+            }
+        };
+
+        let object = self.object_path(&hash);
+        if object.is_file() {
+            fs::remove_file(&temp).context("remove", &temp)?;
+            return Ok(hash);
+        }
+        if let Some(dir) = object.parent() {
+            fs::create_dir_all(dir).context("create", dir)?;
+        }
+        fs::rename(&temp, &object).context("rename", &temp)?;
+        Ok(hash)
+    }
+
+    fn write_obj(&self, kind: ObjectKind, hash: &str, data: &[u8]) -> Result<()> {
+        self.write_loose(hash, Some(kind), data).map(|_| ())
     }
 
-    fn write_obj(&self, hash: &str, data: &Vec<u8>) -> Result<()> {
-        let mut blob = File::create(self.object_dir.join(hash))?;
-        blob.write_all(data)?;
+    /// The hash an object is stored under: that of its header and content,
+    /// or of the content alone for objects from before headers.
+    pub fn hash_raw(&self, kind: Option<ObjectKind>, data: &[u8]) -> String {
+        match kind {
+            Some(kind) => hash_object(self.hash_algorithm, kind, data),
+            None => self.hash_algorithm.digest(&[data]),
+        }
+    }
 
+    /// Deletes the loose copy of `hash`, and its fan-out directory once that
+    /// is empty.
+    pub(crate) fn remove_loose(&self, hash: &str) -> Result<()> {
+        let path = self.object_path(hash);
+        fs::remove_file(&path).context("remove", &path)?;
+        if let Some(dir) = path.parent() {
+            // only succeeds once the directory is empty
+            let _ = fs::remove_dir(dir);
+        }
         Ok(())
     }
+
+    /// Stores an object as a loose file under `hash`, prefixed with its
+    /// header unless it has no kind, and returns the file's path.
+    pub(crate) fn write_loose(
+        &self,
+        hash: &str,
+        kind: Option<ObjectKind>,
+        data: &[u8],
+    ) -> Result<PathBuf> {
+        let path = self.object_path(hash);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("create", dir)?;
+        }
+        let mut stored = kind
+            .map(|kind| object_header(kind, data.len()).into_bytes())
+            .unwrap_or_default();
+        stored.extend_from_slice(data);
+        // a file cut short would be taken for the object and never fetched
+        // again, so it only appears under its hash once whole
+        let temp = self.temp_object_path();
+        if let Err(error) = fs::write(&temp, stored).context("write", &temp) {
+            let _ = fs::remove_file(&temp);
+            return Err(error);
+        }
+        fs::rename(&temp, &path).context("rename", &temp)?;
+        Ok(path)
+    }
+
+    /// A path in the object directory to write an object to before it is
+    /// renamed into place, unique to this process and call.
+    fn temp_object_path(&self) -> PathBuf {
+        static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+        self.object_dir.join(format!(
+            "tmp_obj_{}_{}",
+            process::id(),
+            TEMP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
 }
 
 impl Index {
-    fn new(path: PathBuf, hashtree: BTreeMap<String, String>) -> Self {
-        Index { path, hashtree }
+    pub(crate) fn new(
+        path: PathBuf,
+        lock_timeout: Duration,
+        hash_algorithm: HashAlgorithm,
+    ) -> Self {
+        Index {
+            path,
+            hashtree: BTreeMap::new(),
+            modes: BTreeMap::new(),
+            conflicts: BTreeSet::new(),
+            removed: BTreeSet::new(),
+            stat_cache: BTreeMap::new(),
+            written: None,
+            lock: Mutex::new(None),
+            lock_timeout,
+            hash_algorithm,
+        }
     }
 }
 
 impl Index {
-    fn hashtree(&self) -> &BTreeMap<String, String> {
+    pub(crate) fn hashtree(&self) -> &BTreeMap<String, String> {
         &self.hashtree
     }
 
+    /// Stages `hash` for `path` as a regular file.
     pub fn update(&mut self, path: &str, hash: &str) {
+        self.update_with_mode(path, hash, FileMode::Regular);
+    }
+
+    pub fn update_with_mode(&mut self, path: &str, hash: &str, mode: FileMode) {
         self.hashtree.insert(path.to_string(), hash.to_string());
+        match mode {
+            FileMode::Regular => self.modes.remove(path),
+            mode => self.modes.insert(path.to_string(), mode),
+        };
+        self.conflicts.remove(path);
+        self.removed.remove(path);
+    }
+
+    /// The mode of the staged file at `path`.
+    pub fn mode(&self, path: &str) -> FileMode {
+        self.modes.get(path).copied().unwrap_or_default()
+    }
+
+    /// Replaces the staged changes with whatever turns `base`, the files of
+    /// the HEAD commit with their modes, into `files` with `modes`.
+    pub fn stage_files(
+        &mut self,
+        base: &BTreeMap<String, String>,
+        base_modes: &BTreeMap<String, FileMode>,
+        files: &BTreeMap<String, String>,
+        modes: &BTreeMap<String, FileMode>,
+    ) {
+        self.hashtree = BTreeMap::new();
+        self.modes = BTreeMap::new();
+        self.conflicts = BTreeSet::new();
+        self.removed = BTreeSet::new();
+
+        for (path, hash) in files.iter() {
+            let mode = modes.get(path);
+            if base.get(path) != Some(hash) || base_modes.get(path) != mode {
+                self.update_with_mode(path, hash, mode.copied().unwrap_or_default());
+            }
+        }
+        for path in base.keys() {
+            if !files.contains_key(path) {
+                self.remove(path);
+            }
+        }
+    }
+
+    /// Stages the deletion of `path` for the next commit.
+    pub fn remove(&mut self, path: &str) {
+        self.hashtree.remove(path);
+        self.modes.remove(path);
+        self.conflicts.remove(path);
+        self.removed.insert(path.to_string());
+    }
+
+    /// Drops whatever is staged for `path`, so that HEAD's version of it,
+    /// if it has one, is tracked again.
+    pub fn unstage(&mut self, path: &str) {
+        self.hashtree.remove(path);
+        self.modes.remove(path);
+        self.conflicts.remove(path);
+        self.removed.remove(path);
+    }
+
+    pub fn removed(&self) -> &BTreeSet<String> {
+        &self.removed
+    }
+
+    /// Stages `hash` for `path` but flags the path as still unmerged.
+    pub fn mark_conflict(&mut self, path: &str, hash: &str) {
+        self.hashtree.insert(path.to_string(), hash.to_string());
+        self.modes.remove(path);
+        self.conflicts.insert(path.to_string());
+    }
+
+    pub fn conflicts(&self) -> &BTreeSet<String> {
+        &self.conflicts
     }
 
     pub(crate) fn clear(&mut self) -> Result<()> {
         self.hashtree = BTreeMap::new();
+        self.modes = BTreeMap::new();
+        self.conflicts = BTreeSet::new();
+        self.removed = BTreeSet::new();
         self.write()?;
         Ok(())
     }
 
-    fn write(&self) -> Result<()> {
-        let mut index = File::create(&self.path)?;
-        for (hash, path) in self.hashtree.iter() {
-            writeln!(&mut index, "{hash} {path}");
+    /// Remembers that the working tree file at `path` had `hash` when its
+    /// stat data was `stat`.
+    pub fn record_stat(&mut self, path: &str, stat: Stat, hash: &str) {
+        self.stat_cache
+            .insert(path.to_string(), (stat, hash.to_string()));
+    }
+
+    /// The hash of the file at `path` if it was hashed while its stat data
+    /// was the same as `stat`, unless the file was modified too close to the
+    /// last write of the index to tell.
+    pub fn cached_hash(&self, path: &str, stat: &Stat) -> Option<&str> {
+        let racy = self
+            .written
+            .is_none_or(|written| (stat.mtime, stat.mtime_nsec) >= written);
+        match self.stat_cache.get(path) {
+            Some((cached, hash)) if cached == stat && !racy => Some(hash),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn write(&self) -> Result<()> {
+        let data = index::encode(self, self.hash_algorithm);
+        let held = self
+            .lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        match held {
+            Some(mut lock) => {
+                lock.write_all(&data)?;
+                lock.commit()
+            }
+            None => lock::write(&self.path, &data, self.lock_timeout),
         }
-        Ok(())
     }
 }
 
 impl Commit {
-    pub fn new(parent: Option<&Commit>) -> Commit {
+    pub fn new(parent: Option<&Commit>, message: &str) -> Commit {
         let mut commit = Commit {
             hash: None,
             data: None,
-            parent: match parent {
+            tree: None,
+            parents: match parent {
                 Some(&Commit {
                     hash: Some(ref hash),
                     ..
-                }) => Some(hash.to_string()),
-                _ => None,
+                }) => vec![hash.to_string()],
+                _ => Vec::new(),
             },
             files: BTreeMap::new(),
+            modes: BTreeMap::new(),
+            author: None,
+            committer: None,
+            signature: None,
+            signing_key: None,
+            message: message.trim_end().to_string(),
         };
 
         for (hash, path) in parent.iter().flat_map(|p| p.files.iter()) {
             commit.files.insert(hash.to_string(), path.to_string());
         }
+        if let Some(parent) = parent {
+            commit.modes = parent.modes.clone();
+        }
 
         commit
     }
 
     pub fn from(hash: &str, input: &str) -> Result<Commit> {
-        let mut commit = Commit::new(None);
+        let invalid = || Error::InvalidObject {
+            hash: hash.to_string(),
+            kind: ObjectKind::Commit,
+        };
+        let mut commit = Commit::new(None, "");
         commit.hash = Some(hash.to_string());
 
-        let parent = Regex::new(r"parent ([0-9a-f]{40})").unwrap();
-        let blob = Regex::new(r"blob ([0-9a-f]{40}) (.*)").unwrap();
+        // the signature spans several lines, so it is taken out up front
+        let (input, signature) = sign::split_signature(input.as_bytes());
+        let input = String::from_utf8(input).map_err(|_| invalid())?;
+        let input = input.as_str();
+        commit.signature = signature;
 
-        for line in input.lines() {
+        // headers are separated from the message by the first blank line
+        let (headers, message) = input.split_once("\n\n").unwrap_or((input, ""));
+        commit.message = message.trim_end().to_string();
+
+        let parent = Regex::new(&format!(r"parent ({HEX_PATTERN})")).unwrap();
+        let tree = Regex::new(&format!(r"^tree ({HEX_PATTERN})$")).unwrap();
+        // commits written before trees existed list their files inline
+        let blob = Regex::new(&format!(r"blob ({HEX_PATTERN}) (.*)")).unwrap();
+
+        for line in headers.lines() {
             if let Some(caps) = parent.captures(line) {
                 // this syntax is ugly looking but is definitely better than panicing imo
                 // alternate way to do this would be
                 // commit.parent = Some(caps.get().unwrap().as_str().into());
                 // or
-                // commit.parent = Some(caps.get(1).ok_or_else(|| Error::InvalidObjectStore)?.as_str().into());
+                // commit.parent = Some(caps.get(1).ok_or_else(invalid)?.as_str().into());
                 // if-let-else can be used together to all this at once but looks cluttered
                 let Some(hash) = caps.get(1) else {
-                    return Err(Error::InvalidObjectStore);
+                    return Err(invalid());
                 };
-                commit.parent = Some(hash.as_str().into());
+                commit.parents.push(hash.as_str().into());
+            }
+
+            if let Some(caps) = tree.captures(line) {
+                commit.tree = Some(caps[1].to_string());
+            }
+
+            if let Some(author) = line.strip_prefix("author ") {
+                commit.author = Some(Signature::parse(author).ok_or_else(invalid)?);
+            }
+
+            if let Some(committer) = line.strip_prefix("committer ") {
+                commit.committer = Some(Signature::parse(committer).ok_or_else(invalid)?);
             }
 
             if let Some(caps) = blob.captures(line) {
                 let Some(hash) = caps.get(1) else {
-                    return Err(Error::InvalidObjectStore);
+                    return Err(invalid());
                 };
-                let Some(ref path) = caps.get(3) else {
-                    return Err(Error::InvalidObjectStore);
+                let Some(ref path) = caps.get(2) else {
+                    return Err(invalid());
                 };
 
                 commit
                     .files
-                    .insert(hash.as_str().to_string(), path.as_str().to_string());
+                    .insert(path.as_str().to_string(), hash.as_str().to_string());
             }
         }
 
@@ -342,36 +1971,250 @@ impl Commit {
 }
 
 impl Commit {
-    pub(crate) fn print(&self) {
-        if let Some(ref parent) = self.parent {
-            println!("parent {parent}");
-        }
+    pub fn hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
 
-        for (hash, path) in self.files.iter() {
-            println!("blob {hash} {path}");
-        }
+    /// The first parent, which for a merge is the branch merged into.
+    pub fn parent(&self) -> Option<&str> {
+        self.parents.first().map(|parent| parent.as_str())
+    }
+
+    pub fn parents(&self) -> &[String] {
+        &self.parents
+    }
+
+    pub fn add_parent(&mut self, hash: &str) {
+        self.parents.push(hash.to_string());
+    }
+
+    /// Replaces the files, keeping the modes of those still there.
+    pub(crate) fn set_files(&mut self, files: BTreeMap<String, String>) {
+        self.modes.retain(|path, _| files.contains_key(path));
+        self.files = files;
+    }
+
+    pub(crate) fn set_modes(&mut self, modes: BTreeMap<String, FileMode>) {
+        self.modes = modes;
+    }
+
+    pub fn tree(&self) -> Option<&str> {
+        self.tree.as_deref()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn author(&self) -> Option<&Signature> {
+        self.author.as_ref()
+    }
+
+    pub fn committer(&self) -> Option<&Signature> {
+        self.committer.as_ref()
+    }
+
+    pub fn set_author(&mut self, author: Signature) {
+        self.author = Some(author);
+    }
+
+    pub fn set_committer(&mut self, committer: Signature) {
+        self.committer = Some(committer);
+    }
+
+    pub fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+
+    /// Signs the commit with `key` when it is written.
+    pub fn set_signing_key(&mut self, key: SigningKey) {
+        self.signing_key = Some(Box::new(key));
+    }
+
+    /// Files recorded in this commit, keyed by path.
+    pub fn files(&self) -> &BTreeMap<String, String> {
+        &self.files
+    }
+
+    /// Modes of the files that are not regular ones, keyed by path.
+    pub fn modes(&self) -> &BTreeMap<String, FileMode> {
+        &self.modes
+    }
+
+    pub fn mode(&self, path: &str) -> FileMode {
+        self.modes.get(path).copied().unwrap_or_default()
     }
 
     pub(crate) fn add_from_index(&mut self, index: &Index) {
         for (hash, path) in index.hashtree().iter() {
             self.files.insert(hash.to_string(), path.to_string());
+            self.modes.remove(hash);
+        }
+        self.modes
+            .extend(index.modes.iter().map(|(path, mode)| (path.clone(), *mode)));
+        for path in index.removed() {
+            self.files.remove(path);
+            self.modes.remove(path);
+        }
+    }
+
+    pub(crate) fn update(&mut self, algorithm: HashAlgorithm) {
+        let mut text = String::new();
+
+        if let Some(ref tree) = self.tree {
+            text.push_str(&format!("tree {tree}\n"));
+        }
+
+        for parent in self.parents.iter() {
+            text.push_str(&format!("parent {parent}\n"));
+        }
+
+        if let Some(ref author) = self.author {
+            text.push_str(&format!("author {author}\n"));
+        }
+
+        if let Some(ref committer) = self.committer {
+            text.push_str(&format!("committer {committer}\n"));
+        }
+
+        text.push_str(&format!("\n{}\n", self.message));
+        let mut data = text.into_bytes();
+
+        // the signature covers everything else and goes after the headers
+        if let Some(ref key) = self.signing_key {
+            let signature = key.sign(&data);
+            let end = data.windows(2).position(|w| w == b"\n\n").unwrap_or(0) + 1;
+            let header = sign::fold_header(sign::SIGNATURE_HEADER, &signature);
+            data.splice(end..end, format!("{header}\n").into_bytes());
+            self.signature = Some(signature);
+        }
+
+        self.hash = Some(hash_object(algorithm, ObjectKind::Commit, &data));
+        self.data = Some(data);
+    }
+}
+
+impl Tag {
+    pub fn new(
+        object: &str,
+        kind: ObjectKind,
+        name: &str,
+        tagger: Signature,
+        message: &str,
+    ) -> Tag {
+        Tag {
+            hash: None,
+            data: None,
+            object: object.to_string(),
+            kind,
+            name: name.to_string(),
+            tagger: Some(tagger),
+            message: message.trim_end().to_string(),
+            signature: None,
+            signing_key: None,
         }
     }
 
-    pub(crate) fn update(&mut self) {
-        let mut data: Vec<u8> = Vec::new();
+    /// Tags are told apart from commits by their leading `object` header.
+    fn is_tag(input: &str) -> bool {
+        let object = Regex::new(&format!(r"^object {HEX_PATTERN}\ntype [a-z]+\n")).unwrap();
+        object.is_match(input)
+    }
+
+    pub fn from(hash: &str, input: &str) -> Result<Tag> {
+        let invalid = || Error::InvalidObject {
+            hash: hash.to_string(),
+            kind: ObjectKind::Tag,
+        };
+        let (input, signature) = sign::split_tag_signature(input.as_bytes());
+        let input = String::from_utf8(input).map_err(|_| invalid())?;
+        let (headers, message) = input.split_once("\n\n").unwrap_or((&input, ""));
+        let mut tag = Tag {
+            hash: Some(hash.to_string()),
+            data: None,
+            object: String::new(),
+            kind: ObjectKind::Commit,
+            name: String::new(),
+            tagger: None,
+            message: message.trim_end().to_string(),
+            signature,
+            signing_key: None,
+        };
+
+        for line in headers.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                return Err(invalid());
+            };
+            match key {
+                "object" => tag.object = value.to_string(),
+                "type" => tag.kind = ObjectKind::parse(value).ok_or_else(invalid)?,
+                "tag" => tag.name = value.to_string(),
+                "tagger" => tag.tagger = Signature::parse(value),
+                _ => {}
+            }
+        }
+
+        if tag.object.is_empty() {
+            return Err(invalid());
+        }
+        Ok(tag)
+    }
+}
+
+impl Tag {
+    pub fn hash(&self) -> Option<&str> {
+        self.hash.as_deref()
+    }
+
+    pub fn object(&self) -> &str {
+        &self.object
+    }
+
+    pub fn kind(&self) -> ObjectKind {
+        self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn tagger(&self) -> Option<&Signature> {
+        self.tagger.as_ref()
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn signature(&self) -> Option<&str> {
+        self.signature.as_deref()
+    }
+
+    /// Signs the tag with `key` when it is written.
+    pub fn set_signing_key(&mut self, key: SigningKey) {
+        self.signing_key = Some(Box::new(key));
+    }
+
+    pub(crate) fn update(&mut self, algorithm: HashAlgorithm) {
+        let mut text = String::new();
 
-        if let Some(ref parent) = self.parent {
-            writeln!(&mut data, "parent {parent}");
+        text.push_str(&format!("object {}\n", self.object));
+        text.push_str(&format!("type {}\n", self.kind.as_str()));
+        text.push_str(&format!("tag {}\n", self.name));
+        if let Some(ref tagger) = self.tagger {
+            text.push_str(&format!("tagger {tagger}\n"));
         }
+        text.push_str(&format!("\n{}\n", self.message));
 
-        for (hash, path) in self.files.iter() {
-            writeln!(&mut data, "blob {hash}, {path}");
+        // as with git, the signature of everything before it ends the message
+        if let Some(ref key) = self.signing_key {
+            let signature = key.sign(text.as_bytes());
+            text.push_str(&format!("{signature}\n"));
+            self.signature = Some(signature);
         }
+        let data = text.into_bytes();
 
-        let mut hash = Sha1::new();
-        hash.input(&data);
-        self.hash = Some(hash.result_str());
+        self.hash = Some(hash_object(algorithm, ObjectKind::Tag, &data));
         self.data = Some(data);
     }
 }
@@ -0,0 +1,567 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    fs::{self, File},
+    io,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{symlink, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::{
+    attributes::Attributes,
+    diff::Rename,
+    hash::HashAlgorithm,
+    ignore::Ignore,
+    index::Stat,
+    pathspec::Pathspec,
+    submodule::{self, MODULES_FILE},
+    types::{Blob, Commit, Context, Error, FileMode, FileService, Index, Result},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+}
+
+#[derive(Debug, Default)]
+pub struct Status {
+    pub staged: Vec<(Change, String)>,
+    pub unstaged: Vec<(Change, String)>,
+    pub unmerged: Vec<String>,
+    pub untracked: Vec<String>,
+}
+
+/// The checked out files of a repository, everything under the root
+/// directory except the `.blip` directory itself.
+#[derive(Debug)]
+pub struct WorkTree {
+    root_dir: PathBuf,
+    /// The `.blip` directory, or file in a linked working tree.
+    blip_dir: PathBuf,
+    common_dir: PathBuf,
+    bare: bool,
+    hash_algorithm: HashAlgorithm,
+    /// Loaded on first use, see [`WorkTree::attributes`].
+    attributes: OnceLock<Attributes>,
+}
+
+impl Change {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Change::Added => "new file",
+            Change::Modified => "modified",
+            Change::Deleted => "deleted",
+        }
+    }
+}
+
+impl Status {
+    /// The lines listing `changes`, a label and a path each, with the
+    /// files `renames` pairs up on one line in place of the added one.
+    pub fn describe(changes: &[(Change, String)], renames: &[Rename]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (change, path) in changes {
+            let rename = renames.iter().find(|rename| rename.to == *path);
+            let line = match (change, rename) {
+                (Change::Added, Some(rename)) => {
+                    let label = if rename.copy { "copied:" } else { "renamed:" };
+                    format!("{label:<12}{} -> {path}", rename.from)
+                }
+                (Change::Deleted, _)
+                    if renames
+                        .iter()
+                        .any(|rename| !rename.copy && rename.from == *path) =>
+                {
+                    continue;
+                }
+                _ => format!("{:<12}{path}", format!("{}:", change.label())),
+            };
+            lines.push(line);
+        }
+        lines
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.unstaged.is_empty() && self.unmerged.is_empty()
+    }
+}
+
+impl WorkTree {
+    pub fn new(file_service: &FileService) -> WorkTree {
+        WorkTree {
+            root_dir: file_service.root_dir.clone(),
+            blip_dir: file_service.root_dir.join(".blip"),
+            common_dir: file_service.common_dir.clone(),
+            bare: file_service.bare,
+            hash_algorithm: file_service.hash_algorithm,
+            attributes: OnceLock::new(),
+        }
+    }
+
+    /// Lists every file in the working tree as a path relative to the root.
+    pub fn files(&self) -> Result<BTreeSet<String>> {
+        let mut files = BTreeSet::new();
+        self.walk(&self.root_dir, &mut files)?;
+        Ok(files)
+    }
+
+    fn walk(&self, dir: &Path, files: &mut BTreeSet<String>) -> Result<()> {
+        for entry in fs::read_dir(dir).context("read", dir)? {
+            let entry = entry.context("read", dir)?;
+            let path = entry.path();
+            if path == self.blip_dir {
+                continue;
+            }
+
+            // a nested repository is listed as a whole, see `hash`, and
+            // symbolic links are never followed
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            if is_dir && !path.join(".blip").exists() {
+                self.walk(&path, files)?;
+            } else if let Some(relative) = self.relative(&path) {
+                files.insert(relative);
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the ignore rules that apply to this working tree.
+    pub fn ignore(&self) -> Result<Ignore> {
+        Ignore::load(&self.root_dir, &self.blip_dir)
+    }
+
+    /// The attributes of the paths of this working tree, read on first use.
+    pub fn attributes(&self) -> Result<&Attributes> {
+        if let Some(attributes) = self.attributes.get() {
+            return Ok(attributes);
+        }
+        let attributes = Attributes::load(&self.root_dir, &self.common_dir, self.bare)?;
+        Ok(self.attributes.get_or_init(|| attributes))
+    }
+
+    fn relative(&self, path: &Path) -> Option<String> {
+        let relative = path.strip_prefix(&self.root_dir).ok()?;
+        relative.to_str().map(|path| path.to_string())
+    }
+
+    /// Hashes the file at `path`, or returns `None` if it no longer exists.
+    /// A nested repository, such as a submodule, stands for the commit it
+    /// has checked out.
+    pub fn hash(&self, path: &str) -> Result<Option<String>> {
+        let full_path = self.root_dir.join(path);
+        if full_path.is_symlink() {
+            return Ok(Some(self.hash_file(path)?));
+        }
+        if full_path.join(".blip").exists() {
+            return FileService::open(&full_path)?.read_head_hash();
+        }
+        if !full_path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(self.hash_file(path)?))
+    }
+
+    /// The mode of the file at `path`, or `None` if there is no file there.
+    pub fn mode(&self, path: &str) -> Option<FileMode> {
+        let metadata = fs::symlink_metadata(self.root_dir.join(path)).ok()?;
+        if !metadata.is_file() && !metadata.is_symlink() {
+            return None;
+        }
+        Some(FileMode::from_metadata(&metadata))
+    }
+
+    /// Hashes the file at `path` as it would be stored, see
+    /// [`WorkTree::read_converted`].
+    pub fn hash_file(&self, path: &str) -> Result<String> {
+        if self.attributes()?.converts(path) || self.root_dir.join(path).is_symlink() {
+            let blob = Blob::from_data(self.read_converted(path)?, self.hash_algorithm);
+            return Ok(blob.hash().clone());
+        }
+        Blob::hash_file(&self.root_dir.join(path), self.hash_algorithm)
+    }
+
+    /// Stores the file at `path` as a blob, converted as its attributes
+    /// say, and returns its hash.
+    pub fn write_blob(&self, file_service: &FileService, path: &str) -> Result<String> {
+        if self.attributes()?.converts(path) || self.root_dir.join(path).is_symlink() {
+            let blob = Blob::from_data(self.read_converted(path)?, self.hash_algorithm);
+            file_service.write_blob(&blob)?;
+            return Ok(blob.hash().clone());
+        }
+        file_service.write_blob_file(&self.root_dir.join(path))
+    }
+
+    /// Reads the file at `path` as it is stored, with the conversions its
+    /// attributes ask for, see [`Attributes::to_store`]. A symbolic link is
+    /// stored as the path it points at, never converted.
+    pub fn read_converted(&self, path: &str) -> Result<Vec<u8>> {
+        let full_path = self.root_dir.join(path);
+        if full_path.is_symlink() {
+            let target = fs::read_link(&full_path).context("read", &full_path)?;
+            return Ok(target.as_os_str().as_bytes().to_vec());
+        }
+        let data = self.read_file(path)?;
+        self.attributes()?.to_store(path, data)
+    }
+
+    /// Like [`WorkTree::hash`], but takes the hash from the index's stat
+    /// cache when the file's stat data has not changed since it was hashed.
+    pub fn hash_cached(&self, index: &Index, path: &str) -> Result<Option<String>> {
+        let full_path = self.root_dir.join(path);
+        let Ok(metadata) = fs::symlink_metadata(&full_path) else {
+            return Ok(None);
+        };
+        if metadata.is_dir() {
+            return self.hash(path);
+        }
+        if !metadata.is_file() && !metadata.is_symlink() {
+            return Ok(None);
+        }
+        match index.cached_hash(path, &Stat::from_metadata(&metadata)) {
+            Some(hash) => Ok(Some(hash.to_string())),
+            None => self.hash(path),
+        }
+    }
+
+    /// Brings the index's stat cache up to date for `paths`, hashing only
+    /// the files whose stat data changed, and returns whether anything was
+    /// recorded. Entries for files that are gone are dropped.
+    pub fn refresh<'a>(
+        &self,
+        index: &mut Index,
+        paths: impl IntoIterator<Item = &'a String>,
+    ) -> Result<bool> {
+        let mut changed = false;
+        for path in paths {
+            let full_path = self.root_dir.join(path);
+            let metadata = match fs::symlink_metadata(&full_path) {
+                Ok(metadata) if metadata.is_file() || metadata.is_symlink() => metadata,
+                _ => {
+                    changed |= index.stat_cache.remove(path).is_some();
+                    continue;
+                }
+            };
+            let stat = Stat::from_metadata(&metadata);
+            if index.cached_hash(path, &stat).is_some() {
+                continue;
+            }
+            let hash = self.hash_file(path)?;
+            index.record_stat(path, stat, &hash);
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    pub fn write_file(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.refuse_beyond_symlink(path)?;
+        let full_path = self.root_dir.join(path);
+        if let Some(parent) = full_path.parent() {
+            fs::create_dir_all(parent).context("create", parent)?;
+        }
+        fs::write(&full_path, data).context("write", &full_path)
+    }
+
+    /// Writes the blob `hash` to `path`, streaming it from the object store
+    /// unless its attributes ask for it to be converted, see
+    /// [`Attributes::to_worktree`]. A symbolic link is made to point at the
+    /// path the blob holds, and any other file is made executable or not as
+    /// `mode` says.
+    pub fn checkout_file(
+        &self,
+        file_service: &FileService,
+        path: &str,
+        hash: &str,
+        mode: FileMode,
+    ) -> Result<()> {
+        // never write through a link that is already there
+        self.refuse_beyond_symlink(path)?;
+        let full_path = self.root_dir.join(path);
+        if full_path.is_symlink() {
+            fs::remove_file(&full_path).context("remove", &full_path)?;
+        }
+        if mode == FileMode::Symlink {
+            if let Some(parent) = full_path.parent() {
+                fs::create_dir_all(parent).context("create", parent)?;
+            }
+            if full_path.is_file() {
+                fs::remove_file(&full_path).context("remove", &full_path)?;
+            }
+            let target = file_service.read_blob(hash)?;
+            let target = Path::new(OsStr::from_bytes(&target));
+            return symlink(target, &full_path).context("write", &full_path);
+        }
+
+        let attributes = self.attributes()?;
+        if attributes.converts(path) {
+            let data = attributes.to_worktree(path, file_service.read_blob(hash)?)?;
+            self.write_file(path, &data)?;
+        } else {
+            let mut blob = file_service.open_blob(hash)?;
+            if let Some(parent) = full_path.parent() {
+                fs::create_dir_all(parent).context("create", parent)?;
+            }
+            let mut file = File::create(&full_path).context("write", &full_path)?;
+            io::copy(&mut blob, &mut file).context("write", &full_path)?;
+        }
+        set_executable(&full_path, mode == FileMode::Executable)
+    }
+
+    /// Whether one of the directories leading to `path` is a symbolic link,
+    /// which writing to `path` would follow out of the working tree.
+    pub fn is_beyond_symlink(&self, path: &str) -> bool {
+        let mut dir = self.root_dir.clone();
+        let parts: Vec<&str> = path.split('/').collect();
+        parts[..parts.len() - 1].iter().any(|part| {
+            dir.push(part);
+            dir.is_symlink()
+        })
+    }
+
+    fn refuse_beyond_symlink(&self, path: &str) -> Result<()> {
+        match self.is_beyond_symlink(path) {
+            true => Err(Error::BeyondSymlink(path.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    /// Deletes a file along with any parent directories it leaves empty.
+    pub fn remove_file(&self, path: &str) -> Result<()> {
+        self.refuse_beyond_symlink(path)?;
+        let full_path = self.root_dir.join(path);
+        if full_path.is_file() || full_path.is_symlink() {
+            fs::remove_file(&full_path).context("remove", &full_path)?;
+        }
+
+        let mut dir = full_path.parent();
+        while let Some(parent) = dir {
+            if parent == self.root_dir || fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+        Ok(())
+    }
+
+    /// Moves the working tree from the files of one commit to those of
+    /// another, removing files that only exist in `from`. Submodules only
+    /// get an empty directory, which `blip submodule update` clones them
+    /// into; those already there are left alone. `modes` are those of the
+    /// files in `to` that are not regular ones.
+    pub fn checkout(
+        &self,
+        file_service: &FileService,
+        from: &BTreeMap<String, String>,
+        to: &BTreeMap<String, String>,
+        modes: &BTreeMap<String, FileMode>,
+    ) -> Result<()> {
+        if let Some(path) = from
+            .keys()
+            .chain(to.keys())
+            .find(|path| !is_safe_path(path))
+        {
+            return Err(Error::UnsafePath(path.clone()));
+        }
+        for path in from.keys() {
+            if !to.contains_key(path) {
+                self.remove_file(path)?;
+            }
+        }
+
+        let submodules = submodule::paths(file_service, to)?;
+        for (path, hash) in to.iter() {
+            if submodules.contains(path) {
+                let full_path = self.root_dir.join(path);
+                fs::create_dir_all(&full_path).context("create", &full_path)?;
+                continue;
+            }
+            let mode = modes.get(path).copied().unwrap_or_default();
+            if from.get(path) != Some(hash) || self.mode(path) != Some(mode) {
+                self.checkout_file(file_service, path, hash, mode)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The files tracked at the moment: the HEAD commit overlaid with
+    /// whatever has been staged in the index since.
+    pub fn tracked_files(index: &Index, head: Option<&Commit>) -> BTreeMap<String, String> {
+        let mut tracked = head.map_or_else(BTreeMap::new, |commit| commit.files().clone());
+        for (path, hash) in index.hashtree() {
+            tracked.insert(path.clone(), hash.clone());
+        }
+        for path in index.removed() {
+            tracked.remove(path);
+        }
+        tracked
+    }
+
+    /// The modes of the tracked files that are not regular ones, see
+    /// [`WorkTree::tracked_files`].
+    pub fn tracked_modes(index: &Index, head: Option<&Commit>) -> BTreeMap<String, FileMode> {
+        let mut modes = head.map_or_else(BTreeMap::new, |commit| commit.modes().clone());
+        for path in index.hashtree().keys() {
+            modes.remove(path);
+        }
+        modes.extend(index.modes.iter().map(|(path, mode)| (path.clone(), *mode)));
+        for path in index.removed() {
+            modes.remove(path);
+        }
+        modes
+    }
+
+    /// Hashes the current contents of `files`, leaving out any that have
+    /// been deleted from the working tree.
+    pub fn snapshot(&self, files: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+        let mut snapshot = BTreeMap::new();
+        for path in files.keys() {
+            if let Some(hash) = self.hash(path)? {
+                snapshot.insert(path.clone(), hash);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// The modes of those of `files` in the working tree that are not
+    /// regular ones.
+    pub fn modes(&self, files: &BTreeMap<String, String>) -> BTreeMap<String, FileMode> {
+        files
+            .keys()
+            .filter_map(|path| Some((path.clone(), self.mode(path)?)))
+            .filter(|(_, mode)| *mode != FileMode::Regular)
+            .collect()
+    }
+
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>> {
+        let full_path = self.root_dir.join(path);
+        fs::read(&full_path).context("read", &full_path)
+    }
+
+    /// The paths the `.blipmodules` of the working tree lists as
+    /// submodules, see [`submodule`].
+    pub fn submodules(&self) -> Result<BTreeSet<String>> {
+        if !self.root_dir.join(MODULES_FILE).is_file() {
+            return Ok(BTreeSet::new());
+        }
+        let submodules = submodule::parse(&self.read_file(MODULES_FILE)?)?;
+        Ok(submodules
+            .into_iter()
+            .map(|submodule| submodule.path)
+            .collect())
+    }
+
+    /// Compares the working tree against the index and the HEAD commit.
+    ///
+    /// Since the index only holds changes staged since the last commit, the
+    /// set of tracked files is the HEAD commit overlaid with the index.
+    pub fn status(&self, index: &Index, head: Option<&Commit>) -> Result<Status> {
+        self.status_matching(index, head, &Pathspec::default())
+    }
+
+    /// Like [`WorkTree::status`], but only for the paths `pathspec` matches.
+    pub fn status_matching(
+        &self,
+        index: &Index,
+        head: Option<&Commit>,
+        pathspec: &Pathspec,
+    ) -> Result<Status> {
+        let empty = BTreeMap::new();
+        let committed = head.map_or(&empty, |commit| commit.files());
+        let committed_mode = |path: &str| head.map_or(FileMode::Regular, |head| head.mode(path));
+        let mut tracked = WorkTree::tracked_files(index, head);
+        tracked.retain(|path, _| pathspec.matches(path));
+        let modes = WorkTree::tracked_modes(index, head);
+
+        let mut status = Status::default();
+
+        for (path, hash) in index.hashtree() {
+            if !pathspec.matches(path) {
+                continue;
+            }
+            if index.conflicts().contains(path) {
+                status.unmerged.push(path.clone());
+                continue;
+            }
+            match committed.get(path) {
+                None => status.staged.push((Change::Added, path.clone())),
+                Some(old) if old != hash || index.mode(path) != committed_mode(path) => {
+                    status.staged.push((Change::Modified, path.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+
+        for path in index.removed() {
+            if committed.contains_key(path) && pathspec.matches(path) {
+                status.staged.push((Change::Deleted, path.clone()));
+            }
+        }
+        status.staged.sort_by(|a, b| a.1.cmp(&b.1));
+
+        let submodules = self.submodules()?;
+        for (path, hash) in tracked.iter() {
+            if index.conflicts().contains(path) {
+                continue;
+            }
+            match self.hash_cached(index, path)? {
+                // a submodule that was never cloned is not missing
+                None if submodules.contains(path) && self.root_dir.join(path).is_dir() => {}
+                None => status.unstaged.push((Change::Deleted, path.clone())),
+                Some(current) if &current != hash => {
+                    status.unstaged.push((Change::Modified, path.clone()))
+                }
+                // the same contents as a link instead of a file, or back
+                Some(_)
+                    if self.mode(path).is_some_and(|mode| {
+                        mode != modes.get(path).copied().unwrap_or_default()
+                    }) =>
+                {
+                    status.unstaged.push((Change::Modified, path.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+
+        let ignore = self.ignore()?;
+        for path in self.files()? {
+            if !tracked.contains_key(&path)
+                && !ignore.is_ignored(&path, false)
+                && pathspec.matches(&path)
+            {
+                status.untracked.push(path);
+            }
+        }
+
+        Ok(status)
+    }
+}
+
+/// Whether `path`, relative to the root, stays inside the working tree and
+/// out of the `.blip` directory, in any case, as file systems that ignore
+/// it would let it in.
+pub fn is_safe_path(path: &str) -> bool {
+    path.split('/').all(|part| {
+        !part.is_empty() && part != "." && part != ".." && !part.eq_ignore_ascii_case(".blip")
+    })
+}
+
+/// Gives the file at `full_path` the executable bits wherever it has the
+/// read ones, or takes them all away, leaving the other bits alone.
+fn set_executable(full_path: &Path, executable: bool) -> Result<()> {
+    let metadata = fs::metadata(full_path).context("read", full_path)?;
+    let mode = metadata.permissions().mode();
+    let wanted = match executable {
+        true => mode | (mode & 0o444) >> 2,
+        false => mode & !0o111,
+    };
+    if wanted != mode {
+        let permissions = fs::Permissions::from_mode(wanted);
+        fs::set_permissions(full_path, permissions).context("write", full_path)?;
+    }
+    Ok(())
+}
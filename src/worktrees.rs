@@ -0,0 +1,265 @@
+//! Linked working trees: more working directories for one repository,
+//! each with its own HEAD and index but sharing objects, refs, config and
+//! hooks with the main one, so that several branches can be checked out at
+//! once.
+//!
+//! A linked working tree has a `.blip` file instead of a directory,
+//! holding `blipdir: <dir>` where `<dir>` is `.blip/worktrees/<name>` of
+//! the repository. That directory holds its HEAD and index, a `commondir`
+//! file naming the shared `.blip` relative to it and a `blipdir` file
+//! naming the `.blip` file back, which is how the working trees are found
+//! again.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    refs::{Head, Refs},
+    types::{Context, Error, FileService, Result},
+    worktree::WorkTree,
+};
+
+/// Where the state of linked working trees is kept, under `.blip`.
+const WORKTREES_DIR: &str = "worktrees";
+
+#[derive(Debug)]
+pub struct Worktree {
+    /// The name of its directory under `.blip/worktrees`, `None` for the
+    /// main working tree.
+    pub name: Option<String>,
+    pub path: PathBuf,
+    /// Whether this is the main working tree of a bare repository, which
+    /// is only a place for HEAD and has no files.
+    pub bare: bool,
+    /// Its HEAD, the index and the rest of its state, see
+    /// [`FileService::blip_dir`].
+    pub blip_dir: PathBuf,
+    /// `None` if HEAD cannot be read.
+    pub head: Option<Head>,
+}
+
+impl Worktree {
+    /// Whether the directory of the working tree has been deleted.
+    pub fn is_missing(&self) -> bool {
+        !self.path.join(".blip").exists()
+    }
+
+    /// Opens the working tree, even if its directory is gone.
+    pub fn open(&self) -> Result<FileService> {
+        FileService::open_blip_dir(&self.blip_dir)
+    }
+}
+
+/// Lists the main working tree followed by the linked ones, sorted by name.
+pub fn list(file_service: &FileService) -> Result<Vec<Worktree>> {
+    let common_dir = &file_service.common_dir;
+    let bare = file_service
+        .config()?
+        .get_bool("core.bare")?
+        .unwrap_or(false);
+    let main = match bare {
+        true => common_dir.clone(),
+        false => common_dir.parent().unwrap_or(common_dir).to_path_buf(),
+    };
+    let mut main = describe(file_service, None, main, common_dir.clone());
+    main.bare = bare;
+    let mut worktrees = vec![main];
+
+    let dir = common_dir.join(WORKTREES_DIR);
+    if !dir.is_dir() {
+        return Ok(worktrees);
+    }
+    let mut linked = BTreeMap::new();
+    for entry in fs::read_dir(&dir).context("read", &dir)? {
+        let blip_dir = entry.context("read", &dir)?.path();
+        let link = blip_dir.join("blipdir");
+        let (Some(name), Ok(contents)) = (
+            blip_dir.file_name().and_then(|name| name.to_str()),
+            fs::read_to_string(&link),
+        ) else {
+            continue;
+        };
+        let path = Path::new(contents.trim_end())
+            .parent()
+            .map(Path::to_path_buf);
+        if let Some(path) = path {
+            linked.insert(name.to_string(), (path, blip_dir.clone()));
+        }
+    }
+    for (name, (path, blip_dir)) in linked {
+        worktrees.push(describe(file_service, Some(name), path, blip_dir));
+    }
+    Ok(worktrees)
+}
+
+fn describe(
+    file_service: &FileService,
+    name: Option<String>,
+    path: PathBuf,
+    blip_dir: PathBuf,
+) -> Worktree {
+    let head = fs::read_to_string(blip_dir.join("HEAD"))
+        .ok()
+        .and_then(|contents| Head::parse(&contents, file_service.hash_algorithm));
+    Worktree {
+        name,
+        path,
+        bare: false,
+        blip_dir,
+        head,
+    }
+}
+
+/// The working tree other than the current one that has the branch ref
+/// `name` checked out, if any.
+pub fn checked_out(file_service: &FileService, name: &str) -> Result<Option<Worktree>> {
+    Ok(list(file_service)?.into_iter().find(|worktree| {
+        worktree.blip_dir != file_service.blip_dir
+            && !worktree.bare
+            && worktree.head == Some(Head::Symbolic(name.to_string()))
+    }))
+}
+
+/// Fails if another working tree has the branch ref `name` checked out,
+/// as committing to it there would leave this one behind.
+pub(crate) fn check_not_checked_out(file_service: &FileService, name: &str) -> Result<()> {
+    match checked_out(file_service, name)? {
+        Some(worktree) => Err(Error::Usage(format!(
+            "'{}' is already checked out at '{}'",
+            name.strip_prefix("refs/heads/").unwrap_or(name),
+            worktree.path.display()
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// What to check out in a new working tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checkout {
+    /// An existing branch, which no other working tree may have checked
+    /// out.
+    Branch(String),
+    /// A new branch, starting at the commit given.
+    NewBranch(String, String),
+    /// A detached HEAD at the commit given.
+    Detached(String),
+}
+
+/// Creates a working tree at `path`, which must not exist or be an empty
+/// directory, and checks `checkout` out in it.
+pub fn add(file_service: &FileService, path: &Path, checkout: &Checkout) -> Result<Worktree> {
+    let path = env::current_dir()?.join(path);
+    let is_empty_dir = fs::read_dir(&path).is_ok_and(|mut entries| entries.next().is_none());
+    if path.exists() && !is_empty_dir {
+        return Err(Error::Usage(format!("'{}' already exists", path.display())));
+    }
+
+    let refs = Refs::new(file_service);
+    let (head, hash) = match checkout {
+        Checkout::Branch(branch) => {
+            let name = Refs::branch_ref(branch);
+            check_not_checked_out(file_service, &name)?;
+            if !file_service.bare && file_service.read_head()? == Head::Symbolic(name.clone()) {
+                return Err(Error::Usage(format!(
+                    "'{branch}' is already checked out here"
+                )));
+            }
+            let hash = refs
+                .read(&name)
+                .ok_or_else(|| Error::Usage(format!("branch '{branch}' does not exist")))?;
+            (Head::Symbolic(name), hash)
+        }
+        Checkout::NewBranch(branch, hash) => {
+            refs.create_branch(branch, hash)?;
+            (Head::Symbolic(Refs::branch_ref(branch)), hash.clone())
+        }
+        Checkout::Detached(hash) => (Head::Detached(hash.clone()), hash.clone()),
+    };
+    let commit = file_service.read_commit(&hash)?;
+
+    fs::create_dir_all(&path).context("create", &path)?;
+    let path = fs::canonicalize(&path).context("read", &path)?;
+    let base = path.file_name().map_or("worktree".into(), |name| {
+        name.to_string_lossy().into_owned()
+    });
+    let dir = file_service.common_dir.join(WORKTREES_DIR);
+    let mut name = base.clone();
+    let mut n = 1;
+    while dir.join(&name).exists() {
+        name = format!("{base}{n}");
+        n += 1;
+    }
+
+    let blip_dir = dir.join(&name);
+    fs::create_dir_all(&blip_dir).context("create", &blip_dir)?;
+    let link = path.join(".blip");
+    for (file, contents) in [
+        ("HEAD", head.to_string()),
+        ("commondir", "../..".to_string()),
+        ("blipdir", format!("{}\n", link.display())),
+        ("index", String::new()),
+    ] {
+        let file = blip_dir.join(file);
+        fs::write(&file, contents).context("write", &file)?;
+    }
+    let contents = format!("blipdir: {}\n", blip_dir.display());
+    fs::write(&link, contents).context("write", &link)?;
+
+    let linked = FileService::open(&path)?;
+    WorkTree::new(&linked).checkout(&linked, &BTreeMap::new(), commit.files(), commit.modes())?;
+    Ok(describe(file_service, Some(name), path, blip_dir))
+}
+
+/// `path` made absolute with symlinks resolved, through its parent
+/// directory if it no longer exists.
+fn absolute(path: &Path) -> Result<PathBuf> {
+    let path = env::current_dir()?.join(path);
+    if let Ok(path) = fs::canonicalize(&path) {
+        return Ok(path);
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => Ok(absolute(parent)?.join(name)),
+        _ => Ok(path),
+    }
+}
+
+/// Deletes the linked working tree at `path` along with its state. Unless
+/// `force` is set, a working tree with changes, committed or not, or
+/// untracked files is left alone.
+pub fn remove(file_service: &FileService, path: &Path, force: bool) -> Result<()> {
+    let path = absolute(path)?;
+    let worktree = list(file_service)?
+        .into_iter()
+        .find(|worktree| worktree.path == path)
+        .ok_or_else(|| Error::Usage(format!("'{}' is not a working tree", path.display())))?;
+    if worktree.name.is_none() {
+        return Err(Error::Usage(format!(
+            "'{}' is the main working tree",
+            path.display()
+        )));
+    }
+    if worktree.blip_dir == file_service.blip_dir {
+        return Err(Error::Usage(
+            "cannot remove the working tree you are in".into(),
+        ));
+    }
+
+    if !worktree.is_missing() {
+        if !force {
+            let linked = worktree.open()?;
+            let status = WorkTree::new(&linked)
+                .status(&linked.read_index()?, linked.read_head_commit()?.as_ref())?;
+            if !status.is_clean() || !status.untracked.is_empty() {
+                return Err(Error::Usage(format!(
+                    "'{}' has changes or untracked files; use --force to remove it anyway",
+                    path.display()
+                )));
+            }
+        }
+        fs::remove_dir_all(&path).context("remove", &path)?;
+    }
+    fs::remove_dir_all(&worktree.blip_dir).context("remove", &worktree.blip_dir)
+}
@@ -0,0 +1,171 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use blip::{cli::AddMode, diff::DiffOptions, Commit, Error, Repository};
+use regex::Regex;
+
+/// A directory of its own for a test, removed when the test is over.
+struct Scratch(PathBuf);
+
+impl Scratch {
+    fn new() -> Scratch {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "blip_test_{}_{}",
+            process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Scratch(path)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn write(root: &Path, path: &str, contents: &str) {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn add(repository: &Repository, paths: &[&str]) {
+    let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+    repository.add(&paths, AddMode::Paths, false, None).unwrap();
+}
+
+fn messages(repository: &Repository) -> Vec<String> {
+    repository
+        .log()
+        .unwrap()
+        .map(|commit| commit.unwrap().message().trim_end().to_string())
+        .collect()
+}
+
+#[test]
+fn commits_are_logged_newest_first() {
+    let scratch = Scratch::new();
+    let repository = Repository::init(&scratch.0, Some("main"), None).unwrap();
+    write(&scratch.0, "README", "hello\n");
+    add(&repository, &["README"]);
+    let first = repository.commit("Add a README", false, false).unwrap();
+    write(&scratch.0, "src/main.c", "int main;\n");
+    add(&repository, &["src/main.c"]);
+    let second = repository.commit("Add main", false, false).unwrap();
+
+    assert_eq!(messages(&repository), ["Add main", "Add a README"]);
+    assert_eq!(second.parent(), first.hash());
+    assert_eq!(
+        second.files().keys().collect::<Vec<_>>(),
+        ["README", "src/main.c"]
+    );
+}
+
+#[test]
+fn open_finds_the_repository_above() {
+    let scratch = Scratch::new();
+    Repository::init(&scratch.0, None, None).unwrap();
+    write(&scratch.0, "a/b/file", "");
+
+    let repository = Repository::open(scratch.0.join("a/b")).unwrap();
+    assert_eq!(
+        repository.root_dir().canonicalize().unwrap(),
+        scratch.0.canonicalize().unwrap()
+    );
+}
+
+#[test]
+fn staged_contents_are_committed_not_the_working_tree() {
+    let scratch = Scratch::new();
+    let repository = Repository::init(&scratch.0, None, None).unwrap();
+    write(&scratch.0, "file", "one\n");
+    add(&repository, &["file"]);
+    repository.commit("One", false, false).unwrap();
+    write(&scratch.0, "file", "one\ntwo\nthree\n");
+
+    let changes = repository.unstaged_changes(&[]).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].path, "file");
+    repository
+        .stage_contents("file", Some("one\ntwo\n"))
+        .unwrap();
+    repository.commit("Two", false, false).unwrap();
+
+    let options = DiffOptions::default();
+    let left = repository.diff(&[], false, &[], options).unwrap();
+    assert!(left.contains(" two\n+three\n"), "{left}");
+    assert_eq!(messages(&repository), ["Two", "One"]);
+}
+
+#[test]
+fn amend_replaces_the_head_commit() {
+    let scratch = Scratch::new();
+    let repository = Repository::init(&scratch.0, None, None).unwrap();
+    write(&scratch.0, "file", "one\n");
+    add(&repository, &["file"]);
+    repository.commit("Frist", false, false).unwrap();
+    write(&scratch.0, "other", "two\n");
+    add(&repository, &["other"]);
+    let amended: Commit = repository.amend(Some("First"), false, false).unwrap();
+
+    assert_eq!(messages(&repository), ["First"]);
+    assert_eq!(amended.files().len(), 2);
+}
+
+#[test]
+fn diff_shows_unstaged_then_staged_changes() {
+    let scratch = Scratch::new();
+    let repository = Repository::init(&scratch.0, None, None).unwrap();
+    write(&scratch.0, "file", "one\n");
+    add(&repository, &["file"]);
+    repository.commit("One", false, false).unwrap();
+    write(&scratch.0, "file", "two\n");
+
+    let options = DiffOptions::default();
+    let unstaged = repository.diff(&[], false, &[], options).unwrap();
+    assert!(unstaged.contains("-one\n+two\n"), "{unstaged}");
+    assert_eq!(repository.diff(&[], true, &[], options).unwrap(), "");
+
+    add(&repository, &["file"]);
+    assert_eq!(repository.diff(&[], false, &[], options).unwrap(), "");
+    let staged = repository.diff(&[], true, &[], options).unwrap();
+    assert!(staged.contains("-one\n+two\n"), "{staged}");
+
+    repository.unstage(&["file".to_string()]).unwrap();
+    assert_eq!(repository.diff(&[], true, &[], options).unwrap(), "");
+}
+
+#[test]
+fn grep_searches_tracked_files() {
+    let scratch = Scratch::new();
+    let repository = Repository::init(&scratch.0, None, None).unwrap();
+    write(&scratch.0, "a", "needle\nhay\n");
+    write(&scratch.0, "b", "hay\nneedles\n");
+    write(&scratch.0, "untracked", "needle\n");
+    add(&repository, &["a", "b"]);
+
+    let found = repository.grep(&Regex::new("need").unwrap(), None).unwrap();
+    let found: Vec<(&str, usize)> = found
+        .iter()
+        .map(|found| (found.path.as_str(), found.number))
+        .collect();
+    assert_eq!(found, [("a", 1), ("b", 2)]);
+}
+
+#[test]
+fn diff_refuses_an_unknown_revision() {
+    let scratch = Scratch::new();
+    let repository = Repository::init(&scratch.0, None, None).unwrap();
+    let result = repository.diff(&["nowhere".to_string()], false, &[], DiffOptions::default());
+    assert!(
+        matches!(result, Err(Error::UnknownRevision(_))),
+        "{result:?}"
+    );
+}